@@ -0,0 +1,614 @@
+// transforms written in Rust, listed with the scripts so they're found and run the same way, for
+// hashing, compression and encodings that would be slow or easy to get wrong in JavaScript
+
+use crate::script::{Metadata, Script};
+use flate2::{
+    read::{GzDecoder, ZlibDecoder},
+    write::{GzEncoder, ZlibEncoder},
+    Compression,
+};
+use md5::Md5;
+use rand::RngCore;
+use sha1::Sha1;
+use sha2::{Digest, Sha256, Sha512};
+use std::{
+    io::{Read, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// changes text, scripts run their JavaScript in an executor and builtins run a `Transform` in place
+pub trait Transform: Send + Sync {
+    // the new text, or an error shown to the user
+    fn apply(&self, text: &str) -> Result<String, String>;
+}
+
+type Apply = fn(&str) -> Result<String, String>;
+
+struct Native(Apply);
+
+impl Transform for Native {
+    fn apply(&self, text: &str) -> Result<String, String> {
+        (self.0)(text)
+    }
+}
+
+// metadata of each builtin, as it would be written at the top of a script
+const BUILTINS: &[(&str, Apply)] = &[
+    (
+        r#"{
+            "api": 1,
+            "name": "SHA-256 Hash",
+            "description": "Hashes the text with SHA-256",
+            "icon": "fingerprint",
+            "tags": "sha256,hash,checksum,digest",
+            "pure": true,
+            "tests": [{ "input": "abc", "expected": "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad" }]
+        }"#,
+        sha256,
+    ),
+    (
+        r#"{
+            "api": 1,
+            "name": "CRC32 Checksum",
+            "description": "Checksum of the text with CRC-32",
+            "icon": "fingerprint",
+            "tags": "crc32,checksum,hash",
+            "pure": true,
+            "tests": [{ "input": "123456789", "expected": "cbf43926" }]
+        }"#,
+        crc32_checksum,
+    ),
+    (
+        r#"{
+            "api": 1,
+            "name": "Gzip Compress",
+            "description": "Compresses the text with gzip, as base64",
+            "icon": "collapse",
+            "tags": "gzip,compress,deflate",
+            "pure": true
+        }"#,
+        gzip_compress,
+    ),
+    (
+        r#"{
+            "api": 1,
+            "name": "Gzip Decompress",
+            "description": "Decompresses base64 gzip data",
+            "icon": "collapse",
+            "tags": "gzip,decompress,inflate,gunzip",
+            "pure": true,
+            "tests": [{ "input": "H4sIAAAAAAACA8tIzcnJBwCGphA2BQAAAA==", "expected": "hello" }]
+        }"#,
+        gzip_decompress,
+    ),
+    (
+        r#"{
+            "api": 1,
+            "name": "Zlib Compress",
+            "description": "Compresses the text with zlib, as base64",
+            "icon": "collapse",
+            "tags": "zlib,compress,deflate",
+            "pure": true,
+            "tests": [{ "input": "hello", "expected": "eJzLSM3JyQcABiwCFQ==" }]
+        }"#,
+        zlib_compress,
+    ),
+    (
+        r#"{
+            "api": 1,
+            "name": "Zlib Decompress",
+            "description": "Decompresses base64 zlib data",
+            "icon": "collapse",
+            "tags": "zlib,decompress,inflate",
+            "pure": true,
+            "tests": [{ "input": "eJzLSM3JyQcABiwCFQ==", "expected": "hello" }]
+        }"#,
+        zlib_decompress,
+    ),
+    (
+        r#"{
+            "api": 1,
+            "name": "UUID v4",
+            "description": "Replaces the text with a random UUID",
+            "icon": "identification",
+            "tags": "uuid,guid,random,generate"
+        }"#,
+        uuid_v4,
+    ),
+    (
+        r#"{
+            "api": 1,
+            "name": "UUID v7",
+            "description": "Replaces the text with a UUID ordered by the time it was made",
+            "icon": "identification",
+            "tags": "uuid,guid,time,generate"
+        }"#,
+        uuid_v7,
+    ),
+    (
+        r#"{
+            "api": 1,
+            "name": "Base32 Encode",
+            "description": "Encodes the text with base32",
+            "icon": "metamorphose",
+            "tags": "base32,encode",
+            "pure": true,
+            "tests": [{ "input": "foobar", "expected": "MZXW6YTBOI======" }]
+        }"#,
+        base32_encode,
+    ),
+    (
+        r#"{
+            "api": 1,
+            "name": "Base32 Decode",
+            "description": "Decodes base32 text",
+            "icon": "metamorphose",
+            "tags": "base32,decode",
+            "pure": true,
+            "tests": [{ "input": "mzxw6ytboi======", "expected": "foobar" }]
+        }"#,
+        base32_decode,
+    ),
+    (
+        r#"{
+            "api": 1,
+            "name": "Punycode Encode",
+            "description": "Converts international domain names, one per line, to punycode",
+            "icon": "globe",
+            "tags": "punycode,idn,domain,encode",
+            "pure": true,
+            "tests": [{ "input": "münchen.de", "expected": "xn--mnchen-3ya.de" }]
+        }"#,
+        punycode_encode,
+    ),
+    (
+        r#"{
+            "api": 1,
+            "name": "Punycode Decode",
+            "description": "Converts punycode domain names, one per line, to unicode",
+            "icon": "globe",
+            "tags": "punycode,idn,domain,decode",
+            "pure": true,
+            "tests": [{ "input": "xn--mnchen-3ya.de", "expected": "münchen.de" }]
+        }"#,
+        punycode_decode,
+    ),
+];
+
+pub fn scripts() -> Vec<Script> {
+    BUILTINS
+        .iter()
+        .map(|(header, transform)| {
+            let (metadata, _) = Metadata::parse(header)
+                .unwrap_or_else(|e| panic!("invalid builtin metadata: {}", e));
+            Script::native(metadata, Box::new(Native(*transform)))
+        })
+        .collect()
+}
+
+fn sha256(text: &str) -> Result<String, String> {
+    hash("sha256", text.as_bytes())
+}
+
+// hex digest of the data, by "md5", "sha1", "sha256" or "sha512"
+pub fn hash(algorithm: &str, data: &[u8]) -> Result<String, String> {
+    match algorithm.to_lowercase().replace('-', "").as_str() {
+        "md5" => Ok(format!("{:x}", Md5::digest(data))),
+        "sha1" => Ok(format!("{:x}", Sha1::digest(data))),
+        "sha256" => Ok(format!("{:x}", Sha256::digest(data))),
+        "sha512" => Ok(format!("{:x}", Sha512::digest(data))),
+        _ => Err(format!("unknown hash algorithm \"{}\"", algorithm)),
+    }
+}
+
+fn crc32_checksum(text: &str) -> Result<String, String> {
+    Ok(format!("{:08x}", crc32(text.as_bytes())))
+}
+
+// CRC-32 as used by zip and PNG
+pub fn crc32(data: &[u8]) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn gzip_compress(text: &str) -> Result<String, String> {
+    compress(text.as_bytes(), ZlibFormat::Gzip).map(|data| base64::encode(&data))
+}
+
+fn gzip_decompress(text: &str) -> Result<String, String> {
+    decompress(&decode_base64(text)?, ZlibFormat::Gzip).and_then(into_text)
+}
+
+fn zlib_compress(text: &str) -> Result<String, String> {
+    compress(text.as_bytes(), ZlibFormat::Zlib).map(|data| base64::encode(&data))
+}
+
+fn zlib_decompress(text: &str) -> Result<String, String> {
+    decompress(&decode_base64(text)?, ZlibFormat::Zlib).and_then(into_text)
+}
+
+// the headers and checksums around deflated data
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZlibFormat {
+    Gzip,
+    Zlib,
+}
+
+pub fn compress(data: &[u8], format: ZlibFormat) -> Result<Vec<u8>, String> {
+    let compressed = match format {
+        ZlibFormat::Gzip => {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data).and_then(|_| encoder.finish())
+        }
+        ZlibFormat::Zlib => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data).and_then(|_| encoder.finish())
+        }
+    };
+
+    compressed.map_err(|e| e.to_string())
+}
+
+pub fn decompress(data: &[u8], format: ZlibFormat) -> Result<Vec<u8>, String> {
+    let mut decompressed = Vec::new();
+    let result = match format {
+        ZlibFormat::Gzip => GzDecoder::new(data).read_to_end(&mut decompressed),
+        ZlibFormat::Zlib => ZlibDecoder::new(data).read_to_end(&mut decompressed),
+    };
+
+    result.map(|_| decompressed).map_err(|e| e.to_string())
+}
+
+// base64 is often wrapped onto several lines
+fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let text: String = text.split_whitespace().collect();
+    base64::decode(&text).map_err(|_| "the text isn't base64".to_string())
+}
+
+pub fn into_text(data: Vec<u8>) -> Result<String, String> {
+    String::from_utf8(data).map_err(|_| "the data isn't UTF-8 text".to_string())
+}
+
+fn uuid_v4(_: &str) -> Result<String, String> {
+    Ok(new_uuid_v4())
+}
+
+fn uuid_v7(_: &str) -> Result<String, String> {
+    Ok(new_uuid_v7())
+}
+
+pub fn new_uuid_v4() -> String {
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&random_bytes(16));
+    bytes[6] = 0x40 | (bytes[6] & 0x0f);
+    bytes[8] = 0x80 | (bytes[8] & 0x3f);
+    format_uuid(&bytes)
+}
+
+// the first 48 bits are milliseconds since the unix epoch, so they sort by when they were made
+pub fn new_uuid_v7() -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|since| since.as_millis() as u64)
+        .unwrap_or_default();
+
+    let mut bytes = [0; 16];
+    bytes.copy_from_slice(&random_bytes(16));
+    bytes[..6].copy_from_slice(&millis.to_be_bytes()[2..]);
+    bytes[6] = 0x70 | (bytes[6] & 0x0f);
+    bytes[8] = 0x80 | (bytes[8] & 0x3f);
+    format_uuid(&bytes)
+}
+
+pub fn random_bytes(length: usize) -> Vec<u8> {
+    let mut bytes = vec![0; length];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    bytes
+}
+
+fn format_uuid(bytes: &[u8; 16]) -> String {
+    let hex: String = bytes.iter().map(|byte| format!("{:02x}", byte)).collect();
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
+const BASE32_ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+fn base32_encode(text: &str) -> Result<String, String> {
+    Ok(encode_base32(text.as_bytes()))
+}
+
+fn base32_decode(text: &str) -> Result<String, String> {
+    decode_base32(text).and_then(into_text)
+}
+
+pub fn encode_base32(data: &[u8]) -> String {
+    let mut encoded = String::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for &byte in data {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            bits -= 5;
+            encoded.push(BASE32_ALPHABET[((buffer >> bits) & 31) as usize] as char);
+        }
+        buffer &= (1 << bits) - 1;
+    }
+    if bits > 0 {
+        encoded.push(BASE32_ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    while encoded.len() % 8 != 0 {
+        encoded.push('=');
+    }
+
+    encoded
+}
+
+// lowercase, whitespace and missing padding are accepted
+pub fn decode_base32(text: &str) -> Result<Vec<u8>, String> {
+    let mut decoded = Vec::new();
+    let mut buffer = 0u32;
+    let mut bits = 0;
+    for c in text
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .take_while(|&c| c != '=')
+    {
+        let value = BASE32_ALPHABET
+            .iter()
+            .position(|&letter| letter as char == c.to_ascii_uppercase())
+            .ok_or_else(|| format!("\"{}\" isn't base32", c))?;
+        buffer = (buffer << 5) | value as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            decoded.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+
+    Ok(decoded)
+}
+
+// labels of international domain names are prefixed by this once they're encoded
+const ACE_PREFIX: &str = "xn--";
+
+pub fn punycode_encode(text: &str) -> Result<String, String> {
+    map_labels(text, |label| {
+        if label.is_ascii() {
+            Ok(label.to_string())
+        } else {
+            punycode::encode(&label.to_lowercase())
+                .map(|encoded| format!("{}{}", ACE_PREFIX, encoded))
+                .ok_or_else(|| format!("\"{}\" is too long to encode", label))
+        }
+    })
+}
+
+pub fn punycode_decode(text: &str) -> Result<String, String> {
+    map_labels(text, |label| {
+        let prefixed = label
+            .get(..ACE_PREFIX.len())
+            .map_or(false, |prefix| prefix.eq_ignore_ascii_case(ACE_PREFIX));
+        if prefixed && label.len() > ACE_PREFIX.len() {
+            punycode::decode(&label[ACE_PREFIX.len()..])
+                .ok_or_else(|| format!("\"{}\" isn't valid punycode", label))
+        } else {
+            Ok(label.to_string())
+        }
+    })
+}
+
+// changes each label of the domain on each line
+fn map_labels(text: &str, map: impl Fn(&str) -> Result<String, String>) -> Result<String, String> {
+    text.split('\n')
+        .map(|line| {
+            line.split('.')
+                .map(|label| map(label))
+                .collect::<Result<Vec<String>, String>>()
+                .map(|labels| labels.join("."))
+        })
+        .collect::<Result<Vec<String>, String>>()
+        .map(|lines| lines.join("\n"))
+}
+
+// RFC 3492, None if the text is too long to encode or isn't valid
+mod punycode {
+    const BASE: u32 = 36;
+    const T_MIN: u32 = 1;
+    const T_MAX: u32 = 26;
+    const SKEW: u32 = 38;
+    const DAMP: u32 = 700;
+    const INITIAL_BIAS: u32 = 72;
+    const INITIAL_N: u32 = 128;
+
+    fn adapt(delta: u32, points: u32, first: bool) -> u32 {
+        let mut delta = if first { delta / DAMP } else { delta / 2 };
+        delta += delta / points;
+
+        let mut k = 0;
+        while delta > ((BASE - T_MIN) * T_MAX) / 2 {
+            delta /= BASE - T_MIN;
+            k += BASE;
+        }
+        k + (BASE - T_MIN + 1) * delta / (delta + SKEW)
+    }
+
+    fn threshold(k: u32, bias: u32) -> u32 {
+        if k <= bias {
+            T_MIN
+        } else if k >= bias + T_MAX {
+            T_MAX
+        } else {
+            k - bias
+        }
+    }
+
+    fn digit(d: u32) -> char {
+        if d < 26 {
+            (b'a' + d as u8) as char
+        } else {
+            (b'0' + (d - 26) as u8) as char
+        }
+    }
+
+    pub fn encode(input: &str) -> Option<String> {
+        let code_points: Vec<u32> = input.chars().map(|c| c as u32).collect();
+        let mut output: String = input.chars().filter(char::is_ascii).collect();
+        let basic = output.len() as u32;
+        if basic > 0 {
+            output.push('-');
+        }
+
+        let (mut n, mut delta, mut bias) = (INITIAL_N, 0u32, INITIAL_BIAS);
+        let mut handled = basic;
+        while (handled as usize) < code_points.len() {
+            let m = *code_points.iter().filter(|&&c| c >= n).min()?;
+            delta = delta.checked_add((m - n).checked_mul(handled + 1)?)?;
+            n = m;
+
+            for &c in &code_points {
+                if c < n {
+                    delta = delta.checked_add(1)?;
+                }
+                if c == n {
+                    let mut q = delta;
+                    let mut k = BASE;
+                    loop {
+                        let t = threshold(k, bias);
+                        if q < t {
+                            break;
+                        }
+                        output.push(digit(t + (q - t) % (BASE - t)));
+                        q = (q - t) / (BASE - t);
+                        k += BASE;
+                    }
+                    output.push(digit(q));
+                    bias = adapt(delta, handled + 1, handled == basic);
+                    delta = 0;
+                    handled += 1;
+                }
+            }
+
+            delta += 1;
+            n += 1;
+        }
+
+        Some(output)
+    }
+
+    pub fn decode(input: &str) -> Option<String> {
+        let (basic, extended) = match input.rfind('-') {
+            Some(i) => (&input[..i], &input[i + 1..]),
+            None => ("", input),
+        };
+        if !basic.is_ascii() {
+            return None;
+        }
+
+        let mut output: Vec<char> = basic.chars().collect();
+        let (mut n, mut i, mut bias) = (INITIAL_N, 0u32, INITIAL_BIAS);
+        let mut digits = extended.bytes().peekable();
+        while digits.peek().is_some() {
+            let old_i = i;
+            let mut w = 1u32;
+            let mut k = BASE;
+            loop {
+                let d = match digits.next()? {
+                    byte @ b'a'..=b'z' => byte - b'a',
+                    byte @ b'A'..=b'Z' => byte - b'A',
+                    byte @ b'0'..=b'9' => byte - b'0' + 26,
+                    _ => return None,
+                } as u32;
+                i = i.checked_add(d.checked_mul(w)?)?;
+                let t = threshold(k, bias);
+                if d < t {
+                    break;
+                }
+                w = w.checked_mul(BASE - t)?;
+                k += BASE;
+            }
+
+            let length = output.len() as u32 + 1;
+            bias = adapt(i - old_i, length, old_i == 0);
+            n = n.checked_add(i / length)?;
+            i %= length;
+            output.insert(i as usize, std::char::from_u32(n)?);
+            i += 1;
+        }
+
+        Some(output.into_iter().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixtures() {
+        for (header, transform) in BUILTINS {
+            let (metadata, warnings) = Metadata::parse(header).unwrap();
+            assert!(warnings.is_empty());
+            for fixture in metadata.tests {
+                assert_eq!(
+                    Ok(fixture.expected),
+                    transform(&fixture.input),
+                    "{}",
+                    metadata.name
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trips() {
+        let text = "Boop ♈ 😁\nsecond line";
+        let pairs: [(Apply, Apply); 3] = [
+            (gzip_compress, gzip_decompress),
+            (zlib_compress, zlib_decompress),
+            (base32_encode, base32_decode),
+        ];
+        for (encode, decode) in pairs.iter() {
+            assert_eq!(Ok(text.to_string()), decode(&encode(text).unwrap()));
+        }
+
+        let domains = punycode_encode("Bücher.example\nexample.com").unwrap();
+        assert_eq!("xn--bcher-kva.example\nexample.com", domains);
+        assert_eq!(
+            Ok("bücher.example\nexample.com".to_string()),
+            punycode_decode(&domains)
+        );
+        assert!(base32_decode("not base32!").is_err());
+        assert!(zlib_decompress("aGVsbG8=").is_err());
+    }
+
+    #[test]
+    fn test_uuids() {
+        let v4 = uuid_v4("").unwrap();
+        assert_eq!(36, v4.len());
+        assert_eq!('4', v4.chars().nth(14).unwrap());
+
+        let v7 = uuid_v7("").unwrap();
+        assert_eq!('7', v7.chars().nth(14).unwrap());
+        assert!(v7 <= uuid_v7("").unwrap());
+        assert_ne!(v4, uuid_v4("").unwrap());
+    }
+}
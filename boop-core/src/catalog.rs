@@ -0,0 +1,223 @@
+// every script that can be run, Boop's scripts, the native scripts and the user's scripts
+
+use crate::{builtin, script::Script, Scripts, PROJECT_DIRS};
+use std::{
+    borrow::Cow,
+    error::Error,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+// directory in the scripts directory git repositories of scripts are cloned into, one directory
+// for each repository
+pub const REMOTE_DIR: &str = "remote";
+
+#[derive(Debug)]
+pub enum LoadScriptError {
+    FailedToCreateScriptDirectory,
+    FailedToReadScriptDirectory,
+}
+
+impl fmt::Display for LoadScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadScriptError::FailedToCreateScriptDirectory => {
+                write!(f, "Can't create scripts directory, check your permissions")
+            }
+            LoadScriptError::FailedToReadScriptDirectory => {
+                write!(f, "Can't read scripts directory, check your premissions")
+            }
+        }
+    }
+}
+
+impl Error for LoadScriptError {}
+
+/// The scripts that can be run, sorted by name.
+#[derive(Default)]
+pub struct ScriptCatalog {
+    scripts: Vec<Script>,
+}
+
+impl ScriptCatalog {
+    /// Loads every script, including the user's scripts from Boop-GTK's config directory.
+    pub fn load() -> Result<Self, LoadScriptError> {
+        match Self::load_from(PROJECT_DIRS.config_dir()) {
+            (_, Some(e)) => Err(e),
+            (catalog, None) => Ok(catalog),
+        }
+    }
+
+    /// Loads every script, including the user's scripts from the scripts directory in
+    /// `config_dir`. If the user's scripts can't be read the other scripts are still loaded.
+    pub fn load_from(config_dir: &Path) -> (Self, Option<LoadScriptError>) {
+        let mut scripts = load_internal_scripts();
+        let internal_script_count = scripts.len();
+
+        let error = match load_user_scripts(config_dir) {
+            Ok(mut user_scripts) => {
+                scripts.append(&mut user_scripts);
+                None
+            }
+            Err(e) => Some(e),
+        };
+
+        info!(
+            "found {} scripts ({} internal scripts)",
+            scripts.len(),
+            internal_script_count,
+        );
+
+        // sort alphabetically
+        scripts.sort_by_key(|s| s.metadata.name.clone());
+
+        (ScriptCatalog { scripts }, error)
+    }
+
+    /// The script called `name`, ignoring case if no script has exactly that name.
+    pub fn find(&mut self, name: &str) -> Option<&mut Script> {
+        let index = self
+            .scripts
+            .iter()
+            .position(|script| script.metadata.name == name)
+            .or_else(|| {
+                self.scripts
+                    .iter()
+                    .position(|script| script.metadata.name.eq_ignore_ascii_case(name))
+            })?;
+
+        self.scripts.get_mut(index)
+    }
+
+    pub fn scripts(&self) -> &[Script] {
+        &self.scripts
+    }
+
+    pub fn into_scripts(self) -> Vec<Script> {
+        self.scripts
+    }
+}
+
+fn load_user_scripts(config_dir: &Path) -> Result<Vec<Script>, LoadScriptError> {
+    let scripts_dir: PathBuf = config_dir.join("scripts");
+
+    fs::create_dir_all(&scripts_dir).map_err(|_| LoadScriptError::FailedToCreateScriptDirectory)?;
+
+    let paths =
+        fs::read_dir(&scripts_dir).map_err(|_| LoadScriptError::FailedToReadScriptDirectory)?;
+
+    let mut paths: Vec<PathBuf> = paths
+        .filter_map(Result::ok)
+        .map(|f| f.path())
+        .filter(|path| path.is_file())
+        .collect();
+    remote_script_paths(&scripts_dir.join(REMOTE_DIR), &mut paths);
+
+    Ok(paths
+        .into_iter()
+        .filter_map(|path| match Script::from_file(path.clone()) {
+            Ok(script) => Some(script),
+            Err(e) => {
+                error!("could not load {}: {}", path.display(), e);
+                None
+            }
+        })
+        .collect())
+}
+
+// scripts anywhere in the cloned repositories, apart from hidden directories like .git
+fn remote_script_paths(dir: &Path, paths: &mut Vec<PathBuf>) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+
+    for path in entries.filter_map(Result::ok).map(|entry| entry.path()) {
+        let hidden = path
+            .file_name()
+            .map_or(true, |name| name.to_string_lossy().starts_with('.'));
+        if hidden {
+            continue;
+        }
+
+        if path.is_dir() {
+            remote_script_paths(&path, paths);
+        } else if path
+            .extension()
+            .map_or(false, |extension| extension == "js")
+        {
+            paths.push(path);
+        }
+    }
+}
+
+fn load_internal_scripts() -> Vec<Script> {
+    let mut scripts: Vec<Script> = Vec::with_capacity(Scripts::iter().count());
+
+    // scripts are internal, so we can unwrap "safely"
+    for file in Scripts::iter() {
+        let file: Cow<'_, str> = file;
+        let source: Cow<'static, [u8]> = Scripts::get(&file)
+            .unwrap_or_else(|| panic!("failed to get file: {}", file.to_string()));
+        let script_source = String::from_utf8(source.to_vec())
+            .unwrap_or_else(|e| panic!("{} is not UTF8: {}", file, e));
+        if let Ok(script) = Script::from_source(script_source, PathBuf::new()) {
+            scripts.push(script);
+        }
+    }
+    scripts.extend(builtin::scripts());
+
+    scripts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::ScriptOrigin;
+
+    #[test]
+    fn test_load_from() {
+        let dir = std::env::temp_dir().join(format!("boop-gtk-catalog-{}", std::process::id()));
+        fs::create_dir_all(dir.join("scripts")).unwrap();
+        fs::write(
+            dir.join("scripts").join("shout.js"),
+            "/**\n{\"api\":1,\"name\":\"Shout\"}\n**/\nfunction main(state) {}",
+        )
+        .unwrap();
+        let repository_dir = dir.join("scripts").join(REMOTE_DIR).join("team");
+        fs::create_dir_all(repository_dir.join("json")).unwrap();
+        fs::create_dir_all(repository_dir.join(".git")).unwrap();
+        fs::write(
+            repository_dir.join("json").join("whisper.js"),
+            "/**\n{\"api\":1,\"name\":\"Whisper\"}\n**/\nfunction main(state) {}",
+        )
+        .unwrap();
+        fs::write(repository_dir.join(".git").join("hook.js"), "").unwrap();
+
+        let (mut catalog, error) = ScriptCatalog::load_from(&dir);
+        assert!(error.is_none());
+        assert!(catalog.find("Shout").is_some());
+        assert_eq!(ScriptOrigin::User, catalog.find("shout").unwrap().origin());
+        assert!(catalog.find("Murmur").is_none());
+        assert_eq!(
+            ScriptOrigin::Remote("team".to_string()),
+            catalog.find("Whisper").unwrap().origin()
+        );
+        assert!(catalog
+            .scripts()
+            .iter()
+            .filter(|script| script.is_native())
+            .all(|script| script.origin() == ScriptOrigin::Builtin));
+
+        let names: Vec<&str> = catalog
+            .scripts()
+            .iter()
+            .map(|script| script.metadata.name.as_str())
+            .collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(sorted, names);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,297 @@
+use crate::{permissions::Permissions, script::Metadata};
+use serde::{Deserialize, Serialize};
+use simple_error::SimpleError;
+use std::{
+    collections::{BTreeSet, HashMap},
+    fs,
+    path::Path,
+    time::Duration,
+};
+
+const CONFIG_FILE: &str = "config.json";
+// written by the preferences window, kept apart from the config the user edits by hand
+const APPEARANCE_FILE: &str = "appearance.json";
+
+// user preferences, stored as JSON in the config directory, edited by hand and by the settings
+// Boop changes itself, e.g. starring a script
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    // once the executors of all scripts use more than this, idle executors are killed
+    pub max_script_memory_mb: usize,
+    // a script whose heap grows larger than this is stopped
+    pub script_heap_limit_mb: usize,
+    // seconds a script may run for before it's stopped, unless the script sets its own timeout
+    pub script_timeout_secs: u64,
+    // upper bound on the timeout a script can set for itself
+    pub max_script_timeout_secs: u64,
+    // running a script on a document larger than this asks for confirmation first, 0 to never ask
+    pub max_document_size_kb: usize,
+    // show what a script would change and ask before applying it, instead of applying it straight
+    // away
+    pub preview_changes: bool,
+    // count how scripts and features are used, off unless the user opts in
+    pub usage_metrics: bool,
+    // where usage metrics are sent once the user has reviewed them, nothing is sent if unset
+    pub usage_metrics_url: Option<String>,
+    // accelerator that summons a small window with the command pallete open from any application,
+    // e.g. "<Super>B", only supported on Linux
+    pub quick_pop_up_shortcut: Option<String>,
+    // reopen the tabs, cursors and window size of the last session on launch
+    pub restore_session: bool,
+    // show a button for each script starred in the command pallete in the header bar
+    pub pinned_in_header_bar: bool,
+    // scripts that run for longer than this send a desktop notification when they finish while
+    // the window isn't focused, 0 to never notify
+    pub notify_after_secs: u64,
+    // scripts that replace the whole document open their result in a new tab instead, so the
+    // original can be compared with it
+    pub full_output_in_new_tab: bool,
+    // names of the scripts starred in the command pallete, in the order they were starred
+    pub favorites: Vec<String>,
+    // shortcuts bound in the preferences window, mapping script names to a shortcut, or null to
+    // remove the shortcut a script suggests
+    pub shortcuts: HashMap<String, Option<String>>,
+    // answers the user gave when scripts asked to do something that needs permission
+    pub permissions: Permissions,
+    // names of the scripts turned off in the scripts manager
+    pub disabled_scripts: BTreeSet<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            max_script_memory_mb: 256,
+            script_heap_limit_mb: 512,
+            script_timeout_secs: 5,
+            max_script_timeout_secs: 60,
+            max_document_size_kb: 5 * 1024,
+            preview_changes: false,
+            usage_metrics: false,
+            usage_metrics_url: None,
+            quick_pop_up_shortcut: None,
+            restore_session: true,
+            pinned_in_header_bar: false,
+            notify_after_secs: 10,
+            full_output_in_new_tab: false,
+            favorites: Vec::new(),
+            shortcuts: HashMap::new(),
+            permissions: Permissions::default(),
+            disabled_scripts: BTreeSet::new(),
+        }
+    }
+}
+
+impl Config {
+    // loads the config, falling back to the defaults if it doesn't exist or is invalid
+    pub fn load(config_dir: &Path) -> Self {
+        let path = config_dir.join(CONFIG_FILE);
+
+        match fs::read_to_string(&path) {
+            Ok(source) => serde_jsonrc::from_str(&source).unwrap_or_else(|e| {
+                error!("invalid config file {}: {}", path.display(), e);
+                Config::default()
+            }),
+            Err(_) => Config::default(),
+        }
+    }
+
+    // written to a temporary file first, so the config isn't left half written if we're
+    // interrupted
+    pub fn save(&self, config_dir: &Path) -> Result<(), SimpleError> {
+        let path = config_dir.join(CONFIG_FILE);
+        let temp_path = path.with_extension("json.tmp");
+        let source = serde_jsonrc::to_string_pretty(self)
+            .map_err(|e| SimpleError::with("could not serialize config", e))?;
+
+        fs::create_dir_all(config_dir).map_err(|e| {
+            SimpleError::with(&format!("could not create {}", config_dir.display()), e)
+        })?;
+        fs::write(&temp_path, source).map_err(|e| {
+            SimpleError::with(&format!("could not write {}", temp_path.display()), e)
+        })?;
+        fs::rename(&temp_path, &path)
+            .map_err(|e| SimpleError::with(&format!("could not write {}", path.display()), e))
+    }
+
+    // changes the config on disk, an invalid config is left for the user to fix instead of being
+    // replaced by the defaults
+    pub fn update(config_dir: &Path, change: impl FnOnce(&mut Config)) -> Result<(), SimpleError> {
+        let path = config_dir.join(CONFIG_FILE);
+
+        let mut config = match fs::read_to_string(&path) {
+            Ok(source) => serde_jsonrc::from_str(&source).map_err(|e| {
+                SimpleError::with(&format!("invalid config file {}", path.display()), e)
+            })?,
+            Err(_) => Config::default(),
+        };
+        change(&mut config);
+        config.save(config_dir)
+    }
+
+    pub fn max_script_memory(&self) -> usize {
+        self.max_script_memory_mb * 1024 * 1024
+    }
+
+    pub fn script_heap_limit(&self) -> usize {
+        self.script_heap_limit_mb * 1024 * 1024
+    }
+
+    // true if running a script on a document of `size` bytes should be confirmed
+    pub fn is_document_too_large(&self, size: usize) -> bool {
+        self.max_document_size_kb > 0 && size > self.max_document_size_kb * 1024
+    }
+
+    pub fn script_timeout(&self, metadata: &Metadata) -> Duration {
+        let secs = metadata
+            .timeout
+            .unwrap_or(self.script_timeout_secs)
+            .min(self.max_script_timeout_secs);
+
+        Duration::from_secs(secs)
+    }
+}
+
+// whether Boop is light or dark
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Theme {
+    // follows the desktop's preference
+    System,
+    Light,
+    Dark,
+}
+
+// the keys the editor is used with
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum InputMode {
+    // GTK's own
+    Default,
+    Vim,
+    Emacs,
+}
+
+// how the editor looks and is used, set in the preferences window
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Appearance {
+    // pango font description, e.g. "Source Code Pro 11", the system's monospace font if unset
+    pub font: Option<String>,
+    // ids of the GtkSourceView style schemes used when Boop is light and when it's dark, e.g.
+    // "oblivion", Boop's own schemes if unset
+    pub style_scheme: Option<String>,
+    pub dark_style_scheme: Option<String>,
+    pub show_line_numbers: bool,
+    pub wrap_lines: bool,
+    pub tab_width: u32,
+    pub theme: Theme,
+    pub input_mode: InputMode,
+}
+
+impl Default for Appearance {
+    fn default() -> Self {
+        Appearance {
+            font: None,
+            style_scheme: None,
+            dark_style_scheme: None,
+            show_line_numbers: true,
+            wrap_lines: true,
+            tab_width: 4,
+            theme: Theme::System,
+            input_mode: InputMode::Default,
+        }
+    }
+}
+
+impl Appearance {
+    pub fn load(config_dir: &Path) -> Self {
+        let path = config_dir.join(APPEARANCE_FILE);
+
+        match fs::read_to_string(&path) {
+            Ok(source) => serde_jsonrc::from_str(&source).unwrap_or_else(|e| {
+                error!("invalid appearance file {}: {}", path.display(), e);
+                Appearance::default()
+            }),
+            Err(_) => Appearance::default(),
+        }
+    }
+
+    pub fn save(&self, config_dir: &Path) -> Result<(), SimpleError> {
+        let path = config_dir.join(APPEARANCE_FILE);
+        let source = serde_jsonrc::to_string_pretty(self)
+            .map_err(|e| SimpleError::with("could not serialize appearance", e))?;
+
+        fs::write(&path, source)
+            .map_err(|e| SimpleError::with(&format!("could not write {}", path.display()), e))
+    }
+}
+
+// names of the scripts turned off in the scripts manager, kept in `disabled_scripts` in
+// config.json, they're hidden from the command pallete
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DisabledScripts {
+    pub scripts: BTreeSet<String>,
+}
+
+impl DisabledScripts {
+    pub fn load(config_dir: &Path) -> Self {
+        DisabledScripts {
+            scripts: Config::load(config_dir).disabled_scripts,
+        }
+    }
+
+    pub fn save(&self, config_dir: &Path) -> Result<(), SimpleError> {
+        Config::update(config_dir, |config| {
+            config.disabled_scripts = self.scripts.clone()
+        })
+    }
+
+    pub fn contains(&self, script_name: &str) -> bool {
+        self.scripts.contains(script_name)
+    }
+
+    pub fn set_enabled(&mut self, script_name: &str, enabled: bool) {
+        if enabled {
+            self.scripts.remove(script_name);
+        } else {
+            self.scripts.insert(script_name.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update() {
+        let config_dir =
+            std::env::temp_dir().join(format!("boop-gtk-config-{}", std::process::id()));
+
+        fs::create_dir_all(&config_dir).unwrap();
+        fs::write(
+            config_dir.join(CONFIG_FILE),
+            "{ \"script_timeout_secs\": 10, // edited by hand\n }",
+        )
+        .unwrap();
+        Config::update(&config_dir, |config| {
+            config.favorites.push("Format JSON".to_string())
+        })
+        .unwrap();
+
+        let config = Config::load(&config_dir);
+        assert_eq!(10, config.script_timeout_secs);
+        assert_eq!(vec!["Format JSON"], config.favorites);
+
+        // an invalid config isn't overwritten
+        fs::write(config_dir.join(CONFIG_FILE), "{ \"script_timeout_secs\": ").unwrap();
+        assert!(Config::update(&config_dir, |config| config.favorites.clear()).is_err());
+        assert_eq!(
+            "{ \"script_timeout_secs\": ",
+            fs::read_to_string(config_dir.join(CONFIG_FILE)).unwrap()
+        );
+
+        fs::remove_dir_all(&config_dir).unwrap();
+    }
+}
@@ -0,0 +1,236 @@
+// line by line differences between two texts, used to preview what a script would change before
+// it's applied, and to apply it without replacing lines that didn't change
+
+// texts whose differing lines, multiplied, exceed this aren't compared line by line, every
+// differing line is shown as replaced instead
+const MAX_DIFF_CELLS: usize = 1_000_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+// an empty text has no lines, rather than a single empty one
+fn split_lines(text: &str) -> Vec<&str> {
+    if text.is_empty() {
+        Vec::new()
+    } else {
+        text.split('\n').collect()
+    }
+}
+
+// lines of the text including the newline that ends them, so joining them gives the text back
+fn split_lines_with_endings(text: &str) -> Vec<&str> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    for (end, _) in text.match_indices('\n') {
+        lines.push(&text[start..=end]);
+        start = end + 1;
+    }
+    if start < text.len() {
+        lines.push(&text[start..]);
+    }
+    lines
+}
+
+// the lines of both texts in order, lines removed from `before` are listed before the lines that
+// replace them
+pub fn diff_lines<'a>(before: &'a str, after: &'a str) -> Vec<DiffLine<'a>> {
+    diff(&split_lines(before), &split_lines(after))
+}
+
+fn diff<'a>(before: &[&'a str], after: &[&'a str]) -> Vec<DiffLine<'a>> {
+    // lines shared at the start and end don't need to be compared
+    let prefix = before
+        .iter()
+        .zip(after.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old = &before[prefix..before.len() - suffix];
+    let new = &after[prefix..after.len() - suffix];
+
+    let mut lines: Vec<DiffLine> = before[..prefix]
+        .iter()
+        .map(|line| DiffLine::Same(line))
+        .collect();
+
+    if old.len() * new.len() > MAX_DIFF_CELLS {
+        lines.extend(old.iter().map(|line| DiffLine::Removed(line)));
+        lines.extend(new.iter().map(|line| DiffLine::Added(line)));
+    } else {
+        // length of the longest common subsequence of the lines from i and j onwards
+        let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+        for i in (0..old.len()).rev() {
+            for j in (0..new.len()).rev() {
+                lcs[i][j] = if old[i] == new[j] {
+                    lcs[i + 1][j + 1] + 1
+                } else {
+                    lcs[i + 1][j].max(lcs[i][j + 1])
+                };
+            }
+        }
+
+        let (mut i, mut j) = (0, 0);
+        while i < old.len() || j < new.len() {
+            if i < old.len() && j < new.len() && old[i] == new[j] {
+                lines.push(DiffLine::Same(old[i]));
+                i += 1;
+                j += 1;
+            } else if i < old.len() && (j == new.len() || lcs[i + 1][j] >= lcs[i][j + 1]) {
+                lines.push(DiffLine::Removed(old[i]));
+                i += 1;
+            } else {
+                lines.push(DiffLine::Added(new[j]));
+                j += 1;
+            }
+        }
+    }
+
+    lines.extend(
+        before[before.len() - suffix..]
+            .iter()
+            .map(|line| DiffLine::Same(line)),
+    );
+    lines
+}
+
+// the edits turning `before` into `after`, as (start, end, replacement) in characters of `before`,
+// last first so that no edit moves the text a later one is made to. Lines that changed are
+// compared character by character too, so changing one character is a one character edit
+pub fn edits(before: &str, after: &str) -> Vec<(usize, usize, String)> {
+    let mut edits = Vec::new();
+    // where the next line of `before` starts
+    let mut offset = 0;
+    // start of the lines being replaced, with the text removed from and added to them
+    let mut start = 0;
+    let mut removed = String::new();
+    let mut added = String::new();
+
+    let lines = diff(
+        &split_lines_with_endings(before),
+        &split_lines_with_endings(after),
+    );
+    for line in lines {
+        match line {
+            DiffLine::Same(line) => {
+                if !removed.is_empty() || !added.is_empty() {
+                    edits.extend(trimmed_edit(start, &removed, &added));
+                    removed.clear();
+                    added.clear();
+                }
+                offset += line.chars().count();
+                start = offset;
+            }
+            DiffLine::Removed(line) => {
+                removed.push_str(line);
+                offset += line.chars().count();
+            }
+            DiffLine::Added(line) => added.push_str(line),
+        }
+    }
+    edits.extend(trimmed_edit(start, &removed, &added));
+
+    edits.reverse();
+    edits
+}
+
+// replaces `removed`, at `start`, with `added`, leaving out the characters they start and end with
+fn trimmed_edit(start: usize, removed: &str, added: &str) -> Option<(usize, usize, String)> {
+    let removed: Vec<char> = removed.chars().collect();
+    let added: Vec<char> = added.chars().collect();
+    let prefix = removed
+        .iter()
+        .zip(&added)
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = removed[prefix..]
+        .iter()
+        .rev()
+        .zip(added[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+
+    if removed.len() == added.len() && prefix == removed.len() {
+        None
+    } else {
+        Some((
+            start + prefix,
+            start + removed.len() - suffix,
+            added[prefix..added.len() - suffix].iter().collect(),
+        ))
+    }
+}
+
+// how many lines were added and removed
+pub fn count_changes(lines: &[DiffLine]) -> (usize, usize) {
+    lines
+        .iter()
+        .fold((0, 0), |(added, removed), line| match line {
+            DiffLine::Added(_) => (added + 1, removed),
+            DiffLine::Removed(_) => (added, removed + 1),
+            DiffLine::Same(_) => (added, removed),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DiffLine::*;
+
+    #[test]
+    fn test_diff_lines() {
+        assert_eq!(vec![Same("a"), Same("b")], diff_lines("a\nb", "a\nb"));
+        assert_eq!(vec![Added("a")], diff_lines("", "a"));
+        assert_eq!(vec![Removed("a")], diff_lines("a", ""));
+
+        let lines = diff_lines("a\nb\nc\nd", "a\nc\nx\nd");
+        assert_eq!(
+            vec![Same("a"), Removed("b"), Same("c"), Added("x"), Same("d")],
+            lines
+        );
+        assert_eq!((1, 1), count_changes(&lines));
+
+        assert_eq!(
+            vec![Removed("b"), Same("a"), Added("b")],
+            diff_lines("b\na", "a\nb")
+        );
+    }
+
+    #[test]
+    fn test_edits() {
+        let apply = |before: &str, edits: Vec<(usize, usize, String)>| {
+            let mut chars: Vec<char> = before.chars().collect();
+            for (start, end, text) in edits {
+                chars.splice(start..end, text.chars());
+            }
+            chars.into_iter().collect::<String>()
+        };
+
+        assert!(edits("a\nb", "a\nb").is_empty());
+        assert_eq!(
+            vec![(5, 6, "X".to_string())],
+            edits("one\ntwo\nthree", "one\ntXo\nthree")
+        );
+        assert_eq!(
+            vec![(13, 13, "\nfour".to_string()), (0, 4, String::new())],
+            edits("one\ntwo\nthree", "two\nthree\nfour")
+        );
+
+        for (before, after) in &[
+            ("", "a\nb"),
+            ("a\nb\n", ""),
+            ("a", "a\n"),
+            ("😁\nb\nc\nd", "😁\nc\nx😁\nd\n"),
+        ] {
+            assert_eq!(*after, apply(before, edits(before, after)));
+        }
+    }
+}
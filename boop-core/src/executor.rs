@@ -0,0 +1,3456 @@
+use crate::{
+    diff, json,
+    line_ending::LineEnding,
+    native,
+    permissions::{Permission, Permissions},
+    pool,
+    sandbox::Sandbox,
+    snapshot, Scripts, PROJECT_DIRS,
+};
+use crossbeam::crossbeam_channel::bounded;
+use dirty2::Dirty;
+use rusty_v8 as v8;
+use sha2::{Digest, Sha256};
+use simple_error::{bail, SimpleError};
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    convert::TryFrom,
+    fmt,
+    fs::{self, File},
+    io::Read,
+    os::raw::c_void,
+    path::PathBuf,
+    rc::Rc,
+};
+
+// notifications sent for each execution, later ones are dropped
+const MAX_NOTIFICATIONS: usize = 3;
+// how long to wait for a remote script before falling back to the cached copy
+const REMOTE_REQUIRE_TIMEOUT_MS: u64 = 5000;
+// how long `fetch` waits to connect and for each read, the script's timeout can't interrupt it
+const FETCH_TIMEOUT_MS: u64 = 10000;
+// longest indent `stringifyJSON` uses, as with `JSON.stringify`
+const MAX_JSON_INDENT: usize = 10;
+// lines of console output kept from each run, the rest are dropped
+const MAX_CONSOLE_LINES: usize = 1000;
+// bytes a script's heap may grow to, unless configured otherwise
+pub const DEFAULT_HEAP_LIMIT: usize = 512 * 1024 * 1024;
+
+thread_local! {
+    // each executor runs on its own thread, so these belong to the thread's isolate
+    static ISOLATE_HANDLE: RefCell<Option<v8::IsolateHandle>> = RefCell::new(None);
+    static HEAP_LIMIT_REACHED: Cell<bool> = Cell::new(false);
+}
+
+static BOOP_WRAPPER_START: &str = "
+/***********************************
+*     Start of Boop's wrapper      *
+***********************************/
+            
+(function() {
+    var module = {
+        exports: {}
+    };
+            
+    const moduleWrapper = (function (exports, module) {
+
+/***********************************
+*      End of Boop's wrapper      *
+***********************************/
+
+";
+
+static BOOP_WRAPPER_END: &str = "
+            
+/***********************************
+*     Start of Boop's wrapper      *
+***********************************/
+            
+    }).apply(module.exports, [module.exports, module]);
+
+    return module.exports;
+})();
+            
+/***********************************
+*      End of Boop's wrapper      *
+***********************************/
+";
+
+pub struct Executor {
+    isolate: v8::OwnedIsolate,
+    // bytes the isolate's heap may grow to
+    heap_limit: usize,
+}
+
+struct ExecutorState {
+    global_context: Option<v8::Global<v8::Context>>,
+    main_function: Option<v8::Global<v8::Function>>,
+    // called with each line by scripts with `"stream": true` in their metadata
+    process_line: Option<v8::Global<v8::Function>>,
+    // why the functions couldn't be initialized
+    init_error: Option<ScriptError>,
+    // set on each payload, by name, with the permission they need
+    payload_functions: Vec<(
+        Option<Permission>,
+        v8::Global<v8::String>,
+        v8::Global<v8::Function>,
+    )>,
+    // payload functions needing other permissions aren't set
+    granted: Vec<Permission>,
+}
+
+// functions the script defines for Boop to call, it needs at least one of them
+struct EntryPoints {
+    main: Option<v8::Global<v8::Function>>,
+    process_line: Option<v8::Global<v8::Function>>,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ExecutionStatus {
+    // true if text was selected when execution began
+    is_text_selected: bool,
+
+    info: Option<String>,
+    error: Option<String>,
+
+    // uncaught exception thrown by the script
+    exception: Option<ScriptError>,
+
+    insert: Vec<Insertion>,
+    // where the script moved the cursor to, in UTF-16 code units into the text after it ran
+    cursor: Option<usize>,
+    save_as: Option<SaveAs>,
+    // url to open in the default browser once execution completes
+    open_url: Option<String>,
+    // text to put on the clipboard once execution completes
+    copy: Option<String>,
+    // desktop notifications to send once execution completes
+    notifications: Vec<Notification>,
+    // id of the language the script says its result should be highlighted as
+    language: Option<String>,
+    artifacts: Vec<Artifact>,
+    highlights: Vec<Highlight>,
+    // markdown or HTML to render in the preview panel once execution completes
+    preview: Option<String>,
+    // written with console.log, console.warn and console.error
+    console_output: Vec<(ConsoleLevel, String)>,
+    // JSON of `payload.state`, a plain object so it's read back once the script returns, None if
+    // the state can't be converted to JSON
+    state: Dirty<Option<String>>,
+    full_text: Dirty<String>,
+    text: Dirty<String>,
+    selection: Dirty<String>,
+    // every selected text, in the order they appear in the document, `payload.selections` is a
+    // plain array so it's read back once the script returns
+    selections: Dirty<Vec<String>>,
+
+    // bytes used by the isolate's heap after execution
+    heap_size: usize,
+    // the script was stopped as its heap grew too large, the executor should be recreated
+    exceeded_heap_limit: bool,
+
+    // urls `require` wanted to load code from that the user hasn't been asked about yet, these can
+    // be recorded while the script is initialized, so they are drained when a status is returned
+    // instead of on reset
+    permission_requests: Vec<String>,
+}
+
+impl ExecutionStatus {
+    // the result of a builtin transform of the selection, or the whole text if nothing is selected,
+    // errors are shown like those posted by scripts
+    pub fn transformed(
+        full_text: &str,
+        selection: Option<&str>,
+        result: Result<String, String>,
+    ) -> Self {
+        let mut status = ExecutionStatus::default();
+        *status.full_text.write() = full_text.to_string();
+        status.full_text.clear();
+        *status.selection.write() = selection.unwrap_or("").to_string();
+        status.selection.clear();
+
+        match result {
+            Ok(text) if selection.is_some() => *status.selection.write() = text,
+            Ok(text) => *status.full_text.write() = text,
+            Err(error) => {
+                status.error.replace(error);
+            }
+        }
+
+        status
+    }
+
+    fn reset(&mut self) {
+        self.info = None;
+        self.error = None;
+        self.exception = None;
+        self.insert.clear();
+        self.cursor = None;
+        self.save_as = None;
+        self.open_url = None;
+        self.copy = None;
+        self.notifications.clear();
+        self.language = None;
+        self.artifacts.clear();
+        self.highlights.clear();
+        self.preview = None;
+        self.console_output.clear();
+        self.exceeded_heap_limit = false;
+        self.full_text.write().clear();
+        Dirty::clear(&mut self.full_text);
+        self.text.write().clear();
+        Dirty::clear(&mut self.text);
+    }
+
+    pub fn info(&self) -> Option<&String> {
+        self.info.as_ref()
+    }
+
+    pub fn error(&self) -> Option<&String> {
+        self.error.as_ref()
+    }
+
+    pub fn exception(&self) -> Option<&ScriptError> {
+        self.exception.as_ref()
+    }
+
+    pub fn save_as(&self) -> Option<&SaveAs> {
+        self.save_as.as_ref()
+    }
+
+    pub fn open_url(&self) -> Option<&String> {
+        self.open_url.as_ref()
+    }
+
+    pub fn copy(&self) -> Option<&String> {
+        self.copy.as_ref()
+    }
+
+    pub fn notifications(&self) -> &[Notification] {
+        &self.notifications
+    }
+
+    pub fn language(&self) -> Option<&String> {
+        self.language.as_ref()
+    }
+
+    pub fn artifacts(&self) -> &[Artifact] {
+        &self.artifacts
+    }
+
+    pub fn highlights(&self) -> &[Highlight] {
+        &self.highlights
+    }
+
+    pub fn preview(&self) -> Option<&String> {
+        self.preview.as_ref()
+    }
+
+    pub fn console_output(&self) -> &[(ConsoleLevel, String)] {
+        &self.console_output
+    }
+
+    // the script's state if it changed
+    pub fn state(&self) -> Option<Option<&String>> {
+        if self.state.dirty() {
+            Some(self.state.read().as_ref())
+        } else {
+            None
+        }
+    }
+
+    pub fn cursor(&self) -> Option<usize> {
+        self.cursor
+    }
+
+    pub fn heap_size(&self) -> usize {
+        self.heap_size
+    }
+
+    pub fn exceeded_heap_limit(&self) -> bool {
+        self.exceeded_heap_limit
+    }
+
+    pub fn permission_requests(&self) -> &[String] {
+        &self.permission_requests
+    }
+
+    pub fn into_replacement(self) -> TextReplacement {
+        // not quite sure what the correct behaviour here should be
+        // right now the order of presidence is:
+        // 0. insertion
+        // 1. fullText
+        // 2. selections
+        // 3. selection
+        // 4. text (with select)
+        // 5. text (without selection)
+        // TODO: move into ExecutionStatus
+        if !self.insert.is_empty() {
+            info!("found insertion");
+            TextReplacement::Insert(self.insert)
+        } else if self.full_text.dirty() {
+            info!("found full_text replacement");
+            TextReplacement::Full(self.full_text.unwrap())
+        } else if self.selections.dirty() {
+            info!("found selections replacement");
+            TextReplacement::Selections(self.selections.unwrap())
+        } else if self.selection.dirty() {
+            info!("found selection replacement");
+            TextReplacement::Selection(self.selection.unwrap())
+        } else if self.is_text_selected && self.text.dirty() {
+            info!("found text (with selection) replacement");
+            TextReplacement::Selection(self.text.unwrap())
+        } else if self.text.dirty() {
+            info!("found text (without selection) replacement");
+            TextReplacement::Full(self.text.unwrap())
+        } else {
+            TextReplacement::None
+        }
+    }
+}
+
+// file a script asked to save, a save dialog is shown once execution completes
+#[derive(Clone, Debug, PartialEq)]
+pub struct SaveAs {
+    pub suggested_name: String,
+    pub text: String,
+}
+
+// desktop notification a script asked for, sent once execution completes
+#[derive(Clone, Debug, PartialEq)]
+pub struct Notification {
+    pub title: String,
+    pub body: Option<String>,
+}
+
+// named output of a script, listed in the output pane where it can be saved or opened
+#[derive(Clone, Debug, PartialEq)]
+pub struct Artifact {
+    pub name: String,
+    pub text: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConsoleLevel {
+    Log,
+    Warn,
+    Error,
+}
+
+impl ConsoleLevel {
+    pub fn name(self) -> &'static str {
+        match self {
+            ConsoleLevel::Log => "log",
+            ConsoleLevel::Warn => "warn",
+            ConsoleLevel::Error => "error",
+        }
+    }
+}
+
+// uncaught exception thrown by a script, or the error that stopped it from being initialized
+#[derive(Clone, Debug, PartialEq)]
+pub struct ScriptError {
+    pub message: String,
+    // where the exception was thrown in the script's source, both start at 1
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    // None if the exception isn't an Error, or was thrown outside of a function
+    pub stack_trace: Option<String>,
+}
+
+impl ScriptError {
+    pub fn new(message: impl Into<String>) -> Self {
+        ScriptError {
+            message: message.into(),
+            line: None,
+            column: None,
+            stack_trace: None,
+        }
+    }
+
+    // the exception `tc_scope` caught
+    fn caught(tc_scope: &mut v8::TryCatch<v8::HandleScope>) -> Self {
+        let message = tc_scope
+            .exception()
+            .expect("failed to get exception, but exception was caught")
+            .to_string(tc_scope)
+            // e.g. `throw Symbol()`
+            .map(|message| message.to_rust_string_lossy(tc_scope))
+            .unwrap_or_else(|| "an exception was thrown".to_string());
+
+        let (line, column) = match tc_scope.message() {
+            Some(location) => (
+                location.get_line_number(tc_scope),
+                Some(location.get_start_column() + 1),
+            ),
+            None => (None, None),
+        };
+
+        let stack_trace = tc_scope
+            .stack_trace()
+            .and_then(|stack_trace| stack_trace.to_string(tc_scope))
+            .map(|stack_trace| stack_trace.to_rust_string_lossy(tc_scope))
+            // a stack trace without any frames only repeats the message
+            .filter(|stack_trace| stack_trace.contains('\n'));
+
+        ScriptError {
+            message,
+            line,
+            column,
+            stack_trace,
+        }
+    }
+
+    // the reason a promise was rejected with, usually an Error
+    fn rejected(scope: &mut v8::HandleScope, reason: v8::Local<v8::Value>) -> Self {
+        let message = reason
+            .to_string(scope)
+            .map(|message| message.to_rust_string_lossy(scope))
+            .unwrap_or_else(|| "promise was rejected".to_string());
+
+        let stack_key =
+            v8::String::new(scope, "stack").expect("failed to create JS string 'stack'");
+        let stack_trace = v8::Local::<v8::Object>::try_from(reason)
+            .ok()
+            .and_then(|error| error.get(scope, stack_key.into()))
+            .filter(|stack_trace| stack_trace.is_string())
+            .and_then(|stack_trace| stack_trace.to_string(scope))
+            .map(|stack_trace| stack_trace.to_rust_string_lossy(scope))
+            .filter(|stack_trace| stack_trace.contains('\n'));
+
+        ScriptError {
+            message,
+            line: None,
+            column: None,
+            stack_trace,
+        }
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => {
+                write!(f, "{} (line {}, column {})", self.message, line, column)
+            }
+            (Some(line), None) => write!(f, "{} (line {})", self.message, line),
+            _ => write!(f, "{}", self.message),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HighlightKind {
+    Error,
+    Warning,
+    Info,
+}
+
+impl HighlightKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            HighlightKind::Error => "error",
+            HighlightKind::Warning => "warning",
+            HighlightKind::Info => "info",
+        }
+    }
+}
+
+// range of the document a script marked with `payload.highlight`, shown until the document is
+// next edited
+#[derive(Clone, Debug, PartialEq)]
+pub struct Highlight {
+    // offsets in UTF-16 code units, like JS string indices, into the text after the script ran
+    pub start: usize,
+    pub end: usize,
+    pub kind: HighlightKind,
+}
+
+impl Highlight {
+    // start and end as offsets in characters, which is what GTK uses
+    pub fn char_range(&self, text: &str) -> (usize, usize) {
+        (
+            utf16_to_char_offset(text, self.start),
+            utf16_to_char_offset(text, self.end),
+        )
+    }
+}
+
+// the text in each range of characters, ranges past the end of the text are cut short
+pub fn texts_in_ranges(text: &str, ranges: &[(usize, usize)]) -> Vec<String> {
+    ranges
+        .iter()
+        .map(|&(start, end)| {
+            text.chars()
+                .skip(start)
+                .take(end.saturating_sub(start))
+                .collect()
+        })
+        .collect()
+}
+
+// converts an offset in characters, which is what GTK uses, into one in UTF-16 code units, which
+// is how JS strings are indexed
+pub fn char_to_utf16_offset(text: &str, offset: usize) -> usize {
+    text.chars().take(offset).map(char::len_utf16).sum()
+}
+
+// converts an offset in UTF-16 code units into one in characters, offsets past the end of the
+// text are moved to the end
+pub fn utf16_to_char_offset(text: &str, offset: usize) -> usize {
+    let mut units = 0;
+    for (index, c) in text.chars().enumerate() {
+        if units >= offset {
+            return index;
+        }
+        units += c.len_utf16();
+    }
+
+    text.chars().count()
+}
+
+// text a script inserted with `payload.insert`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Insertion {
+    pub text: String,
+    // offset in UTF-16 code units into the document, None to insert at the cursor, replacing the
+    // selection
+    pub at: Option<usize>,
+}
+
+// the edits making `insertions` to `text`, as (start, end, replacement) in characters, in the
+// order they should be made so that no edit moves the text a later one is made to
+pub fn insertion_edits(
+    insertions: &[Insertion],
+    text: &str,
+    cursor: usize,
+    selection: Option<(usize, usize)>,
+) -> Vec<(usize, usize, String)> {
+    let length = text.chars().count();
+
+    // (start, end, order inserted, replacement)
+    let mut edits: Vec<(usize, usize, usize, String)> = insertions
+        .iter()
+        .enumerate()
+        .filter_map(|(index, insertion)| {
+            insertion.at.map(|at| {
+                let at = utf16_to_char_offset(text, at);
+                (at, at, index, insertion.text.clone())
+            })
+        })
+        .collect();
+
+    // insertions at the cursor are joined, replacing the selection
+    if let Some(first) = insertions
+        .iter()
+        .position(|insertion| insertion.at.is_none())
+    {
+        let (start, end) = selection.unwrap_or((cursor, cursor));
+        let joined = insertions
+            .iter()
+            .filter(|insertion| insertion.at.is_none())
+            .map(|insertion| insertion.text.as_str())
+            .collect();
+        edits.push((start.min(length), end.min(length), first, joined));
+    }
+
+    // last first, edits at the same place are made in reverse so they end up in order
+    edits.sort_by_key(|&(start, _, index, _)| (start, index));
+    edits
+        .into_iter()
+        .rev()
+        .map(|(start, end, _, replacement)| (start, end, replacement))
+        .collect()
+}
+
+// something a running script asks of the application, scripts block until it's answered
+#[derive(Debug)]
+pub enum HostRequest {
+    ChooseFile(Vec<FileFilter>),
+    // initial color, in any format understood by CSS
+    PickColor(Option<String>),
+    // label of the value asked for, and what it starts as
+    Prompt(String, Option<String>),
+    ReadClipboard,
+    // url the script wants to fetch
+    AllowNetwork(String),
+}
+
+impl HostRequest {
+    // what the script must have declared to make the request
+    pub fn permission(&self) -> Option<Permission> {
+        match self {
+            HostRequest::ChooseFile(_) => Some(Permission::Filesystem),
+            HostRequest::ReadClipboard => Some(Permission::Clipboard),
+            HostRequest::AllowNetwork(_) => Some(Permission::Network),
+            HostRequest::PickColor(_) | HostRequest::Prompt(_, _) => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum HostResponse {
+    // None if the user cancelled or the script isn't allowed to read files
+    ChosenFile(Option<ChosenFile>),
+    // None if the user cancelled
+    PickedColor(Option<PickedColor>),
+    // None if the user cancelled
+    Prompted(Option<String>),
+    // None if the clipboard has no text or the script isn't allowed to read it
+    ClipboardText(Option<String>),
+    NetworkAllowed(bool),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FileFilter {
+    pub name: String,
+    // glob patterns, e.g. "*.json"
+    pub patterns: Vec<String>,
+}
+
+#[derive(Clone, Debug)]
+pub struct ChosenFile {
+    pub path: String,
+    pub contents: Vec<u8>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct PickedColor {
+    // "#rrggbb"
+    pub hex: String,
+    // "rgba(r, g, b, a)"
+    pub rgba: String,
+}
+
+type Host = Rc<dyn Fn(HostRequest) -> HostResponse>;
+
+// modules imported by the script, by specifier
+type Modules = Rc<RefCell<HashMap<String, v8::Global<v8::Module>>>>;
+
+// global the exports of CommonJS modules are kept on, for the modules wrapping them to read
+const COMMONJS_EXPORTS_KEY: &str = "__boopCommonJsExports";
+
+// answers requests when there's no one to ask, e.g. when running tests
+pub fn headless_host(request: HostRequest) -> HostResponse {
+    match request {
+        HostRequest::ChooseFile(_) => HostResponse::ChosenFile(None),
+        HostRequest::PickColor(_) => HostResponse::PickedColor(None),
+        // scripts run without a window get the default value
+        HostRequest::Prompt(_, default) => HostResponse::Prompted(default),
+        HostRequest::ReadClipboard => HostResponse::ClipboardText(None),
+        HostRequest::AllowNetwork(_) => HostResponse::NetworkAllowed(false),
+    }
+}
+
+// describes the document a script is executed on, exposed to scripts as `payload.document`
+#[derive(Clone, Debug, Default, Hash)]
+pub struct DocumentContext {
+    // name of the file backing the document, if any
+    pub filename: Option<String>,
+    // id of the sourceview language used to highlight the document
+    pub language: Option<String>,
+    pub encoding: String,
+    // offsets in characters, the cursor is at the end of the selection if there is one
+    pub cursor: usize,
+    pub selection_range: Option<(usize, usize)>,
+    // every caret's selection in characters in the order they appear, cursors are empty, only
+    // when the editor has more than one caret
+    pub selection_ranges: Vec<(usize, usize)>,
+}
+
+// information about the user's environment, exposed to scripts as `payload.env`
+struct Environment {
+    // BCP 47 language tag, e.g. "en-GB"
+    locale: String,
+    // IANA timezone name where possible, e.g. "Europe/London"
+    timezone: String,
+    platform: &'static str,
+    version: &'static str,
+}
+
+lazy_static! {
+    static ref ENVIRONMENT: Environment = Environment::detect();
+}
+
+impl Environment {
+    fn detect() -> Self {
+        Environment {
+            locale: Environment::detect_locale(),
+            timezone: Environment::detect_timezone(),
+            platform: std::env::consts::OS,
+            version: env!("CARGO_PKG_VERSION"),
+        }
+    }
+
+    fn detect_locale() -> String {
+        // the first of these that's set wins, as with setlocale, e.g. "en_GB.UTF-8"
+        let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|locale| !locale.is_empty())
+            .unwrap_or_default();
+        let locale = locale.split(|c| c == '.' || c == '@').next().unwrap_or("");
+
+        match locale {
+            "" | "C" | "POSIX" => "en-US".to_string(),
+            locale => locale.replace('_', "-"),
+        }
+    }
+
+    fn detect_timezone() -> String {
+        if let Ok(tz) = std::env::var("TZ") {
+            if !tz.is_empty() {
+                return tz.trim_start_matches(':').to_string();
+            }
+        }
+
+        // on most unix systems /etc/localtime links into the zoneinfo database
+        if let Ok(path) = std::fs::read_link("/etc/localtime") {
+            let path = path.display().to_string();
+            if let Some(index) = path.find("zoneinfo/") {
+                return path[index + "zoneinfo/".len()..].to_string();
+            }
+        }
+
+        "UTC".to_string()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TextReplacement {
+    Full(String),
+    Selection(String),
+    // a replacement for each selection, in the order they appear in the document
+    Selections(Vec<String>),
+    Insert(Vec<Insertion>),
+    None,
+}
+
+// a change to a range of a text, offsets are in characters of the text before any change is made
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub start: usize,
+    pub end: usize,
+    // replaces the range, empty if the range is removed
+    pub text: String,
+}
+
+// what a replacement does to a text, so it can be previewed or checked without comparing the text
+// before and after
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChangeSet {
+    // in the order they appear in the text, none overlap
+    pub changes: Vec<Change>,
+}
+
+impl ChangeSet {
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    // makes the changes to the text they describe
+    pub fn apply(&self, text: &str) -> String {
+        let mut chars: Vec<char> = text.chars().collect();
+        // last first, so no change moves the text a later one is made to
+        for change in self.changes.iter().rev() {
+            chars.splice(change.start..change.end, change.text.chars());
+        }
+        chars.into_iter().collect()
+    }
+}
+
+// the first occurrence of `selection` in `text`, in characters
+pub fn find_selection(text: &str, selection: Option<&str>) -> Option<(usize, usize)> {
+    let selection = selection?;
+    text.find(selection).map(|start| {
+        let start = text[..start].chars().count();
+        (start, start + selection.chars().count())
+    })
+}
+
+impl TextReplacement {
+    // the changes the replacement makes to `full_text`, a selection replacement without a
+    // selection is inserted at the cursor
+    pub fn change_set(
+        &self,
+        full_text: &str,
+        selection: Option<(usize, usize)>,
+        cursor: usize,
+    ) -> ChangeSet {
+        let replace_selection = |text: &str| {
+            let (start, end) = selection.unwrap_or((cursor, cursor));
+            vec![Change {
+                start,
+                end,
+                text: text.to_string(),
+            }]
+        };
+
+        let changes = match self {
+            // only the parts of lines that changed are replaced
+            TextReplacement::Full(text) => diff::edits(full_text, text)
+                .into_iter()
+                .rev()
+                .map(|(start, end, text)| Change { start, end, text })
+                .collect(),
+            TextReplacement::Selection(text) => replace_selection(text),
+            // the editor's own selection, `selections_change_set` replaces those of other carets
+            TextReplacement::Selections(texts) => match texts.first() {
+                Some(text) if selection.is_some() => replace_selection(text),
+                _ => Vec::new(),
+            },
+            TextReplacement::Insert(insertions) => {
+                insertion_edits(insertions, full_text, cursor, selection)
+                    .into_iter()
+                    .rev()
+                    .map(|(start, end, text)| Change { start, end, text })
+                    .collect()
+            }
+            TextReplacement::None => Vec::new(),
+        };
+
+        ChangeSet { changes }
+    }
+
+    // the changes replacing each caret's selection in `ranges` with the script's selection at the
+    // same index, None unless the script changed `payload.selections`
+    pub fn selections_change_set(&self, ranges: &[(usize, usize)]) -> Option<ChangeSet> {
+        match self {
+            TextReplacement::Selections(texts) => Some(ChangeSet {
+                changes: ranges
+                    .iter()
+                    .zip(texts)
+                    .map(|(&(start, end), text)| Change {
+                        start,
+                        end,
+                        text: text.clone(),
+                    })
+                    .collect(),
+            }),
+            _ => None,
+        }
+    }
+
+    // applies the replacement to text outside of an editor, the selection is the first occurrence
+    // of `selection` in `full_text` and the cursor is at the end of the text
+    pub fn apply(self, full_text: &str, selection: Option<&str>) -> String {
+        self.change_set(
+            full_text,
+            find_selection(full_text, selection),
+            full_text.chars().count(),
+        )
+        .apply(full_text)
+    }
+}
+
+impl Executor {
+    pub fn new(source: &str, heap_limit: usize) -> Self {
+        Executor::with_isolate(Executor::isolate(heap_limit), source, heap_limit)
+    }
+
+    // an isolate whose heap may grow to `heap_limit` bytes, it has to be created on the thread the
+    // executor runs on
+    pub fn isolate(heap_limit: usize) -> v8::OwnedIsolate {
+        info!("initalizing isolate");
+
+        // library scripts are already loaded in isolates made from the startup snapshot
+        let params = v8::CreateParams::default().heap_limits(0, heap_limit);
+        let params = match snapshot::blob() {
+            Some(blob) => params.snapshot_blob(blob),
+            None => params,
+        };
+        v8::Isolate::new(params)
+    }
+
+    // loads the script into an isolate created by `Executor::isolate`
+    pub fn with_isolate(mut isolate: v8::OwnedIsolate, source: &str, heap_limit: usize) -> Self {
+        ISOLATE_HANDLE.with(|handle| handle.replace(Some(isolate.thread_safe_handle())));
+        isolate.add_near_heap_limit_callback(near_heap_limit, std::ptr::null_mut());
+
+        // set status slot, stores execution infomation, this is set before the context is
+        // initialized as `require` can record permission requests while the script loads
+        let status_slot: Rc<RefCell<ExecutionStatus>> =
+            Rc::new(RefCell::new(ExecutionStatus::default()));
+        isolate.set_slot(status_slot);
+
+        // set host slot, answers requests made by the script
+        let host_slot: Host = Rc::new(headless_host);
+        isolate.set_slot(host_slot);
+
+        // set sandbox slot, the files the script may read and write, there's none until a
+        // directory is given
+        let sandbox_slot: Rc<RefCell<Sandbox>> = Rc::new(RefCell::new(Sandbox::default()));
+        isolate.set_slot(sandbox_slot);
+
+        // set modules slot, modules are imported while the script is initialized
+        let modules_slot: Modules = Rc::new(RefCell::new(HashMap::new()));
+        isolate.set_slot(modules_slot);
+
+        // set up execution context
+        let (global_context, entry_points, payload_functions) = {
+            let scope = &mut v8::HandleScope::new(&mut isolate);
+            // let context = v8::Context::new(scope);
+            let (context, entry_points) = Executor::initialize_context(source, scope);
+            let scope = &mut v8::ContextScope::new(scope, context);
+            let payload_functions = Executor::payload_functions(scope);
+            (
+                v8::Global::new(scope, context),
+                entry_points,
+                payload_functions,
+            )
+        };
+
+        let (entry_points, init_error) = match entry_points {
+            Ok(entry_points) => (Some(entry_points), None),
+            Err(init_error) => {
+                error!("failed to initialize script: {}", init_error);
+                (None, Some(init_error))
+            }
+        };
+        let (main_function, process_line) = entry_points
+            .map(|entry_points| (entry_points.main, entry_points.process_line))
+            .unwrap_or((None, None));
+
+        // set state slot, stores v8 details
+        let state_slot: Rc<RefCell<ExecutorState>> = Rc::new(RefCell::new(ExecutorState {
+            global_context: Some(global_context),
+            main_function,
+            process_line,
+            init_error,
+            payload_functions,
+            granted: Vec::new(),
+        }));
+        isolate.set_slot(state_slot);
+
+        Executor {
+            isolate,
+            heap_limit,
+        }
+    }
+
+    // sets what answers requests the script makes of the application while it runs
+    pub fn set_host(&mut self, host: impl Fn(HostRequest) -> HostResponse + 'static) {
+        let host_slot: Host = Rc::new(host);
+        self.isolate.set_slot(host_slot);
+    }
+
+    // what the script has been allowed to do, by its metadata and the user
+    pub fn set_permissions(&mut self, granted: Vec<Permission>) {
+        self.isolate
+            .get_slot::<Rc<RefCell<ExecutorState>>>()
+            .expect("failed to get state slot")
+            .borrow_mut()
+            .granted = granted;
+    }
+
+    // the directory the script may read and write files in
+    pub fn set_sandbox(&mut self, dir: PathBuf) {
+        self.isolate
+            .set_slot(Rc::new(RefCell::new(Sandbox::new(dir))));
+    }
+
+    // handle that can stop a running script from another thread
+    pub fn isolate_handle(&self) -> v8::IsolateHandle {
+        self.isolate.thread_safe_handle()
+    }
+
+    // error that prevented the script from being initialized, e.g. a syntax error
+    pub fn init_error(&self) -> Option<ScriptError> {
+        self.isolate
+            .get_slot::<Rc<RefCell<ExecutorState>>>()
+            .expect("failed to get state slot")
+            .borrow()
+            .init_error
+            .clone()
+    }
+
+    // load source code from internal files or external filesystem depending on the path
+    fn load_raw_source(path: String) -> Result<String, SimpleError> {
+        if path.starts_with("@boop/") {
+            // script is internal
+
+            let internal_path = path.replace("@boop/", "lib/");
+            info!(
+                "found internal script, real path: #BINARY#/{}",
+                internal_path
+            );
+
+            let raw_source = String::from_utf8(
+                Scripts::get(&internal_path)
+                    .ok_or_else(|| {
+                        SimpleError::new(format!("no internal script with path \"{}\"", path))
+                    })?
+                    .to_vec(),
+            )
+            .map_err(|e| SimpleError::with("problem with file encoding", e))?;
+
+            return Ok(raw_source);
+        }
+
+        let mut external_path = PROJECT_DIRS.config_dir().to_path_buf();
+        external_path.push("scripts");
+        external_path.push(&path);
+
+        info!(
+            "found external script, real path: {}",
+            external_path.display()
+        );
+
+        let mut raw_source = String::new();
+        File::open(external_path)
+            .map_err(|e| SimpleError::with(&format!("could not open \"{}\"", path), e))?
+            .read_to_string(&mut raw_source)
+            .map_err(|e| SimpleError::with("problem reading file", e))?;
+
+        Ok(raw_source)
+    }
+
+    // load source code from an https url the user has allowed scripts to load, the source is cached
+    // so it can still be loaded when offline
+    fn load_remote_source(
+        scope: &mut v8::HandleScope<'_>,
+        url: &str,
+    ) -> Result<String, SimpleError> {
+        if !url.starts_with("https://") {
+            bail!("cannot load \"{}\", only https urls can be required", url);
+        }
+
+        match Permissions::load(PROJECT_DIRS.config_dir())
+            .remote_require
+            .get(url)
+        {
+            Some(true) => {}
+            Some(false) => bail!("loading \"{}\" was denied", url),
+            None => {
+                let status_slot = scope
+                    .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+                    .expect("failed to get status slot");
+                let mut status = status_slot.borrow_mut();
+                if !status
+                    .permission_requests
+                    .iter()
+                    .any(|request| request == url)
+                {
+                    status.permission_requests.push(url.to_string());
+                }
+
+                bail!("loading \"{}\" needs permission", url);
+            }
+        }
+
+        // cached copies are keyed by a hash of the url
+        let hash = format!("{:x}", Sha256::digest(url.as_bytes()));
+        let mut cache_path = PROJECT_DIRS.cache_dir().to_path_buf();
+        cache_path.push("require");
+        cache_path.push(format!("{}.js", hash));
+
+        info!("fetching {}", url);
+
+        let response = ureq::get(url)
+            .timeout_connect(REMOTE_REQUIRE_TIMEOUT_MS)
+            .timeout_read(REMOTE_REQUIRE_TIMEOUT_MS)
+            .call();
+
+        let fetched = match response.synthetic_error() {
+            Some(e) => Err(e.to_string()),
+            None if !response.ok() => {
+                Err(format!("{} {}", response.status(), response.status_text()))
+            }
+            None => response.into_string().map_err(|e| e.to_string()),
+        };
+
+        match fetched {
+            Ok(raw_source) => {
+                if let Err(e) = fs::create_dir_all(PROJECT_DIRS.cache_dir().join("require"))
+                    .and_then(|_| fs::write(&cache_path, &raw_source))
+                {
+                    warn!("could not cache {}: {}", url, e);
+                }
+
+                Ok(raw_source)
+            }
+            Err(e) => {
+                warn!("could not fetch {} ({}), using cached copy", url, e);
+
+                fs::read_to_string(&cache_path).map_err(|_| {
+                    SimpleError::new(format!(
+                        "could not fetch \"{}\" and there is no cached copy: {}",
+                        url, e
+                    ))
+                })
+            }
+        }
+    }
+
+    fn initialize_context<'s>(
+        source: &str,
+        scope: &mut v8::HandleScope<'s, ()>,
+    ) -> (v8::Local<'s, v8::Context>, Result<EntryPoints, ScriptError>) {
+        let scope = &mut v8::EscapableHandleScope::new(scope);
+        let context = v8::Context::new(scope);
+        let global = context.global(scope);
+        let scope = &mut v8::ContextScope::new(scope, context);
+
+        let require_key =
+            v8::String::new(scope, "require").expect("failed to created 'require' string");
+        let require_val = v8::Function::new(scope, Executor::global_require)
+            .expect("failed to created require function");
+        global.set(scope, require_key.into(), require_val.into());
+
+        let fetch_key = v8::String::new(scope, "fetch").expect("failed to created 'fetch' string");
+        let fetch_val = v8::Function::new(scope, Executor::global_fetch)
+            .expect("failed to created fetch function");
+        global.set(scope, fetch_key.into(), fetch_val.into());
+
+        // console, its output is shown once the script has run
+        {
+            let console = v8::Object::new(scope);
+            let methods: [(&str, v8::Local<v8::Function>); 4] = [
+                (
+                    "log",
+                    v8::Function::new(scope, Executor::console_log)
+                        .expect("failed to create console_log function"),
+                ),
+                (
+                    "info",
+                    v8::Function::new(scope, Executor::console_log)
+                        .expect("failed to create console_log function"),
+                ),
+                (
+                    "warn",
+                    v8::Function::new(scope, Executor::console_warn)
+                        .expect("failed to create console_warn function"),
+                ),
+                (
+                    "error",
+                    v8::Function::new(scope, Executor::console_error)
+                        .expect("failed to create console_error function"),
+                ),
+            ];
+            for (name, function) in &methods {
+                let key = v8::String::new(scope, name).expect("failed to create JS string");
+                console.set(scope, key.into(), (*function).into());
+            }
+
+            let console_key =
+                v8::String::new(scope, "console").expect("failed to create 'console' string");
+            global.set(scope, console_key.into(), console.into());
+        }
+
+        // complile and run script, main is exported by ES modules and global otherwise
+        let tc_scope = &mut v8::TryCatch::new(scope);
+        let exports = if is_es_module(source) {
+            info!("script is an ES module");
+            Executor::compile_module(tc_scope, "main.js", source).and_then(|module| {
+                module
+                    .instantiate_module(tc_scope, Executor::resolve_module)
+                    .filter(|instantiated| *instantiated)?;
+                module.evaluate(tc_scope)?;
+                v8::Local::<v8::Object>::try_from(module.get_module_namespace()).ok()
+            })
+        } else {
+            let code = v8::String::new(tc_scope, source).expect("failed to created JS string");
+            v8::Script::compile(tc_scope, code, None)
+                .and_then(|compiled_script| compiled_script.run(tc_scope))
+                .map(|_| global)
+        };
+
+        let exports = match exports {
+            Some(exports) => exports,
+            None => {
+                let exception = if tc_scope.has_caught() {
+                    ScriptError::caught(tc_scope)
+                } else {
+                    ScriptError::new("script could not be loaded")
+                };
+
+                error!("<<JS EXCEPTION>> {}", exception);
+
+                return (tc_scope.escape(context), Err(exception));
+            }
+        };
+
+        // extract main function, and processLine for scripts that stream
+        let entry_points = EntryPoints {
+            main: Executor::exported_function(tc_scope, exports, "main"),
+            process_line: Executor::exported_function(tc_scope, exports, "processLine"),
+        };
+
+        if entry_points.main.is_none() && entry_points.process_line.is_none() {
+            return (
+                tc_scope.escape(context),
+                Err(ScriptError::new("script has no main function")),
+            );
+        }
+
+        (tc_scope.escape(context), Ok(entry_points))
+    }
+
+    fn exported_function(
+        scope: &mut v8::HandleScope<'_>,
+        exports: v8::Local<'_, v8::Object>,
+        name: &str,
+    ) -> Option<v8::Global<v8::Function>> {
+        let key = v8::String::new(scope, name).expect("failed to create JS string");
+        exports
+            .get(scope, key.into())
+            .and_then(|function| v8::Local::<v8::Function>::try_from(function).ok())
+            .map(|function| v8::Global::new(scope, function))
+    }
+
+    // functions set on the payload, created once for each context rather than each execution
+    fn payload_functions(
+        scope: &mut v8::HandleScope<'_>,
+    ) -> Vec<(
+        Option<Permission>,
+        v8::Global<v8::String>,
+        v8::Global<v8::Function>,
+    )> {
+        let functions = vec![
+            (
+                "postInfo",
+                v8::Function::new(scope, Executor::payload_post_info)
+                    .expect("failed to create post_info function"),
+            ),
+            (
+                "postError",
+                v8::Function::new(scope, Executor::payload_post_error)
+                    .expect("failed to create post_error function"),
+            ),
+            (
+                "postLanguage",
+                v8::Function::new(scope, Executor::payload_post_language)
+                    .expect("failed to create post_language function"),
+            ),
+            (
+                "insert",
+                v8::Function::new(scope, Executor::payload_insert)
+                    .expect("failed to create payload_insert function"),
+            ),
+            (
+                "saveAs",
+                v8::Function::new(scope, Executor::payload_save_as)
+                    .expect("failed to create payload_save_as function"),
+            ),
+            (
+                "chooseFile",
+                v8::Function::new(scope, Executor::payload_choose_file)
+                    .expect("failed to create payload_choose_file function"),
+            ),
+            (
+                "readFile",
+                v8::Function::new(scope, Executor::payload_read_file)
+                    .expect("failed to create payload_read_file function"),
+            ),
+            (
+                "writeFile",
+                v8::Function::new(scope, Executor::payload_write_file)
+                    .expect("failed to create payload_write_file function"),
+            ),
+            (
+                "pickColor",
+                v8::Function::new(scope, Executor::payload_pick_color)
+                    .expect("failed to create payload_pick_color function"),
+            ),
+            (
+                "prompt",
+                v8::Function::new(scope, Executor::payload_prompt)
+                    .expect("failed to create payload_prompt function"),
+            ),
+            (
+                "openUrl",
+                v8::Function::new(scope, Executor::payload_open_url)
+                    .expect("failed to create payload_open_url function"),
+            ),
+            (
+                "copy",
+                v8::Function::new(scope, Executor::payload_copy)
+                    .expect("failed to create payload_copy function"),
+            ),
+            (
+                "notify",
+                v8::Function::new(scope, Executor::payload_notify)
+                    .expect("failed to create payload_notify function"),
+            ),
+            (
+                "readClipboard",
+                v8::Function::new(scope, Executor::payload_read_clipboard)
+                    .expect("failed to create payload_read_clipboard function"),
+            ),
+            (
+                "addArtifact",
+                v8::Function::new(scope, Executor::payload_add_artifact)
+                    .expect("failed to create payload_add_artifact function"),
+            ),
+            (
+                "postPreview",
+                v8::Function::new(scope, Executor::payload_post_preview)
+                    .expect("failed to create payload_post_preview function"),
+            ),
+            (
+                "highlight",
+                v8::Function::new(scope, Executor::payload_highlight)
+                    .expect("failed to create payload_highlight function"),
+            ),
+            (
+                "moveCursor",
+                v8::Function::new(scope, Executor::payload_move_cursor)
+                    .expect("failed to create payload_move_cursor function"),
+            ),
+            (
+                "parseJSON",
+                v8::Function::new(scope, Executor::payload_parse_json)
+                    .expect("failed to create payload_parse_json function"),
+            ),
+            (
+                "stringifyJSON",
+                v8::Function::new(scope, Executor::payload_stringify_json)
+                    .expect("failed to create payload_stringify_json function"),
+            ),
+        ];
+
+        functions
+            .into_iter()
+            .map(|(name, function)| {
+                let key = v8::String::new(scope, name).expect("failed to create JS string");
+                (
+                    Permission::required_by(name),
+                    v8::Global::new(scope, key),
+                    v8::Global::new(scope, function),
+                )
+            })
+            .collect()
+    }
+
+    // runs the script as a dry run, describing what it would change, in an isolate of its own taken
+    // from the pool, so neither its globals nor its `payload.state` outlive the preview, the
+    // selection is the first occurrence of `selection` in `full_text`
+    pub fn preview(
+        source: &str,
+        heap_limit: usize,
+        full_text: &str,
+        selection: Option<&str>,
+    ) -> ChangeSet {
+        let (sender, receiver) = bounded(1);
+        let (source, full_text, selection) = (
+            source.to_string(),
+            full_text.to_string(),
+            selection.map(str::to_string),
+        );
+
+        pool::spawn(heap_limit, move |isolate| {
+            let mut executor = Executor::with_isolate(isolate, &source, heap_limit);
+
+            let selection_range = find_selection(&full_text, selection.as_deref());
+            let cursor = selection_range
+                .map(|(_, end)| end)
+                .unwrap_or_else(|| full_text.chars().count());
+            let document = DocumentContext {
+                cursor,
+                selection_range,
+                ..Default::default()
+            };
+
+            let change_set = executor
+                .execute(&full_text, selection.as_deref(), &document, None)
+                .into_replacement()
+                .change_set(&full_text, selection_range, cursor);
+            // the caller may have given up waiting
+            let _ = sender.send(change_set);
+        });
+
+        // the thread only stops without sending if the script crashed V8
+        receiver.recv().unwrap_or_default()
+    }
+
+    pub fn execute(
+        &mut self,
+        full_text: &str,
+        selection: Option<&str>,
+        document: &DocumentContext,
+        state: Option<&str>,
+    ) -> ExecutionStatus {
+        let heap_limit = self.heap_limit;
+
+        // the watchdog may have fired just after the previous execution finished
+        self.isolate
+            .thread_safe_handle()
+            .cancel_terminate_execution();
+        HEAP_LIMIT_REACHED.with(|reached| reached.set(false));
+
+        // setup execution status
+        {
+            let status_slot = self
+                .isolate
+                .get_slot_mut::<Rc<RefCell<ExecutionStatus>>>()
+                .expect("failed to get mutable access to status slot");
+
+            let mut status = status_slot.borrow_mut();
+
+            status.reset();
+            *status.full_text.write() = full_text.to_string();
+            status.full_text.clear();
+            *status.text.write() = selection.unwrap_or(full_text).to_string();
+            status.text.clear();
+            *status.selection.write() = selection.unwrap_or("").to_string();
+            status.selection.clear();
+            *status.selections.write() = if document.selection_ranges.is_empty() {
+                selection.map(|s| vec![s.to_string()]).unwrap_or_default()
+            } else {
+                texts_in_ranges(full_text, &document.selection_ranges)
+            };
+            status.selections.clear();
+            *status.state.write() = state.map(|state| state.to_string());
+            status.state.clear();
+        }
+
+        // prepare payload and execute main
+        // TODO: use ObjectTemplate, problem: rusty_v8 doesn't have set_accessor_with_setter or even set_accessor for
+        // object templates
+        {
+            let state_slot = self
+                .isolate
+                .get_slot_mut::<Rc<RefCell<ExecutorState>>>()
+                .expect("failed to get mutable access to state slot")
+                .clone();
+            let state_slot = state_slot.borrow();
+
+            let context = state_slot
+                .global_context
+                .as_ref()
+                .expect("global_context is not initalizied");
+            let scope = &mut v8::HandleScope::with_context(&mut self.isolate, context);
+
+            // payload is the object passed into function main
+            let payload = v8::Object::new(scope);
+
+            // getter/setters: full_text, full_text_bytes, text, selection, persistent
+            {
+                let full_text_key = v8::String::new(scope, "fullText")
+                    .expect("failed to create JS string for 'fullText'");
+                let full_text_bytes_key = v8::String::new(scope, "fullTextBytes")
+                    .expect("failed to create JS string for 'fullTextBytes'");
+                let text_key =
+                    v8::String::new(scope, "text").expect("failed to create JS string for 'text'");
+                let selection_key = v8::String::new(scope, "selection")
+                    .expect("failed to create JS string for 'selection'");
+                let persistent_key = v8::String::new(scope, "persistent")
+                    .expect("failed to create JS string for 'persistent'");
+
+                payload.set_accessor_with_setter(
+                    scope,
+                    full_text_key.into(),
+                    Executor::payload_full_text_getter,
+                    Executor::payload_full_text_setter,
+                );
+                payload.set_accessor_with_setter(
+                    scope,
+                    full_text_bytes_key.into(),
+                    Executor::payload_full_text_bytes_getter,
+                    Executor::payload_full_text_bytes_setter,
+                );
+                payload.set_accessor_with_setter(
+                    scope,
+                    text_key.into(),
+                    Executor::payload_text_getter,
+                    Executor::payload_text_setter,
+                );
+                payload.set_accessor_with_setter(
+                    scope,
+                    selection_key.into(),
+                    Executor::payload_selection_getter,
+                    Executor::payload_selection_setter,
+                );
+                payload.set_accessor_with_setter(
+                    scope,
+                    persistent_key.into(),
+                    Executor::payload_persistent_getter,
+                    Executor::payload_persistent_setter,
+                );
+            }
+
+            // functions, created with the context and reused by each execution: post_info,
+            // post_error, post_language, insert, save_as, choose_file, read_file, write_file,
+            // pick_color, prompt,
+            // open_url, copy, notify, read_clipboard, add_artifact, highlight, move_cursor, parse_json,
+            // stringify_json, choose_file and read_clipboard are only set once they're granted
+            {
+                for (permission, key, function) in &state_slot.payload_functions {
+                    if let Some(permission) = permission {
+                        if !state_slot.granted.contains(permission) {
+                            continue;
+                        }
+                    }
+
+                    let key = key.get(scope);
+                    let function = function.get(scope);
+                    payload.set(scope, key.into(), function.into());
+                }
+            }
+
+            // values: cursor_offset, selection_range, in UTF-16 code units like JS string indices
+            {
+                let cursor_key = v8::String::new(scope, "cursorOffset")
+                    .expect("failed to create JS string 'cursorOffset'");
+                let selection_range_key = v8::String::new(scope, "selectionRange")
+                    .expect("failed to create JS string 'selectionRange'");
+
+                let cursor_val = v8::Number::new(
+                    scope,
+                    char_to_utf16_offset(full_text, document.cursor) as f64,
+                );
+                let selection_range_val: v8::Local<v8::Value> = match document.selection_range {
+                    Some((start, end)) => {
+                        let range = v8::Object::new(scope);
+                        for (name, offset) in &[("start", start), ("end", end)] {
+                            let key =
+                                v8::String::new(scope, name).expect("failed to create JS string");
+                            let offset = v8::Number::new(
+                                scope,
+                                char_to_utf16_offset(full_text, *offset) as f64,
+                            );
+                            range.set(scope, key.into(), offset.into());
+                        }
+                        range.into()
+                    }
+                    None => v8::null(scope).into(),
+                };
+
+                payload.set(scope, cursor_key.into(), cursor_val.into());
+                payload.set(scope, selection_range_key.into(), selection_range_val);
+            }
+
+            // arrays: selections
+            {
+                let selections_key = v8::String::new(scope, "selections")
+                    .expect("failed to create JS string 'selections'");
+
+                let selections = scope
+                    .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+                    .expect("failed to get status slot")
+                    .borrow()
+                    .selections
+                    .read()
+                    .clone();
+                let elements: Vec<v8::Local<'_, v8::Value>> = selections
+                    .iter()
+                    .map(|selection| {
+                        v8::String::new(scope, selection)
+                            .expect("failed to create JS string for selection")
+                            .into()
+                    })
+                    .collect();
+                let selections_val = v8::Array::new_with_elements(scope, &elements);
+
+                payload.set(scope, selections_key.into(), selections_val.into());
+            }
+
+            // objects: state, parsed from JSON each execution so changes to it are kept
+            {
+                let state_key =
+                    v8::String::new(scope, "state").expect("failed to create JS string 'state'");
+
+                let state_val = state
+                    .and_then(|state| v8::String::new(scope, state))
+                    .and_then(|json| v8::json::parse(scope, json))
+                    .filter(|value| value.is_object())
+                    .unwrap_or_else(|| v8::Object::new(scope).into());
+
+                payload.set(scope, state_key.into(), state_val);
+            }
+
+            // objects: document, env
+            {
+                let document_key = v8::String::new(scope, "document")
+                    .expect("failed to create JS string 'document'");
+                let env_key =
+                    v8::String::new(scope, "env").expect("failed to create JS string 'env'");
+
+                let document_val = Executor::string_object(
+                    scope,
+                    &[
+                        ("filename", document.filename.as_deref()),
+                        ("language", document.language.as_deref()),
+                        ("lineEnding", Some(LineEnding::dominant(full_text).id())),
+                        ("encoding", Some(document.encoding.as_str())),
+                    ],
+                );
+                let env_val = Executor::string_object(
+                    scope,
+                    &[
+                        ("locale", Some(ENVIRONMENT.locale.as_str())),
+                        ("timezone", Some(ENVIRONMENT.timezone.as_str())),
+                        ("platform", Some(ENVIRONMENT.platform)),
+                        ("version", Some(ENVIRONMENT.version)),
+                    ],
+                );
+
+                payload.set(scope, document_key.into(), document_val.into());
+                payload.set(scope, env_key.into(), env_val.into());
+            }
+
+            // value: line_ending, the characters most of the document's lines end with, so
+            // scripts can split and join lines the same way
+            {
+                let line_ending_key = v8::String::new(scope, "lineEnding")
+                    .expect("failed to create JS string 'lineEnding'");
+                let line_ending_val =
+                    v8::String::new(scope, LineEnding::dominant(full_text).sequence())
+                        .expect("failed to create JS string");
+                payload.set(scope, line_ending_key.into(), line_ending_val.into());
+            }
+
+            match state_slot.main_function.as_ref() {
+                Some(main_function) => {
+                    let main_function = main_function.get(scope);
+                    let tc_scope = &mut v8::TryCatch::new(scope);
+                    let result = main_function.call(tc_scope, payload.into(), &[payload.into()]);
+                    // async mains return a promise, the payload is read once it has settled
+                    let promise =
+                        result.and_then(|result| v8::Local::<v8::Promise>::try_from(result).ok());
+                    let rejection = promise.and_then(|promise| Executor::settle(tc_scope, promise));
+
+                    if (result.is_none() || promise.is_some()) && tc_scope.has_terminated() {
+                        Executor::record_termination(tc_scope, heap_limit);
+                    } else if result.is_none() {
+                        assert!(tc_scope.has_caught());
+                        let exception = ScriptError::caught(tc_scope);
+
+                        error!("<<JS EXCEPTION>> {}", exception);
+
+                        tc_scope
+                            .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+                            .expect("failed to get status slot")
+                            .borrow_mut()
+                            .exception
+                            .replace(exception);
+                    } else if let Some(exception) = rejection {
+                        error!("<<JS REJECTION>> {}", exception);
+
+                        tc_scope
+                            .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+                            .expect("failed to get status slot")
+                            .borrow_mut()
+                            .exception
+                            .replace(exception);
+                    } else {
+                        Executor::read_selections(tc_scope, payload);
+                        Executor::read_state(tc_scope, payload);
+                    }
+                }
+                None => {
+                    // script failed to initialize, or only streams, there is nothing to run
+                    scope
+                        .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+                        .expect("failed to get status slot")
+                        .borrow_mut()
+                        .exception = state_slot
+                        .init_error
+                        .clone()
+                        .or_else(|| Some(ScriptError::new("script has no main function")));
+                }
+            }
+        }
+
+        self.take_status()
+    }
+
+    // runs a script with `"stream": true` in its metadata, calling `processLine(line, index)` with
+    // each line of the selection, or the whole text, so the text is never copied into a JS string
+    // whole. The lines it returns are joined with the text's line ending, returning null or
+    // undefined removes the line
+    pub fn stream(&mut self, full_text: &str, selection: Option<&str>) -> ExecutionStatus {
+        let heap_limit = self.heap_limit;
+
+        self.isolate
+            .thread_safe_handle()
+            .cancel_terminate_execution();
+        HEAP_LIMIT_REACHED.with(|reached| reached.set(false));
+
+        {
+            let status_slot = self
+                .isolate
+                .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+                .expect("failed to get status slot");
+            let mut status = status_slot.borrow_mut();
+            status.reset();
+            Dirty::clear(&mut status.selection);
+            Dirty::clear(&mut status.selections);
+        }
+
+        let text = selection.unwrap_or(full_text);
+        let line_ending = LineEnding::dominant(text).sequence();
+        // a final line ending doesn't start another line
+        let (lines, trailing) = match text.strip_suffix(line_ending) {
+            Some(lines) => (lines, line_ending),
+            None => (text, ""),
+        };
+
+        let output = {
+            let state_slot = self
+                .isolate
+                .get_slot::<Rc<RefCell<ExecutorState>>>()
+                .expect("failed to get state slot")
+                .clone();
+            let state_slot = state_slot.borrow();
+
+            let context = state_slot
+                .global_context
+                .as_ref()
+                .expect("global_context is not initalizied");
+            let scope = &mut v8::HandleScope::with_context(&mut self.isolate, context);
+
+            match state_slot.process_line.as_ref() {
+                Some(process_line) => {
+                    let process_line = process_line.get(scope);
+                    let tc_scope = &mut v8::TryCatch::new(scope);
+                    let undefined: v8::Local<v8::Value> = v8::undefined(tc_scope).into();
+
+                    let mut output = String::with_capacity(text.len());
+                    let mut kept_lines = 0;
+                    let mut failed = false;
+                    for (index, line) in lines.split(line_ending).enumerate() {
+                        // handles are freed after each line
+                        let scope = &mut v8::HandleScope::new(tc_scope);
+                        let line =
+                            v8::String::new(scope, line).expect("failed to create JS string");
+                        let index = v8::Number::new(scope, index as f64);
+                        let result =
+                            process_line.call(scope, undefined, &[line.into(), index.into()]);
+                        let result = match result {
+                            Some(result) => result,
+                            None => {
+                                failed = true;
+                                break;
+                            }
+                        };
+
+                        if result.is_undefined() || result.is_null() {
+                            continue;
+                        }
+                        let result = match result.to_string(scope) {
+                            Some(result) => result.to_rust_string_lossy(scope),
+                            None => {
+                                failed = true;
+                                break;
+                            }
+                        };
+                        if kept_lines > 0 {
+                            output.push_str(line_ending);
+                        }
+                        output.push_str(&result);
+                        kept_lines += 1;
+                    }
+
+                    if failed && tc_scope.has_terminated() {
+                        Executor::record_termination(tc_scope, heap_limit);
+                        None
+                    } else if failed {
+                        let exception = ScriptError::caught(tc_scope);
+                        error!("<<JS EXCEPTION>> {}", exception);
+                        tc_scope
+                            .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+                            .expect("failed to get status slot")
+                            .borrow_mut()
+                            .exception
+                            .replace(exception);
+                        None
+                    } else {
+                        output.push_str(trailing);
+                        Some(output)
+                    }
+                }
+                None => {
+                    scope
+                        .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+                        .expect("failed to get status slot")
+                        .borrow_mut()
+                        .exception = state_slot
+                        .init_error
+                        .clone()
+                        .or_else(|| Some(ScriptError::new("script has no processLine function")));
+                    None
+                }
+            }
+        };
+
+        if let Some(output) = output {
+            let status_slot = self
+                .isolate
+                .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+                .expect("failed to get status slot");
+            let mut status = status_slot.borrow_mut();
+            match selection {
+                Some(_) => *status.selection.write() = output,
+                None => *status.full_text.write() = output,
+            }
+        }
+
+        self.take_status()
+    }
+
+    // records why the script was stopped, by the watchdog or as the heap was full, and allows the
+    // isolate to run again
+    fn record_termination(tc_scope: &mut v8::TryCatch<v8::HandleScope>, heap_limit: usize) {
+        warn!("script execution was terminated");
+        tc_scope.thread_safe_handle().cancel_terminate_execution();
+
+        let slot = tc_scope
+            .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get status slot")
+            .clone();
+        let mut status = slot.borrow_mut();
+        if HEAP_LIMIT_REACHED.with(|reached| reached.replace(false)) {
+            status.exceeded_heap_limit = true;
+            status.exception.replace(ScriptError::new(format!(
+                "script exceeded memory limit ({} MB)",
+                heap_limit / (1024 * 1024)
+            )));
+        } else {
+            status
+                .exception
+                .replace(ScriptError::new("script took too long and was stopped"));
+        }
+    }
+
+    // the status of the execution that just finished
+    fn take_status(&mut self) -> ExecutionStatus {
+        let mut heap_statistics = v8::HeapStatistics::default();
+        self.isolate.get_heap_statistics(&mut heap_statistics);
+
+        let status_slot = self
+            .isolate
+            .get_slot_mut::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get mutable access to status slot");
+
+        let mut status = (status_slot).borrow_mut();
+        status.heap_size = heap_statistics.used_heap_size();
+
+        let result = status.clone();
+        status.permission_requests.clear();
+        result
+    }
+
+    // runs the callbacks queued on the promise returned by main, scripts have no timers or other
+    // events to wait for so it has settled once the microtask queue is empty, if it ever will,
+    // callbacks that run for too long are stopped by the watchdog, returns why it didn't fulfil
+    fn settle(
+        tc_scope: &mut v8::TryCatch<v8::HandleScope>,
+        promise: v8::Local<v8::Promise>,
+    ) -> Option<ScriptError> {
+        tc_scope.perform_microtask_checkpoint();
+        if tc_scope.has_terminated() {
+            return None;
+        }
+
+        match promise.state() {
+            v8::PromiseState::Fulfilled => None,
+            v8::PromiseState::Rejected => {
+                let reason = promise.result(tc_scope);
+                Some(ScriptError::rejected(tc_scope, reason))
+            }
+            v8::PromiseState::Pending => Some(ScriptError::new(
+                "main returned a promise that never settled",
+            )),
+        }
+    }
+
+    // records changes the script made to `payload.selections`, a value that isn't an array is
+    // ignored
+    fn read_selections(scope: &mut v8::HandleScope<'_>, payload: v8::Local<'_, v8::Object>) {
+        let selections_key =
+            v8::String::new(scope, "selections").expect("failed to create JS string 'selections'");
+        let array = match payload
+            .get(scope, selections_key.into())
+            .and_then(|value| v8::Local::<v8::Array>::try_from(value).ok())
+        {
+            Some(array) => array,
+            None => {
+                warn!("payload.selections is not an array, ignoring it");
+                return;
+            }
+        };
+
+        let mut selections = Vec::new();
+        for index in 0..array.length() {
+            let selection = array
+                .get_index(scope, index)
+                .and_then(|selection| selection.to_string(scope))
+                .map(|selection| selection.to_rust_string_lossy(scope))
+                .unwrap_or_default();
+            selections.push(selection);
+        }
+
+        let slot = scope
+            .get_slot_mut::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get mutable access to status slot");
+        let mut status = slot.borrow_mut();
+        if *status.selections.read() != selections {
+            info!("setting {} selections", selections.len());
+            *status.selections.write() = selections;
+        }
+    }
+
+    // records `payload.state` as JSON if it changed
+    fn read_state(scope: &mut v8::HandleScope<'_>, payload: v8::Local<'_, v8::Object>) {
+        let state_key =
+            v8::String::new(scope, "state").expect("failed to create JS string 'state'");
+        let state = payload
+            .get(scope, state_key.into())
+            .filter(|state| !state.is_undefined())
+            .and_then(|state| v8::json::stringify(scope, state))
+            .map(|json| json.to_rust_string_lossy(scope));
+
+        let slot = scope
+            .get_slot_mut::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get mutable access to status slot");
+        let mut status = slot.borrow_mut();
+        // an empty object is what scripts without any state are given
+        let unchanged = *status.state.read() == state
+            || (status.state.read().is_none() && state.as_deref() == Some("{}"));
+        if !unchanged {
+            info!(
+                "setting state ({} bytes)",
+                state.as_ref().map(|s| s.len()).unwrap_or(0)
+            );
+            *status.state.write() = state;
+        }
+    }
+
+    // creates an object with a string (or null) property for each field
+    fn string_object<'s>(
+        scope: &mut v8::HandleScope<'s>,
+        fields: &[(&str, Option<&str>)],
+    ) -> v8::Local<'s, v8::Object> {
+        let object = v8::Object::new(scope);
+
+        for (name, value) in fields.iter() {
+            let key = v8::String::new(scope, name).expect("failed to create JS string for key");
+            let value: v8::Local<'_, v8::Value> = match value {
+                Some(value) => v8::String::new(scope, value)
+                    .expect("failed to create JS string for value")
+                    .into(),
+                None => v8::null(scope).into(),
+            };
+            object.set(scope, key.into(), value);
+        }
+
+        object
+    }
+
+    // a value a script gave converted to a string, None if that threw, e.g. for a Symbol or an
+    // object whose `toString` throws, callbacks then return so the exception is thrown to the
+    // script, a panic can't unwind through V8 and would abort the app
+    pub fn script_string(
+        scope: &mut v8::HandleScope<'_>,
+        value: v8::Local<'_, v8::Value>,
+    ) -> Option<String> {
+        value
+            .to_string(scope)
+            .map(|string| string.to_rust_string_lossy(scope))
+    }
+
+    fn global_require(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let mut path = match Executor::script_string(scope, args.get(0)) {
+            Some(path) => path,
+            None => return,
+        };
+
+        info!("loading {}", path);
+
+        // helpers written in Rust, there's no source to run
+        if path == native::MODULE_NAME {
+            let module = native::module(scope);
+            rv.set(module.into());
+            return;
+        }
+
+        let raw_source = if path.contains("://") {
+            Executor::load_remote_source(scope, &path)
+        } else {
+            // append extension
+            if !path.ends_with(".js") {
+                path.push_str(".js");
+            }
+
+            if let Some(exports) = Executor::snapshot_library(scope, &path) {
+                rv.set(exports);
+                return;
+            }
+
+            Executor::load_raw_source(path)
+        };
+
+        match raw_source {
+            Ok(raw_source) => {
+                // errors in the module are thrown to the script that required it
+                if let Some(export) = Executor::run_commonjs(scope, &raw_source) {
+                    rv.set(export);
+                }
+            }
+            Err(e) => {
+                warn!("problem requiring script, {}", e);
+
+                let message = v8::String::new(scope, &e.to_string())
+                    .expect("failed to create JS string from error");
+                let exception = v8::Exception::error(scope, message);
+                scope.throw_exception(exception);
+            }
+        }
+    }
+
+    // a blocking `fetch(url, { method, headers, body })`, returning
+    // { ok, status, statusText, url, headers, body, text(), json() }, scripts need `"network": true`
+    // in their metadata and the user's permission
+    fn global_fetch(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let url = match Executor::script_string(scope, args.get(0)) {
+            Some(url) => url,
+            None => return,
+        };
+
+        let mut method = "GET".to_string();
+        let mut headers = Vec::new();
+        let mut body = None;
+        if let Ok(options) = v8::Local::<v8::Object>::try_from(args.get(1)) {
+            let method_key =
+                v8::String::new(scope, "method").expect("failed to create JS string 'method'");
+            let headers_key =
+                v8::String::new(scope, "headers").expect("failed to create JS string 'headers'");
+            let body_key =
+                v8::String::new(scope, "body").expect("failed to create JS string 'body'");
+
+            // a getter or `toString` that throws leaves its exception to be thrown to the script
+            let value = match options.get(scope, method_key.into()) {
+                Some(value) => value,
+                None => return,
+            };
+            if !value.is_undefined() && !value.is_null() {
+                method = match Executor::script_string(scope, value) {
+                    Some(method) => method.to_uppercase(),
+                    None => return,
+                };
+            }
+            let value = match options.get(scope, headers_key.into()) {
+                Some(value) => value,
+                None => return,
+            };
+            if let Ok(object) = v8::Local::<v8::Object>::try_from(value) {
+                headers = match Executor::string_pairs(scope, object) {
+                    Some(headers) => headers,
+                    None => return,
+                };
+            }
+            let value = match options.get(scope, body_key.into()) {
+                Some(value) => value,
+                None => return,
+            };
+            if !value.is_undefined() && !value.is_null() {
+                body = match Executor::script_string(scope, value) {
+                    Some(body) => Some(body),
+                    None => return,
+                };
+            }
+        }
+
+        let result = Executor::fetch(scope, &url, &method, &headers, body.as_deref());
+        match result {
+            Ok(response) => rv.set(response.into()),
+            Err(e) => {
+                warn!("could not fetch {}: {}", url, e);
+
+                let message = v8::String::new(scope, &e.to_string())
+                    .expect("failed to create JS string from error");
+                let exception = v8::Exception::type_error(scope, message);
+                scope.throw_exception(exception);
+            }
+        }
+    }
+
+    fn fetch<'s>(
+        scope: &mut v8::HandleScope<'s>,
+        url: &str,
+        method: &str,
+        headers: &[(String, String)],
+        body: Option<&str>,
+    ) -> Result<v8::Local<'s, v8::Object>, SimpleError> {
+        if !url.starts_with("https://") && !url.starts_with("http://") {
+            bail!(
+                "cannot fetch \"{}\", only http and https urls can be fetched",
+                url
+            );
+        }
+
+        let host = scope
+            .get_slot::<Host>()
+            .expect("failed to get host slot")
+            .clone();
+        match host(HostRequest::AllowNetwork(url.to_string())) {
+            HostResponse::NetworkAllowed(true) => {}
+            HostResponse::NetworkAllowed(false) => bail!(
+                "fetching \"{}\" was denied, scripts need \"network\": true in their metadata",
+                url
+            ),
+            response => bail!("expected network permission, but got {:?}", response),
+        }
+
+        info!("fetching {} {}", method, url);
+
+        let mut request = ureq::request(method, url);
+        request
+            .timeout_connect(FETCH_TIMEOUT_MS)
+            .timeout_read(FETCH_TIMEOUT_MS);
+        for (name, value) in headers {
+            request.set(name, value);
+        }
+        let response = match body {
+            Some(body) => request.send_string(body),
+            None => request.call(),
+        };
+        if let Some(e) = response.synthetic_error() {
+            bail!("failed to fetch \"{}\": {}", url, e);
+        }
+
+        let ok = response.ok();
+        let status = response.status();
+        let status_text = response.status_text().to_string();
+        let final_url = response.get_url().to_string();
+        let response_headers: Vec<(String, Option<String>)> = response
+            .headers_names()
+            .into_iter()
+            .map(|name| {
+                let value = response.header(&name).map(|value| value.to_string());
+                (name, value)
+            })
+            .collect();
+        let text = response
+            .into_string()
+            .map_err(|e| SimpleError::with(&format!("failed to read \"{}\"", url), e))?;
+
+        let header_fields: Vec<(&str, Option<&str>)> = response_headers
+            .iter()
+            .map(|(name, value)| (name.as_str(), value.as_deref()))
+            .collect();
+        let headers_val = Executor::string_object(scope, &header_fields);
+        let object = Executor::string_object(
+            scope,
+            &[
+                ("statusText", Some(status_text.as_str())),
+                ("url", Some(final_url.as_str())),
+                ("body", Some(text.as_str())),
+            ],
+        );
+
+        let ok_key = v8::String::new(scope, "ok").expect("failed to create JS string 'ok'");
+        let ok_val = v8::Boolean::new(scope, ok);
+        object.set(scope, ok_key.into(), ok_val.into());
+        let status_key =
+            v8::String::new(scope, "status").expect("failed to create JS string 'status'");
+        let status_val = v8::Integer::new(scope, status as i32);
+        object.set(scope, status_key.into(), status_val.into());
+        let headers_key =
+            v8::String::new(scope, "headers").expect("failed to create JS string 'headers'");
+        object.set(scope, headers_key.into(), headers_val.into());
+
+        let text_key = v8::String::new(scope, "text").expect("failed to create JS string 'text'");
+        let text_val = v8::Function::new(scope, Executor::response_text)
+            .expect("failed to create response_text function");
+        object.set(scope, text_key.into(), text_val.into());
+        let json_key = v8::String::new(scope, "json").expect("failed to create JS string 'json'");
+        let json_val = v8::Function::new(scope, Executor::response_json)
+            .expect("failed to create response_json function");
+        object.set(scope, json_key.into(), json_val.into());
+
+        Ok(object)
+    }
+
+    // the properties of an object as strings, e.g. the headers given to `fetch`, None if reading
+    // or converting one threw
+    fn string_pairs(
+        scope: &mut v8::HandleScope<'_>,
+        object: v8::Local<'_, v8::Object>,
+    ) -> Option<Vec<(String, String)>> {
+        let names = object.get_own_property_names(scope)?;
+
+        let mut pairs = Vec::new();
+        for i in 0..names.length() {
+            let index = v8::Integer::new(scope, i as i32);
+            let name = names.get(scope, index.into())?;
+            let value = object.get(scope, name)?;
+            let name = Executor::script_string(scope, name)?;
+            let value = Executor::script_string(scope, value)?;
+            pairs.push((name, value));
+        }
+
+        Some(pairs)
+    }
+
+    // `response.text()`, the body of the response `fetch` returned
+    fn response_text(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let body_key = v8::String::new(scope, "body").expect("failed to create JS string 'body'");
+        let body = args
+            .this()
+            .get(scope, body_key.into())
+            .unwrap_or_else(|| v8::undefined(scope).into());
+        rv.set(body);
+    }
+
+    // `response.json()`, the body of the response `fetch` returned parsed as JSON, parse errors are
+    // thrown to the script
+    fn response_json(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let body_key = v8::String::new(scope, "body").expect("failed to create JS string 'body'");
+        let body = args
+            .this()
+            .get(scope, body_key.into())
+            .and_then(|body| body.to_string(scope));
+        if let Some(json) = body.and_then(|body| v8::json::parse(scope, body)) {
+            rv.set(json);
+        }
+    }
+
+    // exports of a library script that was run when the startup snapshot was made, they're shared
+    // by everything that requires the library, as with Node's module cache
+    fn snapshot_library<'s>(
+        scope: &mut v8::HandleScope<'s>,
+        path: &str,
+    ) -> Option<v8::Local<'s, v8::Value>> {
+        let global = scope.get_current_context().global(scope);
+        let libraries_key =
+            v8::String::new(scope, snapshot::LIBRARIES_KEY).expect("failed to create JS string");
+        let libraries = global
+            .get(scope, libraries_key.into())
+            .and_then(|libraries| v8::Local::<v8::Object>::try_from(libraries).ok())?;
+
+        let path_key = v8::String::new(scope, path).expect("failed to create JS string");
+        libraries
+            .get(scope, path_key.into())
+            .filter(|exports| !exports.is_undefined())
+    }
+
+    // runs a CommonJS module, returning what it exports
+    pub fn run_commonjs<'s>(
+        scope: &mut v8::HandleScope<'s>,
+        raw_source: &str,
+    ) -> Option<v8::Local<'s, v8::Value>> {
+        let source = format!("{}{}{}", BOOP_WRAPPER_START, raw_source, BOOP_WRAPPER_END);
+
+        let code = v8::String::new(scope, &source).expect("failed to create JS string from source");
+
+        v8::Script::compile(scope, code, None)
+            .and_then(|compiled_script| compiled_script.run(scope))
+    }
+
+    fn compile_module<'s>(
+        scope: &mut v8::HandleScope<'s>,
+        name: &str,
+        source: &str,
+    ) -> Option<v8::Local<'s, v8::Module>> {
+        let resource_name = v8::String::new(scope, name).expect("failed to create JS string");
+        let resource_line_offset = v8::Integer::new(scope, 0);
+        let resource_column_offset = v8::Integer::new(scope, 0);
+        let resource_is_shared_cross_origin = v8::Boolean::new(scope, false);
+        let script_id = v8::Integer::new(scope, 0);
+        let source_map_url = v8::String::new(scope, "").expect("failed to create JS string");
+        let resource_is_opaque = v8::Boolean::new(scope, true);
+        let is_wasm = v8::Boolean::new(scope, false);
+        let is_module = v8::Boolean::new(scope, true);
+        let origin = v8::ScriptOrigin::new(
+            resource_name.into(),
+            resource_line_offset,
+            resource_column_offset,
+            resource_is_shared_cross_origin,
+            script_id,
+            source_map_url.into(),
+            resource_is_opaque,
+            is_wasm,
+            is_module,
+        );
+
+        let code = v8::String::new(scope, source).expect("failed to create JS string");
+        let source = v8::script_compiler::Source::new(code, &origin);
+        v8::script_compiler::compile_module(scope, source)
+    }
+
+    // called by V8 for each import while a module is instantiated
+    #[allow(unsafe_code)]
+    fn resolve_module<'s>(
+        context: v8::Local<'s, v8::Context>,
+        specifier: v8::Local<'s, v8::String>,
+        _referrer: v8::Local<'s, v8::Module>,
+    ) -> Option<v8::Local<'s, v8::Module>> {
+        // safe as V8 only calls this from within `instantiate_module`, on the isolate's thread
+        let scope = &mut unsafe { v8::CallbackScope::new(context) };
+        let specifier = specifier.to_rust_string_lossy(scope);
+        Executor::import_module(scope, &specifier)
+    }
+
+    // the module imported as `specifier`, loaded like `require` loads scripts, CommonJS modules
+    // are wrapped in an ES module exporting each of their exports by name, and all of them as
+    // the default export
+    fn import_module<'s>(
+        scope: &mut v8::HandleScope<'s>,
+        specifier: &str,
+    ) -> Option<v8::Local<'s, v8::Module>> {
+        let modules = scope
+            .get_slot::<Modules>()
+            .expect("failed to get modules slot")
+            .clone();
+        if let Some(module) = modules.borrow().get(specifier) {
+            return Some(module.get(scope));
+        }
+
+        info!("importing {}", specifier);
+
+        let raw_source = if specifier.contains("://") {
+            Err(SimpleError::new(format!(
+                "remote scripts can't be imported, use require(\"{}\") instead",
+                specifier
+            )))
+        } else if specifier.ends_with(".js") {
+            Executor::load_raw_source(specifier.to_string())
+        } else {
+            Executor::load_raw_source(format!("{}.js", specifier))
+        };
+
+        let raw_source = match raw_source {
+            Ok(raw_source) => raw_source,
+            Err(e) => {
+                warn!("problem importing script, {}", e);
+
+                let message = v8::String::new(scope, &e.to_string())
+                    .expect("failed to create JS string from error");
+                let exception = v8::Exception::error(scope, message);
+                scope.throw_exception(exception);
+                return None;
+            }
+        };
+
+        let module = if is_es_module(&raw_source) {
+            Executor::compile_module(scope, specifier, &raw_source)?
+        } else {
+            let path = format!("{}.js", specifier.trim_end_matches(".js"));
+            let exports = match Executor::snapshot_library(scope, &path) {
+                Some(exports) => exports,
+                None => Executor::run_commonjs(scope, &raw_source)?,
+            };
+
+            let global = scope.get_current_context().global(scope);
+            let exports_key =
+                v8::String::new(scope, COMMONJS_EXPORTS_KEY).expect("failed to create JS string");
+            let all_exports = match global
+                .get(scope, exports_key.into())
+                .and_then(|value| v8::Local::<v8::Object>::try_from(value).ok())
+            {
+                Some(all_exports) => all_exports,
+                None => {
+                    let all_exports = v8::Object::new(scope);
+                    global.set(scope, exports_key.into(), all_exports.into());
+                    all_exports
+                }
+            };
+            let specifier_key =
+                v8::String::new(scope, specifier).expect("failed to create JS string");
+            all_exports.set(scope, specifier_key.into(), exports);
+
+            let mut names = Vec::new();
+            if let Some(names_array) = v8::Local::<v8::Object>::try_from(exports)
+                .ok()
+                .and_then(|exports| exports.get_own_property_names(scope))
+            {
+                for index in 0..names_array.length() {
+                    if let Some(name) = names_array
+                        .get_index(scope, index)
+                        .and_then(|name| name.to_string(scope))
+                    {
+                        names.push(name.to_rust_string_lossy(scope));
+                    }
+                }
+            }
+
+            Executor::compile_module(scope, specifier, &commonjs_facade(specifier, &names))?
+        };
+
+        modules
+            .borrow_mut()
+            .insert(specifier.to_string(), v8::Global::new(scope, module));
+        Some(module)
+    }
+
+    fn console_log(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        rv: v8::ReturnValue<'_>,
+    ) {
+        Executor::console(scope, args, rv, ConsoleLevel::Log)
+    }
+
+    fn console_warn(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        rv: v8::ReturnValue<'_>,
+    ) {
+        Executor::console(scope, args, rv, ConsoleLevel::Warn)
+    }
+
+    fn console_error(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        rv: v8::ReturnValue<'_>,
+    ) {
+        Executor::console(scope, args, rv, ConsoleLevel::Error)
+    }
+
+    // arguments are joined with spaces, objects and arrays are shown as JSON
+    fn console(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+        level: ConsoleLevel,
+    ) {
+        let mut parts = Vec::new();
+        for index in 0..args.length() {
+            let arg = args.get(index);
+            let json = if arg.is_object() && !arg.is_function() && !arg.is_native_error() {
+                // e.g. circular objects can't be stringified, the exception isn't rethrown
+                let tc_scope = &mut v8::TryCatch::new(scope);
+                v8::json::stringify(tc_scope, arg).map(|json| json.to_rust_string_lossy(tc_scope))
+            } else {
+                None
+            };
+            let part = json
+                .or_else(|| arg.to_string(scope).map(|s| s.to_rust_string_lossy(scope)))
+                .unwrap_or_default();
+            parts.push(part);
+        }
+        let line = parts.join(" ");
+        debug!("console.{}: {}", level.name(), line);
+
+        let slot = scope
+            .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get status slot")
+            .clone();
+        let mut status = slot.borrow_mut();
+        if status.console_output.len() < MAX_CONSOLE_LINES {
+            status.console_output.push((level, line));
+        }
+
+        let undefined = v8::undefined(scope).into();
+        rv.set(undefined)
+    }
+
+    fn payload_post_info(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let info = match Executor::script_string(scope, args.get(0)) {
+            Some(info) => info,
+            None => return,
+        };
+
+        scope
+            .get_slot_mut::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get mutable access to status slot")
+            .borrow_mut()
+            .info
+            .replace(info);
+
+        let undefined = v8::undefined(scope).into();
+        rv.set(undefined)
+    }
+
+    fn payload_post_error(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let error = match Executor::script_string(scope, args.get(0)) {
+            Some(error) => error,
+            None => return,
+        };
+
+        scope
+            .get_slot_mut::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get mutable access to status slot")
+            .borrow_mut()
+            .error
+            .replace(error);
+
+        let undefined = v8::undefined(scope).into();
+        rv.set(undefined)
+    }
+
+    // parses the given text, or the payload's text, as JSON, invalid JSON is posted as an error
+    // saying where it's invalid and null is returned
+    fn payload_parse_json(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let slot = scope
+            .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get status slot")
+            .clone();
+
+        let text = if args.get(0).is_undefined() {
+            slot.borrow().text.read().clone()
+        } else {
+            match Executor::script_string(scope, args.get(0)) {
+                Some(text) => text,
+                None => return,
+            }
+        };
+
+        match json::parse(scope, &text) {
+            Ok(value) => rv.set(value),
+            Err(e) => {
+                info!("invalid JSON: {}", e);
+                slot.borrow_mut()
+                    .error
+                    .replace(format!("Invalid JSON: {}", e));
+
+                let null = v8::null(scope).into();
+                rv.set(null);
+            }
+        }
+    }
+
+    // like `JSON.stringify`, but indented by 2 spaces unless told otherwise, by a number of spaces
+    // or a string, with 0 or an empty string giving compact JSON
+    fn payload_stringify_json(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let indent = if args.get(1).is_undefined() {
+            "  ".to_string()
+        } else if args.get(1).is_number() {
+            let spaces = args
+                .get(1)
+                .number_value(scope)
+                .unwrap_or_default()
+                .max(0.0)
+                .min(MAX_JSON_INDENT as f64);
+            " ".repeat(spaces as usize)
+        } else {
+            match Executor::script_string(scope, args.get(1)) {
+                Some(indent) => indent.chars().take(MAX_JSON_INDENT).collect(),
+                None => return,
+            }
+        };
+
+        // values JSON can't represent give undefined, as with `JSON.stringify`
+        let value = args.get(0);
+        if value.is_undefined() || value.is_function() {
+            return;
+        }
+        // errors, e.g. from circular references, are thrown to the script
+        let compact = match v8::json::stringify(scope, value) {
+            Some(compact) => compact.to_rust_string_lossy(scope),
+            None => return,
+        };
+
+        let text = if indent.is_empty() {
+            compact
+        } else {
+            json::indent(&compact, &indent)
+        };
+        let text = v8::String::new(scope, &text).expect("failed to create JS string for JSON");
+        rv.set(text.into());
+    }
+
+    // e.g. `payload.postLanguage("json")`, ids are GtkSourceView's language ids
+    fn payload_post_language(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let language = match Executor::script_string(scope, args.get(0)) {
+            Some(language) => language,
+            None => return,
+        };
+
+        scope
+            .get_slot_mut::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get mutable access to status slot")
+            .borrow_mut()
+            .language
+            .replace(language.trim().to_lowercase());
+
+        let undefined = v8::undefined(scope).into();
+        rv.set(undefined)
+    }
+
+    fn payload_insert(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let text = match Executor::script_string(scope, args.get(0)) {
+            Some(text) => text,
+            None => return,
+        };
+        let at = if args.get(1).is_undefined() {
+            None
+        } else {
+            Some(args.get(1).integer_value(scope).unwrap_or(0).max(0) as usize)
+        };
+
+        scope
+            .get_slot_mut::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get mutable access to status slot")
+            .borrow_mut()
+            .insert
+            .push(Insertion { text, at });
+
+        let undefined = v8::undefined(scope).into();
+        rv.set(undefined)
+    }
+
+    fn payload_save_as(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let suggested_name = match Executor::script_string(scope, args.get(0)) {
+            Some(suggested_name) => suggested_name,
+            None => return,
+        };
+        let text = match Executor::script_string(scope, args.get(1)) {
+            Some(text) => text,
+            None => return,
+        };
+
+        info!(
+            "save requested for {} ({} bytes)",
+            suggested_name,
+            text.len()
+        );
+
+        scope
+            .get_slot_mut::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get mutable access to status slot")
+            .borrow_mut()
+            .save_as
+            .replace(SaveAs {
+                suggested_name,
+                text,
+            });
+
+        let undefined = v8::undefined(scope).into();
+        rv.set(undefined)
+    }
+
+    // asks the application to show a file chooser, returns { path, text, bytes } for the chosen file
+    // or null
+    fn payload_choose_file(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let filters = Executor::file_filters(scope, args.get(0));
+        info!("file requested, filters: {:?}", filters);
+
+        let host = scope
+            .get_slot::<Host>()
+            .expect("failed to get host slot")
+            .clone();
+        let chosen_file = match host(HostRequest::ChooseFile(filters)) {
+            HostResponse::ChosenFile(chosen_file) => chosen_file,
+            response => {
+                warn!("expected a chosen file, but got {:?}", response);
+                None
+            }
+        };
+
+        match chosen_file {
+            Some(chosen_file) => {
+                // the script may read and write the file with its path from now on
+                scope
+                    .get_slot::<Rc<RefCell<Sandbox>>>()
+                    .expect("failed to get sandbox slot")
+                    .borrow_mut()
+                    .grant(PathBuf::from(&chosen_file.path));
+
+                let text = String::from_utf8_lossy(&chosen_file.contents).into_owned();
+                let file = Executor::string_object(
+                    scope,
+                    &[
+                        ("path", Some(chosen_file.path.as_str())),
+                        ("text", Some(text.as_str())),
+                    ],
+                );
+
+                let bytes_key =
+                    v8::String::new(scope, "bytes").expect("failed to create JS string 'bytes'");
+                let bytes_val = Executor::uint8_array(scope, chosen_file.contents);
+                file.set(scope, bytes_key.into(), bytes_val.into());
+
+                rv.set(file.into());
+            }
+            None => {
+                let null = v8::null(scope).into();
+                rv.set(null);
+            }
+        }
+    }
+
+    // reads a file in the script's directory, or one the user chose, throwing if it can't
+    fn payload_read_file(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let path = match Executor::script_string(scope, args.get(0)) {
+            Some(path) => path,
+            None => return,
+        };
+
+        let result = scope
+            .get_slot::<Rc<RefCell<Sandbox>>>()
+            .expect("failed to get sandbox slot")
+            .borrow()
+            .read(&path);
+        match result {
+            Ok(text) => {
+                let text = v8::String::new(scope, &text).expect("failed to create JS string");
+                rv.set(text.into());
+            }
+            Err(e) => {
+                warn!("could not read {}: {}", path, e);
+
+                let message = v8::String::new(scope, &e.to_string())
+                    .expect("failed to create JS string from error");
+                let exception = v8::Exception::error(scope, message);
+                scope.throw_exception(exception);
+            }
+        }
+    }
+
+    // writes a file in the script's directory, or one the user chose, throwing if it can't
+    fn payload_write_file(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let path = match Executor::script_string(scope, args.get(0)) {
+            Some(path) => path,
+            None => return,
+        };
+        let text = match Executor::script_string(scope, args.get(1)) {
+            Some(text) => text,
+            None => return,
+        };
+        info!("write requested for {} ({} bytes)", path, text.len());
+
+        let result = scope
+            .get_slot::<Rc<RefCell<Sandbox>>>()
+            .expect("failed to get sandbox slot")
+            .borrow()
+            .write(&path, &text);
+        if let Err(e) = result {
+            warn!("could not write {}: {}", path, e);
+
+            let message = v8::String::new(scope, &e.to_string())
+                .expect("failed to create JS string from error");
+            let exception = v8::Exception::error(scope, message);
+            scope.throw_exception(exception);
+            return;
+        }
+
+        let undefined = v8::undefined(scope).into();
+        rv.set(undefined)
+    }
+
+    // asks the application to show a color picker, returns { hex, rgba } for the picked color or null
+    fn payload_pick_color(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let initial = Some(args.get(0))
+            .filter(|initial| initial.is_string())
+            .and_then(|initial| Executor::script_string(scope, initial));
+        info!("color requested, initial: {:?}", initial);
+
+        let host = scope
+            .get_slot::<Host>()
+            .expect("failed to get host slot")
+            .clone();
+        let picked_color = match host(HostRequest::PickColor(initial)) {
+            HostResponse::PickedColor(picked_color) => picked_color,
+            response => {
+                warn!("expected a picked color, but got {:?}", response);
+                None
+            }
+        };
+
+        match picked_color {
+            Some(picked_color) => {
+                let color = Executor::string_object(
+                    scope,
+                    &[
+                        ("hex", Some(picked_color.hex.as_str())),
+                        ("rgba", Some(picked_color.rgba.as_str())),
+                    ],
+                );
+                rv.set(color.into());
+            }
+            None => {
+                let null = v8::null(scope).into();
+                rv.set(null);
+            }
+        }
+    }
+
+    // asks the user for a value, shown with a label and starting as the default, returns the text
+    // they entered or null if they cancelled
+    fn payload_prompt(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let label = if !args.get(0).is_undefined() && !args.get(0).is_null() {
+            match Executor::script_string(scope, args.get(0)) {
+                Some(label) => label,
+                None => return,
+            }
+        } else {
+            "Value".to_string()
+        };
+        let default = if !args.get(1).is_undefined() && !args.get(1).is_null() {
+            match Executor::script_string(scope, args.get(1)) {
+                Some(default) => Some(default),
+                None => return,
+            }
+        } else {
+            None
+        };
+        info!("value requested: {}, default: {:?}", label, default);
+
+        let host = scope
+            .get_slot::<Host>()
+            .expect("failed to get host slot")
+            .clone();
+        let value = match host(HostRequest::Prompt(label, default)) {
+            HostResponse::Prompted(value) => value,
+            response => {
+                warn!("expected a value, but got {:?}", response);
+                None
+            }
+        };
+
+        match value {
+            Some(value) => {
+                let value = v8::String::new(scope, &value)
+                    .expect("failed to create JS string for prompted value");
+                rv.set(value.into());
+            }
+            None => {
+                let null = v8::null(scope).into();
+                rv.set(null);
+            }
+        }
+    }
+
+    // filters are given as an array of glob patterns, or objects with a name and patterns, e.g.
+    // ["*.txt", { name: "JSON", patterns: ["*.json"] }]
+    fn file_filters(
+        scope: &mut v8::HandleScope<'_>,
+        value: v8::Local<'_, v8::Value>,
+    ) -> Vec<FileFilter> {
+        let array = match v8::Local::<v8::Array>::try_from(value) {
+            Ok(array) => array,
+            Err(_) => return Vec::new(),
+        };
+
+        let name_key = v8::String::new(scope, "name").expect("failed to create JS string 'name'");
+        let patterns_key =
+            v8::String::new(scope, "patterns").expect("failed to create JS string 'patterns'");
+
+        let mut filters = Vec::new();
+        for i in 0..array.length() {
+            let index = v8::Integer::new(scope, i as i32);
+            let element = match array.get(scope, index.into()) {
+                Some(element) => element,
+                None => continue,
+            };
+
+            if element.is_string() {
+                let pattern = match Executor::script_string(scope, element) {
+                    Some(pattern) => pattern,
+                    None => continue,
+                };
+                filters.push(FileFilter {
+                    name: pattern.clone(),
+                    patterns: vec![pattern],
+                });
+            } else if let Ok(object) = v8::Local::<v8::Object>::try_from(element) {
+                let patterns = object
+                    .get(scope, patterns_key.into())
+                    .map(|patterns| Executor::file_filters(scope, patterns))
+                    .unwrap_or_default()
+                    .into_iter()
+                    .flat_map(|filter| filter.patterns)
+                    .collect::<Vec<String>>();
+                let name = object
+                    .get(scope, name_key.into())
+                    .filter(|name| name.is_string())
+                    .and_then(|name| Executor::script_string(scope, name))
+                    .unwrap_or_else(|| patterns.join(", "));
+
+                filters.push(FileFilter { name, patterns });
+            }
+        }
+
+        filters
+    }
+
+    pub fn uint8_array<'s>(
+        scope: &mut v8::HandleScope<'s>,
+        bytes: Vec<u8>,
+    ) -> v8::Local<'s, v8::Uint8Array> {
+        let length = bytes.len();
+
+        let backing_store =
+            v8::ArrayBuffer::new_backing_store_from_boxed_slice(bytes.into_boxed_slice())
+                .make_shared();
+        let array_buffer = v8::ArrayBuffer::with_backing_store(scope, &backing_store);
+
+        v8::Uint8Array::new(scope, array_buffer, 0, length).expect("failed to construct Uint8Array")
+    }
+
+    fn payload_add_artifact(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let name = match Executor::script_string(scope, args.get(0)) {
+            Some(name) => name,
+            None => return,
+        };
+        let text = match Executor::script_string(scope, args.get(1)) {
+            Some(text) => text,
+            None => return,
+        };
+
+        info!("artifact added: {} ({} bytes)", name, text.len());
+
+        let slot = scope
+            .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get status slot")
+            .clone();
+        let mut status = slot.borrow_mut();
+
+        // adding an artifact with the same name replaces it
+        status.artifacts.retain(|artifact| artifact.name != name);
+        status.artifacts.push(Artifact { name, text });
+
+        let undefined = v8::undefined(scope).into();
+        rv.set(undefined)
+    }
+
+    fn payload_highlight(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let start = args.get(0).integer_value(scope).unwrap_or(0).max(0) as usize;
+        let end = args.get(1).integer_value(scope).unwrap_or(0).max(0) as usize;
+        let kind = if args.get(2).is_undefined() {
+            "error".to_string()
+        } else {
+            match Executor::script_string(scope, args.get(2)) {
+                Some(kind) => kind,
+                None => return,
+            }
+        };
+
+        let slot = scope
+            .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get status slot")
+            .clone();
+        let mut status = slot.borrow_mut();
+
+        let kind = match kind.as_str() {
+            "error" => HighlightKind::Error,
+            "warning" => HighlightKind::Warning,
+            "info" => HighlightKind::Info,
+            _ => {
+                status.error.replace(format!(
+                    "unknown highlight \"{}\", expected error, warning or info",
+                    kind
+                ));
+                return;
+            }
+        };
+
+        status.highlights.push(Highlight {
+            start: start.min(end),
+            end: start.max(end),
+            kind,
+        });
+
+        let undefined = v8::undefined(scope).into();
+        rv.set(undefined)
+    }
+
+    fn payload_move_cursor(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let offset = args.get(0).integer_value(scope).unwrap_or(0).max(0) as usize;
+
+        scope
+            .get_slot_mut::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get mutable access to status slot")
+            .borrow_mut()
+            .cursor
+            .replace(offset);
+
+        let undefined = v8::undefined(scope).into();
+        rv.set(undefined)
+    }
+
+    fn payload_open_url(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let url = match Executor::script_string(scope, args.get(0)) {
+            Some(url) => url,
+            None => return,
+        };
+
+        let slot = scope
+            .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get status slot")
+            .clone();
+        let mut status = slot.borrow_mut();
+
+        // only web pages, other schemes could launch arbitrary programs
+        if url.starts_with("https://") || url.starts_with("http://") {
+            info!("open requested for {}", url);
+            status.open_url.replace(url);
+        } else {
+            warn!("refusing to open {}", url);
+            status.error.replace(format!(
+                "cannot open \"{}\", only web pages can be opened",
+                url
+            ));
+        }
+
+        let undefined = v8::undefined(scope).into();
+        rv.set(undefined)
+    }
+
+    // the text is copied once the script finishes, the last copy wins
+    fn payload_copy(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let text = match Executor::script_string(scope, args.get(0)) {
+            Some(text) => text,
+            None => return,
+        };
+        info!("copy requested, {} bytes", text.len());
+
+        scope
+            .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get status slot")
+            .borrow_mut()
+            .copy
+            .replace(text);
+
+        let undefined = v8::undefined(scope).into();
+        rv.set(undefined)
+    }
+
+    // `postPreview(markdownOrHtml)`, the last preview posted is shown
+    fn payload_post_preview(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let preview = match Executor::script_string(scope, args.get(0)) {
+            Some(preview) => preview,
+            None => return,
+        };
+        info!("preview posted, {} bytes", preview.len());
+
+        scope
+            .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get status slot")
+            .borrow_mut()
+            .preview
+            .replace(preview);
+
+        let undefined = v8::undefined(scope).into();
+        rv.set(undefined)
+    }
+
+    // `notify(title, body)`, only the first few notifications of each execution are sent so a
+    // script can't flood the desktop
+    fn payload_notify(
+        scope: &mut v8::HandleScope<'_>,
+        args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let title = match Executor::script_string(scope, args.get(0)) {
+            Some(title) => title,
+            None => return,
+        };
+        let body = if !args.get(1).is_undefined() && !args.get(1).is_null() {
+            match Executor::script_string(scope, args.get(1)) {
+                Some(body) => Some(body),
+                None => return,
+            }
+        } else {
+            None
+        };
+        info!("notification requested, {}", title);
+
+        {
+            let status_slot = scope
+                .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+                .expect("failed to get status slot");
+            let mut status = status_slot.borrow_mut();
+            if status.notifications.len() < MAX_NOTIFICATIONS {
+                status.notifications.push(Notification { title, body });
+            } else {
+                warn!("too many notifications, {} was dropped", title);
+            }
+        }
+
+        let undefined = v8::undefined(scope).into();
+        rv.set(undefined)
+    }
+
+    // returns the text on the clipboard, or null if there's none or the script isn't allowed to
+    // read it
+    fn payload_read_clipboard(
+        scope: &mut v8::HandleScope<'_>,
+        _args: v8::FunctionCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        info!("clipboard requested");
+
+        let host = scope
+            .get_slot::<Host>()
+            .expect("failed to get host slot")
+            .clone();
+        let text = match host(HostRequest::ReadClipboard) {
+            HostResponse::ClipboardText(text) => text,
+            response => {
+                warn!("expected the clipboard's text, but got {:?}", response);
+                None
+            }
+        };
+
+        match text {
+            Some(text) => {
+                let text = v8::String::new(scope, &text)
+                    .expect("failed to create JS string for clipboard text");
+                rv.set(text.into());
+            }
+            None => {
+                let null = v8::null(scope).into();
+                rv.set(null);
+            }
+        }
+    }
+
+    fn payload_full_text_getter(
+        scope: &mut v8::HandleScope<'_>,
+        _key: v8::Local<'_, v8::Name>,
+        _args: v8::PropertyCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let full_text = scope
+            .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get status slot")
+            .borrow()
+            .full_text
+            .read()
+            .clone();
+
+        rv.set(
+            v8::String::new(scope, &full_text)
+                .expect("failed to construct JS string from full_text")
+                .into(),
+        );
+    }
+
+    fn payload_full_text_setter(
+        scope: &mut v8::HandleScope<'_>,
+        _key: v8::Local<'_, v8::Name>,
+        value: v8::Local<'_, v8::Value>,
+        _args: v8::PropertyCallbackArguments<'_>,
+    ) {
+        let new_value = match Executor::script_string(scope, value) {
+            Some(new_value) => new_value,
+            None => return,
+        };
+
+        info!("setting full_text ({} bytes)", new_value.len());
+
+        let slot = scope
+            .get_slot_mut::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get mutable access to status slot");
+
+        let mut slot = slot.borrow_mut();
+
+        let full_text = slot.full_text.write();
+
+        *full_text = new_value;
+    }
+
+    fn payload_full_text_bytes_getter(
+        scope: &mut v8::HandleScope<'_>,
+        _key: v8::Local<'_, v8::Name>,
+        _args: v8::PropertyCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let bytes = scope
+            .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get status slot")
+            .borrow()
+            .full_text
+            .read()
+            .clone()
+            .into_bytes();
+
+        rv.set(Executor::uint8_array(scope, bytes).into());
+    }
+
+    fn payload_full_text_bytes_setter(
+        scope: &mut v8::HandleScope<'_>,
+        _key: v8::Local<'_, v8::Name>,
+        value: v8::Local<'_, v8::Value>,
+        _args: v8::PropertyCallbackArguments<'_>,
+    ) {
+        let slot = scope
+            .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get status slot")
+            .clone();
+
+        let view = match v8::Local::<v8::ArrayBufferView>::try_from(value) {
+            Ok(view) => view,
+            Err(_) => {
+                slot.borrow_mut()
+                    .error
+                    .replace("fullTextBytes must be set to a Uint8Array".to_string());
+                return;
+            }
+        };
+
+        let mut bytes = vec![0; view.byte_length()];
+        view.copy_contents(&mut bytes);
+
+        info!("setting full_text from bytes ({} bytes)", bytes.len());
+
+        match String::from_utf8(bytes) {
+            Ok(new_value) => {
+                let mut slot = slot.borrow_mut();
+                let full_text = slot.full_text.write();
+                *full_text = new_value;
+            }
+            Err(e) => {
+                slot.borrow_mut().error.replace(format!(
+                    "fullTextBytes is not valid UTF-8: {}",
+                    e.utf8_error()
+                ));
+            }
+        }
+    }
+
+    fn payload_text_getter(
+        scope: &mut v8::HandleScope<'_>,
+        _key: v8::Local<'_, v8::Name>,
+        _args: v8::PropertyCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let text = scope
+            .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get status slot")
+            .borrow()
+            .text
+            .read()
+            .clone();
+
+        rv.set(
+            v8::String::new(scope, &text)
+                .expect("faield to create JS string from text")
+                .into(),
+        );
+    }
+
+    fn payload_text_setter(
+        scope: &mut v8::HandleScope<'_>,
+        _key: v8::Local<'_, v8::Name>,
+        value: v8::Local<'_, v8::Value>,
+        _args: v8::PropertyCallbackArguments<'_>,
+    ) {
+        let new_value = match Executor::script_string(scope, value) {
+            Some(new_value) => new_value,
+            None => return,
+        };
+
+        info!("setting text ({} bytes)", new_value.len());
+
+        let slot = scope
+            .get_slot_mut::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("faield to get mutable access status slot");
+
+        let mut slot = slot.borrow_mut();
+
+        let text = slot.text.write();
+
+        *text = new_value;
+    }
+
+    fn payload_selection_getter(
+        scope: &mut v8::HandleScope<'_>,
+        _key: v8::Local<'_, v8::Name>,
+        _args: v8::PropertyCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let selection = scope
+            .get_slot::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get status slot")
+            .borrow()
+            .selection
+            .read()
+            .clone();
+
+        rv.set(
+            v8::String::new(scope, &selection)
+                .expect("problem constructing JS string")
+                .into(),
+        );
+    }
+
+    fn payload_selection_setter(
+        scope: &mut v8::HandleScope<'_>,
+        _key: v8::Local<'_, v8::Name>,
+        value: v8::Local<'_, v8::Value>,
+        _args: v8::PropertyCallbackArguments<'_>,
+    ) {
+        let new_value = match Executor::script_string(scope, value) {
+            Some(new_value) => new_value,
+            None => return,
+        };
+
+        info!("setting selection ({} bytes)", new_value.len());
+
+        let slot = scope
+            .get_slot_mut::<Rc<RefCell<ExecutionStatus>>>()
+            .expect("failed to get mutable access to status slot");
+
+        let mut slot = slot.borrow_mut();
+
+        let selection = slot.selection.write();
+
+        *selection = new_value;
+    }
+
+    // `payload.persistent` is the older name of `payload.state`, both are kept in the same file
+    fn payload_persistent_getter(
+        scope: &mut v8::HandleScope<'_>,
+        _key: v8::Local<'_, v8::Name>,
+        args: v8::PropertyCallbackArguments<'_>,
+        mut rv: v8::ReturnValue<'_>,
+    ) {
+        let state_key =
+            v8::String::new(scope, "state").expect("failed to create JS string 'state'");
+        let state = args
+            .this()
+            .get(scope, state_key.into())
+            .unwrap_or_else(|| v8::undefined(scope).into());
+        rv.set(state);
+    }
+
+    fn payload_persistent_setter(
+        scope: &mut v8::HandleScope<'_>,
+        _key: v8::Local<'_, v8::Name>,
+        value: v8::Local<'_, v8::Value>,
+        args: v8::PropertyCallbackArguments<'_>,
+    ) {
+        // setting undefined removes the state
+        let state_key =
+            v8::String::new(scope, "state").expect("failed to create JS string 'state'");
+        args.this().set(scope, state_key.into(), value);
+    }
+}
+
+// called by V8 when the heap is nearly full, the script is terminated and the limit is raised
+// so it can be stopped cleanly instead of the whole process being aborted
+extern "C" fn near_heap_limit(
+    _data: *mut c_void,
+    current_heap_limit: usize,
+    _initial_heap_limit: usize,
+) -> usize {
+    warn!("script is near its heap limit, terminating");
+
+    ISOLATE_HANDLE.with(|handle| {
+        if let Some(handle) = handle.borrow().as_ref() {
+            handle.terminate_execution();
+        }
+    });
+    HEAP_LIMIT_REACHED.with(|reached| reached.set(true));
+
+    current_heap_limit * 2
+}
+
+// true if the source uses import or export statements, so has to be loaded as an ES module
+pub fn is_es_module(source: &str) -> bool {
+    source.lines().map(str::trim_start).any(|line| {
+        (line.starts_with("import ") || line.starts_with("import{"))
+            || (line.starts_with("export ") || line.starts_with("export{"))
+    })
+}
+
+// source of an ES module exporting what a CommonJS module exported, names that aren't
+// identifiers can only be reached through the default export
+fn commonjs_facade(specifier: &str, names: &[String]) -> String {
+    let mut source = format!(
+        "const exports = globalThis.{}[{:?}];\nexport default exports;\n",
+        COMMONJS_EXPORTS_KEY, specifier
+    );
+
+    let is_identifier = |name: &str| {
+        name.chars().enumerate().all(|(index, c)| {
+            c == '_' || c == '$' || c.is_ascii_alphabetic() || (index > 0 && c.is_ascii_digit())
+        })
+    };
+    for (index, name) in names
+        .iter()
+        .filter(|name| !name.is_empty() && name.as_str() != "default" && is_identifier(name))
+        .enumerate()
+    {
+        source.push_str(&format!(
+            "const export{} = exports.{};\nexport {{ export{} as {} }};\n",
+            index, name, index, name
+        ));
+    }
+
+    source
+}
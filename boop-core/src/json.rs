@@ -0,0 +1,172 @@
+// JSON helpers for scripts, `payload.parseJSON` builds JS values straight from the parser so large
+// documents aren't parsed twice, and keys stay in the order they're written
+
+use rusty_v8 as v8;
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use std::fmt;
+
+// parses `text` into a JS value, errors say where the JSON is invalid, e.g. "expected `,` or `}` at
+// line 3 column 5"
+pub fn parse<'s>(
+    scope: &mut v8::HandleScope<'s>,
+    text: &str,
+) -> Result<v8::Local<'s, v8::Value>, serde_jsonrc::Error> {
+    let mut deserializer = serde_jsonrc::Deserializer::from_str(text);
+    let value = ValueSeed { scope }.deserialize(&mut deserializer)?;
+    deserializer.end()?;
+
+    Ok(value)
+}
+
+struct ValueSeed<'a, 's> {
+    scope: &'a mut v8::HandleScope<'s>,
+}
+
+impl<'de, 'a, 's> DeserializeSeed<'de> for ValueSeed<'a, 's> {
+    type Value = v8::Local<'s, v8::Value>;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de, 'a, 's> Visitor<'de> for ValueSeed<'a, 's> {
+    type Value = v8::Local<'s, v8::Value>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a JSON value")
+    }
+
+    fn visit_bool<E: de::Error>(self, value: bool) -> Result<Self::Value, E> {
+        Ok(v8::Boolean::new(self.scope, value).into())
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<Self::Value, E> {
+        Ok(v8::Number::new(self.scope, value as f64).into())
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<Self::Value, E> {
+        Ok(v8::Number::new(self.scope, value as f64).into())
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<Self::Value, E> {
+        Ok(v8::Number::new(self.scope, value).into())
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<Self::Value, E> {
+        v8::String::new(self.scope, value)
+            .map(|value| value.into())
+            .ok_or_else(|| E::custom("string is too long"))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<Self::Value, E> {
+        Ok(v8::null(self.scope).into())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let array = v8::Array::new(self.scope, 0);
+        let mut index = 0;
+        while let Some(element) = seq.next_element_seed(ValueSeed {
+            scope: &mut *self.scope,
+        })? {
+            array.set_index(self.scope, index, element);
+            index += 1;
+        }
+
+        Ok(array.into())
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let object = v8::Object::new(self.scope);
+        while let Some(key) = map.next_key::<String>()? {
+            let key = v8::String::new(self.scope, &key)
+                .ok_or_else(|| de::Error::custom("key is too long"))?;
+            let value = map.next_value_seed(ValueSeed {
+                scope: &mut *self.scope,
+            })?;
+            object.set(self.scope, key.into(), value);
+        }
+
+        Ok(object.into())
+    }
+}
+
+// indents compact JSON, e.g. from `JSON.stringify`, by `indent` for each level, empty objects and
+// arrays are kept on one line
+pub fn indent(compact: &str, indent: &str) -> String {
+    let mut pretty = String::with_capacity(compact.len() * 2);
+    let mut level = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    let mut chars = compact.chars().peekable();
+
+    let newline = |pretty: &mut String, level: usize| {
+        pretty.push('\n');
+        for _ in 0..level {
+            pretty.push_str(indent);
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        if in_string {
+            pretty.push(c);
+            if escaped {
+                escaped = false;
+            } else if c == '\\' {
+                escaped = true;
+            } else if c == '"' {
+                in_string = false;
+            }
+            continue;
+        }
+
+        match c {
+            '"' => {
+                in_string = true;
+                pretty.push(c);
+            }
+            '{' | '[' => {
+                pretty.push(c);
+                match chars.peek() {
+                    Some(&close) if close == '}' || close == ']' => {
+                        pretty.push(close);
+                        chars.next();
+                    }
+                    _ => {
+                        level += 1;
+                        newline(&mut pretty, level);
+                    }
+                }
+            }
+            '}' | ']' => {
+                level = level.saturating_sub(1);
+                newline(&mut pretty, level);
+                pretty.push(c);
+            }
+            ',' => {
+                pretty.push(c);
+                newline(&mut pretty, level);
+            }
+            ':' => pretty.push_str(": "),
+            c if c.is_whitespace() => {}
+            c => pretty.push(c),
+        }
+    }
+
+    pretty
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_indent() {
+        assert_eq!(
+            "{\n  \"a\": [\n    1,\n    {}\n  ],\n  \"b\": \"x, {y}: \\\"z\\\"\",\n  \"c\": []\n}",
+            indent(r#"{"a":[1,{}],"b":"x, {y}: \"z\"","c":[]}"#, "  ")
+        );
+        assert_eq!("[\n\ttrue\n]", indent("[true]", "\t"));
+        assert_eq!("\"a\"", indent("\"a\"", "  "));
+    }
+}
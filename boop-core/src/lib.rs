@@ -0,0 +1,72 @@
+//! The script engine behind Boop-GTK, without any of the UI or GTK, so other tools can run the
+//! same scripts.
+//!
+//! ```no_run
+//! use boop_core::catalog::ScriptCatalog;
+//!
+//! boop_core::init();
+//!
+//! let mut catalog = ScriptCatalog::load().expect("failed to load scripts");
+//! let script = catalog.find("Sort lines").expect("failed to find script");
+//! let sorted = script.run("b\na\n", None).expect("failed to run script");
+//! assert_eq!("a\nb\n", sorted);
+//! ```
+//!
+//! Scripts are loaded from Boop's scripts, the native scripts in [`builtin`], and the scripts
+//! directory in the user's config directory. [`script::Script::run`] runs a script on some text,
+//! optionally on a selection of it. It's `run` rather than `execute(text, selection)` as
+//! [`script::Script::execute`] was already taken by the form Boop-GTK uses, which with
+//! [`script::Script::start`] gives more control over how it's run and what the script can ask
+//! for.
+
+// rusty_v8 0.8 only offers unsafe constructors for the scope module imports are resolved in and
+// for the isolate a snapshot is made with, those two places allow it, nothing else may
+#![deny(unsafe_code)]
+
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate log;
+
+pub mod builtin;
+pub mod catalog;
+pub mod config;
+pub mod diff;
+pub mod executor;
+pub mod json;
+pub mod line_ending;
+pub mod native;
+pub mod permissions;
+pub mod pool;
+pub mod sandbox;
+pub mod script;
+pub mod snapshot;
+pub mod testing;
+
+use directories::ProjectDirs;
+use rust_embed::RustEmbed;
+use rusty_v8 as v8;
+use std::sync::Once;
+
+lazy_static! {
+    pub static ref PROJECT_DIRS: directories::ProjectDirs =
+        ProjectDirs::from("uk.co", "mrbenshef", "boop-gtk")
+            .expect("Unable to find a configuration location for your platform");
+}
+
+// Boop's scripts and the libraries they can require
+#[derive(RustEmbed)]
+#[folder = "../submodules/Boop/Boop/Boop/scripts/"]
+pub struct Scripts;
+
+static INIT: Once = Once::new();
+
+/// Initializes V8, this must be called before any script is run. It can be called more than
+/// once, only the first call does anything.
+pub fn init() {
+    INIT.call_once(|| {
+        v8::V8::initialize_platform(v8::new_default_platform().unwrap());
+        v8::V8::initialize();
+        info!("V8 initialized");
+    });
+}
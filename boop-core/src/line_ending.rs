@@ -0,0 +1,149 @@
+//! Line endings of text. Text from Windows ends its lines with "\r\n", and text pasted from
+//! several places can mix them, which confuses scripts that split lines on "\n". Scripts get the
+//! document's line ending as `payload.lineEnding`.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+    Cr,
+    /// More than one kind.
+    Mixed,
+}
+
+impl LineEnding {
+    /// The line ending every line of `text` ends with, `None` if the text is a single line.
+    pub fn detect(text: &str) -> Option<LineEnding> {
+        let mut line_breaks = line_breaks(text);
+        let first = line_breaks.next()?;
+
+        if line_breaks.all(|line_ending| line_ending == first) {
+            Some(first)
+        } else {
+            Some(LineEnding::Mixed)
+        }
+    }
+
+    /// The line ending most lines of `text` end with, LF if it's a single line. Ties go to the
+    /// first one found.
+    pub fn dominant(text: &str) -> LineEnding {
+        // in the order they're found
+        let mut counts: Vec<(LineEnding, usize)> = Vec::new();
+        for line_ending in line_breaks(text) {
+            match counts.iter_mut().find(|(kind, _)| *kind == line_ending) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((line_ending, 1)),
+            }
+        }
+
+        // max_by_key picks the last of equal counts
+        counts
+            .iter()
+            .rev()
+            .max_by_key(|(_, count)| *count)
+            .map_or(LineEnding::Lf, |(kind, _)| *kind)
+    }
+
+    /// The characters that end a line, mixed line endings are converted to "\n".
+    pub fn sequence(self) -> &'static str {
+        match self {
+            LineEnding::Lf | LineEnding::Mixed => "\n",
+            LineEnding::CrLf => "\r\n",
+            LineEnding::Cr => "\r",
+        }
+    }
+
+    /// "lf", "crlf", "cr" or "mixed", e.g. for `payload.document.lineEnding`.
+    pub fn id(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "lf",
+            LineEnding::CrLf => "crlf",
+            LineEnding::Cr => "cr",
+            LineEnding::Mixed => "mixed",
+        }
+    }
+
+    /// The line ending text can be converted to with `id`.
+    pub fn from_id(id: &str) -> Option<LineEnding> {
+        match id {
+            "lf" => Some(LineEnding::Lf),
+            "crlf" => Some(LineEnding::CrLf),
+            "cr" => Some(LineEnding::Cr),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LineEnding::Lf => write!(f, "LF"),
+            LineEnding::CrLf => write!(f, "CRLF"),
+            LineEnding::Cr => write!(f, "CR"),
+            LineEnding::Mixed => write!(f, "Mixed"),
+        }
+    }
+}
+
+/// Each line break in `text`, "\r\n" is one line break.
+pub fn line_breaks(text: &str) -> impl Iterator<Item = LineEnding> + '_ {
+    let bytes = text.as_bytes();
+    bytes
+        .iter()
+        .enumerate()
+        .filter_map(move |(index, byte)| match byte {
+            b'\n' if index > 0 && bytes[index - 1] == b'\r' => None,
+            b'\n' => Some(LineEnding::Lf),
+            b'\r' if bytes.get(index + 1) == Some(&b'\n') => Some(LineEnding::CrLf),
+            b'\r' => Some(LineEnding::Cr),
+            _ => None,
+        })
+}
+
+/// `text` with every line ending `line_ending`.
+pub fn convert(text: &str, line_ending: LineEnding) -> String {
+    let sequence = line_ending.sequence();
+    let mut converted = String::with_capacity(text.len());
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\r' => {
+                if chars.peek() == Some(&'\n') {
+                    chars.next();
+                }
+                converted.push_str(sequence);
+            }
+            '\n' => converted.push_str(sequence),
+            c => converted.push(c),
+        }
+    }
+    converted
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(None, LineEnding::detect("one line"));
+        assert_eq!(Some(LineEnding::Lf), LineEnding::detect("a\nb\n"));
+        assert_eq!(Some(LineEnding::CrLf), LineEnding::detect("a\r\nb\r\n"));
+        assert_eq!(Some(LineEnding::Cr), LineEnding::detect("a\rb"));
+        assert_eq!(Some(LineEnding::Mixed), LineEnding::detect("a\r\nb\nc"));
+
+        assert_eq!(LineEnding::Lf, LineEnding::dominant("one line"));
+        assert_eq!(LineEnding::CrLf, LineEnding::dominant("a\r\nb\nc\r\n"));
+        assert_eq!(LineEnding::Cr, LineEnding::dominant("a\rb\nc"));
+    }
+
+    #[test]
+    fn test_convert() {
+        assert_eq!("a\r\nb\r\nc\r\n", convert("a\nb\r\nc\r", LineEnding::CrLf));
+        assert_eq!("a\nb\n\nc", convert("a\r\nb\r\rc", LineEnding::Lf));
+        assert_eq!("a\rb", convert("a\nb", LineEnding::Cr));
+        assert_eq!("a\nb", convert("a\rb", LineEnding::Mixed));
+    }
+}
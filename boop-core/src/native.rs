@@ -0,0 +1,292 @@
+// `require("@boop/native")`, helpers for scripts written in Rust, shared with the builtin
+// transforms, data can be given as a string, which is encoded as UTF-8, or a Uint8Array, and
+// binary results are Uint8Arrays
+
+use crate::{
+    builtin::{self, ZlibFormat},
+    executor::Executor,
+};
+use rusty_v8 as v8;
+use std::convert::TryFrom;
+
+pub const MODULE_NAME: &str = "@boop/native";
+
+// longest array `randomBytes` makes
+const MAX_RANDOM_BYTES: usize = 1024 * 1024;
+
+pub fn module<'s>(scope: &mut v8::HandleScope<'s>) -> v8::Local<'s, v8::Object> {
+    let module = v8::Object::new(scope);
+    let functions: [(&str, v8::Local<v8::Function>); 13] = [
+        (
+            "hash",
+            v8::Function::new(scope, hash).expect("failed to create hash function"),
+        ),
+        (
+            "crc32",
+            v8::Function::new(scope, crc32).expect("failed to create crc32 function"),
+        ),
+        (
+            "gzip",
+            v8::Function::new(scope, gzip).expect("failed to create gzip function"),
+        ),
+        (
+            "gunzip",
+            v8::Function::new(scope, gunzip).expect("failed to create gunzip function"),
+        ),
+        (
+            "deflate",
+            v8::Function::new(scope, deflate).expect("failed to create deflate function"),
+        ),
+        (
+            "inflate",
+            v8::Function::new(scope, inflate).expect("failed to create inflate function"),
+        ),
+        (
+            "base32Encode",
+            v8::Function::new(scope, base32_encode)
+                .expect("failed to create base32Encode function"),
+        ),
+        (
+            "base32Decode",
+            v8::Function::new(scope, base32_decode)
+                .expect("failed to create base32Decode function"),
+        ),
+        (
+            "toASCII",
+            v8::Function::new(scope, to_ascii).expect("failed to create toASCII function"),
+        ),
+        (
+            "toUnicode",
+            v8::Function::new(scope, to_unicode).expect("failed to create toUnicode function"),
+        ),
+        (
+            "randomBytes",
+            v8::Function::new(scope, random_bytes).expect("failed to create randomBytes function"),
+        ),
+        (
+            "uuid",
+            v8::Function::new(scope, uuid).expect("failed to create uuid function"),
+        ),
+        (
+            "decodeUTF8",
+            v8::Function::new(scope, decode_utf8).expect("failed to create decodeUTF8 function"),
+        ),
+    ];
+    for (name, function) in &functions {
+        let key = v8::String::new(scope, name).expect("failed to create JS string");
+        module.set(scope, key.into(), (*function).into());
+    }
+
+    module
+}
+
+// the bytes of a string or Uint8Array argument
+fn bytes(
+    scope: &mut v8::HandleScope<'_>,
+    value: v8::Local<'_, v8::Value>,
+) -> Result<Vec<u8>, String> {
+    if let Ok(view) = v8::Local::<v8::ArrayBufferView>::try_from(value) {
+        let mut bytes = vec![0; view.byte_length()];
+        view.copy_contents(&mut bytes);
+        Ok(bytes)
+    } else {
+        Some(value)
+            .filter(|value| value.is_string())
+            .and_then(|value| Executor::script_string(scope, value))
+            .map(String::into_bytes)
+            .ok_or_else(|| "expected a string or a Uint8Array".to_string())
+    }
+}
+
+// sets the result, errors are thrown to the script
+fn set_string(
+    scope: &mut v8::HandleScope<'_>,
+    mut rv: v8::ReturnValue<'_>,
+    result: Result<String, String>,
+) {
+    match result {
+        Ok(text) => {
+            let text = v8::String::new(scope, &text).expect("failed to create JS string");
+            rv.set(text.into());
+        }
+        Err(e) => throw(scope, &e),
+    }
+}
+
+fn set_bytes(
+    scope: &mut v8::HandleScope<'_>,
+    mut rv: v8::ReturnValue<'_>,
+    result: Result<Vec<u8>, String>,
+) {
+    match result {
+        Ok(bytes) => rv.set(Executor::uint8_array(scope, bytes).into()),
+        Err(e) => throw(scope, &e),
+    }
+}
+
+fn throw(scope: &mut v8::HandleScope<'_>, message: &str) {
+    let message = v8::String::new(scope, message).expect("failed to create JS string from error");
+    let exception = v8::Exception::error(scope, message);
+    scope.throw_exception(exception);
+}
+
+// `hash(algorithm, data)`, the hex digest by "md5", "sha1", "sha256" or "sha512"
+fn hash(
+    scope: &mut v8::HandleScope<'_>,
+    args: v8::FunctionCallbackArguments<'_>,
+    rv: v8::ReturnValue<'_>,
+) {
+    let algorithm = match Executor::script_string(scope, args.get(0)) {
+        Some(algorithm) => algorithm,
+        None => return,
+    };
+    let result = bytes(scope, args.get(1)).and_then(|data| builtin::hash(&algorithm, &data));
+    set_string(scope, rv, result);
+}
+
+fn crc32(
+    scope: &mut v8::HandleScope<'_>,
+    args: v8::FunctionCallbackArguments<'_>,
+    mut rv: v8::ReturnValue<'_>,
+) {
+    match bytes(scope, args.get(0)) {
+        Ok(data) => {
+            let checksum = v8::Number::new(scope, builtin::crc32(&data) as f64);
+            rv.set(checksum.into());
+        }
+        Err(e) => throw(scope, &e),
+    }
+}
+
+fn gzip(
+    scope: &mut v8::HandleScope<'_>,
+    args: v8::FunctionCallbackArguments<'_>,
+    rv: v8::ReturnValue<'_>,
+) {
+    let result =
+        bytes(scope, args.get(0)).and_then(|data| builtin::compress(&data, ZlibFormat::Gzip));
+    set_bytes(scope, rv, result);
+}
+
+fn gunzip(
+    scope: &mut v8::HandleScope<'_>,
+    args: v8::FunctionCallbackArguments<'_>,
+    rv: v8::ReturnValue<'_>,
+) {
+    let result =
+        bytes(scope, args.get(0)).and_then(|data| builtin::decompress(&data, ZlibFormat::Gzip));
+    set_bytes(scope, rv, result);
+}
+
+// zlib, as made by `deflate` in most languages
+fn deflate(
+    scope: &mut v8::HandleScope<'_>,
+    args: v8::FunctionCallbackArguments<'_>,
+    rv: v8::ReturnValue<'_>,
+) {
+    let result =
+        bytes(scope, args.get(0)).and_then(|data| builtin::compress(&data, ZlibFormat::Zlib));
+    set_bytes(scope, rv, result);
+}
+
+fn inflate(
+    scope: &mut v8::HandleScope<'_>,
+    args: v8::FunctionCallbackArguments<'_>,
+    rv: v8::ReturnValue<'_>,
+) {
+    let result =
+        bytes(scope, args.get(0)).and_then(|data| builtin::decompress(&data, ZlibFormat::Zlib));
+    set_bytes(scope, rv, result);
+}
+
+fn base32_encode(
+    scope: &mut v8::HandleScope<'_>,
+    args: v8::FunctionCallbackArguments<'_>,
+    rv: v8::ReturnValue<'_>,
+) {
+    let result = bytes(scope, args.get(0)).map(|data| builtin::encode_base32(&data));
+    set_string(scope, rv, result);
+}
+
+fn base32_decode(
+    scope: &mut v8::HandleScope<'_>,
+    args: v8::FunctionCallbackArguments<'_>,
+    rv: v8::ReturnValue<'_>,
+) {
+    let text = match Executor::script_string(scope, args.get(0)) {
+        Some(text) => text,
+        None => return,
+    };
+    set_bytes(scope, rv, builtin::decode_base32(&text));
+}
+
+// `toASCII(domain)`, e.g. "münchen.de" to "xn--mnchen-3ya.de"
+fn to_ascii(
+    scope: &mut v8::HandleScope<'_>,
+    args: v8::FunctionCallbackArguments<'_>,
+    rv: v8::ReturnValue<'_>,
+) {
+    let domain = match Executor::script_string(scope, args.get(0)) {
+        Some(domain) => domain,
+        None => return,
+    };
+    set_string(scope, rv, builtin::punycode_encode(&domain));
+}
+
+fn to_unicode(
+    scope: &mut v8::HandleScope<'_>,
+    args: v8::FunctionCallbackArguments<'_>,
+    rv: v8::ReturnValue<'_>,
+) {
+    let domain = match Executor::script_string(scope, args.get(0)) {
+        Some(domain) => domain,
+        None => return,
+    };
+    set_string(scope, rv, builtin::punycode_decode(&domain));
+}
+
+// `randomBytes(length)`, not for keys or anything else that must stay secret
+fn random_bytes(
+    scope: &mut v8::HandleScope<'_>,
+    args: v8::FunctionCallbackArguments<'_>,
+    rv: v8::ReturnValue<'_>,
+) {
+    let length = args
+        .get(0)
+        .number_value(scope)
+        .filter(|length| *length >= 0.0 && *length <= MAX_RANDOM_BYTES as f64)
+        .ok_or_else(|| format!("length must be between 0 and {}", MAX_RANDOM_BYTES));
+    set_bytes(
+        scope,
+        rv,
+        length.map(|length| builtin::random_bytes(length as usize)),
+    );
+}
+
+// `uuid(version)`, version 4 unless 7 is asked for
+fn uuid(
+    scope: &mut v8::HandleScope<'_>,
+    args: v8::FunctionCallbackArguments<'_>,
+    rv: v8::ReturnValue<'_>,
+) {
+    let result = if args.get(0).is_undefined() {
+        Ok(builtin::new_uuid_v4())
+    } else {
+        match args.get(0).number_value(scope) {
+            Some(version) if version == 4.0 => Ok(builtin::new_uuid_v4()),
+            Some(version) if version == 7.0 => Ok(builtin::new_uuid_v7()),
+            _ => Err("only version 4 and 7 UUIDs can be made".to_string()),
+        }
+    };
+    set_string(scope, rv, result);
+}
+
+// `decodeUTF8(bytes)`, e.g. of what `gunzip` gives
+fn decode_utf8(
+    scope: &mut v8::HandleScope<'_>,
+    args: v8::FunctionCallbackArguments<'_>,
+    rv: v8::ReturnValue<'_>,
+) {
+    let result = bytes(scope, args.get(0)).and_then(builtin::into_text);
+    set_string(scope, rv, result);
+}
@@ -0,0 +1,155 @@
+use crate::config::Config;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use simple_error::SimpleError;
+use std::{collections::HashMap, path::Path};
+
+// identifies a script to the answers the user gave it, e.g. "/home/a/shout.js#3fa9...", a script
+// that's replaced or changed is asked again, even if it has the same name
+pub fn script_key(path: &Path, source: &str) -> String {
+    format!("{}#{:x}", path.display(), Sha256::digest(source.as_bytes()))
+}
+
+// what a script may ask to do, declared in its metadata, e.g. `"permissions": ["clipboard"]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Permission {
+    // reading files the user chooses
+    Filesystem,
+    // fetching urls
+    Network,
+    // reading the clipboard
+    Clipboard,
+}
+
+impl Permission {
+    pub const ALL: [Permission; 3] = [
+        Permission::Filesystem,
+        Permission::Network,
+        Permission::Clipboard,
+    ];
+
+    // e.g. "read files you choose", shown after the script's name
+    pub fn description(self) -> &'static str {
+        match self {
+            Permission::Filesystem => "read files you choose",
+            Permission::Network => "access the network",
+            Permission::Clipboard => "read the clipboard",
+        }
+    }
+
+    // the permission the payload function needs, None if any script may call it
+    pub fn required_by(payload_function: &str) -> Option<Permission> {
+        match payload_function {
+            "chooseFile" => Some(Permission::Filesystem),
+            "readClipboard" => Some(Permission::Clipboard),
+            _ => None,
+        }
+    }
+}
+
+// answers the user gave when scripts asked to do something that needs permission, kept in
+// `permissions` in config.json so each question is only asked once
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Permissions {
+    // urls `require` may load code from, mapped to whether the user allowed it
+    pub remote_require: HashMap<String, bool>,
+    // scripts that may read files the user chooses, by `script_key`
+    pub read_files: HashMap<String, bool>,
+    // scripts that may open web pages in the default browser, by `script_key`
+    pub open_urls: HashMap<String, bool>,
+    // scripts that may read the clipboard, by `script_key`
+    pub read_clipboard: HashMap<String, bool>,
+    // scripts that may fetch urls, by `script_key`
+    pub network: HashMap<String, bool>,
+}
+
+impl Permissions {
+    pub fn load(config_dir: &Path) -> Self {
+        Config::load(config_dir).permissions
+    }
+
+    pub fn save(&self, config_dir: &Path) -> Result<(), SimpleError> {
+        Config::update(config_dir, |config| config.permissions = self.clone())
+    }
+
+    // answers for each script, by `script_key`
+    pub fn answers(&self, permission: Permission) -> &HashMap<String, bool> {
+        match permission {
+            Permission::Filesystem => &self.read_files,
+            Permission::Network => &self.network,
+            Permission::Clipboard => &self.read_clipboard,
+        }
+    }
+
+    pub fn answers_mut(&mut self, permission: Permission) -> &mut HashMap<String, bool> {
+        match permission {
+            Permission::Filesystem => &mut self.read_files,
+            Permission::Network => &mut self.network,
+            Permission::Clipboard => &mut self.read_clipboard,
+        }
+    }
+
+    // the permissions a script declared that the user hasn't refused, the user is asked the first
+    // time the script uses each of them
+    pub fn granted(&self, script_key: &str, declared: &[Permission]) -> Vec<Permission> {
+        declared
+            .iter()
+            .copied()
+            .filter(|permission| self.answers(*permission).get(script_key) != Some(&false))
+            .collect()
+    }
+
+    // forgets the user's answer, so they're asked again the next time the script needs it
+    pub fn forget(&mut self, permission: Permission, script_key: &str) {
+        self.answers_mut(permission).remove(script_key);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_granted() {
+        let mut permissions = Permissions::default();
+        let declared = [Permission::Filesystem, Permission::Clipboard];
+        assert_eq!(declared.to_vec(), permissions.granted("Shout", &declared));
+
+        permissions
+            .answers_mut(Permission::Clipboard)
+            .insert("Shout".to_string(), false);
+        permissions
+            .answers_mut(Permission::Network)
+            .insert("Shout".to_string(), true);
+        assert_eq!(
+            vec![Permission::Filesystem],
+            permissions.granted("Shout", &declared)
+        );
+        assert_eq!(declared.to_vec(), permissions.granted("Whisper", &declared));
+
+        permissions.forget(Permission::Clipboard, "Shout");
+        assert_eq!(declared.to_vec(), permissions.granted("Shout", &declared));
+
+        assert_eq!(
+            Some(Permission::Filesystem),
+            Permission::required_by("chooseFile")
+        );
+        assert_eq!(None, Permission::required_by("postInfo"));
+    }
+
+    #[test]
+    fn test_script_key() {
+        let path = Path::new("/home/a/shout.js");
+        let key = script_key(path, "function main(state) {}");
+
+        assert!(key.starts_with("/home/a/shout.js#"));
+        assert_eq!(key, script_key(path, "function main(state) {}"));
+        assert_ne!(key, script_key(path, "function main(state) { fetch() }"));
+        assert_ne!(
+            key,
+            script_key(Path::new("/home/a/whisper.js"), "function main(state) {}")
+        );
+    }
+}
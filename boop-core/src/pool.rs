@@ -0,0 +1,177 @@
+// threads with an isolate already created, so a script that hasn't been run yet doesn't wait for
+// V8 to set one up, another is warmed up each time one is taken
+
+use crate::executor::Executor;
+use crossbeam::crossbeam_channel::{bounded, Sender};
+use rusty_v8 as v8;
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+// isolates kept waiting for each heap limit scripts are run with
+const WARM_ISOLATES: usize = 2;
+
+// run on the thread the isolate was created on, isolates can't move between threads
+type Job = Box<dyn FnOnce(v8::OwnedIsolate) + Send>;
+
+struct WarmThread {
+    heap_limit: usize,
+    sender: Sender<Job>,
+    // heap the waiting isolate uses, 0 until it's created
+    heap_size: Arc<AtomicUsize>,
+}
+
+lazy_static! {
+    static ref WARM_THREADS: Mutex<Vec<WarmThread>> = Mutex::new(Vec::new());
+}
+
+// runs `job` with an isolate whose heap may grow to `heap_limit` bytes, on a warm thread if there
+// is one and a new thread otherwise
+pub fn spawn(heap_limit: usize, job: impl FnOnce(v8::OwnedIsolate) + Send + 'static) {
+    let warm_thread = {
+        let mut threads = WARM_THREADS.lock().expect("warm threads lock is poisoned");
+        threads
+            .iter()
+            .position(|thread| thread.heap_limit == heap_limit)
+            .map(|index| threads.remove(index))
+    };
+
+    let job: Job = Box::new(job);
+    let job = match warm_thread {
+        Some(warm_thread) => warm_thread.sender.send(job).err().map(|e| e.into_inner()),
+        None => Some(job),
+    };
+    if let Some(job) = job {
+        thread::spawn(move || job(Executor::isolate(heap_limit)));
+    }
+
+    warm_up(heap_limit);
+}
+
+// starts threads until there are `WARM_ISOLATES` waiting with isolates for `heap_limit`
+pub fn warm_up(heap_limit: usize) {
+    let mut threads = WARM_THREADS.lock().expect("warm threads lock is poisoned");
+    let warm = threads
+        .iter()
+        .filter(|thread| thread.heap_limit == heap_limit)
+        .count();
+
+    for _ in warm..WARM_ISOLATES {
+        let (sender, receiver) = bounded::<Job>(1);
+        let heap_size = Arc::new(AtomicUsize::new(0));
+        let t_heap_size = heap_size.clone();
+        thread::spawn(move || {
+            let mut isolate = Executor::isolate(heap_limit);
+            let mut heap_statistics = v8::HeapStatistics::default();
+            isolate.get_heap_statistics(&mut heap_statistics);
+            t_heap_size.store(heap_statistics.used_heap_size(), Ordering::SeqCst);
+            debug!("isolate warmed up");
+
+            // the sender is only dropped without sending when the application exits
+            if let Ok(job) = receiver.recv() {
+                job(isolate);
+            }
+        });
+        threads.push(WarmThread {
+            heap_limit,
+            sender,
+            heap_size,
+        });
+    }
+}
+
+// heap used by the isolates waiting to be taken, counted towards the executors' memory budget
+pub fn heap_size() -> usize {
+    WARM_THREADS
+        .lock()
+        .expect("warm threads lock is poisoned")
+        .iter()
+        .map(|thread| thread.heap_size.load(Ordering::SeqCst))
+        .sum()
+}
+
+// drops the waiting isolates, their threads stop once they see the sender is gone, the pool is
+// warmed up again the next time an isolate is taken
+pub fn evict() {
+    WARM_THREADS
+        .lock()
+        .expect("warm threads lock is poisoned")
+        .clear();
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::script::tests::setup;
+    use std::time::Duration;
+
+    lazy_static! {
+        // held by tests that count the warm isolates or evict them
+        pub(crate) static ref POOL_LOCK: Mutex<()> = Mutex::new(());
+    }
+
+    #[test]
+    fn test_spawn() {
+        let _guard = setup();
+        let _pool = POOL_LOCK.lock().unwrap();
+
+        // not used by other tests, so none of their threads are taken
+        let heap_limit = 64 * 1024 * 1024;
+        let (sender, receiver) = bounded(0);
+        for _ in 0..WARM_ISOLATES + 1 {
+            let sender = sender.clone();
+            spawn(heap_limit, move |mut isolate| {
+                let scope = &mut v8::HandleScope::new(&mut isolate);
+                let context = v8::Context::new(scope);
+                let scope = &mut v8::ContextScope::new(scope, context);
+                let code = v8::String::new(scope, "1 + 1").unwrap();
+                let result = v8::Script::compile(scope, code, None)
+                    .and_then(|script| script.run(scope))
+                    .and_then(|result| result.integer_value(scope));
+                sender.send(result).unwrap();
+            });
+        }
+
+        for _ in 0..WARM_ISOLATES + 1 {
+            assert_eq!(Some(2), receiver.recv().unwrap());
+        }
+        let warm = WARM_THREADS
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|thread| thread.heap_limit == heap_limit)
+            .count();
+        assert_eq!(WARM_ISOLATES, warm);
+    }
+
+    #[test]
+    fn test_evict() {
+        let _guard = setup();
+        let _pool = POOL_LOCK.lock().unwrap();
+
+        // not used by other tests
+        let heap_limit = 96 * 1024 * 1024;
+        warm_up(heap_limit);
+        let warm_heap_size = || -> Vec<usize> {
+            WARM_THREADS
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|thread| thread.heap_limit == heap_limit)
+                .map(|thread| thread.heap_size.load(Ordering::SeqCst))
+                .collect()
+        };
+        while warm_heap_size().contains(&0) {
+            thread::sleep(Duration::from_millis(1));
+        }
+        assert_eq!(WARM_ISOLATES, warm_heap_size().len());
+        assert!(heap_size() >= warm_heap_size().iter().sum());
+
+        evict();
+        assert!(warm_heap_size().is_empty());
+    }
+}
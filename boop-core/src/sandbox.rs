@@ -0,0 +1,216 @@
+//! Files scripts can read and write with `payload.readFile` and `payload.writeFile`, each script
+//! has its own directory, and can also use files the user chose for it while it's loaded.
+
+use sha2::{Digest, Sha256};
+use simple_error::{bail, SimpleError};
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+// in the data directory, with a directory for each script
+const SANDBOX_DIR: &str = "sandbox";
+// larger files are refused, so a script can't fill the disk or the isolate's heap by mistake
+pub const MAX_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The directory `script_name` may use in `data_dir`, named after the script with anything but
+/// letters, numbers, '-' and '_' replaced by '_', and a hash of the name so scripts whose names
+/// only differ by those characters don't share it.
+pub fn script_dir(data_dir: &Path, script_name: &str) -> PathBuf {
+    let name: String = script_name
+        .trim()
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let hash = format!("{:x}", Sha256::digest(script_name.as_bytes()));
+
+    data_dir
+        .join(SANDBOX_DIR)
+        .join(format!("{}-{}", name, &hash[..8]))
+}
+
+// `path` with any links resolved, the parts of it that don't exist yet are kept as they are as
+// they can't be links, unless they're links to nothing
+fn canonicalize_existing(path: &Path) -> io::Result<PathBuf> {
+    let mut existing = path;
+    let mut missing = Vec::new();
+    loop {
+        match existing.canonicalize() {
+            Ok(canonical) => {
+                return Ok(missing
+                    .iter()
+                    .rev()
+                    .fold(canonical, |canonical, name| canonical.join(name)))
+            }
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                if fs::symlink_metadata(existing).is_ok() {
+                    return Err(e);
+                }
+                match (existing.parent(), existing.file_name()) {
+                    (Some(parent), Some(name)) => {
+                        missing.push(name);
+                        existing = parent;
+                    }
+                    _ => return Err(e),
+                }
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// The files a script may use.
+#[derive(Debug, Default)]
+pub struct Sandbox {
+    // None for scripts run without a sandbox, e.g. by the self-tests
+    dir: Option<PathBuf>,
+    // files the user chose for the script
+    granted: Vec<PathBuf>,
+}
+
+impl Sandbox {
+    pub fn new(dir: PathBuf) -> Self {
+        Sandbox {
+            dir: Some(dir),
+            granted: Vec::new(),
+        }
+    }
+
+    /// Lets the script use `path` by its absolute path, until it's reloaded.
+    pub fn grant(&mut self, path: PathBuf) {
+        if !self.granted.contains(&path) {
+            self.granted.push(path);
+        }
+    }
+
+    /// The file `path` refers to, a path relative to the script's directory that stays inside it,
+    /// or the absolute path of a file the user chose.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, SimpleError> {
+        let requested = Path::new(path);
+        if requested.is_absolute() {
+            if self.granted.iter().any(|granted| granted == requested) {
+                return Ok(requested.to_path_buf());
+            }
+            bail!("\"{}\" wasn't chosen by the user", path);
+        }
+
+        let dir = match &self.dir {
+            Some(dir) => dir,
+            None => bail!("scripts can't use files here"),
+        };
+        if path.is_empty()
+            || requested
+                .components()
+                .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            bail!("\"{}\" isn't in the script's directory", path);
+        }
+        let resolved = dir.join(requested);
+
+        // a link could point anywhere, including a link to a directory the file would be created in
+        match (canonicalize_existing(dir), canonicalize_existing(&resolved)) {
+            (Ok(dir), Ok(target)) if target.starts_with(&dir) => {}
+            _ => bail!("\"{}\" isn't in the script's directory", path),
+        }
+
+        Ok(resolved)
+    }
+
+    pub fn read(&self, path: &str) -> Result<String, SimpleError> {
+        let resolved = self.resolve(path)?;
+
+        let size = fs::metadata(&resolved)
+            .map_err(|e| SimpleError::with(&format!("could not read \"{}\"", path), e))?
+            .len();
+        if size > MAX_FILE_SIZE {
+            bail!("\"{}\" is larger than {} bytes", path, MAX_FILE_SIZE);
+        }
+
+        fs::read_to_string(&resolved)
+            .map_err(|e| SimpleError::with(&format!("could not read \"{}\"", path), e))
+    }
+
+    // directories in the script's directory are created as needed
+    pub fn write(&self, path: &str, text: &str) -> Result<(), SimpleError> {
+        if text.len() as u64 > MAX_FILE_SIZE {
+            bail!("\"{}\" would be larger than {} bytes", path, MAX_FILE_SIZE);
+        }
+        let resolved = self.resolve(path)?;
+
+        if let Some(parent) = resolved.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| SimpleError::with(&format!("could not write \"{}\"", path), e))?;
+        }
+        fs::write(&resolved, text)
+            .map_err(|e| SimpleError::with(&format!("could not write \"{}\"", path), e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sandbox() {
+        let data_dir =
+            std::env::temp_dir().join(format!("boop-gtk-sandbox-{}", std::process::id()));
+        let dir = script_dir(&data_dir, "Load ../Mapping");
+        assert_eq!(
+            data_dir.join("sandbox").join("Load____Mapping-6be1bdd8"),
+            dir
+        );
+        assert_ne!(script_dir(&data_dir, "a/b"), script_dir(&data_dir, "a_b"));
+
+        let mut sandbox = Sandbox::new(dir.clone());
+        sandbox.write("tables/mapping.json", "{}").unwrap();
+        assert_eq!("{}", sandbox.read("tables/mapping.json").unwrap());
+        assert!(dir.join("tables").join("mapping.json").is_file());
+
+        assert!(sandbox.read("../other/secret.txt").is_err());
+        assert!(sandbox.write("", "text").is_err());
+        assert!(sandbox.read("missing.txt").is_err());
+
+        let chosen = data_dir.join("chosen.txt");
+        fs::write(&chosen, "chosen").unwrap();
+        let chosen_path = chosen.display().to_string();
+        assert!(sandbox.read(&chosen_path).is_err());
+        sandbox.grant(chosen.clone());
+        assert_eq!("chosen", sandbox.read(&chosen_path).unwrap());
+
+        assert!(Sandbox::default().read("mapping.json").is_err());
+
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sandbox_links() {
+        let data_dir =
+            std::env::temp_dir().join(format!("boop-gtk-sandbox-links-{}", std::process::id()));
+        let dir = script_dir(&data_dir, "Links");
+        let outside = data_dir.join("outside");
+        fs::create_dir_all(&dir).unwrap();
+        fs::create_dir_all(&outside).unwrap();
+        std::os::unix::fs::symlink(&outside, dir.join("link")).unwrap();
+        std::os::unix::fs::symlink(outside.join("missing.txt"), dir.join("dangling.txt")).unwrap();
+
+        let sandbox = Sandbox::new(dir.clone());
+        assert!(sandbox.write("link/new.txt", "text").is_err());
+        assert!(sandbox.write("link/nested/new.txt", "text").is_err());
+        assert!(sandbox.write("dangling.txt", "text").is_err());
+        assert!(!outside.join("new.txt").exists());
+        assert!(!outside.join("nested").exists());
+        assert!(!outside.join("missing.txt").exists());
+
+        sandbox.write("inside/new.txt", "text").unwrap();
+        assert_eq!("text", sandbox.read("inside/new.txt").unwrap());
+
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+}
@@ -0,0 +1,3220 @@
+use crate::builtin::Transform;
+use crate::catalog::REMOTE_DIR;
+use crate::config::Config;
+use crate::executor::{
+    find_selection, headless_host, DocumentContext, ExecutionStatus, Executor, HostRequest,
+    HostResponse, ScriptError, DEFAULT_HEAP_LIMIT,
+};
+use crate::permissions::{self, Permission, Permissions};
+use crate::pool;
+use crate::sandbox;
+use crate::PROJECT_DIRS;
+use crossbeam::crossbeam_channel::{bounded, RecvTimeoutError, TryRecvError};
+use crossbeam::{Receiver, Sender};
+use serde::Deserialize;
+use simple_error::{bail, SimpleError};
+
+// results kept for each pure script, the cache is emptied once it's full
+const MAX_CACHED_RESULTS: usize = 32;
+// how long to wait for a terminated script to stop before terminating it again
+const TERMINATE_RETRY: Duration = Duration::from_millis(100);
+// how often a script that's being waited for is checked to see if its executor crashed
+const CRASH_CHECK_INTERVAL: Duration = Duration::from_millis(250);
+use std::{
+    borrow::Cow,
+    collections::{hash_map::DefaultHasher, HashMap},
+    fmt, fs,
+    hash::{Hash, Hasher},
+    io::{self, BufRead, BufReader},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+pub struct Script {
+    pub metadata: Metadata,
+    pub path: PathBuf,
+    // None until a script loaded from a file is first run, only its metadata is read at startup
+    source: Option<String>,
+    // `permissions::script_key` of the source, once it's loaded
+    permission_key: Option<String>,
+    // metadata the script was loaded with, the JSON between `/**` and `**/`
+    header: String,
+    channel: Option<ExecutorChannel>,
+
+    // heap used by the executor after its last execution, 0 if it has no executor
+    heap_size: usize,
+    last_used: Option<Instant>,
+
+    // error from initializing the script or its last execution, shared with the executor thread
+    last_error: Arc<Mutex<Option<String>>>,
+    // set by the executor thread once the isolate is created, used to stop scripts that time out
+    isolate_handle: Arc<Mutex<Option<rusty_v8::IsolateHandle>>>,
+    // set if the executor thread panicked, it's replaced when the script is next run
+    crashed: Arc<AtomicBool>,
+    // makes the executor thread panic when it receives the next request
+    #[cfg(test)]
+    panic_next: Arc<AtomicBool>,
+    running: Option<Running>,
+
+    // results of pure scripts, keyed by a hash of the script and its input
+    results: HashMap<u64, ExecutionStatus>,
+    // cached result returned by the next `poll`
+    ready: Option<ExecutionStatus>,
+
+    // JSON of the object the script keeps in `payload.state`
+    state: Option<String>,
+    // permissions the script declared that the user hasn't refused
+    granted: Vec<Permission>,
+
+    // bytes the executor's heap may grow to before the script is stopped
+    heap_limit: usize,
+
+    // run in place of an executor by builtin transforms
+    transform: Option<Box<dyn Transform>>,
+}
+#[derive(Debug)]
+enum ExecutorJob {
+    Request(
+        (
+            String,
+            Option<String>,
+            DocumentContext,
+            Option<String>,
+            Vec<Permission>,
+        ),
+    ),
+    Responce(ExecutionStatus),
+    // sent by the executor thread while a script is running, answered with a HostResponse
+    HostRequest(HostRequest),
+    HostResponse(HostResponse),
+    Kill,
+}
+
+struct ExecutorChannel {
+    sender: Sender<ExecutorJob>,
+    receiver: Receiver<ExecutorJob>,
+}
+
+// a request that was sent to the executor but hasn't been responded to yet
+struct Running {
+    timeout: Duration,
+    // when the script is terminated, moved back each time the script makes a request
+    deadline: Instant,
+    terminated: bool,
+    // stopped by the user, the result is discarded
+    cancelled: bool,
+    // where the result is cached, if the script is pure
+    cache_key: Option<u64>,
+}
+
+#[derive(Debug)]
+pub enum ParseScriptError {
+    NoMetadata,
+    InvalidMetadata(serde_jsonrc::error::Error),
+    MissingName,
+    FailedToRead(std::io::Error),
+}
+
+impl fmt::Display for ParseScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseScriptError::NoMetadata => write!(f, "no metadata"),
+            ParseScriptError::InvalidMetadata(e) => write!(f, "invalid metadata: {}", e),
+            ParseScriptError::MissingName => write!(f, "metadata has no name"),
+            ParseScriptError::FailedToRead(e) => write!(f, "failed to read script: {}", e),
+        }
+    }
+}
+
+// fields scripts can set in their metadata, others are ignored with a warning as they're most
+// likely misspelt
+const METADATA_FIELDS: &[&str] = &[
+    "api",
+    "name",
+    "description",
+    "author",
+    "icon",
+    "tags",
+    "bias",
+    "shortcut",
+    "timeout",
+    "pure",
+    "tests",
+    "outputLanguage",
+    "network",
+    "stream",
+    "permissions",
+];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Metadata {
+    pub api: u32,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    pub author: Option<String>,
+    #[serde(default)]
+    pub icon: String,
+    pub tags: Option<String>,
+    // raises (or lowers if negative) the script in search results, most useful for short queries
+    #[serde(default)]
+    pub bias: f64,
+    // suggested shortcut, accelerators separated by spaces, e.g. "<Primary>K F"
+    pub shortcut: Option<String>,
+    // seconds the script may run for, overrides the default in the config up to its maximum
+    pub timeout: Option<u64>,
+    // the script always gives the same result for the same input, so results can be reused
+    #[serde(default)]
+    pub pure: bool,
+    // examples of input and expected output, run by `boop-gtk --test-scripts`
+    #[serde(default)]
+    pub tests: Vec<Fixture>,
+    // id of the GtkSourceView language the result is highlighted as, e.g. "json"
+    #[serde(rename = "outputLanguage")]
+    pub output_language: Option<String>,
+    // the script may use `fetch`, once the user allows it, the same as declaring the "network"
+    // permission
+    #[serde(default)]
+    pub network: bool,
+    // `processLine` is called with each line instead of calling `main` with the whole text
+    #[serde(default)]
+    pub stream: bool,
+    // what the script may ask the user to let it do, the payload functions that need permission
+    // are only set for scripts that declare it
+    #[serde(default)]
+    pub permissions: Vec<Permission>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Fixture {
+    pub input: String,
+    pub selection: Option<String>,
+    pub expected: String,
+}
+
+impl Metadata {
+    // parses the metadata at the top of a script
+    pub fn from_source(source: &str) -> Result<(Self, Vec<String>), ParseScriptError> {
+        Metadata::parse(header(source).ok_or(ParseScriptError::NoMetadata)?)
+    }
+
+    // parses the JSON between `/**` and `**/`, returning the metadata and warnings about fields
+    // that were ignored
+    pub fn parse(header: &str) -> Result<(Self, Vec<String>), ParseScriptError> {
+        let value: serde_jsonrc::Value =
+            serde_jsonrc::from_str(header).map_err(ParseScriptError::InvalidMetadata)?;
+
+        let has_name = value
+            .get("name")
+            .and_then(serde_jsonrc::Value::as_str)
+            .map(|name| !name.trim().is_empty())
+            .unwrap_or(false);
+        if !has_name {
+            return Err(ParseScriptError::MissingName);
+        }
+
+        let warnings = value
+            .as_object()
+            .map(|fields| {
+                fields
+                    .keys()
+                    .filter(|field| !METADATA_FIELDS.contains(&field.as_str()))
+                    .map(|field| format!("unknown metadata field \"{}\"", field))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        let mut metadata: Metadata =
+            serde_jsonrc::from_value(value).map_err(ParseScriptError::InvalidMetadata)?;
+        metadata.name = metadata.name.trim().to_string();
+        metadata.icon = metadata.icon.to_lowercase();
+        metadata.output_language = metadata
+            .output_language
+            .map(|language| language.trim().to_lowercase());
+        if metadata.network && !metadata.permissions.contains(&Permission::Network) {
+            metadata.permissions.push(Permission::Network);
+        }
+
+        Ok((metadata, warnings))
+    }
+}
+
+// the JSON between `/**` and `**/` at the top of a script
+fn header(source: &str) -> Option<&str> {
+    let start = source.find("/**")? + 3;
+    let end = source[start..].find("**/")?;
+
+    Some(&source[start..start + end])
+}
+
+// reads lines from the top of the file until the end of its metadata, or the whole file if it
+// has none
+fn read_header(path: &Path) -> io::Result<String> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    let mut text = String::new();
+    while reader.read_line(&mut text)? > 0 {
+        if header(&text).is_some() {
+            break;
+        }
+    }
+
+    Ok(text)
+}
+
+// where a script was loaded from
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptOrigin {
+    // shipped with Boop, or a native transform
+    Builtin,
+    // in the scripts directory
+    User,
+    // cloned from a git repository into the scripts directory, with the repository's name
+    Remote(String),
+}
+
+impl Script {
+    // only reads the script's metadata, the rest of it is read when it's first run
+    pub fn from_file(path: PathBuf) -> Result<Self, ParseScriptError> {
+        let text = read_header(&path).map_err(ParseScriptError::FailedToRead)?;
+        let (metadata, header) = Script::parse_metadata(&text, &path)?;
+
+        Ok(Script::new(metadata, header, None, path))
+    }
+
+    pub fn from_source(source: String, path: PathBuf) -> Result<Self, ParseScriptError> {
+        let (metadata, header) = Script::parse_metadata(&source, &path)?;
+
+        Ok(Script::new(metadata, header, Some(source), path))
+    }
+
+    // the script's metadata and the header it was parsed from
+    fn parse_metadata(source: &str, path: &Path) -> Result<(Metadata, String), ParseScriptError> {
+        let (metadata, warnings) = Metadata::from_source(source)?;
+        for warning in warnings {
+            warn!("{} ({}): {}", metadata.name, path.display(), warning);
+        }
+
+        Ok((metadata, header(source).unwrap_or_default().to_string()))
+    }
+
+    // a builtin transform, it has no source and never starts an executor
+    pub fn native(metadata: Metadata, transform: Box<dyn Transform>) -> Self {
+        let mut script = Script::new(metadata, String::new(), Some(String::new()), PathBuf::new());
+        script.transform = Some(transform);
+        script
+    }
+
+    fn new(metadata: Metadata, header: String, source: Option<String>, path: PathBuf) -> Self {
+        let permission_key = source
+            .as_deref()
+            .map(|source| permissions::script_key(&path, source));
+        let granted = metadata.permissions.clone();
+
+        Script {
+            metadata,
+            source,
+            permission_key,
+            header,
+            channel: None,
+            path,
+            heap_size: 0,
+            last_used: None,
+            last_error: Arc::new(Mutex::new(None)),
+            isolate_handle: Arc::new(Mutex::new(None)),
+            crashed: Arc::new(AtomicBool::new(false)),
+            #[cfg(test)]
+            panic_next: Arc::new(AtomicBool::new(false)),
+            running: None,
+            results: HashMap::new(),
+            ready: None,
+            state: None,
+            granted,
+            heap_limit: DEFAULT_HEAP_LIMIT,
+            transform: None,
+        }
+    }
+
+    pub fn is_native(&self) -> bool {
+        self.transform.is_some()
+    }
+
+    pub fn origin(&self) -> ScriptOrigin {
+        if self.path.as_os_str().is_empty() {
+            return ScriptOrigin::Builtin;
+        }
+
+        // scripts/remote/<name>/..., with at least the script's file after the name
+        let components: Vec<Cow<'_, str>> = self
+            .path
+            .components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect();
+        components
+            .windows(4)
+            .rev()
+            .find(|window| window[0] == "scripts" && window[1] == REMOTE_DIR)
+            .map(|window| ScriptOrigin::Remote(window[2].to_string()))
+            .unwrap_or(ScriptOrigin::User)
+    }
+
+    fn init_executor_thread(&mut self) {
+        assert!(self.channel.is_none());
+
+        let (sender, receiver) = bounded(0);
+
+        {
+            let t_name = self.metadata.name.clone();
+            let t_source = self.source.clone().unwrap_or_default();
+            let (t_sender, t_receiver) = (sender.clone(), receiver.clone());
+            let t_last_error = self.last_error.clone();
+            let t_isolate_handle = self.isolate_handle.clone();
+            let t_heap_limit = self.heap_limit;
+            let t_stream = self.metadata.stream;
+            let t_crashed = self.crashed.clone();
+            #[cfg(test)]
+            let t_panic_next = self.panic_next.clone();
+            pool::spawn(t_heap_limit, move |isolate| {
+                // a panic stops the thread but not the application, the script notices and starts
+                // another thread with a fresh isolate
+                let name = t_name.clone();
+                let result = panic::catch_unwind(AssertUnwindSafe(move || {
+                    info!("thread spawned for {}", t_name);
+                    let mut executor = Executor::with_isolate(isolate, &t_source, t_heap_limit);
+                    executor.set_sandbox(sandbox::script_dir(PROJECT_DIRS.data_dir(), &t_name));
+                    debug!("executor created");
+
+                    // forward requests to the thread waiting on the script
+                    {
+                        let (h_sender, h_receiver) = (t_sender.clone(), t_receiver.clone());
+                        executor.set_host(move |request| {
+                            h_sender.send(ExecutorJob::HostRequest(request)).unwrap(); // blocks until send
+                            match h_receiver.recv().unwrap() {
+                                ExecutorJob::HostResponse(response) => response,
+                                job => {
+                                    panic!("expected a host responce on channel, but got {:?}", job)
+                                }
+                            }
+                        });
+                    }
+
+                    t_isolate_handle
+                        .lock()
+                        .expect("isolate handle lock is poisoned")
+                        .replace(executor.isolate_handle());
+
+                    if let Some(init_error) = executor.init_error() {
+                        t_last_error
+                            .lock()
+                            .expect("last error lock is poisoned")
+                            .replace(init_error.to_string());
+                    }
+
+                    loop {
+                        match t_receiver.recv().unwrap() // blocks until receive 
+                    {
+                        ExecutorJob::Request((full_text, selection, document, state, granted)) => {
+                            info!(
+                                "request received, full_text: {} bytes, selection: {} bytes",
+                                full_text.len(),
+                                selection.as_ref().map(|s| s.len()).unwrap_or(0),
+                            );
+                            #[cfg(test)]
+                            {
+                                if t_panic_next.swap(false, Ordering::SeqCst) {
+                                    panic!("executor thread asked to panic");
+                                }
+                            }
+                            // answers may have changed since the script last ran
+                            executor.set_permissions(granted);
+                            let result = if t_stream {
+                                executor.stream(&full_text, selection.as_deref())
+                            } else {
+                                executor.execute(&full_text, selection.as_deref(), &document, state.as_deref())
+                            };
+                            t_sender.send(ExecutorJob::Responce(result)).unwrap(); // blocks until send
+                            // TODO: handle
+                        }
+                        ExecutorJob::Responce(_)
+                        | ExecutorJob::HostRequest(_)
+                        | ExecutorJob::HostResponse(_) => {
+                            warn!("executor thread received a responce on channel");
+                        }
+                        ExecutorJob::Kill => {
+                            info!("killing thread for {}", t_name);
+                            return;
+                        }
+                    }
+                    }
+                }));
+
+                if result.is_err() {
+                    error!("executor for {} crashed", name);
+                    t_crashed.store(true, Ordering::SeqCst);
+                }
+            })
+        };
+
+        self.channel = Some(ExecutorChannel { sender, receiver });
+    }
+
+    // forgets the executor thread if it crashed, a new one is started when the script is next run,
+    // the old thread may still write to what it shared with the script so that's replaced too
+    fn recover(&mut self) {
+        if !self.crashed.load(Ordering::SeqCst) {
+            return;
+        }
+
+        warn!("restarting the script engine for {}", self.metadata.name);
+        self.channel = None;
+        self.heap_size = 0;
+        self.isolate_handle = Arc::new(Mutex::new(None));
+        self.crashed = Arc::new(AtomicBool::new(false));
+    }
+
+    // error from initializing the script or the exception thrown by its last execution
+    pub fn last_error(&self) -> Option<String> {
+        self.last_error
+            .lock()
+            .expect("last error lock is poisoned")
+            .clone()
+    }
+
+    // starts the executor thread ahead of time, so the next execution doesn't wait for the
+    // isolate to be created
+    pub fn warm_up(&mut self) {
+        self.recover();
+
+        if self.channel.is_none() && self.transform.is_none() {
+            if let Err(e) = self.load_source() {
+                warn!("{}", e);
+                return;
+            }
+
+            self.init_executor_thread();
+        }
+    }
+
+    // kills the thread associated with this script, it will be recreated when `execute` is called
+    pub fn kill_thread(&mut self) {
+        self.recover();
+
+        // the executor can't receive the kill until the running script has responded
+        if let Some(running) = &mut self.running {
+            running.deadline = Instant::now();
+            while self.receive(&headless_host, true).is_none() {}
+        }
+
+        if let Some(channel) = &self.channel {
+            channel.sender.send(ExecutorJob::Kill).unwrap(); // blocks until send
+        }
+
+        self.channel = None;
+        self.heap_size = 0;
+        *self
+            .isolate_handle
+            .lock()
+            .expect("isolate handle lock is poisoned") = None;
+    }
+
+    // forgets the script's globals, cached results and last error, every script has its own
+    // isolate so others aren't affected, a new one is created the next time the script runs
+    pub fn reset(&mut self) {
+        self.kill_thread();
+        self.results.clear();
+        *self.last_error.lock().expect("last error lock is poisoned") = None;
+    }
+
+    pub fn set_state(&mut self, state: Option<String>) {
+        self.state = state;
+    }
+
+    // what the script may do from the next time it runs, by the answers the user gave it
+    pub fn set_permissions(&mut self, permissions: &Permissions) {
+        self.granted = permissions.granted(&self.permission_key(), &self.metadata.permissions);
+    }
+
+    // identifies the script to the answers the user gave it, see `permissions::script_key`
+    pub fn permission_key(&self) -> String {
+        match &self.permission_key {
+            Some(permission_key) => permission_key.clone(),
+            None => permissions::script_key(&self.path, &self.source().unwrap_or_default()),
+        }
+    }
+
+    // bytes the script's heap may grow to, the executor is restarted to apply a new limit
+    pub fn set_heap_limit(&mut self, heap_limit: usize) {
+        if heap_limit != self.heap_limit {
+            self.heap_limit = heap_limit;
+            if !self.is_running() {
+                self.kill_thread();
+            }
+        }
+    }
+
+    // the script's source, read from its file if it hasn't been run yet
+    pub fn source(&self) -> io::Result<Cow<'_, str>> {
+        match &self.source {
+            Some(source) => Ok(Cow::Borrowed(source)),
+            None => fs::read_to_string(&self.path).map(Cow::Owned),
+        }
+    }
+
+    // true if the script was loaded from `source`, scripts that haven't been run yet only compare
+    // their metadata, as the rest of the file is read when they're run
+    pub fn is_loaded_from(&self, source: &str) -> bool {
+        match &self.source {
+            Some(loaded) => loaded == source,
+            None => header(source) == Some(self.header.as_str()),
+        }
+    }
+
+    // reads the rest of a script loaded from a file, the first time it's run
+    fn load_source(&mut self) -> Result<(), SimpleError> {
+        if self.source.is_none() {
+            info!("reading {}", self.path.display());
+            let source = fs::read_to_string(&self.path).map_err(|e| {
+                SimpleError::with(&format!("could not read {}", self.path.display()), e)
+            })?;
+            self.permission_key = Some(permissions::script_key(&self.path, &source));
+            self.source = Some(source);
+        }
+
+        Ok(())
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.is_some() || self.ready.is_some()
+    }
+
+    // stops the running script, `poll` returns an error once it has stopped
+    pub fn cancel(&mut self) {
+        if let Some(running) = self.running.as_mut() {
+            info!("cancelling {}", self.metadata.name);
+            running.cancelled = true;
+            running.deadline = Instant::now();
+        }
+    }
+
+    // runs the script, it is stopped if it runs for longer than `timeout`, requests the script
+    // makes while running are answered by `host`
+    pub fn execute(
+        &mut self,
+        full_text: &str,
+        selection: Option<&str>,
+        document: &DocumentContext,
+        timeout: Duration,
+        host: &dyn Fn(HostRequest) -> HostResponse,
+    ) -> Result<ExecutionStatus, SimpleError> {
+        self.start(full_text, selection, document, timeout)?;
+
+        loop {
+            if let Some(result) = self.receive(host, true) {
+                return result;
+            }
+        }
+    }
+
+    /// Runs the script on `text`, or on `selection` of it if given, and returns the new text, the
+    /// short form of [`Script::execute`] for tools embedding the engine.
+    /// The selection is the first occurrence of `selection` in `text`. Scripts are stopped after
+    /// the default timeout, and any file, clipboard or prompt requests they make are refused.
+    pub fn run(&mut self, text: &str, selection: Option<&str>) -> Result<String, SimpleError> {
+        let selection_range = find_selection(text, selection);
+        let document = DocumentContext {
+            cursor: selection_range
+                .map(|(_, end)| end)
+                .unwrap_or_else(|| text.chars().count()),
+            selection_range,
+            ..Default::default()
+        };
+        let timeout = Config::default().script_timeout(&self.metadata);
+        let status = self.execute(text, selection, &document, timeout, &headless_host)?;
+
+        if let Some(exception) = status.exception() {
+            bail!("{} threw {}", self.metadata.name, exception);
+        }
+        if let Some(error) = status.error() {
+            bail!("{}: {}", self.metadata.name, error);
+        }
+
+        Ok(status.into_replacement().apply(text, selection))
+    }
+
+    // starts running the script without waiting for it, `poll` returns the result once it has
+    // finished
+    pub fn start(
+        &mut self,
+        full_text: &str,
+        selection: Option<&str>,
+        document: &DocumentContext,
+        timeout: Duration,
+    ) -> Result<(), SimpleError> {
+        if self.is_running() {
+            bail!("{} is already running", self.metadata.name);
+        }
+
+        if let Some(transform) = &self.transform {
+            let result = transform.apply(selection.unwrap_or(full_text));
+            self.ready = Some(ExecutionStatus::transformed(full_text, selection, result));
+            return Ok(());
+        }
+
+        self.load_source()?;
+
+        let cache_key = if self.metadata.pure {
+            let mut hasher = DefaultHasher::new();
+            (&self.source, full_text, selection, document, &self.state).hash(&mut hasher);
+            Some(hasher.finish())
+        } else {
+            None
+        };
+
+        let cached = cache_key.and_then(|key| self.results.get(&key).cloned());
+        if cached.is_some() {
+            debug!("reusing result of {}", self.metadata.name);
+            self.ready = cached;
+            return Ok(());
+        }
+
+        self.warm_up();
+        let channel = self.channel.as_ref().expect("channel is none");
+
+        // send request
+        channel
+            .sender
+            .send(ExecutorJob::Request((
+                full_text.to_owned(),
+                selection.map(|s| s.to_owned()),
+                document.clone(),
+                self.state.clone(),
+                self.granted.clone(),
+            )))
+            .map_err(|e| SimpleError::with("cannot send text to channel", e))?;
+
+        self.running = Some(Running {
+            timeout,
+            deadline: Instant::now() + timeout,
+            terminated: false,
+            cancelled: false,
+            cache_key,
+        });
+
+        Ok(())
+    }
+
+    // answers requests the running script has made, returns its result once it has finished or
+    // None while it's still running
+    pub fn poll(
+        &mut self,
+        host: &dyn Fn(HostRequest) -> HostResponse,
+    ) -> Option<Result<ExecutionStatus, SimpleError>> {
+        if !self.is_running() {
+            return Some(Err(SimpleError::new(format!(
+                "{} is not running",
+                self.metadata.name
+            ))));
+        }
+
+        self.receive(host, false)
+    }
+
+    // handles the next message from the running script, waiting for one if `block` is set
+    fn receive(
+        &mut self,
+        host: &dyn Fn(HostRequest) -> HostResponse,
+        block: bool,
+    ) -> Option<Result<ExecutionStatus, SimpleError>> {
+        if let Some(status) = self.ready.take() {
+            self.last_used = Some(Instant::now());
+            *self.last_error.lock().expect("last error lock is poisoned") = None;
+            return Some(Ok(status));
+        }
+
+        let running = self.running.as_mut()?;
+        let channel = self.channel.as_ref().expect("channel is none");
+
+        let job = if !block {
+            channel.receiver.try_recv().map_err(|e| match e {
+                TryRecvError::Empty => RecvTimeoutError::Timeout,
+                TryRecvError::Disconnected => RecvTimeoutError::Disconnected,
+            })
+        } else {
+            // woken regularly to check the executor hasn't crashed
+            channel.receiver.recv_timeout(
+                running
+                    .deadline
+                    .saturating_duration_since(Instant::now())
+                    .min(CRASH_CHECK_INTERVAL),
+            )
+        };
+
+        let result = match job {
+            Ok(ExecutorJob::HostRequest(request)) => {
+                let response = match request {
+                    // scripts must declare what they need before the user is asked, requests for
+                    // anything else are refused like they are without a window
+                    request
+                        if request.permission().map_or(false, |permission| {
+                            !self.metadata.permissions.contains(&permission)
+                        }) =>
+                    {
+                        warn!(
+                            "{} made {:?} without declaring the permission",
+                            self.metadata.name, request
+                        );
+                        headless_host(request)
+                    }
+                    request => host(request),
+                };
+                match channel.sender.send(ExecutorJob::HostResponse(response)) {
+                    Ok(()) => {
+                        // time spent answering requests doesn't count towards the timeout
+                        running.deadline = Instant::now() + running.timeout;
+                        return None;
+                    }
+                    Err(e) => Err(SimpleError::with("cannot send responce to channel", e)),
+                }
+            }
+            Ok(ExecutorJob::Responce(status)) => Ok(status),
+            Ok(job) => Err(SimpleError::new(format!(
+                "expected a responce on channel, but got a request: {:?}",
+                job
+            ))),
+            Err(RecvTimeoutError::Timeout) if self.crashed.load(Ordering::SeqCst) => {
+                let message = format!(
+                    "{} crashed, the script engine was restarted",
+                    self.metadata.name
+                );
+                *self.last_error.lock().expect("last error lock is poisoned") =
+                    Some(message.clone());
+                Err(SimpleError::new(message))
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if Instant::now() >= running.deadline {
+                    if !running.terminated && !running.cancelled {
+                        warn!(
+                            "{} is still running after {:?}, terminating",
+                            self.metadata.name, running.timeout
+                        );
+                    }
+                    // terminated again until it stops, the executor clears termination before
+                    // each run so the first may be missed if the script had only just started
+                    terminate(&self.isolate_handle);
+                    running.terminated = true;
+                    running.deadline = Instant::now() + TERMINATE_RETRY;
+                }
+                return None;
+            }
+            Err(e) => Err(SimpleError::with("cannot receive result on channel", e)),
+        };
+
+        let running = self.running.take().expect("script is not running");
+        self.recover();
+        if running.cancelled {
+            return Some(Err(SimpleError::new(format!(
+                "{} was cancelled",
+                self.metadata.name
+            ))));
+        }
+
+        let cache_key = running.cache_key;
+        if let Ok(status) = &result {
+            if let Some(key) = cache_key {
+                // only successful results are reused, so failures are retried
+                if status.exception().is_none() && status.permission_requests().is_empty() {
+                    if self.results.len() >= MAX_CACHED_RESULTS {
+                        self.results.clear();
+                    }
+                    self.results.insert(key, status.clone());
+                }
+            }
+
+            if let Some(state) = status.state() {
+                self.state = state.cloned();
+            }
+
+            self.heap_size = status.heap_size();
+            self.last_used = Some(Instant::now());
+            *self.last_error.lock().expect("last error lock is poisoned") =
+                status.exception().map(ScriptError::to_string);
+
+            // the heap limit was raised so the script could be stopped, start over with a fresh
+            // isolate
+            if status.exceeded_heap_limit() {
+                self.kill_thread();
+            }
+        }
+
+        Some(result)
+    }
+}
+
+// stops the script running on an executor, if its isolate has been created
+fn terminate(isolate_handle: &Mutex<Option<rusty_v8::IsolateHandle>>) {
+    if let Some(handle) = isolate_handle
+        .lock()
+        .expect("isolate handle lock is poisoned")
+        .as_ref()
+    {
+        handle.terminate_execution();
+    }
+}
+
+// kills the executors of the least recently used scripts until the heap used by all executors,
+// and the isolates warmed up for them, fits in `budget` bytes, the executor of the script at
+// `keep` is left alone
+pub fn enforce_memory_budget(scripts: &mut [Script], budget: usize, keep: usize) {
+    let warm = pool::heap_size();
+    let mut used: usize = scripts.iter().map(|script| script.heap_size).sum::<usize>() + warm;
+    if used <= budget {
+        return;
+    }
+
+    // warm isolates haven't been used yet, so they go first
+    if warm > 0 {
+        info!(
+            "executors use {} bytes (budget {} bytes), dropping warm isolates",
+            used, budget
+        );
+        pool::evict();
+        used -= warm;
+    }
+
+    let mut idle: Vec<usize> = (0..scripts.len())
+        .filter(|&index| {
+            index != keep && scripts[index].channel.is_some() && !scripts[index].is_running()
+        })
+        .collect();
+    idle.sort_by_key(|&index| scripts[index].last_used);
+
+    for index in idle {
+        if used <= budget {
+            break;
+        }
+
+        info!(
+            "executors use {} bytes (budget {} bytes), killing executor for {}",
+            used, budget, scripts[index].metadata.name
+        );
+        used -= scripts[index].heap_size;
+        scripts[index].kill_thread();
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::{
+        executor::{
+            Change, ChangeSet, ChosenFile, ConsoleLevel, FileFilter, Highlight, HighlightKind,
+            Insertion, Notification, TextReplacement,
+        },
+        script::ParseScriptError,
+    };
+    use std::{borrow::Cow, thread};
+
+    const TIMEOUT: Duration = Duration::from_secs(10);
+
+    #[must_use]
+    pub(crate) struct SetupGuard {}
+
+    // V8 can only be initialized once, so every test that runs scripts initializes it through here
+    pub(crate) fn setup() -> SetupGuard {
+        crate::init();
+        SetupGuard {}
+    }
+
+    #[test]
+    fn test_retain_execution_context() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Counter\",
+                    \"description\":\"Counts up\",
+                    \"author\":\"Ben\",
+                    \"icon\":\"html\",
+                    \"tags\":\"count\"
+                }
+            **/
+            
+            let number = 0;
+            
+            function main(state) {
+                number += 1;
+                state.text = number;
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        for i in 1..10 {
+            let status = script.execute("", None, &Default::default(), TIMEOUT, &headless_host);
+            assert!(status.is_ok());
+            assert_eq!(
+                TextReplacement::Full(i.to_string()),
+                status.unwrap().into_replacement()
+            );
+        }
+    }
+
+    #[test]
+    fn test_reuse_payload_functions() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Same Functions\",
+                    \"description\":\"Checks the payload's functions are reused\",
+                    \"icon\":\"html\"
+                }
+            **/
+
+            let last;
+
+            function main(state) {
+                state.text = String(last === state.postInfo);
+                last = state.postInfo;
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        for expected in &["false", "true"] {
+            let status = script
+                .execute("", None, &Default::default(), TIMEOUT, &headless_host)
+                .unwrap();
+            assert_eq!(
+                TextReplacement::Full(expected.to_string()),
+                status.into_replacement()
+            );
+        }
+    }
+
+    #[test]
+    fn test_save_as() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Export\",
+                    \"description\":\"Exports as CSV\",
+                    \"author\":\"Ben\",
+                    \"icon\":\"table\",
+                    \"tags\":\"csv\"
+                }
+            **/
+
+            function main(state) {
+                state.saveAs(\"export.csv\", state.text.split(\" \").join(\",\"));
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let status = script
+            .execute("a b c", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        let save_as = status.save_as().expect("no save requested");
+        assert_eq!("export.csv", save_as.suggested_name);
+        assert_eq!("a,b,c", save_as.text);
+        assert_eq!(TextReplacement::None, status.into_replacement());
+    }
+
+    #[test]
+    fn test_full_text_bytes() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Byte Length\",
+                    \"description\":\"Replaces text with its UTF-8 bytes, reversed\",
+                    \"author\":\"Ben\",
+                    \"icon\":\"counter\",
+                    \"tags\":\"bytes\"
+                }
+            **/
+
+            function main(state) {
+                const bytes = state.fullTextBytes;
+                state.postInfo(bytes.length);
+                state.fullTextBytes = bytes.reverse();
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let status = script
+            .execute("ab😁", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(Some(&"6".to_string()), status.info());
+        assert!(status.error().is_some()); // reversed emoji bytes are not valid UTF-8
+
+        let status = script
+            .execute("abc", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert!(status.error().is_none());
+        assert_eq!(
+            TextReplacement::Full("cba".to_string()),
+            status.into_replacement()
+        );
+    }
+
+    #[test]
+    fn test_document_context() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Describe Document\",
+                    \"description\":\"Describes the document\",
+                    \"author\":\"Ben\",
+                    \"icon\":\"term\",
+                    \"tags\":\"document\"
+                }
+            **/
+
+            function main(state) {
+                const d = state.document;
+                state.text = [d.filename, d.language, d.lineEnding, d.encoding].join(\",\");
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let document = DocumentContext {
+            filename: Some("notes.py".to_string()),
+            language: Some("python".to_string()),
+            encoding: "UTF-8".to_string(),
+            ..Default::default()
+        };
+        let status = script
+            .execute("a\r\nb", None, &document, TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(
+            TextReplacement::Full("notes.py,python,crlf,UTF-8".to_string()),
+            status.into_replacement()
+        );
+
+        let status = script
+            .execute("a", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(
+            TextReplacement::Full(",,lf,".to_string()),
+            status.into_replacement()
+        );
+    }
+
+    #[test]
+    fn test_line_ending() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Reverse Lines\",
+                    \"description\":\"Reverses the lines\",
+                    \"author\":\"Ben\",
+                    \"icon\":\"term\",
+                    \"tags\":\"lines\"
+                }
+            **/
+
+            function main(state) {
+                state.text = state.text.split(state.lineEnding).reverse().join(state.lineEnding);
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let status = script
+            .execute(
+                "a\r\nb\r\nc",
+                None,
+                &Default::default(),
+                TIMEOUT,
+                &headless_host,
+            )
+            .unwrap();
+        assert_eq!(
+            TextReplacement::Full("c\r\nb\r\na".to_string()),
+            status.into_replacement()
+        );
+    }
+
+    #[test]
+    fn test_environment() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Describe Environment\",
+                    \"description\":\"Describes the environment\",
+                    \"author\":\"Ben\",
+                    \"icon\":\"globe\",
+                    \"tags\":\"env\"
+                }
+            **/
+
+            function main(state) {
+                state.text = [state.env.platform, state.env.version].join(\" \");
+                state.postInfo(state.env.locale && state.env.timezone);
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let status = script
+            .execute("", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert!(!status.info().unwrap().is_empty());
+        assert_eq!(
+            TextReplacement::Full(format!(
+                "{} {}",
+                std::env::consts::OS,
+                env!("CARGO_PKG_VERSION")
+            )),
+            status.into_replacement()
+        );
+    }
+
+    #[test]
+    fn test_enforce_memory_budget() {
+        let _guard = setup();
+        let _pool = pool::tests::POOL_LOCK.lock().unwrap();
+
+        let mut scripts: Vec<Script> = (0..3)
+            .map(|i| {
+                Script::from_source(
+                    format!(
+                        "
+                        /**
+                            {{
+                                \"api\":1,
+                                \"name\":\"Script {}\",
+                                \"description\":\"Does nothing\",
+                                \"icon\":\"html\"
+                            }}
+                        **/
+
+                        function main(state) {{}}",
+                        i
+                    ),
+                    PathBuf::new(),
+                )
+                .unwrap()
+            })
+            .collect();
+
+        for script in scripts.iter_mut() {
+            script
+                .execute("", None, &Default::default(), TIMEOUT, &headless_host)
+                .unwrap();
+            assert!(script.heap_size > 0);
+        }
+
+        // within budget, nothing is killed
+        enforce_memory_budget(&mut scripts, usize::MAX, 0);
+        assert!(scripts.iter().all(|script| script.channel.is_some()));
+
+        // over budget, the warm isolates and everything but the kept script are killed
+        enforce_memory_budget(&mut scripts, 0, 1);
+        assert!(scripts[0].channel.is_none());
+        assert!(scripts[1].channel.is_some());
+        assert!(scripts[2].channel.is_none());
+        assert_eq!(0, scripts[0].heap_size);
+    }
+
+    #[test]
+    fn test_last_error() {
+        let _guard = setup();
+
+        let mut broken = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Broken\",
+                    \"description\":\"Doesn't compile\",
+                    \"icon\":\"html\"
+                }
+            **/
+
+            function main(state) {"
+                .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let status = broken
+            .execute("", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        let exception = status.exception().unwrap();
+        assert!(exception.message.contains("SyntaxError"));
+        assert!(exception.line.is_some());
+        assert!(broken.last_error().is_some());
+
+        let mut throws = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Throws\",
+                    \"description\":\"Throws on empty input\",
+                    \"icon\":\"html\"
+                }
+            **/
+
+            function main(state) {
+                if (state.text === \"\") throw new Error(\"no input\");
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        throws
+            .execute("", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert!(throws
+            .last_error()
+            .unwrap()
+            .starts_with("Error: no input (line 12, column "));
+        let status = throws
+            .execute("", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert!(status
+            .exception()
+            .unwrap()
+            .stack_trace
+            .as_ref()
+            .unwrap()
+            .contains("at main"));
+
+        // error is cleared by a successful run
+        throws
+            .execute("input", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(None, throws.last_error());
+    }
+
+    #[test]
+    fn test_timeout() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Spin\",
+                    \"description\":\"Loops forever on empty input\",
+                    \"icon\":\"html\",
+                    \"timeout\":30
+                }
+            **/
+
+            function main(state) {
+                while (state.text === \"\") {}
+                state.text = \"done\";
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+        assert_eq!(Some(30), script.metadata.timeout);
+
+        let status = script
+            .execute(
+                "",
+                None,
+                &Default::default(),
+                Duration::from_millis(100),
+                &headless_host,
+            )
+            .unwrap();
+        assert!(status.exception().unwrap().message.contains("too long"));
+
+        // isolate can still be used after being terminated
+        let status = script
+            .execute("input", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(None, status.exception());
+        assert_eq!(
+            TextReplacement::Full("done".to_string()),
+            status.into_replacement()
+        );
+
+        // cancelled scripts are stopped before their timeout
+        script
+            .start("", None, &Default::default(), TIMEOUT)
+            .unwrap();
+        script.cancel();
+        let result = loop {
+            if let Some(result) = script.poll(&headless_host) {
+                break result;
+            }
+            thread::sleep(Duration::from_millis(1));
+        };
+        assert!(result.unwrap_err().as_str().contains("cancelled"));
+        assert!(!script.is_running());
+    }
+
+    #[test]
+    fn test_recover_from_crash() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Done\",
+                    \"description\":\"Replaces the text\",
+                    \"icon\":\"html\"
+                }
+            **/
+
+            function main(state) {
+                state.text = \"done\";
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let status = script
+            .execute("input", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(
+            TextReplacement::Full("done".to_string()),
+            status.into_replacement()
+        );
+
+        // the executor thread panics while handling the request
+        script.panic_next.store(true, Ordering::SeqCst);
+        let result = script.execute("input", None, &Default::default(), TIMEOUT, &headless_host);
+        assert_eq!(
+            "Done crashed, the script engine was restarted",
+            result.unwrap_err().as_str()
+        );
+        assert!(script.crashed.load(Ordering::SeqCst));
+        assert!(!script.is_running());
+
+        // a new executor is started for the next run
+        let status = script
+            .execute("input", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(
+            TextReplacement::Full("done".to_string()),
+            status.into_replacement()
+        );
+    }
+
+    #[test]
+    fn test_unconvertible_values() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Symbols\",
+                    \"description\":\"Gives payload functions values that aren't strings\",
+                    \"icon\":\"html\"
+                }
+            **/
+
+            function main(state) {
+                if (state.text == \"info\") {
+                    state.postInfo(Symbol(\"info\"));
+                } else if (state.text == \"insert\") {
+                    state.insert({ toString() { throw new Error(\"no text\"); } });
+                } else if (state.text == \"throw\") {
+                    throw Symbol(\"thrown\");
+                }
+                state.text = \"done\";
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        // the error converting the value is thrown to the script, rather than crashing the app
+        let status = script
+            .execute("info", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert!(status
+            .exception()
+            .unwrap()
+            .message
+            .contains("Cannot convert a Symbol value to a string"));
+        let status = script
+            .execute("insert", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert!(status.exception().unwrap().message.contains("no text"));
+        let status = script
+            .execute("throw", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert!(status.exception().is_some());
+        assert!(!script.crashed.load(Ordering::SeqCst));
+
+        let status = script
+            .execute("input", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(
+            TextReplacement::Full("done".to_string()),
+            status.into_replacement()
+        );
+    }
+
+    #[test]
+    fn test_reset() {
+        let _guard = setup();
+
+        let counter = |name: &str| {
+            Script::from_source(
+                format!(
+                    "
+                    /**
+                        {{
+                            \"api\":1,
+                            \"name\":\"{}\",
+                            \"description\":\"Counts its runs\",
+                            \"icon\":\"html\"
+                        }}
+                    **/
+
+                    var count = 0;
+
+                    function main(state) {{
+                        count++;
+                        state.text = String(count);
+                    }}",
+                    name
+                ),
+                PathBuf::new(),
+            )
+            .unwrap()
+        };
+        let run = |script: &mut Script| {
+            script
+                .execute("", None, &Default::default(), TIMEOUT, &headless_host)
+                .unwrap()
+                .into_replacement()
+        };
+
+        let mut first = counter("First");
+        let mut second = counter("Second");
+        assert_eq!(TextReplacement::Full("1".to_string()), run(&mut first));
+        assert_eq!(TextReplacement::Full("2".to_string()), run(&mut first));
+        // globals aren't shared between scripts
+        assert_eq!(TextReplacement::Full("1".to_string()), run(&mut second));
+
+        first.reset();
+        assert_eq!(TextReplacement::Full("1".to_string()), run(&mut first));
+        assert_eq!(TextReplacement::Full("2".to_string()), run(&mut second));
+    }
+
+    #[test]
+    fn test_heap_limit() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Hoard\",
+                    \"description\":\"Keeps everything on empty input\",
+                    \"icon\":\"html\"
+                }
+            **/
+
+            function main(state) {
+                const hoard = [];
+                while (state.text === \"\") hoard.push(\"x\".repeat(1024) + hoard.length);
+                state.text = \"done\";
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+        script.set_heap_limit(32 * 1024 * 1024);
+
+        let status = script
+            .execute("", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(
+            "script exceeded memory limit (32 MB)",
+            status.exception().unwrap().message
+        );
+        // the executor is restarted
+        assert!(script.channel.is_none());
+
+        let status = script
+            .execute("input", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(None, status.exception());
+    }
+
+    #[test]
+    fn test_choose_file() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Insert File\",
+                    \"description\":\"Inserts the contents of a file\",
+                    \"icon\":\"html\",
+                    \"permissions\":[\"filesystem\"]
+                }
+            **/
+
+            function main(state) {
+                const file = state.chooseFile([\"*.txt\", { name: \"JSON\", patterns: [\"*.json\"] }]);
+                state.text = file ? file.path + \":\" + file.text + \":\" + file.bytes.length : \"none\";
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let host = |request: HostRequest| match request {
+            HostRequest::ChooseFile(filters) => {
+                assert_eq!(
+                    vec![
+                        FileFilter {
+                            name: "*.txt".to_string(),
+                            patterns: vec!["*.txt".to_string()]
+                        },
+                        FileFilter {
+                            name: "JSON".to_string(),
+                            patterns: vec!["*.json".to_string()]
+                        },
+                    ],
+                    filters
+                );
+                HostResponse::ChosenFile(Some(ChosenFile {
+                    path: "/tmp/a.txt".to_string(),
+                    contents: b"hello".to_vec(),
+                }))
+            }
+            request => panic!("unexpected request {:?}", request),
+        };
+
+        let status = script
+            .execute("", None, &Default::default(), TIMEOUT, &host)
+            .unwrap();
+        assert_eq!(
+            TextReplacement::Full("/tmp/a.txt:hello:5".to_string()),
+            status.into_replacement()
+        );
+
+        let status = script
+            .execute("", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(
+            TextReplacement::Full("none".to_string()),
+            status.into_replacement()
+        );
+    }
+
+    #[test]
+    fn test_prompt() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Repeat\",
+                    \"description\":\"Repeats the text\",
+                    \"icon\":\"html\"
+                }
+            **/
+
+            function main(state) {
+                const times = state.prompt(\"Times\", 2);
+                state.text = times === null ? \"cancelled\" : state.text.repeat(Number(times));
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let host = |request: HostRequest| match request {
+            HostRequest::Prompt(label, default) => {
+                assert_eq!("Times", label);
+                assert_eq!(Some("2".to_string()), default);
+                HostResponse::Prompted(Some("3".to_string()))
+            }
+            request => panic!("unexpected request {:?}", request),
+        };
+
+        let status = script
+            .execute("ab", None, &Default::default(), TIMEOUT, &host)
+            .unwrap();
+        assert_eq!(
+            TextReplacement::Full("ababab".to_string()),
+            status.into_replacement()
+        );
+
+        // the default is used when there's no one to ask
+        let status = script
+            .execute("ab", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(
+            TextReplacement::Full("abab".to_string()),
+            status.into_replacement()
+        );
+
+        let cancel = |_: HostRequest| HostResponse::Prompted(None);
+        let status = script
+            .execute("ab", None, &Default::default(), TIMEOUT, &cancel)
+            .unwrap();
+        assert_eq!(
+            TextReplacement::Full("cancelled".to_string()),
+            status.into_replacement()
+        );
+    }
+
+    #[test]
+    fn test_open_url() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Open\",
+                    \"description\":\"Opens the text as a url\",
+                    \"icon\":\"html\"
+                }
+            **/
+
+            function main(state) {
+                state.openUrl(state.text);
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let status = script
+            .execute(
+                "https://example.com/?q=boop",
+                None,
+                &Default::default(),
+                TIMEOUT,
+                &headless_host,
+            )
+            .unwrap();
+        assert_eq!(
+            Some(&"https://example.com/?q=boop".to_string()),
+            status.open_url()
+        );
+
+        let status = script
+            .execute(
+                "file:///usr/bin/xterm",
+                None,
+                &Default::default(),
+                TIMEOUT,
+                &headless_host,
+            )
+            .unwrap();
+        assert_eq!(None, status.open_url());
+        assert!(status.error().is_some());
+    }
+
+    #[test]
+    fn test_clipboard() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Append Clipboard\",
+                    \"description\":\"Appends the clipboard and copies the result\",
+                    \"icon\":\"html\",
+                    \"permissions\":[\"clipboard\"]
+                }
+            **/
+
+            function main(state) {
+                const clipboard = state.readClipboard();
+                state.text += clipboard === null ? \"\" : clipboard;
+                state.copy(\"copied \" + state.text);
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let host = |request: HostRequest| match request {
+            HostRequest::ReadClipboard => HostResponse::ClipboardText(Some("b".to_string())),
+            request => panic!("unexpected request {:?}", request),
+        };
+
+        let status = script
+            .execute("a", None, &Default::default(), TIMEOUT, &host)
+            .unwrap();
+        assert_eq!(Some(&"copied ab".to_string()), status.copy());
+        assert_eq!(
+            TextReplacement::Full("ab".to_string()),
+            status.into_replacement()
+        );
+
+        let status = script
+            .execute("a", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(Some(&"copied a".to_string()), status.copy());
+
+        // readClipboard isn't set once the user refuses
+        let mut permissions = Permissions::default();
+        permissions
+            .answers_mut(Permission::Clipboard)
+            .insert(script.permission_key(), false);
+        script.set_permissions(&permissions);
+        let status = script
+            .execute("a", None, &Default::default(), TIMEOUT, &host)
+            .unwrap();
+        assert!(status.exception().is_some());
+    }
+
+    #[test]
+    fn test_fetch_permission() {
+        let _guard = setup();
+
+        let source = |network: bool| {
+            format!(
+                "
+                /**
+                    {{
+                        \"api\":1,
+                        \"name\":\"Expand URL\",
+                        \"description\":\"Follows redirects of the url\",
+                        \"icon\":\"html\",
+                        \"network\":{}
+                    }}
+                **/
+
+                function main(state) {{
+                    state.text = fetch(state.text).url;
+                }}",
+                network
+            )
+        };
+
+        // the user isn't asked unless the script declares it uses the network
+        let mut script = Script::from_source(source(false), PathBuf::new()).unwrap();
+        assert!(!script.metadata.network);
+        let host = |request: HostRequest| panic!("unexpected request {:?}", request);
+        let status = script
+            .execute(
+                "https://example.com",
+                None,
+                &Default::default(),
+                TIMEOUT,
+                &host,
+            )
+            .unwrap();
+        assert!(status.exception().unwrap().message.contains("was denied"));
+
+        let mut script = Script::from_source(source(true), PathBuf::new()).unwrap();
+        assert!(script.metadata.network);
+        let host = |request: HostRequest| match request {
+            HostRequest::AllowNetwork(url) => {
+                assert_eq!("https://example.com", url);
+                HostResponse::NetworkAllowed(false)
+            }
+            request => panic!("unexpected request {:?}", request),
+        };
+        let status = script
+            .execute(
+                "https://example.com",
+                None,
+                &Default::default(),
+                TIMEOUT,
+                &host,
+            )
+            .unwrap();
+        assert!(status.exception().unwrap().message.contains("was denied"));
+
+        // only web pages are fetched, without asking
+        let status = script
+            .execute(
+                "file:///etc/passwd",
+                None,
+                &Default::default(),
+                TIMEOUT,
+                &host,
+            )
+            .unwrap();
+        assert!(status
+            .exception()
+            .unwrap()
+            .message
+            .contains("only http and https urls"));
+    }
+
+    #[test]
+    fn test_fetch_unconvertible_arguments() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Fetch Symbols\",
+                    \"description\":\"Gives fetch values that aren't strings\",
+                    \"icon\":\"html\",
+                    \"network\":true
+                }
+            **/
+
+            const throws = { toString() { throw new Error(\"not a string\"); } };
+
+            function main(state) {
+                if (state.text == \"url\") {
+                    fetch(Symbol());
+                } else if (state.text == \"method\") {
+                    fetch(\"https://example.com\", { method: throws });
+                } else if (state.text == \"header\") {
+                    fetch(\"https://example.com\", { headers: { Accept: throws } });
+                } else if (state.text == \"body\") {
+                    fetch(\"https://example.com\", { method: \"POST\", body: Symbol() });
+                }
+                state.text = \"done\";
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        // nothing is fetched, so the user isn't asked
+        let host = |request: HostRequest| panic!("unexpected request {:?}", request);
+        let status = script
+            .execute("url", None, &Default::default(), TIMEOUT, &host)
+            .unwrap();
+        assert!(status
+            .exception()
+            .unwrap()
+            .message
+            .contains("Cannot convert a Symbol value to a string"));
+        for text in &["method", "header"] {
+            let status = script
+                .execute(text, None, &Default::default(), TIMEOUT, &host)
+                .unwrap();
+            assert!(status.exception().unwrap().message.contains("not a string"));
+        }
+        let status = script
+            .execute("body", None, &Default::default(), TIMEOUT, &host)
+            .unwrap();
+        assert!(status.exception().is_some());
+
+        let status = script
+            .execute("input", None, &Default::default(), TIMEOUT, &host)
+            .unwrap();
+        assert_eq!(
+            TextReplacement::Full("done".to_string()),
+            status.into_replacement()
+        );
+    }
+
+    #[test]
+    fn test_notify() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Notify\",
+                    \"description\":\"Sends notifications\",
+                    \"icon\":\"html\"
+                }
+            **/
+
+            function main(state) {
+                state.notify(\"Done\", \"Converted \" + state.text.length + \" characters\");
+                for (let i = 0; i < 10; i++) {
+                    state.notify(\"Again\");
+                }
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let status = script
+            .execute("abc", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(3, status.notifications().len());
+        assert_eq!(
+            Notification {
+                title: "Done".to_string(),
+                body: Some("Converted 3 characters".to_string()),
+            },
+            status.notifications()[0]
+        );
+        assert_eq!(None, status.notifications()[1].body);
+    }
+
+    #[test]
+    fn test_undeclared_permissions() {
+        let _guard = setup();
+
+        let source = |permissions: &str| {
+            format!(
+                "
+                /**
+                    {{
+                        \"api\":1,
+                        \"name\":\"Check Permissions\",
+                        \"description\":\"Lists the functions that need permission\",
+                        \"icon\":\"html\",
+                        \"permissions\":{}
+                    }}
+                **/
+
+                function main(state) {{
+                    state.text = typeof state.chooseFile + \" \" + typeof state.readClipboard;
+                }}",
+                permissions
+            )
+        };
+
+        let mut script = Script::from_source(source("[]"), PathBuf::new()).unwrap();
+        let status = script
+            .execute("", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(
+            TextReplacement::Full("undefined undefined".to_string()),
+            status.into_replacement()
+        );
+
+        let mut script = Script::from_source(source("[\"clipboard\"]"), PathBuf::new()).unwrap();
+        assert_eq!(vec![Permission::Clipboard], script.metadata.permissions);
+        let status = script
+            .execute("", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(
+            TextReplacement::Full("undefined function".to_string()),
+            status.into_replacement()
+        );
+
+        let (metadata, warnings) =
+            Metadata::parse("{\"api\":1,\"name\":\"A\",\"network\":true}").unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(vec![Permission::Network], metadata.permissions);
+    }
+
+    #[test]
+    fn test_async() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Shout Later\",
+                    \"description\":\"Uppercases the text after a while\",
+                    \"icon\":\"html\"
+                }
+            **/
+
+            const later = (value) => Promise.resolve(value);
+
+            async function main(state) {
+                if (state.text === \"reject\") {
+                    throw new Error(\"rejected\");
+                }
+                if (state.text === \"never\") {
+                    await new Promise(() => {});
+                }
+                state.text = await later(state.text.toUpperCase());
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let status = script
+            .execute("hello", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(None, status.exception());
+        assert_eq!(
+            TextReplacement::Full("HELLO".to_string()),
+            status.into_replacement()
+        );
+
+        let status = script
+            .execute("reject", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!("Error: rejected", status.exception().unwrap().message);
+
+        let status = script
+            .execute("never", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(
+            "main returned a promise that never settled",
+            status.exception().unwrap().message
+        );
+    }
+
+    #[test]
+    fn test_stream() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Number Lines\",
+                    \"description\":\"Numbers each line, dropping empty ones\",
+                    \"icon\":\"html\",
+                    \"stream\":true
+                }
+            **/
+
+            function processLine(line, index) {
+                if (line === \"throw\") {
+                    throw new Error(\"bad line \" + index);
+                }
+                return line === \"\" ? null : index + \": \" + line;
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let status = script
+            .execute(
+                "a\r\n\r\nb\r\n",
+                None,
+                &Default::default(),
+                TIMEOUT,
+                &headless_host,
+            )
+            .unwrap();
+        assert_eq!(None, status.exception());
+        assert_eq!(
+            TextReplacement::Full("0: a\r\n2: b\r\n".to_string()),
+            status.into_replacement()
+        );
+
+        let status = script
+            .execute(
+                "x\ny",
+                Some("y"),
+                &Default::default(),
+                TIMEOUT,
+                &headless_host,
+            )
+            .unwrap();
+        assert_eq!(
+            TextReplacement::Selection("0: y".to_string()),
+            status.into_replacement()
+        );
+
+        let status = script
+            .execute(
+                "a\nthrow",
+                None,
+                &Default::default(),
+                TIMEOUT,
+                &headless_host,
+            )
+            .unwrap();
+        assert_eq!("Error: bad line 1", status.exception().unwrap().message);
+        assert_eq!(TextReplacement::None, status.into_replacement());
+    }
+
+    #[test]
+    fn test_json() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Format JSON\",
+                    \"description\":\"Formats JSON\",
+                    \"icon\":\"html\"
+                }
+            **/
+
+            function main(state) {
+                const value = state.parseJSON();
+                if (value !== null) {
+                    state.text = state.stringifyJSON(value, 4);
+                }
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let status = script
+            .execute(
+                "{\"b\": [1, 2.5, null], \"a\": {\"c\": \"d\"}}",
+                None,
+                &Default::default(),
+                TIMEOUT,
+                &headless_host,
+            )
+            .unwrap();
+        assert_eq!(None, status.error());
+        assert_eq!(
+            TextReplacement::Full(
+                "{\n    \"b\": [\n        1,\n        2.5,\n        null\n    ],\n    \"a\": {\n        \"c\": \"d\"\n    }\n}"
+                    .to_string()
+            ),
+            status.into_replacement()
+        );
+
+        let status = script
+            .execute(
+                "{\n  \"a\": 1\n  \"b\": 2\n}",
+                None,
+                &Default::default(),
+                TIMEOUT,
+                &headless_host,
+            )
+            .unwrap();
+        assert_eq!(
+            Some(&"Invalid JSON: expected `,` or `}` at line 3 column 3".to_string()),
+            status.error()
+        );
+    }
+
+    #[test]
+    fn test_native() {
+        let mut script = crate::builtin::scripts()
+            .into_iter()
+            .find(|script| script.metadata.name == "Base32 Encode")
+            .unwrap();
+        assert!(script.is_native());
+
+        let status = script
+            .execute(
+                "a foobar b",
+                Some("foobar"),
+                &Default::default(),
+                TIMEOUT,
+                &headless_host,
+            )
+            .unwrap();
+        assert_eq!(
+            TextReplacement::Selection("MZXW6YTBOI======".to_string()),
+            status.into_replacement()
+        );
+        // no executor is started
+        assert!(script.channel.is_none());
+
+        let mut script = crate::builtin::scripts()
+            .into_iter()
+            .find(|script| script.metadata.name == "Base32 Decode")
+            .unwrap();
+        let status = script
+            .execute("!!", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(Some(&"\"!\" isn't base32".to_string()), status.error());
+        assert_eq!(TextReplacement::None, status.into_replacement());
+    }
+
+    #[test]
+    fn test_native_module() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Native\",
+                    \"description\":\"Uses the native module\",
+                    \"icon\":\"html\"
+                }
+            **/
+
+            const native = require(\"@boop/native\");
+
+            function main(state) {
+                if (state.text === \"crc\") {
+                    native.hash(\"crc\", \"abc\");
+                }
+                const zipped = native.gzip(state.text);
+                state.text = [
+                    native.hash(\"sha256\", \"abc\").slice(0, 8),
+                    native.crc32(\"123456789\").toString(16),
+                    native.decodeUTF8(native.gunzip(zipped)),
+                    native.base32Encode(\"foobar\"),
+                    native.toASCII(\"münchen.de\"),
+                    native.randomBytes(3).length,
+                    native.uuid(7)[14],
+                ].join(\" \");
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let status = script
+            .execute("boop", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(None, status.exception());
+        assert_eq!(
+            TextReplacement::Full(
+                "ba7816bf cbf43926 boop MZXW6YTBOI====== xn--mnchen-3ya.de 3 7".to_string()
+            ),
+            status.into_replacement()
+        );
+
+        let status = script
+            .execute("crc", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(
+            "Error: unknown hash algorithm \"crc\"",
+            status.exception().unwrap().message
+        );
+    }
+
+    #[test]
+    fn test_artifacts() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Split Lines\",
+                    \"description\":\"Splits lines into files\",
+                    \"icon\":\"html\"
+                }
+            **/
+
+            function main(state) {
+                state.text.split(\"\\n\").forEach((line, i) => state.addArtifact(i + \".txt\", line));
+                state.addArtifact(\"0.txt\", \"replaced\");
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let status = script
+            .execute("a\nb", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        let artifacts: Vec<(&str, &str)> = status
+            .artifacts()
+            .iter()
+            .map(|artifact| (artifact.name.as_str(), artifact.text.as_str()))
+            .collect();
+        assert_eq!(vec![("1.txt", "b"), ("0.txt", "replaced")], artifacts);
+    }
+
+    #[test]
+    fn test_post_preview() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Preview Markdown\",
+                    \"description\":\"Renders markdown\",
+                    \"icon\":\"html\"
+                }
+            **/
+
+            function main(state) {
+                state.postPreview(\"draft\");
+                state.postPreview(state.text);
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let status = script
+            .execute(
+                "# Title",
+                None,
+                &Default::default(),
+                TIMEOUT,
+                &headless_host,
+            )
+            .unwrap();
+        assert_eq!(Some(&"# Title".to_string()), status.preview());
+        assert_eq!(TextReplacement::None, status.into_replacement());
+    }
+
+    #[test]
+    fn test_start_and_poll() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Reverse\",
+                    \"description\":\"Reverses text\",
+                    \"icon\":\"html\"
+                }
+            **/
+
+            function main(state) {
+                state.text = state.text.split(\"\").reverse().join(\"\");
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        script
+            .start("abc", None, &Default::default(), TIMEOUT)
+            .unwrap();
+        assert!(script.is_running());
+        assert!(script
+            .start("abc", None, &Default::default(), TIMEOUT)
+            .is_err());
+
+        let status = loop {
+            if let Some(result) = script.poll(&headless_host) {
+                break result.unwrap();
+            }
+            thread::sleep(Duration::from_millis(1));
+        };
+        assert_eq!(
+            TextReplacement::Full("cba".to_string()),
+            status.into_replacement()
+        );
+        assert!(script.poll(&headless_host).unwrap().is_err());
+
+        // killing a running script waits for it to finish
+        script
+            .start("abc", None, &Default::default(), TIMEOUT)
+            .unwrap();
+        script.kill_thread();
+        assert!(!script.is_running());
+        assert!(script.channel.is_none());
+    }
+
+    #[test]
+    fn test_metadata() {
+        let (metadata, warnings) = Metadata::parse(
+            "{ \"api\": 1, \"name\": \" Sort \", \"icon\": \"Sort\", \"tgas\": \"lines\" }",
+        )
+        .unwrap();
+        assert_eq!("Sort", metadata.name);
+        assert_eq!("sort", metadata.icon);
+        assert_eq!("", metadata.description);
+        assert_eq!(0.0, metadata.bias);
+        assert_eq!(None, metadata.output_language);
+        assert_eq!(
+            vec!["unknown metadata field \"tgas\"".to_string()],
+            warnings
+        );
+
+        let (metadata, warnings) = Metadata::parse(
+            "{ \"api\": 1, \"name\": \"Format JSON\", \"outputLanguage\": \"JSON\" }",
+        )
+        .unwrap();
+        assert_eq!(Some("json"), metadata.output_language.as_deref());
+        assert!(warnings.is_empty());
+
+        match Metadata::parse("{ \"api\": 1, \"name\": \"\" }") {
+            Err(ParseScriptError::MissingName) => {}
+            result => panic!("expected missing name, got {:?}", result),
+        }
+        match Metadata::parse("{ \"api\": 1, \"name\": \"Sort\", }x") {
+            Err(ParseScriptError::InvalidMetadata(_)) => {}
+            result => panic!("expected invalid metadata, got {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_lazy_source() {
+        let _guard = setup();
+
+        let dir = std::env::temp_dir().join(format!("boop-gtk-lazy-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("upper.js");
+        let header = "/**\n{ \"api\": 1, \"name\": \"Upper\" }\n**/\n";
+        fs::write(
+            &path,
+            format!("{}function main(state) {{ state.text = \"a\"; }}", header),
+        )
+        .unwrap();
+
+        let mut script = Script::from_file(path.clone()).unwrap();
+        assert_eq!("Upper", script.metadata.name);
+        assert_eq!(None, script.source);
+
+        // the rest of the file is read when the script is first run
+        let source = format!("{}function main(state) {{ state.text = \"b\"; }}", header);
+        fs::write(&path, &source).unwrap();
+        assert!(script.is_loaded_from(&source));
+        let status = script
+            .execute("", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(
+            TextReplacement::Full("b".to_string()),
+            status.into_replacement()
+        );
+        assert_eq!(Some(source.as_str()), script.source.as_deref());
+        assert!(!script.is_loaded_from(&source.replace('b', "c")));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_pure() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Counter\",
+                    \"description\":\"Counts up, but claims to be pure\",
+                    \"icon\":\"html\",
+                    \"pure\":true
+                }
+            **/
+
+            let number = 0;
+
+            function main(state) {
+                number += 1;
+                state.text = number;
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        for (input, expected) in &[("a", "1"), ("a", "1"), ("b", "2"), ("a", "1")] {
+            let status = script
+                .execute(input, None, &Default::default(), TIMEOUT, &headless_host)
+                .unwrap();
+            assert_eq!(
+                TextReplacement::Full(expected.to_string()),
+                status.into_replacement()
+            );
+        }
+        assert!(!script.is_running());
+    }
+
+    #[test]
+    fn test_persistent() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Remember\",
+                    \"description\":\"Remembers the previous text\",
+                    \"icon\":\"html\"
+                }
+            **/
+
+            function main(state) {
+                const previous = state.persistent;
+                state.persistent = { text: state.text };
+                state.text = previous ? previous.text : \"none\";
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        // kept in the same place as `payload.state`
+        script.set_state(Some("{\"text\":\"stored\"}".to_string()));
+        let status = script
+            .execute("a", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(Some(Some(&"{\"text\":\"a\"}".to_string())), status.state());
+        assert_eq!(
+            TextReplacement::Full("stored".to_string()),
+            status.into_replacement()
+        );
+
+        // state survives the executor being killed
+        script.kill_thread();
+        let status = script
+            .execute("b", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(
+            TextReplacement::Full("a".to_string()),
+            status.into_replacement()
+        );
+    }
+
+    #[test]
+    fn test_cursor() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Mark Cursor\",
+                    \"description\":\"Marks the cursor and the end of the selection\",
+                    \"icon\":\"html\"
+                }
+            **/
+
+            function main(state) {
+                const range = state.selectionRange;
+                state.insert(\"[\", range ? range.start : state.cursorOffset);
+                state.insert(\"]\", range ? range.end : state.cursorOffset);
+                state.moveCursor(state.cursorOffset + 1);
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        // the emoji is two UTF-16 code units, but one character
+        let text = "😁ab";
+        let document = DocumentContext {
+            cursor: 2,
+            selection_range: Some((1, 2)),
+            ..Default::default()
+        };
+        let status = script
+            .execute(text, Some("a"), &document, TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(Some(4), status.cursor());
+        assert_eq!("😁[a]b", status.into_replacement().apply(text, Some("a")));
+
+        let document = DocumentContext {
+            cursor: 1,
+            ..Default::default()
+        };
+        let status = script
+            .execute(text, None, &document, TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!("😁[]ab", status.into_replacement().apply(text, None));
+    }
+
+    #[test]
+    fn test_selections() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Upcase Selections\",
+                    \"description\":\"Upcases each selection\",
+                    \"icon\":\"html\"
+                }
+            **/
+
+            function main(state) {
+                state.selections = state.selections.map(s => s.toUpperCase());
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let status = script
+            .execute(
+                "ab",
+                Some("b"),
+                &Default::default(),
+                TIMEOUT,
+                &headless_host,
+            )
+            .unwrap();
+        assert_eq!(
+            TextReplacement::Selections(vec!["B".to_string()]),
+            status.into_replacement()
+        );
+
+        // nothing to change without a selection
+        let status = script
+            .execute("ab", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(TextReplacement::None, status.into_replacement());
+
+        // a selection for each caret
+        let document = DocumentContext {
+            selection_ranges: vec![(0, 1), (2, 2), (3, 5)],
+            ..Default::default()
+        };
+        let status = script
+            .execute("ab cd", Some("a"), &document, TIMEOUT, &headless_host)
+            .unwrap();
+        let replacement = status.into_replacement();
+        assert_eq!(
+            TextReplacement::Selections(vec!["A".to_string(), "".to_string(), "CD".to_string()]),
+            replacement
+        );
+        assert_eq!(
+            "Ab CD",
+            replacement
+                .selections_change_set(&document.selection_ranges)
+                .unwrap()
+                .apply("ab cd")
+        );
+    }
+
+    #[test]
+    fn test_change_set() {
+        let change = |start, end, text: &str| Change {
+            start,
+            end,
+            text: text.to_string(),
+        };
+
+        // only the part of the text that differs is replaced
+        let change_set = TextReplacement::Full("a😁XYd".to_string()).change_set("a😁bcd", None, 0);
+        assert_eq!(vec![change(2, 4, "XY")], change_set.changes);
+        assert_eq!("a😁XYd", change_set.apply("a😁bcd"));
+        assert!(TextReplacement::Full("ab".to_string())
+            .change_set("ab", None, 0)
+            .is_empty());
+
+        let change_set =
+            TextReplacement::Selection("X".to_string()).change_set("abc", Some((1, 2)), 2);
+        assert_eq!(vec![change(1, 2, "X")], change_set.changes);
+
+        let change_set = TextReplacement::Insert(vec![
+            Insertion {
+                text: "]".to_string(),
+                at: Some(2),
+            },
+            Insertion {
+                text: "[".to_string(),
+                at: Some(0),
+            },
+        ])
+        .change_set("ab", None, 0);
+        assert_eq!(
+            vec![change(0, 0, "["), change(2, 2, "]")],
+            change_set.changes
+        );
+        assert_eq!("[ab]", change_set.apply("ab"));
+
+        assert_eq!(
+            ChangeSet::default(),
+            TextReplacement::None.change_set("ab", None, 0)
+        );
+    }
+
+    #[test]
+    fn test_preview() {
+        let _guard = setup();
+
+        let source = "
+            let runs = 0;
+            function main(state) {
+                runs += 1;
+                state.text = runs > 1 ? state.text : state.text.toUpperCase();
+            }";
+        let changes = vec![Change {
+            start: 2,
+            end: 3,
+            text: "B".to_string(),
+        }];
+        // each preview starts with the script as it was loaded
+        for _ in 0..2 {
+            assert_eq!(
+                changes,
+                Executor::preview(source, DEFAULT_HEAP_LIMIT, "a b c", Some("b")).changes
+            );
+        }
+        assert!(Executor::preview(source, DEFAULT_HEAP_LIMIT, "A B", None).is_empty());
+    }
+
+    #[test]
+    fn test_state() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Counter\",
+                    \"description\":\"Counts its runs\",
+                    \"icon\":\"html\"
+                }
+            **/
+
+            function main(state) {
+                if (state.text === \"count\") {
+                    state.state.counter = (state.state.counter || 0) + 1;
+                }
+                state.text = String(state.state.counter);
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        // scripts that don't change their state don't have it saved
+        let status = script
+            .execute("read", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(None, status.state());
+
+        script.set_state(Some("{\"counter\":41}".to_string()));
+        let status = script
+            .execute("count", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(Some(Some(&"{\"counter\":42}".to_string())), status.state());
+
+        // state survives the executor being killed
+        script.kill_thread();
+        let status = script
+            .execute("read", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(
+            TextReplacement::Full("42".to_string()),
+            status.into_replacement()
+        );
+    }
+
+    #[test]
+    fn test_highlight() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Find Bang\",
+                    \"description\":\"Highlights the first exclamation mark\",
+                    \"icon\":\"html\"
+                }
+            **/
+
+            function main(state) {
+                const index = state.text.indexOf(\"!\");
+                state.highlight(index, index + 1);
+                state.highlight(0, 2, \"info\");
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let text = "😁a!";
+        let status = script
+            .execute(text, None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        let highlights = status.highlights();
+        assert_eq!(2, highlights.len());
+        assert_eq!(HighlightKind::Error, highlights[0].kind);
+        assert_eq!((3, 4), (highlights[0].start, highlights[0].end));
+        // the emoji is two UTF-16 code units, but one character
+        assert_eq!((2, 3), highlights[0].char_range(text));
+        assert_eq!((0, 1), highlights[1].char_range(text));
+        assert_eq!(
+            (3, 3),
+            Highlight {
+                start: 10,
+                end: 20,
+                kind: HighlightKind::Info
+            }
+            .char_range(text)
+        );
+    }
+
+    #[test]
+    fn test_console() {
+        let _guard = setup();
+
+        let mut script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Chatty\",
+                    \"description\":\"Logs while it runs\",
+                    \"icon\":\"html\"
+                }
+            **/
+
+            function main(state) {
+                const circular = {};
+                circular.self = circular;
+
+                console.log(\"text is\", state.text, { length: state.text.length });
+                console.warn([1, 2]);
+                console.error(new Error(\"oops\"), circular);
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let status = script
+            .execute("abc", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(None, status.exception());
+        assert_eq!(
+            &[
+                (ConsoleLevel::Log, "text is abc {\"length\":3}".to_string()),
+                (ConsoleLevel::Warn, "[1,2]".to_string()),
+                (
+                    ConsoleLevel::Error,
+                    "Error: oops [object Object]".to_string()
+                ),
+            ],
+            status.console_output()
+        );
+    }
+
+    #[test]
+    fn test_fixtures() {
+        let _guard = setup();
+
+        let script = Script::from_source(
+            "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Upper Case\",
+                    \"description\":\"Upper cases text\",
+                    \"icon\":\"html\",
+                    \"tests\":[
+                        { \"input\":\"abc\", \"expected\":\"ABC\" },
+                        { \"input\":\"abc def\", \"selection\":\"def\", \"expected\":\"abc DEF\" },
+                        { \"input\":\"abc\", \"expected\":\"abc\" }
+                    ]
+                }
+            **/
+
+            function main(state) {
+                state.text = state.text.toUpperCase();
+            }"
+            .to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let broken = Script::from_source(
+            "/** { \"api\": 1, \"name\": \"Broken\" } **/ function main(state) {".to_string(),
+            PathBuf::new(),
+        )
+        .unwrap();
+
+        let dir = std::env::temp_dir().join(format!("boop-gtk-fixtures-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("a.js"), "function main(state) {}").unwrap();
+        fs::write(
+            dir.join("b.js"),
+            "/** { \"api\": 1, \"name\": \"B\", \"tgas\": \"\" } **/ function main(state) {}",
+        )
+        .unwrap();
+
+        let mut scripts = vec![script, broken];
+        let report = crate::testing::run_tests(&mut scripts, &Default::default(), &dir);
+        assert_eq!(1, report.loaded);
+        assert_eq!(2, report.passed);
+        let failures: Vec<(&str, Option<usize>)> = report
+            .failures
+            .iter()
+            .map(|failure| (failure.script.as_str(), failure.fixture))
+            .collect();
+        assert_eq!(
+            vec![
+                ("a.js", None),
+                ("b.js", None),
+                ("Upper Case", Some(2)),
+                ("Broken", None)
+            ],
+            failures
+        );
+        assert_eq!("unknown metadata field \"tgas\"", report.failures[1].reason);
+        assert!(scripts[0].channel.is_none());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_builtin_scripts() {
+        let _guard = setup();
+
+        use rust_embed::RustEmbed;
+
+        #[derive(RustEmbed)]
+        #[folder = "../submodules/Boop/Boop/Boop/scripts/"]
+        struct Scripts;
+
+        for file in Scripts::iter() {
+            println!("testing {}", file);
+
+            let source: Cow<'static, [u8]> = Scripts::get(&file).unwrap();
+            let script_source = String::from_utf8(source.to_vec()).unwrap();
+
+            match Script::from_source(script_source, PathBuf::new()) {
+                Ok(mut script) => {
+                    script
+                        .execute(
+                            "foobar ♈ ♉ ♊ ♋ ♌ ♍ ♎ ♏ ♐ ♑ ♒ ♓ 😁 😝 😋 😄",
+                            None,
+                            &Default::default(),
+                            TIMEOUT,
+                            &headless_host,
+                        )
+                        .unwrap();
+                }
+                Err(e) => match e {
+                    ParseScriptError::NoMetadata => {
+                        assert!(file.starts_with("lib/")); // only library files should fail
+                    }
+                    e => panic!(e),
+                },
+            }
+        }
+    }
+
+    #[test]
+    fn test_boop_modules() {
+        let _guard = setup();
+
+        use rust_embed::RustEmbed;
+
+        #[derive(RustEmbed)]
+        #[folder = "../submodules/Boop/"]
+        struct Boop;
+
+        // every module required by the bundled and community scripts should be available
+        let mut modules: Vec<String> = Boop::iter()
+            .filter(|file| file.ends_with(".js"))
+            .flat_map(|file| {
+                let source = String::from_utf8(Boop::get(&file).unwrap().to_vec()).unwrap();
+                source
+                    .match_indices("@boop/")
+                    .map(|(start, _)| {
+                        source[start..]
+                            .split(|c| c == '"' || c == '\'' || c == '`')
+                            .next()
+                            .unwrap()
+                            .to_string()
+                    })
+                    .collect::<Vec<String>>()
+            })
+            .collect();
+        modules.sort();
+        modules.dedup();
+
+        for module in modules.iter().chain(&["@boop/missing".to_string()]) {
+            println!("requiring {}", module);
+
+            let mut script = Script::from_source(
+                format!(
+                    "
+                    /**
+                        {{
+                            \"api\":1,
+                            \"name\":\"Require\",
+                            \"description\":\"Requires a module\",
+                            \"icon\":\"html\"
+                        }}
+                    **/
+
+                    function main(state) {{
+                        require(\"{}\");
+                    }}",
+                    module
+                ),
+                PathBuf::new(),
+            )
+            .unwrap();
+
+            let status = script
+                .execute("", None, &Default::default(), TIMEOUT, &headless_host)
+                .unwrap();
+            if module == "@boop/missing" {
+                assert!(status
+                    .exception()
+                    .unwrap()
+                    .message
+                    .contains("no internal script"));
+            } else {
+                assert_eq!(None, status.exception());
+            }
+
+            // and can be imported by scripts that are ES modules
+            let mut script = Script::from_source(
+                format!(
+                    "
+                    /**
+                        {{
+                            \"api\":1,
+                            \"name\":\"Import\",
+                            \"description\":\"Imports a module\",
+                            \"icon\":\"html\"
+                        }}
+                    **/
+
+                    import * as module from \"{}\";
+
+                    export function main(state) {{}}",
+                    module
+                ),
+                PathBuf::new(),
+            )
+            .unwrap();
+
+            let status = script
+                .execute("", None, &Default::default(), TIMEOUT, &headless_host)
+                .unwrap();
+            assert_eq!(module == "@boop/missing", status.exception().is_some());
+        }
+    }
+
+    #[test]
+    fn test_es_module() {
+        let _guard = setup();
+
+        let source = |import: &str| {
+            format!(
+                "
+                /**
+                    {{
+                        \"api\":1,
+                        \"name\":\"Shout\",
+                        \"description\":\"Uppercases text\",
+                        \"icon\":\"html\"
+                    }}
+                **/
+
+                {}
+
+                const shout = text => text.toUpperCase();
+
+                export function main(state) {{
+                    state.text = shout(state.text);
+                }}",
+                import
+            )
+        };
+
+        let mut script = Script::from_source(source(""), PathBuf::new()).unwrap();
+        let status = script
+            .execute("hi", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert_eq!(None, status.exception());
+        assert_eq!(
+            TextReplacement::Full("HI".to_string()),
+            status.into_replacement()
+        );
+
+        let mut script = Script::from_source(
+            source("import { encode } from \"@boop/missing\";"),
+            PathBuf::new(),
+        )
+        .unwrap();
+        let status = script
+            .execute("hi", None, &Default::default(), TIMEOUT, &headless_host)
+            .unwrap();
+        assert!(status
+            .exception()
+            .unwrap()
+            .message
+            .contains("no internal script"));
+    }
+
+    #[test]
+    fn test_extra_scripts() {
+        let _guard = setup();
+
+        use rust_embed::RustEmbed;
+
+        #[derive(RustEmbed)]
+        #[folder = "../submodules/Boop/Scripts/"]
+        struct Scripts;
+
+        for file in Scripts::iter() {
+            if !file.ends_with(".js") {
+                continue; // not a javascript file
+            }
+
+            println!("testing {}", file);
+
+            let source: Cow<'static, [u8]> = Scripts::get(&file).unwrap();
+            let script_source = String::from_utf8(source.to_vec()).unwrap();
+
+            match Script::from_source(script_source, PathBuf::new()) {
+                Ok(mut script) => {
+                    script
+                        .execute(
+                            "foobar ♈ ♉ ♊ ♋ ♌ ♍ ♎ ♏ ♐ ♑ ♒ ♓ 😁 😝 😋 😄",
+                            None,
+                            &Default::default(),
+                            TIMEOUT,
+                            &headless_host,
+                        )
+                        .unwrap();
+                }
+                Err(e) => panic!(e),
+            }
+        }
+    }
+}
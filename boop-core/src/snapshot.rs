@@ -0,0 +1,177 @@
+// startup snapshot, a V8 heap where Boop's library scripts have already been compiled and run, so
+// isolates made from it can `require` them straight away. It's made on the first run and kept in
+// the data dir, V8 can only read snapshots made by the same build so they're keyed by the versions
+// of Boop and V8 as well as the library sources
+
+use crate::{
+    executor::{is_es_module, Executor},
+    Scripts,
+};
+use rusty_v8 as v8;
+use sha2::{Digest, Sha256};
+use std::{fs, path::Path, sync::RwLock};
+
+// global the library exports are kept in, keyed by the path they're required with
+pub const LIBRARIES_KEY: &str = "__boopLibraries";
+
+lazy_static! {
+    static ref BLOB: RwLock<Option<&'static [u8]>> = RwLock::new(None);
+}
+
+// snapshot isolates should be made from, if one has been loaded
+pub fn blob() -> Option<&'static [u8]> {
+    *BLOB.read().expect("failed to read snapshot")
+}
+
+// loads the snapshot from `data_dir`, making it if there isn't one for this build, isolates are
+// made without one if this fails
+pub fn load(data_dir: &Path) {
+    let snapshots_dir = data_dir.join("snapshots");
+    let path = snapshots_dir.join(format!("{}.bin", key()));
+
+    let blob = match fs::read(&path) {
+        Ok(blob) => blob,
+        Err(_) => {
+            info!("creating startup snapshot");
+
+            let blob = match create() {
+                Some(blob) => blob,
+                None => {
+                    warn!("could not create startup snapshot");
+                    return;
+                }
+            };
+
+            if let Err(e) = save(&snapshots_dir, &path, &blob) {
+                warn!("could not save startup snapshot: {}", e);
+            }
+
+            blob
+        }
+    };
+
+    // isolates borrow the snapshot for as long as they live, it's only loaded once
+    let blob: &'static [u8] = Box::leak(blob.into_boxed_slice());
+    BLOB.write()
+        .expect("failed to write snapshot")
+        .replace(blob);
+}
+
+// written to a temporary file first, as V8 can't read a partially written snapshot, snapshots from
+// other builds are removed
+fn save(snapshots_dir: &Path, path: &Path, blob: &[u8]) -> std::io::Result<()> {
+    fs::create_dir_all(snapshots_dir)?;
+
+    for entry in fs::read_dir(snapshots_dir)?.filter_map(Result::ok) {
+        if entry.path() != path {
+            fs::remove_file(entry.path())?;
+        }
+    }
+
+    let temporary_path = path.with_extension("tmp");
+    fs::write(&temporary_path, blob)?;
+    fs::rename(&temporary_path, path)
+}
+
+fn key() -> String {
+    let mut checksum = Sha256::new();
+    checksum.update(env!("CARGO_PKG_VERSION").as_bytes());
+    checksum.update(v8::V8::get_version().as_bytes());
+    for (path, source) in libraries() {
+        checksum.update(path.as_bytes());
+        checksum.update(source.as_bytes());
+    }
+
+    format!("{:x}", checksum.finalize())
+}
+
+// the library scripts, as the path they're required with and their source
+fn libraries() -> Vec<(String, String)> {
+    let mut libraries: Vec<(String, String)> = Scripts::iter()
+        .filter(|file| file.starts_with("lib/") && file.ends_with(".js"))
+        .filter_map(|file| {
+            let source = String::from_utf8(Scripts::get(&file)?.to_vec()).ok()?;
+            Some((file.replacen("lib/", "@boop/", 1), source))
+        })
+        .collect();
+    libraries.sort();
+
+    libraries
+}
+
+// runs each library script in a new context and snapshots it, libraries that throw are left out,
+// so are ES modules as they're imported rather than required
+#[allow(unsafe_code)]
+fn create() -> Option<Vec<u8>> {
+    let mut snapshot_creator = v8::SnapshotCreator::new(None);
+    // safe as the isolate is only taken once, and is forgotten rather than dropped as the snapshot
+    // creator disposes of it
+    let mut isolate = unsafe { snapshot_creator.get_owned_isolate() };
+
+    {
+        let scope = &mut v8::HandleScope::new(&mut isolate);
+        let context = v8::Context::new(scope);
+        let scope = &mut v8::ContextScope::new(scope, context);
+
+        let all_exports = v8::Object::new(scope);
+        for (path, source) in libraries() {
+            if is_es_module(&source) {
+                continue;
+            }
+
+            let tc_scope = &mut v8::TryCatch::new(scope);
+            match Executor::run_commonjs(tc_scope, &source) {
+                Some(exports) => {
+                    let key = v8::String::new(tc_scope, &path).expect("failed to create JS string");
+                    all_exports.set(tc_scope, key.into(), exports);
+                }
+                None => warn!("{} could not be added to the startup snapshot", path),
+            }
+        }
+
+        let global = context.global(scope);
+        let libraries_key =
+            v8::String::new(scope, LIBRARIES_KEY).expect("failed to create JS string");
+        global.set(scope, libraries_key.into(), all_exports.into());
+
+        snapshot_creator.set_default_context(context);
+    }
+
+    std::mem::forget(isolate);
+
+    snapshot_creator
+        .create_blob(v8::FunctionCodeHandling::Keep)
+        .map(|blob| blob.to_vec())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::tests::setup;
+    use std::convert::TryFrom;
+
+    #[test]
+    fn test_create() {
+        let _guard = setup();
+
+        let blob: &'static [u8] = Box::leak(create().unwrap().into_boxed_slice());
+        let mut isolate = v8::Isolate::new(v8::CreateParams::default().snapshot_blob(blob));
+        let scope = &mut v8::HandleScope::new(&mut isolate);
+        let context = v8::Context::new(scope);
+        let scope = &mut v8::ContextScope::new(scope, context);
+
+        let global = context.global(scope);
+        let libraries_key = v8::String::new(scope, LIBRARIES_KEY).unwrap();
+        let all_exports = global
+            .get(scope, libraries_key.into())
+            .and_then(|all_exports| v8::Local::<v8::Object>::try_from(all_exports).ok())
+            .unwrap();
+        let names = all_exports.get_own_property_names(scope).unwrap();
+
+        let expected = libraries()
+            .into_iter()
+            .filter(|(_, source)| !is_es_module(source))
+            .count();
+        assert_eq!(expected as u32, names.length());
+    }
+}
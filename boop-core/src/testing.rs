@@ -0,0 +1,184 @@
+use crate::{
+    config::Config,
+    executor::{headless_host, Executor},
+    script::{Metadata, Script},
+};
+use std::{fmt, fs, path::Path};
+
+// a script that couldn't be loaded, or a fixture whose output didn't match what its script
+// expected
+#[derive(Debug)]
+pub struct TestFailure {
+    // name of the script, or its file if its metadata couldn't be read
+    pub script: String,
+    // index of the fixture in the script's `tests` array, None if the script itself failed
+    pub fixture: Option<usize>,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+pub struct TestReport {
+    // scripts that were loaded without errors
+    pub loaded: usize,
+    pub passed: usize,
+    pub failures: Vec<TestFailure>,
+}
+
+impl TestReport {
+    pub fn is_success(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+impl fmt::Display for TestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for failure in &self.failures {
+            match failure.fixture {
+                Some(fixture) => writeln!(
+                    f,
+                    "FAILED {} (test {}): {}",
+                    failure.script,
+                    fixture + 1,
+                    failure.reason
+                )?,
+                None => writeln!(f, "FAILED {}: {}", failure.script, failure.reason)?,
+            }
+        }
+
+        write!(
+            f,
+            "{} scripts loaded, {} passed, {} failed",
+            self.loaded,
+            self.passed,
+            self.failures.len()
+        )
+    }
+}
+
+// checks the metadata of each file in `scripts_dir`, then that each script loads and runs the
+// fixtures declared in its `tests` metadata, executors are killed afterwards so state left behind
+// by the fixtures doesn't leak into normal use
+pub fn run_tests(scripts: &mut [Script], config: &Config, scripts_dir: &Path) -> TestReport {
+    let mut report = TestReport::default();
+
+    report.failures.extend(check_metadata(scripts_dir));
+
+    for script in scripts.iter_mut() {
+        if let Some(reason) = load_error(script, config) {
+            report.failures.push(TestFailure {
+                script: script.metadata.name.clone(),
+                fixture: None,
+                reason,
+            });
+            continue;
+        }
+        report.loaded += 1;
+
+        if script.metadata.tests.is_empty() {
+            continue;
+        }
+
+        info!(
+            "running {} tests for {}",
+            script.metadata.tests.len(),
+            script.metadata.name
+        );
+
+        let timeout = config.script_timeout(&script.metadata);
+
+        for (index, fixture) in script.metadata.tests.clone().into_iter().enumerate() {
+            let result = script.execute(
+                &fixture.input,
+                fixture.selection.as_deref(),
+                &Default::default(),
+                timeout,
+                &headless_host,
+            );
+
+            let reason = match result {
+                Err(e) => Some(format!("could not execute script: {}", e)),
+                Ok(status) => match status.exception() {
+                    Some(exception) => Some(format!("threw {}", exception)),
+                    None => {
+                        let output = status
+                            .into_replacement()
+                            .apply(&fixture.input, fixture.selection.as_deref());
+                        if output == fixture.expected {
+                            None
+                        } else {
+                            Some(format!("expected {:?}, got {:?}", fixture.expected, output))
+                        }
+                    }
+                },
+            };
+
+            match reason {
+                Some(reason) => report.failures.push(TestFailure {
+                    script: script.metadata.name.clone(),
+                    fixture: Some(index),
+                    reason,
+                }),
+                None => report.passed += 1,
+            }
+        }
+
+        script.kill_thread();
+    }
+
+    report
+}
+
+// scripts whose metadata is invalid aren't loaded, and fields that aren't known are most likely
+// misspelt, so both fail
+fn check_metadata(scripts_dir: &Path) -> Vec<TestFailure> {
+    let mut failures = Vec::new();
+
+    let mut paths: Vec<_> = match fs::read_dir(scripts_dir) {
+        Ok(entries) => entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .collect(),
+        Err(e) => {
+            warn!("could not read {}: {}", scripts_dir.display(), e);
+            return failures;
+        }
+    };
+    paths.sort();
+
+    for path in paths {
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let reasons = match fs::read_to_string(&path) {
+            Ok(source) => match Metadata::from_source(&source) {
+                Ok((_, warnings)) => warnings,
+                Err(e) => vec![format!("invalid script: {}", e)],
+            },
+            Err(e) => vec![format!("could not read script: {}", e)],
+        };
+
+        failures.extend(reasons.into_iter().map(|reason| TestFailure {
+            script: file_name.clone(),
+            fixture: None,
+            reason,
+        }));
+    }
+
+    failures
+}
+
+// why the script can't be loaded, e.g. a syntax error or a missing main function
+fn load_error(script: &Script, config: &Config) -> Option<String> {
+    if script.is_native() {
+        return None;
+    }
+
+    match script.source() {
+        Ok(source) => Executor::new(&source, config.script_heap_limit())
+            .init_error()
+            .map(|e| format!("could not load script: {}", e)),
+        Err(e) => Some(format!("could not read script: {}", e)),
+    }
+}
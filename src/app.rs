@@ -1,35 +1,140 @@
 use crate::{
-    command_pallete::CommandPalleteDialog,
+    browser::ScriptBrowser,
+    carets::Carets,
+    cli, color_scheme,
+    command_pallete::{CommandPalleteDialog, RunMode, PIPELINE_ID_BASE},
+    completion::{self, Completion},
+    config::{Appearance, Config, DisabledScripts, Theme},
+    diff::{self, DiffLine},
+    editor::{self, ScriptEditor},
     executor::{self},
-    script::Script,
+    favorites::Favorites,
+    files,
+    find::FindBar,
+    importer, installer,
+    keybindings::Keybindings,
+    line_ending::{self, LineEnding},
+    manager::ScriptManager,
+    markdown, metrics,
+    permissions::{Permission, Permissions},
+    pipeline::{Pipeline, Pipelines},
+    preferences::{self, Preferences},
+    preview,
+    queue::{ExecutionQueue, QueuedRun},
+    recent::Recent,
+    script::{self, Script},
+    script_state,
+    search::SearchIndex,
+    selection,
+    shortcuts::{Conflict, KeyResult, Shortcuts},
+    stats::{self, Stats},
+    store::Store,
+    suggestions::{self, ContentKind},
+    sync,
+    tabs::{Session, Tabs},
+    testing,
+    watcher::ScriptEvent,
+    PROJECT_DIRS,
 };
 use gdk_pixbuf::prelude::*;
 use gladis::Gladis;
 use gtk::prelude::*;
 use sourceview::prelude::*;
 
-use executor::TextReplacement;
-use gtk::{AboutDialog, ApplicationWindow, Button, ModelButton, Statusbar};
+use executor::{
+    insertion_edits, utf16_to_char_offset, Artifact, ChosenFile, ConsoleLevel, DocumentContext,
+    ExecutionStatus, FileFilter, Highlight, HighlightKind, HostRequest, HostResponse, Notification,
+    PickedColor, SaveAs, ScriptError, TextReplacement,
+};
+use gio::prelude::*;
+use gtk::{AboutDialog, ApplicationWindow, Button, Label, ModelButton, Statusbar};
+use simple_error::{bail, SimpleError};
 use std::{
-    path::Path,
+    cell::{Cell, RefCell, RefMut},
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
     sync::{Arc, RwLock},
+    time::Duration,
 };
 
 const HEADER_BUTTON_GET_STARTED: &str = "Press Ctrl+Shift+P to get started";
 const HEADER_BUTTON_CHOOSE_ACTION: &str = "Select an action";
 
+// how often a running script is checked on
+const POLL_INTERVAL_MS: u32 = 10;
+// how often the open tabs are saved, besides when the window is closed
+const SESSION_SAVE_INTERVAL_SECS: u32 = 30;
+
+// the status bar's counts are updated once the text has stopped changing for this long
+const STATS_DELAY_MS: u32 = 150;
+
+// id the status bar's language selector uses for text that isn't highlighted
+const PLAIN_TEXT_ID: &str = "plain";
+
+const POP_UP_WIDTH: i32 = 480;
+const POP_UP_HEIGHT: i32 = 320;
+
+const PREVIEW_WIDTH: i32 = 700;
+const PREVIEW_HEIGHT: i32 = 500;
+
 #[derive(Gladis, Clone, Shrinkwrap)]
 pub struct AppWidgets {
     #[shrinkwrap(main_field)]
     window: ApplicationWindow,
 
     header_button: Button,
+    cancel_queue_button: Button,
+    cancel_script_button: Button,
+    run_spinner: gtk::Spinner,
     source_view: sourceview::View,
+    editor_box: gtk::Box,
+    editor_overlay: gtk::Overlay,
+    tabs_notebook: gtk::Notebook,
+    file_label: Label,
     status_bar: Statusbar,
+    stats_label: Label,
+    convert_button: gtk::MenuButton,
+    language_combo: gtk::ComboBoxText,
+    message_revealer: gtk::Revealer,
+    message_label: Label,
+    message_copy_button: Button,
+    message_expand_button: gtk::ToggleButton,
+    console_expander: gtk::Expander,
+    console_view: gtk::TextView,
+
+    output_pane: gtk::Box,
+    output_label: Label,
+    output_close_button: Button,
+    output_artifacts: gtk::ListBox,
+    output_window: gtk::ScrolledWindow,
+    output_view: sourceview::View,
+    preview_window: gtk::ScrolledWindow,
+    preview_label: Label,
+
+    history_button: gtk::ToggleButton,
+    pinned_box: gtk::Box,
+    history_revealer: gtk::Revealer,
+    history_list: gtk::ListBox,
 
     reset_scripts_button: ModelButton,
+    self_test_button: ModelButton,
+    clear_data_button: ModelButton,
+    usage_metrics_button: ModelButton,
     config_directory_button: ModelButton,
+    install_script_button: ModelButton,
+    import_scripts_button: ModelButton,
+    new_script_button: ModelButton,
+    new_pipeline_button: ModelButton,
+    open_file_button: ModelButton,
+    save_file_button: ModelButton,
+    save_file_as_button: ModelButton,
+    transform_clipboard_button: ModelButton,
+    browse_scripts_button: ModelButton,
+    manage_scripts_button: ModelButton,
     more_scripts_button: ModelButton,
+    preferences_button: ModelButton,
     about_button: ModelButton,
 
     about_dialog: AboutDialog,
@@ -40,20 +145,103 @@ pub struct App {
     #[shrinkwrap(main_field)]
     widgets: AppWidgets,
 
+    config_dir: PathBuf,
+    data_dir: PathBuf,
     context_id: u32,
+    shortcut_context_id: u32,
     scripts: Arc<RwLock<Vec<Script>>>,
+    shortcuts: Rc<RefCell<Shortcuts>>,
+    search_index: Rc<RefCell<SearchIndex>>,
+    config: Rc<RefCell<Config>>,
+    // scripts run and waiting to run on each tab, by tab id
+    queues: Rc<RefCell<HashMap<usize, ExecutionQueue>>>,
+    // results of scripts that finished while another tab was shown, applied once their tab is
+    // shown again
+    finished: Rc<RefCell<HashMap<usize, (QueuedRun, Result<ExecutionStatus, SimpleError>)>>>,
+    store: Rc<RefCell<Store>>,
+    recent: Rc<RefCell<Recent>>,
+    favorites: Rc<RefCell<Favorites>>,
+    // scripts hidden from the command pallete
+    disabled_scripts: Rc<RefCell<DisabledScripts>>,
+    // true while ranges highlighted by a script are shown
+    highlighted: Rc<Cell<bool>>,
+    tabs: Tabs,
+    completion: Completion,
+    find_bar: FindBar,
+    carets: Carets,
+    keybindings: Keybindings,
+    appearance: Rc<RefCell<Appearance>>,
+    // the editor font picked in the preferences
+    font_provider: gtk::CssProvider,
+    // the desktop prefers dark applications
+    system_dark: Rc<Cell<bool>>,
+    // the last script to finish and how long it ran for, shown in the status bar
+    last_run: Rc<RefCell<Option<(String, Duration)>>>,
+    // an update of the status bar's counts is waiting for the text to stop changing
+    stats_pending: Rc<Cell<bool>>,
 }
 
 impl App {
     pub fn new(config_dir: &Path, scripts: Arc<RwLock<Vec<Script>>>) -> Self {
+        let widgets = AppWidgets::from_resource("/co/uk/mrbenshef/Boop-GTK/boop-gtk.glade")
+            .unwrap_or_else(|e| panic!("failed to load boop-gtk.glade: {}", e)); // TODO: don't debug print once gladis errors implement display
+        let tabs = Tabs::new(widgets.tabs_notebook.clone(), widgets.source_view.clone());
+        let completion = completion::setup(&widgets.source_view);
+        let find_bar = FindBar::new(&widgets.source_view);
+        widgets
+            .editor_box
+            .pack_start(find_bar.widget(), false, true, 0);
+        // between the tabs and the editor
+        widgets.editor_box.reorder_child(find_bar.widget(), 1);
+        let carets = Carets::new(&widgets.source_view);
+        widgets.editor_overlay.add_overlay(carets.widget());
+        widgets
+            .editor_overlay
+            .set_overlay_pass_through(carets.widget(), true);
+        carets.widget().show();
+        let keybindings = Keybindings::new(&widgets.source_view);
+        widgets
+            .status_bar
+            .pack_end(keybindings.widget(), false, false, 0);
+        keybindings.widget().show();
+
         let mut app = App {
-            widgets: AppWidgets::from_resource("/co/uk/mrbenshef/Boop-GTK/boop-gtk.glade")
-                .unwrap_or_else(|e| panic!("failed to load boop-gtk.glade: {}", e)), // TODO: don't debug print once gladis errors implement display
+            widgets,
+            config_dir: config_dir.to_path_buf(),
+            data_dir: PROJECT_DIRS.data_dir().to_path_buf(),
             context_id: 0,
+            shortcut_context_id: 0,
             scripts,
+            shortcuts: Rc::new(RefCell::new(Shortcuts::default())),
+            search_index: Rc::new(RefCell::new(SearchIndex::default())),
+            config: Rc::new(RefCell::new(Config::load(config_dir))),
+            queues: Rc::new(RefCell::new(HashMap::new())),
+            finished: Rc::new(RefCell::new(HashMap::new())),
+            store: Rc::new(RefCell::new(Store::load(PROJECT_DIRS.data_dir()))),
+            recent: Rc::new(RefCell::new(Recent::load(config_dir))),
+            favorites: Rc::new(RefCell::new(Favorites::load(config_dir))),
+            disabled_scripts: Rc::new(RefCell::new(DisabledScripts::load(config_dir))),
+            highlighted: Rc::new(Cell::new(false)),
+            tabs,
+            completion,
+            find_bar,
+            carets,
+            keybindings,
+            appearance: Rc::new(RefCell::new(Appearance::load(config_dir))),
+            font_provider: gtk::CssProvider::new(),
+            system_dark: Rc::new(Cell::new(
+                gtk::Settings::get_default()
+                    .and_then(|settings| settings.get_property_gtk_theme_name())
+                    .map(|theme_name| color_scheme::theme_name_is_dark(&theme_name))
+                    .unwrap_or(false),
+            )),
+            last_run: Rc::new(RefCell::new(None)),
+            stats_pending: Rc::new(Cell::new(false)),
         };
 
         app.context_id = app.status_bar.get_context_id("script execution");
+        app.shortcut_context_id = app.status_bar.get_context_id("shortcuts");
+        app.setup_status_bar();
         app.header_button.set_label(HEADER_BUTTON_GET_STARTED);
         app.about_dialog.set_logo(
             gdk_pixbuf::Pixbuf::from_resource("/co/uk/mrbenshef/Boop-GTK/boop-gtk.png")
@@ -61,6 +249,80 @@ impl App {
                 .as_ref(),
         );
         app.setup_syntax_highlighting(config_dir);
+        app.setup_shortcuts(config_dir);
+        for view in &[&app.source_view, &app.output_view] {
+            view.get_style_context()
+                .add_provider(&app.font_provider, gtk::STYLE_PROVIDER_PRIORITY_APPLICATION);
+        }
+        app.apply_appearance();
+        app.update_pinned_buttons();
+
+        // long messages are shown in full below the editor
+        app.message_expand_button.set_no_show_all(true);
+        {
+            let message_revealer = app.message_revealer.clone();
+            app.message_expand_button.connect_toggled(move |button| {
+                message_revealer.set_reveal_child(button.get_active())
+            });
+        }
+        {
+            let message_label = app.message_label.clone();
+            app.message_copy_button.connect_clicked(move |_| {
+                gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD).set_text(&message_label.get_text())
+            });
+        }
+
+        // highlights are only shown until the text changes
+        for view in &[&app.source_view, &app.output_view] {
+            let app_ = app.clone();
+            view.get_buffer()
+                .expect("failed to get buffer")
+                .connect_changed(move |_| app_.clear_highlights());
+        }
+
+        // output pane is shown once a script is run with RunMode::OutputPane
+        app.output_pane.set_no_show_all(true);
+        app.output_artifacts.set_no_show_all(true);
+        app.preview_window.set_no_show_all(true);
+        {
+            let output_pane = app.output_pane.clone();
+            app.output_close_button
+                .connect_clicked(move |_| output_pane.hide());
+        }
+
+        // changes made by scripts, shown in a sidebar
+        app.show_history();
+        {
+            let history_revealer = app.history_revealer.clone();
+            app.history_button.connect_toggled(move |button| {
+                history_revealer.set_reveal_child(button.get_active())
+            });
+        }
+
+        // drop scripts waiting for the running one to finish
+        app.cancel_queue_button.set_no_show_all(true);
+        {
+            let app_ = app.clone();
+            app.cancel_queue_button.connect_clicked(move |_| {
+                let cancelled = app_.queue().cancel_queued();
+                info!("cancelled {} queued scripts", cancelled);
+                app_.run_next();
+            });
+        }
+
+        // shown once a script writes to the console
+        app.console_expander.set_no_show_all(true);
+
+        // stop the running script, shown while one is running
+        app.cancel_script_button.set_no_show_all(true);
+        app.run_spinner.set_no_show_all(true);
+        app.file_label.set_no_show_all(true);
+        {
+            let app_ = app.clone();
+            app.cancel_script_button.connect_clicked(move |_| {
+                app_.cancel_script();
+            });
+        }
 
         let context_id = app.context_id;
 
@@ -73,11 +335,72 @@ impl App {
                     .expect("scripts lock is poisoned")
                     .iter_mut()
                 {
-                    script.kill_thread();
+                    script.reset();
+                }
+            });
+        }
+
+        // run the test fixtures declared by scripts and show the results
+        {
+            let window = app.window.clone();
+            let scripts = app.scripts.clone();
+            let config = app.config.clone();
+            let scripts_dir = app.config_dir.join("scripts");
+            app.self_test_button.connect_clicked(move |_| {
+                let report = testing::run_tests(
+                    &mut scripts.write().expect("scripts lock is poisoned"),
+                    &config.borrow(),
+                    &scripts_dir,
+                );
+
+                let dialog = gtk::MessageDialog::new(
+                    Some(&window),
+                    gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+                    if report.is_success() {
+                        gtk::MessageType::Info
+                    } else {
+                        gtk::MessageType::Warning
+                    },
+                    gtk::ButtonsType::Close,
+                    "Script Self-Tests",
+                );
+                dialog.set_property_secondary_text(Some(&report.to_string()));
+                dialog.run();
+                dialog.close();
+            });
+        }
+
+        // forget script state, usage and history
+        {
+            let app_ = app.clone();
+            app.clear_data_button.connect_clicked(move |_| {
+                if !app_.ask_permission(
+                    "Clear stored data?",
+                    "State kept by scripts, how often they were used and the history of changes \
+                     will be forgotten.",
+                ) {
+                    return;
+                }
+
+                if let Err(e) = app_
+                    .store
+                    .borrow_mut()
+                    .clear(&app_.data_dir)
+                    .and_then(|_| script_state::clear(&app_.config_dir))
+                {
+                    error!("could not clear stored data: {}", e);
+                    app_.push_error("failed to clear stored data");
                 }
+                app_.show_history();
             });
         }
 
+        {
+            let app_ = app.clone();
+            app.usage_metrics_button
+                .connect_clicked(move |_| app_.show_usage_metrics());
+        }
+
         // launch config directory in default file manager
         {
             let status_bar = app.status_bar.clone();
@@ -94,6 +417,150 @@ impl App {
             });
         }
 
+        {
+            let app_ = app.clone();
+            app.install_script_button
+                .connect_clicked(move |_| app_.install_script());
+        }
+
+        {
+            let app_ = app.clone();
+            app.import_scripts_button
+                .connect_clicked(move |_| app_.import_scripts());
+        }
+
+        {
+            let app_ = app.clone();
+            app.new_script_button
+                .connect_clicked(move |_| app_.edit_script(None));
+        }
+
+        {
+            let app_ = app.clone();
+            app.new_pipeline_button
+                .connect_clicked(move |_| app_.edit_pipeline(None));
+        }
+
+        {
+            let app_ = app.clone();
+            app.transform_clipboard_button
+                .connect_clicked(move |_| app_.transform_clipboard());
+        }
+
+        {
+            let app_ = app.clone();
+            app.browse_scripts_button
+                .connect_clicked(move |_| app_.browse_scripts());
+        }
+
+        {
+            let app_ = app.clone();
+            app.manage_scripts_button
+                .connect_clicked(move |_| app_.manage_scripts());
+        }
+
+        {
+            let app_ = app.clone();
+            app.open_file_button
+                .connect_clicked(move |_| app_.open_file());
+        }
+        {
+            let app_ = app.clone();
+            app.save_file_button.connect_clicked(move |_| {
+                app_.save_file();
+            });
+        }
+        {
+            let app_ = app.clone();
+            app.save_file_as_button.connect_clicked(move |_| {
+                app_.save_file_as();
+            });
+        }
+
+        // the file the current tab belongs to is shown in the header bar
+        {
+            let app_ = app.clone();
+            app.source_view
+                .get_buffer()
+                .expect("failed to get buffer")
+                .connect_modified_changed(move |_| app_.update_file_label());
+        }
+        {
+            let app_ = app.clone();
+            // connected after the tabs, so the tab's buffer is already shown
+            app.tabs_notebook.connect_switch_page(move |_, _, _| {
+                app_.update_file_label();
+                app_.show_language();
+                app_.queue_stats_update();
+                app_.find_bar.attach();
+                app_.carets.clear();
+                app_.show_history();
+                app_.finish_waiting();
+            });
+        }
+        app.watch_stats(&app.source_view.get_buffer().expect("failed to get buffer"));
+        app.carets
+            .watch(&app.source_view.get_buffer().expect("failed to get buffer"));
+        app.update_stats();
+
+        // pasted text is highlighted as the language it looks like
+        {
+            let app_ = app.clone();
+            app.source_view
+                .get_buffer()
+                .expect("failed to get buffer")
+                .downcast::<sourceview::Buffer>()
+                .expect("faild to downcast TextBuffer to sourceview Buffer")
+                .connect_paste_done(move |buffer, _| app_.pasted(buffer));
+        }
+
+        // files dropped onto the editor open in new tabs, dropped text is still inserted
+        {
+            let uri_list = gdk::Atom::intern("text/uri-list");
+            if let Some(targets) = app.source_view.drag_dest_get_target_list() {
+                targets.add_uri_targets(0);
+            }
+            // the text view would take the file's uri as text if it's offered that too
+            app.source_view
+                .connect_drag_drop(move |view, context, _, _, time| {
+                    if context.list_targets().contains(&uri_list) {
+                        view.drag_get_data(context, &uri_list, time);
+                        gtk::Inhibit(true)
+                    } else {
+                        gtk::Inhibit(false)
+                    }
+                });
+
+            let app_ = app.clone();
+            app.source_view.connect_drag_data_received(
+                move |view, context, _, _, data, _, time| {
+                    let uris = data.get_uris();
+                    if uris.is_empty() {
+                        return;
+                    }
+                    glib::signal::signal_stop_emission_by_name(view, "drag-data-received");
+                    context.drag_finish(true, false, time);
+
+                    for uri in uris {
+                        match glib::filename_from_uri(&uri) {
+                            Ok((path, _)) => app_.load_file(path),
+                            Err(e) => {
+                                error!("could not open {}: {}", uri, e);
+                                app_.push_error(format!("can't open {}", uri));
+                            }
+                        }
+                    }
+                },
+            );
+        }
+
+        // ask before closing with unsaved files
+        {
+            let app_ = app.clone();
+            app.window
+                .connect_delete_event(move |_, _| gtk::Inhibit(!app_.confirm_close()));
+        }
+
         // launch more scripts page in default web browser
         {
             let status_bar = app.status_bar.clone();
@@ -105,6 +572,12 @@ impl App {
             });
         }
 
+        {
+            let app_ = app.clone();
+            app.preferences_button
+                .connect_clicked(move |_| app_.show_preferences());
+        }
+
         {
             let about_dialog = app.about_dialog.clone();
             app.about_button.connect_clicked(move |_| {
@@ -118,9 +591,216 @@ impl App {
                 .connect_clicked(move |_| app_.open_command_pallete());
         }
 
+        {
+            let app_ = app.clone();
+            app.window
+                .connect_key_press_event(move |_, event| app_.on_key_press(event));
+        }
+
         app
     }
 
+    // the label showing the most recent message in the status bar
+    fn status_label(&self) -> Option<gtk::Label> {
+        self.status_bar
+            .get_message_area()?
+            .get_children()
+            .into_iter()
+            .find_map(|child| child.downcast::<gtk::Label>().ok())
+    }
+
+    fn setup_status_bar(&self) {
+        // allow multi-line messages from scripts
+        if let Some(label) = self.status_label() {
+            label.set_single_line_mode(false);
+            label.set_selectable(true);
+        }
+
+        // conversions of the line endings and encoding the counts show
+        let line_endings = gio::Menu::new();
+        for line_ending in &[LineEnding::Lf, LineEnding::CrLf, LineEnding::Cr] {
+            line_endings.append(
+                Some(&format!("Convert to {}", line_ending)),
+                Some(&format!("app.convert_line_endings::{}", line_ending.id())),
+            );
+        }
+        let encoding = gio::Menu::new();
+        encoding.append(Some("Save as UTF-8"), Some("app.convert_to_utf8"));
+        let menu = gio::Menu::new();
+        menu.append_section(Some("Line Endings"), &line_endings);
+        menu.append_section(Some("Encoding"), &encoding);
+        self.convert_button.set_menu_model(Some(&menu));
+    }
+
+    fn setup_shortcuts(&self, config_dir: &Path) {
+        let conflicts = self.bind_shortcuts(config_dir);
+        if !conflicts.is_empty() {
+            self.push_error(format!(
+                "{} shortcut(s) not bound: {}",
+                conflicts.len(),
+                conflicts
+                    .iter()
+                    .map(Conflict::to_string)
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            ));
+
+            // wait for the window to be shown
+            let app = self.clone();
+            glib::idle_add_local(move || {
+                app.resolve_shortcut_conflicts(&conflicts);
+                glib::Continue(false)
+            });
+        }
+    }
+
+    // binds the shortcuts scripts suggest and the user picked, returning those that couldn't be
+    fn bind_shortcuts(&self, config_dir: &Path) -> Vec<Conflict> {
+        let overrides = Shortcuts::load_overrides(config_dir);
+        let (shortcuts, conflicts) = Shortcuts::new(
+            &self.scripts.read().expect("scripts lock is poisoned"),
+            &overrides,
+        );
+        let previous = self.shortcuts.replace(shortcuts).accels();
+        self.set_shortcut_accels(&previous);
+        conflicts
+    }
+
+    // sets the accelerators of `app.run_script` for the scripts bound to a single key, removing
+    // the `previous` ones, the window needs to belong to the application
+    pub fn set_shortcut_accels(&self, previous: &[(String, String)]) {
+        let application = match self.window.get_application() {
+            Some(application) => application,
+            None => return,
+        };
+
+        for (script_name, _) in previous {
+            application.set_accels_for_action(&format!("app.run_script::{}", script_name), &[]);
+        }
+        for (script_name, accel) in self.shortcuts.borrow().accels() {
+            application
+                .set_accels_for_action(&format!("app.run_script::{}", script_name), &[&accel]);
+        }
+    }
+
+    // lets the user pick a different shortcut for each script whose shortcut couldn't be bound
+    fn resolve_shortcut_conflicts(&self, conflicts: &[Conflict]) {
+        let dialog = gtk::Dialog::with_buttons(
+            Some("Shortcut Conflicts"),
+            Some(&self.window),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            &[
+                ("_Ignore", gtk::ResponseType::Cancel),
+                ("_Save", gtk::ResponseType::Accept),
+            ],
+        );
+
+        let grid = gtk::Grid::new();
+        grid.set_row_spacing(6);
+        grid.set_column_spacing(12);
+        grid.set_border_width(12);
+
+        let help = Label::new(Some(
+            "These shortcuts couldn't be bound. Enter a different shortcut for each script, \
+             e.g. <Primary><Alt>J, or leave it empty to remove it.",
+        ));
+        help.set_line_wrap(true);
+        help.set_xalign(0.0);
+        grid.attach(&help, 0, 0, 2, 1);
+
+        let entries: Vec<gtk::Entry> = conflicts
+            .iter()
+            .enumerate()
+            .map(|(index, conflict)| {
+                let label = Label::new(Some(&conflict.to_string()));
+                label.set_xalign(0.0);
+                label.set_line_wrap(true);
+                grid.attach(&label, 0, index as i32 + 1, 1, 1);
+
+                let entry = gtk::Entry::new();
+                entry.set_text(&conflict.shortcut);
+                grid.attach(&entry, 1, index as i32 + 1, 1, 1);
+
+                entry
+            })
+            .collect();
+
+        dialog.get_content_area().add(&grid);
+        dialog.show_all();
+
+        if dialog.run() == gtk::ResponseType::Accept {
+            let mut overrides = Shortcuts::load_overrides(&self.config_dir);
+            for (conflict, entry) in conflicts.iter().zip(entries) {
+                let shortcut = entry.get_text().trim().to_string();
+                overrides.insert(
+                    conflict.script_name.clone(),
+                    Some(shortcut).filter(|shortcut| !shortcut.is_empty()),
+                );
+            }
+
+            match Shortcuts::save_overrides(&self.config_dir, &overrides) {
+                Ok(()) => {
+                    dialog.close();
+                    self.clear_messages();
+                    self.setup_shortcuts(&self.config_dir);
+                    return;
+                }
+                Err(e) => {
+                    error!("could not save shortcuts: {}", e);
+                    self.push_error("failed to save shortcuts");
+                }
+            }
+        }
+
+        dialog.close();
+    }
+
+    fn on_key_press(&self, event: &gdk::EventKey) -> gtk::Inhibit {
+        if event.get_is_modifier() {
+            return gtk::Inhibit(false);
+        }
+
+        if event.get_keyval() == gdk::keys::constants::Escape && self.cancel_script() {
+            return gtk::Inhibit(true);
+        }
+
+        if self.keybindings.key_pressed(event) {
+            return gtk::Inhibit(true);
+        }
+
+        let result = self
+            .shortcuts
+            .borrow_mut()
+            .handle_key(*event.get_keyval(), event.get_state());
+
+        self.status_bar.remove_all(self.shortcut_context_id);
+
+        match result {
+            KeyResult::Unhandled => gtk::Inhibit(false),
+            KeyResult::Pending => {
+                let strokes = self.shortcuts.borrow().pending_label();
+                self.status_bar.push(
+                    self.shortcut_context_id,
+                    &format!("{} was pressed, waiting for next key...", strokes),
+                );
+                gtk::Inhibit(true)
+            }
+            KeyResult::Matched(script_name) => {
+                match self.window.get_application() {
+                    Some(application) => {
+                        application.activate_action("run_script", Some(&script_name.to_variant()))
+                    }
+                    None => {
+                        self.record_feature("shortcut");
+                        self.run_script_named(&script_name, RunMode::Replace);
+                    }
+                }
+                gtk::Inhibit(true)
+            }
+            KeyResult::Cancelled => gtk::Inhibit(true),
+        }
+    }
+
     fn setup_syntax_highlighting(&self, config_dir: &Path) {
         let language_manager =
             sourceview::LanguageManager::get_default().expect("failed to get language manager");
@@ -134,6 +814,11 @@ impl App {
 
         info!("language manager search directorys: {}", dirs.join(":"));
 
+        // Boop's style schemes are written to config_dir too
+        if let Some(style_scheme_manager) = sourceview::StyleSchemeManager::get_default() {
+            style_scheme_manager.append_search_path(&config_dir_path);
+        }
+
         let boop_language = language_manager.get_language("boop");
         if boop_language.is_none() {
             self.status_bar
@@ -149,108 +834,2901 @@ impl App {
             .expect("faild to downcast TextBuffer to sourceview Buffer");
         buffer.set_highlight_syntax(true);
         buffer.set_language(boop_language.as_ref());
+
+        // languages can also be picked from the status bar, by name
+        let mut languages: Vec<(String, String)> = language_manager
+            .get_language_ids()
+            .iter()
+            .filter_map(|id| language_manager.get_language(id))
+            .filter(|language| !language.get_hidden())
+            .filter_map(|language| {
+                Some((
+                    language.get_id()?.to_string(),
+                    language.get_name()?.to_string(),
+                ))
+            })
+            .collect();
+        languages.sort_by_key(|(_, name)| name.to_lowercase());
+        self.language_combo
+            .append(Some(PLAIN_TEXT_ID), "Plain Text");
+        for (id, name) in &languages {
+            self.language_combo.append(Some(id), name);
+        }
+        self.show_language();
+
+        let app = self.clone();
+        self.language_combo.connect_changed(move |combo| {
+            let id = match combo.get_active_id() {
+                Some(id) => id,
+                None => return,
+            };
+            let buffer = app.source_buffer();
+            let current = buffer
+                .get_language()
+                .and_then(|language| language.get_id())
+                .map(|id| id.to_string())
+                .unwrap_or_else(|| PLAIN_TEXT_ID.to_string());
+            if current != id.as_str() {
+                buffer.set_language(
+                    sourceview::LanguageManager::get_default()
+                        .and_then(|language_manager| language_manager.get_language(&id))
+                        .as_ref(),
+                );
+            }
+        });
     }
 
-    fn push_error_(status_bar: gtk::Statusbar, context_id: u32, error: impl std::fmt::Display) {
-        status_bar.push(context_id, &format!("ERROR: {}", error));
+    pub fn show_preferences(&self) {
+        let app = self.clone();
+        let app_ = self.clone();
+        let app__ = self.clone();
+        Preferences::new(
+            &self.window,
+            self.appearance.clone(),
+            self.scripts.clone(),
+            self.shortcuts.clone(),
+            self.config_dir.clone(),
+            move || app.apply_appearance(),
+            // conflicts were checked as each shortcut was picked
+            move || {
+                app_.bind_shortcuts(&app_.config_dir);
+            },
+            move || app__.sync_repositories(),
+        )
+        .show();
     }
 
-    pub fn push_error(&self, error: impl std::fmt::Display) {
-        App::push_error_(self.status_bar.clone(), self.context_id, error);
+    // clones or pulls the git repositories scripts are synced from in the background, the watcher
+    // loads the scripts that changed
+    pub fn sync_repositories(&self) {
+        let repositories = sync::Repositories::load(&self.config_dir).repositories;
+        if repositories.is_empty() {
+            return;
+        }
+
+        self.status_bar
+            .push(self.context_id, "Syncing script repositories…");
+        let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+        sync::sync_in_background(self.config_dir.join("scripts"), repositories, sender);
+
+        let app = self.clone();
+        receiver.attach(None, move |(name, result)| {
+            match result {
+                Ok(()) => app
+                    .status_bar
+                    .push(app.context_id, &format!("Synced scripts from {}", name)),
+                Err(e) => {
+                    error!("could not sync {}: {}", name, e);
+                    app.push_error(format!("could not sync {}: {}", name, e));
+                }
+            };
+            glib::Continue(true)
+        });
     }
 
-    pub fn open_command_pallete(&self) {
-        let dialog = CommandPalleteDialog::new(&self.window, self.scripts.clone());
-        dialog.show_all();
+    // true if Boop should be dark, because it's set to be or the desktop is
+    fn is_dark(&self) -> bool {
+        match self.appearance.borrow().theme {
+            Theme::System => self.system_dark.get(),
+            Theme::Light => false,
+            Theme::Dark => true,
+        }
+    }
 
-        self.header_button.set_label(HEADER_BUTTON_CHOOSE_ACTION);
+    // follows the desktop's preference for dark applications unless Boop is set to be light or
+    // dark
+    pub fn set_system_dark(&self, dark: bool) {
+        if self.system_dark.replace(dark) != dark {
+            self.apply_appearance();
+        }
+    }
 
-        if let gtk::ResponseType::Other(script_id) = dialog.run() {
-            info!(
-                "executing {}",
-                self.scripts.read().expect("scripts lock is poisoned")[script_id as usize]
-                    .metadata
-                    .name
-            );
+    // GtkSourceView's own schemes are used if Boop's couldn't be loaded
+    fn style_scheme(&self) -> Option<sourceview::StyleScheme> {
+        let (id, default, fallback) = if self.is_dark() {
+            (
+                self.appearance.borrow().dark_style_scheme.clone(),
+                preferences::DEFAULT_DARK_STYLE_SCHEME,
+                "oblivion",
+            )
+        } else {
+            (
+                self.appearance.borrow().style_scheme.clone(),
+                preferences::DEFAULT_STYLE_SCHEME,
+                "classic",
+            )
+        };
+        let manager = sourceview::StyleSchemeManager::get_default()?;
+        manager
+            .get_scheme(id.as_deref().unwrap_or(default))
+            .or_else(|| manager.get_scheme(fallback))
+    }
 
-            self.status_bar.remove_all(self.context_id);
+    // shows the editor and output pane as they're set up in the preferences
+    fn apply_appearance(&self) {
+        let appearance = self.appearance.borrow().clone();
 
-            let buffer = &self.source_view.get_buffer().expect("failed to get buffer");
+        for view in &[&self.source_view, &self.output_view] {
+            view.set_show_line_numbers(appearance.show_line_numbers);
+            view.set_wrap_mode(if appearance.wrap_lines {
+                gtk::WrapMode::Char
+            } else {
+                gtk::WrapMode::None
+            });
+            view.set_tab_width(appearance.tab_width);
+        }
+        self.keybindings.set_input_mode(appearance.input_mode);
 
-            let buffer_text = buffer
-                .get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false)
-                .expect("failed to get buffer text");
-
-            let selection_text = buffer
-                .get_selection_bounds()
-                .map(|(start, end)| buffer.get_text(&start, &end, false))
-                .flatten()
-                .map(|s| s.to_string());
-
-            let status_result = self.scripts.write().expect("scripts lock is poisoned")
-                [script_id as usize]
-                .execute(buffer_text.as_str(), selection_text.as_deref());
-
-            match status_result {
-                Ok(status) => {
-                    // TODO: how to handle multiple messages?
-                    if let Some(error) = status.error() {
-                        self.status_bar.push(self.context_id, &error);
-                    } else if let Some(info) = status.info() {
-                        self.status_bar.push(self.context_id, &info);
-                    }
-                    self.do_replacement(status.into_replacement());
-                }
-                Err(e) => {
-                    self.status_bar.push(self.context_id, e.as_str());
-                }
-            }
+        let style_scheme = self.style_scheme();
+        let output_buffer = self
+            .output_view
+            .get_buffer()
+            .expect("failed to get buffer")
+            .downcast::<sourceview::Buffer>()
+            .expect("faild to downcast TextBuffer to sourceview Buffer");
+        for buffer in self.tabs.buffers().iter().chain(Some(&output_buffer)) {
+            buffer.set_style_scheme(style_scheme.as_ref());
         }
 
-        self.header_button.set_label(HEADER_BUTTON_GET_STARTED);
+        if let Err(e) = self
+            .font_provider
+            .load_from_data(preferences::font_css(appearance.font.as_deref()).as_bytes())
+        {
+            error!("could not load font CSS: {}", e);
+        }
 
-        dialog.close();
+        if let Some(settings) = gtk::Settings::get_default() {
+            settings.set_property_gtk_application_prefer_dark_theme(self.is_dark());
+        }
     }
 
-    fn do_replacement(&self, replacement: TextReplacement) {
-        let buffer = &self.source_view.get_buffer().expect("failed to get buffer");
+    fn source_buffer(&self) -> sourceview::Buffer {
+        self.source_view
+            .get_buffer()
+            .expect("failed to get buffer")
+            .downcast::<sourceview::Buffer>()
+            .expect("faild to downcast TextBuffer to sourceview Buffer")
+    }
 
-        match replacement {
-            TextReplacement::Full(text) => {
-                info!("replacing full text");
-                buffer.set_text(&text);
-            }
-            TextReplacement::Selection(text) => {
-                info!("replacing selection");
-                match &mut buffer.get_selection_bounds() {
-                    Some((start, end)) => {
-                        buffer.delete(start, end);
-                        buffer.insert(start, &text);
-                    }
-                    None => {
-                        error!("tried to do a selection replacement, but no text is selected!");
-                    }
+    // selects the language of the current tab in the status bar
+    fn show_language(&self) {
+        let id = self
+            .source_buffer()
+            .get_language()
+            .and_then(|language| language.get_id())
+            .map(|id| id.to_string())
+            .unwrap_or_else(|| PLAIN_TEXT_ID.to_string());
+        self.language_combo.set_active_id(Some(&id));
+    }
+
+    // highlights the buffer as the language a script asked for, or the one its text looks like,
+    // text that doesn't look like anything keeps the language it had
+    fn detect_language(&self, buffer: &sourceview::Buffer, hint: Option<&str>) {
+        let id = match hint {
+            Some(id) => id,
+            None => {
+                let text = buffer
+                    .get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false)
+                    .expect("failed to get buffer text");
+                match suggestions::detect(&text).and_then(ContentKind::language_id) {
+                    Some(id) => id,
+                    None => return,
                 }
             }
-            TextReplacement::Insert(insertions) => {
-                let insert_text = insertions.join("");
-                info!("inserting {} bytes", insert_text.len());
+        };
 
+        match sourceview::LanguageManager::get_default()
+            .and_then(|language_manager| language_manager.get_language(id))
+        {
+            Some(language) => {
+                if buffer
+                    .get_language()
+                    .and_then(|current| current.get_id())
+                    .as_deref()
+                    != Some(id)
+                {
+                    info!("highlighting as {}", id);
+                    buffer.set_language(Some(&language));
+                }
+            }
+            None => {
+                warn!("no language {}", id);
+                if hint.is_some() {
+                    self.push_error(format!("there is no language \"{}\" to highlight as", id));
+                }
+            }
+        }
+
+        self.show_language();
+    }
+
+    // pasted text is highlighted as the language it looks like, and checked for line endings that
+    // differ from the document's
+    fn pasted(&self, buffer: &sourceview::Buffer) {
+        self.detect_language(buffer, None);
+        if let Some(text) = buffer.get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false)
+        {
+            self.check_text(&text);
+        }
+    }
+
+    // mixed line endings and characters that couldn't be decoded confuse scripts, so they're
+    // pointed out when text is opened or pasted
+    fn check_text(&self, text: &str) {
+        if text.contains('\u{FFFD}') {
+            self.status_bar.push(
+                self.context_id,
+                "The document has characters that couldn't be decoded, shown as \u{FFFD}",
+            );
+        } else if LineEnding::detect(text) == Some(LineEnding::Mixed) {
+            self.status_bar.push(
+                self.context_id,
+                "The document has mixed line endings, convert them from the menu next to its counts",
+            );
+        }
+    }
+
+    fn push_error_(status_bar: gtk::Statusbar, context_id: u32, error: impl std::fmt::Display) {
+        status_bar.push(context_id, &format!("ERROR: {}", error));
+    }
+
+    pub fn push_error(&self, error: impl std::fmt::Display) {
+        App::push_error_(self.status_bar.clone(), self.context_id, error);
+    }
+
+    // shows an exception in the status bar, its stack trace can be expanded
+    fn push_script_error(&self, script_name: &str, error: &ScriptError) {
+        self.push_error(format!("{} threw {}", script_name, error));
+
+        if let Some(stack_trace) = &error.stack_trace {
+            self.message_label
+                .set_markup(&markdown::to_monospace_markup(stack_trace));
+            self.message_expand_button.set_active(false);
+            self.message_expand_button.show();
+        }
+    }
+
+    // push a message written in markdown, rendered with pango markup, long messages are shortened
+    // and can be expanded to show in full
+    fn push_markdown(&self, message: &str) {
+        let summary = markdown::summary(message);
+        let shown = summary.as_deref().unwrap_or(message);
+        self.status_bar.push(self.context_id, shown);
+        if let Some(label) = self.status_label() {
+            label.set_markup(&markdown::to_pango_markup(shown));
+        }
+
+        if summary.is_some() {
+            self.message_label
+                .set_markup(&if markdown::looks_like_data(message) {
+                    markdown::to_monospace_markup(message)
+                } else {
+                    markdown::to_pango_markup(message)
+                });
+        }
+        self.message_expand_button.set_active(false);
+        self.message_expand_button.set_visible(summary.is_some());
+    }
+
+    // tells the user a script they're editing was reloaded
+    pub fn on_script_event(&self, event: ScriptEvent) {
+        match event {
+            ScriptEvent::Reloaded(name) => self
+                .status_bar
+                .push(self.context_id, &format!("Reloaded script {}", name)),
+            ScriptEvent::Removed(name) => self
+                .status_bar
+                .push(self.context_id, &format!("Removed script {}", name)),
+            ScriptEvent::Failed(path, error) => {
+                self.push_error(format!("could not load {}: {}", path.display(), error))
+            }
+        };
+
+        // pinned scripts may have been removed or given another icon
+        self.update_pinned_buttons();
+    }
+
+    fn clear_messages(&self) {
+        self.status_bar.remove_all(self.context_id);
+        self.message_expand_button.set_active(false);
+        self.message_expand_button.hide();
+    }
+
+    pub fn open_command_pallete(&self) {
+        self.show_command_pallete("");
+    }
+
+    // opens the find bar, with the replace row if `replace`
+    pub fn find(&self, replace: bool) {
+        self.find_bar.show(replace);
+    }
+
+    // opens an empty scratchpad in a new tab
+    pub fn new_tab(&self) {
+        let language = sourceview::LanguageManager::get_default()
+            .and_then(|language_manager| language_manager.get_language("boop"));
+        let buffer = self.tabs.open(None, None, "", language.as_ref());
+        self.watch_buffer(&buffer);
+    }
+
+    pub fn close_tab(&self) {
+        self.tabs.close_current();
+    }
+
+    // buffers of new tabs need the handlers the first one was set up with
+    fn watch_buffer(&self, buffer: &sourceview::Buffer) {
+        self.completion.watch(buffer);
+        {
+            let app = self.clone();
+            buffer.connect_changed(move |_| app.clear_highlights());
+        }
+        {
+            let app = self.clone();
+            buffer.connect_paste_done(move |buffer, _| app.pasted(buffer));
+        }
+        buffer.set_style_scheme(self.style_scheme().as_ref());
+        self.watch_stats(buffer.upcast_ref());
+        self.carets.watch(buffer.upcast_ref());
+        let app = self.clone();
+        buffer.connect_modified_changed(move |_| app.update_file_label());
+    }
+
+    // the status bar's counts follow the text and the selection
+    fn watch_stats(&self, buffer: &gtk::TextBuffer) {
+        {
+            let app = self.clone();
+            buffer.connect_changed(move |_| app.queue_stats_update());
+        }
+        let app = self.clone();
+        buffer.connect_mark_set(move |_, _, mark| {
+            let name = mark.get_name();
+            if name.as_deref() == Some("insert") || name.as_deref() == Some("selection_bound") {
+                app.queue_stats_update();
+            }
+        });
+    }
+
+    // counting a large document on every key press would make typing lag
+    fn queue_stats_update(&self) {
+        if self.stats_pending.replace(true) {
+            return;
+        }
+
+        let app = self.clone();
+        glib::timeout_add_local(STATS_DELAY_MS, move || {
+            app.stats_pending.set(false);
+            app.update_stats();
+            glib::Continue(false)
+        });
+    }
+
+    // counts of the current tab's text and selection, its encoding and line endings, and the last
+    // script that ran
+    fn update_stats(&self) {
+        let buffer = self.source_view.get_buffer().expect("failed to get buffer");
+        let text = buffer
+            .get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false)
+            .map(|text| text.to_string())
+            .unwrap_or_default();
+        let selection = buffer
+            .get_selection_bounds()
+            .and_then(|(start, end)| buffer.get_text(&start, &end, false))
+            .map(|text| text.to_string());
+
+        let last_run = self.last_run.borrow();
+        self.stats_label.set_text(&stats::summary(
+            &Stats::new(&text, selection.as_deref()),
+            self.tabs.current_encoding(),
+            LineEnding::detect(&text),
+            last_run
+                .as_ref()
+                .map(|(script_name, duration)| (script_name.as_str(), *duration)),
+        ));
+    }
+
+    // shows the name of the file the current tab belongs to, marked if it has unsaved changes
+    fn update_file_label(&self) {
+        match self.tabs.current_file() {
+            (title, Some(path)) => {
+                let unsaved = if self.tabs.is_current_unsaved() {
+                    " \u{2022}"
+                } else {
+                    ""
+                };
+                self.file_label.set_text(&format!("{}{}", title, unsaved));
+                self.file_label
+                    .set_tooltip_text(Some(&path.display().to_string()));
+                self.file_label.show();
+                self.window
+                    .set_title(&format!("{}{} - Boop-GTK", title, unsaved));
+            }
+            (_, None) => {
+                self.file_label.hide();
+                self.window.set_title("Boop-GTK");
+            }
+        }
+    }
+
+    // opens a file in a new tab
+    pub fn open_file(&self) {
+        let dialog = gtk::FileChooserNative::new(
+            Some("Open File"),
+            Some(&self.window),
+            gtk::FileChooserAction::Open,
+            Some("_Open"),
+            Some("_Cancel"),
+        );
+
+        if dialog.run() == gtk::ResponseType::Accept {
+            if let Some(path) = dialog.get_filename() {
+                self.load_file(path);
+            }
+        }
+    }
+
+    fn load_file(&self, path: PathBuf) {
+        let file = match files::read_text(&path) {
+            Ok(file) => file,
+            Err(e) => {
+                error!("could not open file: {}", e);
+                self.push_error(e);
+                return;
+            }
+        };
+
+        info!("opening {} ({})", path.display(), file.encoding);
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let language = sourceview::LanguageManager::get_default().and_then(|language_manager| {
+            language_manager.guess_language(Some(file_name.as_str()), None)
+        });
+        let buffer = self
+            .tabs
+            .open(None, Some(path), &file.text, language.as_ref());
+        self.tabs.set_current_encoding(file.encoding);
+        self.watch_buffer(&buffer);
+        self.update_file_label();
+
+        if file.encoding != "UTF-8" {
+            self.status_bar.push(
+                self.context_id,
+                &format!(
+                    "Opened {} as {}, it's saved as {} until it's converted to UTF-8",
+                    file_name, file.encoding, file.encoding
+                ),
+            );
+        }
+        self.check_text(&file.text);
+    }
+
+    // converts every line ending in the current tab, as a change that can be reverted from the
+    // history
+    pub fn convert_line_endings(&self, line_ending: LineEnding) {
+        if self.queue().running().is_some() {
+            self.push_error("can't convert line endings while a script is running");
+            return;
+        }
+
+        let buffer = self.source_view.get_buffer().expect("failed to get buffer");
+        let text = buffer
+            .get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false)
+            .expect("failed to get buffer text");
+        let message = match LineEnding::detect(&text) {
+            None => "The document has no line endings to convert".to_string(),
+            Some(current) if current == line_ending => {
+                format!("Every line already ends with {}", line_ending)
+            }
+            Some(_) => {
+                info!("converting line endings to {}", line_ending);
+                self.do_replacement(
+                    &format!("Convert to {}", line_ending),
+                    TextReplacement::Full(line_ending::convert(&text, line_ending)),
+                );
+                format!("Converted line endings to {}", line_ending)
+            }
+        };
+        self.status_bar.push(self.context_id, &message);
+    }
+
+    // files are saved in the encoding they were read as until they're converted, which is a
+    // change that needs saving
+    pub fn convert_to_utf8(&self) {
+        let encoding = self.tabs.current_encoding();
+        if encoding == "UTF-8" {
+            self.status_bar
+                .push(self.context_id, "The document is already UTF-8");
+            return;
+        }
+
+        info!("converting from {} to UTF-8", encoding);
+        self.tabs.set_current_encoding("UTF-8");
+        self.source_view
+            .get_buffer()
+            .expect("failed to get buffer")
+            .set_modified(true);
+        self.update_stats();
+        self.status_bar.push(
+            self.context_id,
+            &format!(
+                "Converted from {}, save to write the file as UTF-8",
+                encoding
+            ),
+        );
+    }
+
+    // saves the current tab to its file, or asks where to save it, false if it wasn't saved
+    pub fn save_file(&self) -> bool {
+        match self.tabs.current_file() {
+            (_, Some(_)) => self.write_file(None),
+            (_, None) => self.save_file_as(),
+        }
+    }
+
+    pub fn save_file_as(&self) -> bool {
+        let (title, path) = self.tabs.current_file();
+        let dialog = gtk::FileChooserNative::new(
+            Some("Save As"),
+            Some(&self.window),
+            gtk::FileChooserAction::Save,
+            Some("_Save"),
+            Some("_Cancel"),
+        );
+        dialog.set_do_overwrite_confirmation(true);
+        match &path {
+            Some(path) => {
+                dialog.set_filename(path);
+            }
+            None => dialog.set_current_name(&format!("{}.txt", title)),
+        }
+
+        if dialog.run() != gtk::ResponseType::Accept {
+            return false;
+        }
+        match dialog.get_filename() {
+            Some(path) => self.write_file(Some(path)),
+            None => false,
+        }
+    }
+
+    fn write_file(&self, path: Option<PathBuf>) -> bool {
+        match self.tabs.save_current(path) {
+            Ok(path) => {
+                self.update_file_label();
+                self.status_bar
+                    .push(self.context_id, &format!("Saved {}", path.display()));
+                true
+            }
+            Err(e) => {
+                error!("could not save file: {}", e);
+                self.push_error(e);
+                false
+            }
+        }
+    }
+
+    // asks whether to save files with unsaved changes before the window closes, false if it
+    // shouldn't close
+    fn confirm_close(&self) -> bool {
+        let unsaved = self.tabs.unsaved();
+        if unsaved.is_empty() {
+            return true;
+        }
+
+        match self.tabs.confirm_unsaved(&unsaved) {
+            gtk::ResponseType::Accept => match self.tabs.save_all() {
+                Ok(()) => true,
+                Err(e) => {
+                    error!("could not save files: {}", e);
+                    self.push_error(e);
+                    false
+                }
+            },
+            gtk::ResponseType::Reject => true,
+            _ => false,
+        }
+    }
+
+    // reopens the tabs open when the window was last closed and the window's size, the session
+    // is saved when the window is closed and every so often in case Boop doesn't exit cleanly
+    pub fn restore_session(&self) {
+        if !self.config.borrow().restore_session {
+            return;
+        }
+
+        let session = Session::load(&self.data_dir);
+        if let Some((width, height)) = session.window_size {
+            self.window.resize(width, height);
+        }
+        if session.maximized {
+            self.window.maximize();
+        }
+        for buffer in self.tabs.restore(&session) {
+            self.watch_buffer(&buffer);
+        }
+        // the view can only scroll once it's been allocated a size
+        {
+            let source_view = self.source_view.clone();
+            glib::idle_add_local(move || {
+                if let Some(insert) = source_view
+                    .get_buffer()
+                    .and_then(|buffer| buffer.get_insert())
+                {
+                    source_view.scroll_mark_onscreen(&insert);
+                }
+                glib::Continue(false)
+            });
+        }
+
+        {
+            let app = self.clone();
+            self.window.connect_delete_event(move |_, _| {
+                app.save_session();
+                gtk::Inhibit(false)
+            });
+        }
+        let app = self.clone();
+        glib::timeout_add_seconds_local(SESSION_SAVE_INTERVAL_SECS, move || {
+            app.save_session();
+            glib::Continue(true)
+        });
+    }
+
+    fn save_session(&self) {
+        let mut session = self.tabs.session();
+        session.maximized = self.window.is_maximized();
+        // the size of a maximized window isn't the size it should be restored to
+        session.window_size = if session.maximized {
+            Session::load(&self.data_dir).window_size
+        } else {
+            Some(self.window.get_size())
+        };
+
+        if let Err(e) = session.save(&self.data_dir) {
+            error!("could not save session: {}", e);
+        }
+    }
+
+    // makes the window a small one without decorations, kept above other windows and hidden
+    // instead of closed, so it can be summoned by the global shortcut
+    pub fn make_pop_up(&self) {
+        self.window.set_decorated(false);
+        self.window.set_keep_above(true);
+        self.window.set_skip_taskbar_hint(true);
+        self.window.set_position(gtk::WindowPosition::Center);
+        self.window.set_default_size(POP_UP_WIDTH, POP_UP_HEIGHT);
+
+        self.window.connect_delete_event(|window, _| {
+            window.hide();
+            gtk::Inhibit(true)
+        });
+        // runs after the handler that cancels a running script with Escape
+        self.window.connect_key_press_event(|window, event| {
+            if event.get_keyval() == gdk::keys::constants::Escape {
+                window.hide();
+                gtk::Inhibit(true)
+            } else {
+                gtk::Inhibit(false)
+            }
+        });
+        self.window.show_all();
+    }
+
+    // raises the pop-up with the command pallete open
+    pub fn show_pop_up(&self) {
+        self.window.present();
+        self.open_command_pallete();
+    }
+
+    // raises the window and opens the command pallete searching for `query`, the best match is
+    // selected so it can be run with Enter
+    pub fn select_script(&self, query: &str) {
+        self.window.present();
+        self.show_command_pallete(query);
+    }
+
+    fn show_command_pallete(&self, query: &str) {
+        // scripts are suggested for the selection, or the whole document if nothing is selected
+        let buffer = self.source_view.get_buffer().expect("failed to get buffer");
+        let (start, end) = buffer
+            .get_selection_bounds()
+            .unwrap_or_else(|| (buffer.get_start_iter(), buffer.get_end_iter()));
+        let text = buffer
+            .get_text(&start, &end, false)
+            .expect("failed to get buffer text");
+
+        let pipelines = Pipelines::load(&self.config_dir);
+        let dialog = self.new_command_pallete(&pipelines.pipelines, &text);
+        dialog.show_all();
+        if !query.is_empty() {
+            dialog.set_query(query);
+        }
+
+        self.header_button.set_label(HEADER_BUTTON_CHOOSE_ACTION);
+
+        if let gtk::ResponseType::Other(script_id) = dialog.run() {
+            let pipeline = (script_id as u64)
+                .checked_sub(PIPELINE_ID_BASE)
+                .and_then(|index| pipelines.pipelines.get(index as usize));
+            if let Some(pipeline) = pipeline {
+                if dialog.edit_requested() {
+                    self.edit_pipeline(Some(pipeline));
+                } else {
+                    self.run_pipeline(pipeline, dialog.run_mode());
+                }
+            } else if dialog.edit_requested() {
+                self.edit_script(Some(script_id as usize));
+            } else {
+                self.run_script(script_id as usize, dialog.run_mode());
+            }
+        }
+
+        self.header_button.set_label(HEADER_BUTTON_GET_STARTED);
+
+        dialog.close();
+    }
+
+    // lists the scripts by category, running the one that's chosen
+    pub fn browse_scripts(&self) {
+        let browser = ScriptBrowser::new(
+            &self.window,
+            &self.scripts.read().expect("scripts lock is poisoned"),
+        );
+        browser.show_all();
+        let response = browser.run();
+        browser.close();
+
+        if let gtk::ResponseType::Other(script_id) = response {
+            self.run_script(script_id as usize, RunMode::Replace);
+        }
+    }
+
+    // lists every script, where they're from, and whether they're shown in the command pallete
+    pub fn manage_scripts(&self) {
+        ScriptManager::new(
+            &self.window,
+            self.scripts.clone(),
+            self.disabled_scripts.clone(),
+            self.config_dir.clone(),
+        )
+        .show();
+    }
+
+    // scripts are suggested for `text`
+    fn new_command_pallete(&self, pipelines: &[Pipeline], text: &str) -> CommandPalleteDialog {
+        let dialog = CommandPalleteDialog::new(
+            &self.window,
+            self.scripts.clone(),
+            self.search_index.clone(),
+            &self.shortcuts.borrow(),
+            pipelines,
+            &self
+                .recent
+                .borrow()
+                .scripts
+                .iter()
+                .cloned()
+                .collect::<Vec<String>>(),
+            &self.store.borrow().usage,
+            self.favorites.clone(),
+            &self.disabled_scripts.borrow(),
+            self.config.borrow().max_script_memory(),
+            text,
+        );
+
+        let app = self.clone();
+        dialog.connect_favorites_changed(move || app.favorites_changed());
+        dialog
+    }
+
+    fn favorites_changed(&self) {
+        if let Err(e) = self.favorites.borrow().save(&self.config_dir) {
+            error!("could not save favorites: {}", e);
+            self.push_error("failed to save favorites");
+        }
+        self.update_pinned_buttons();
+    }
+
+    // a button in the header bar for each starred script, if they're enabled in the config
+    fn update_pinned_buttons(&self) {
+        for child in self.pinned_box.get_children() {
+            self.pinned_box.remove(&child);
+        }
+        if !self.config.borrow().pinned_in_header_bar {
+            return;
+        }
+
+        let scripts = self.scripts.read().expect("scripts lock is poisoned");
+        for script in self
+            .favorites
+            .borrow()
+            .scripts
+            .iter()
+            .filter_map(|name| scripts.iter().find(|s| s.metadata.name == *name))
+        {
+            let icon_name = format!("boop-gtk-{}-symbolic", script.metadata.icon.to_lowercase());
+            let button = gtk::Button::from_icon_name(Some(&icon_name), gtk::IconSize::Button);
+            button.set_tooltip_text(Some(&script.metadata.name));
+            button.set_focus_on_click(false);
+
+            let app = self.clone();
+            let script_name = script.metadata.name.clone();
+            button.connect_clicked(move |_| app.run_script_named(&script_name, RunMode::Replace));
+
+            self.pinned_box.add(&button);
+        }
+        self.pinned_box.show_all();
+    }
+
+    // runs the script or pipeline chosen in the command pallete on the clipboard, replacing its
+    // text with the result, the document isn't changed
+    pub fn transform_clipboard(&self) {
+        let clipboard = gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD);
+        let text = match clipboard.wait_for_text() {
+            Some(text) => text.to_string(),
+            None => {
+                self.push_error("the clipboard has no text");
+                return;
+            }
+        };
+
+        let pipelines = Pipelines::load(&self.config_dir);
+        let dialog = self.new_command_pallete(&pipelines.pipelines, &text);
+        dialog.show_all();
+        self.header_button.set_label(HEADER_BUTTON_CHOOSE_ACTION);
+        let response = dialog.run();
+        self.header_button.set_label(HEADER_BUTTON_GET_STARTED);
+        dialog.close();
+
+        let script_id = match response {
+            gtk::ResponseType::Other(script_id) => script_id as u64,
+            _ => return,
+        };
+
+        let (name, result) = {
+            let mut scripts = match self.scripts.try_write() {
+                Ok(scripts) => scripts,
+                Err(_) => {
+                    self.push_error("can't transform the clipboard while scripts reload");
+                    return;
+                }
+            };
+            let config = self.config.borrow();
+
+            match script_id
+                .checked_sub(PIPELINE_ID_BASE)
+                .and_then(|index| pipelines.pipelines.get(index as usize))
+            {
+                Some(pipeline) => (
+                    pipeline.name.clone(),
+                    pipeline.run(
+                        &mut scripts,
+                        &text,
+                        |metadata| config.script_timeout(metadata),
+                        &Permissions::load(&self.config_dir),
+                        &|request| self.host_request(&pipeline.name, &pipeline.name, request),
+                    ),
+                ),
+                None => {
+                    let name = scripts[script_id as usize].metadata.name.clone();
+                    let permission_key = scripts[script_id as usize].permission_key();
+                    let result = cli::execute_script(
+                        &mut scripts,
+                        &name,
+                        &text,
+                        None,
+                        &config,
+                        &self.config_dir,
+                        &|request| self.host_request(&name, &permission_key, request),
+                    )
+                    .and_then(|status| match status.error() {
+                        Some(error) => Err(SimpleError::new(error)),
+                        None => Ok(status.into_replacement().apply(&text, None)),
+                    });
+                    (name, result)
+                }
+            }
+        };
+
+        match result {
+            Ok(output) => {
+                clipboard.set_text(&output);
+                self.clear_messages();
+                self.status_bar.push(
+                    self.context_id,
+                    &format!("Transformed the clipboard with {}", name),
+                );
+            }
+            Err(e) => {
+                error!("could not transform the clipboard with {}: {}", name, e);
+                self.push_error(format!("{} failed: {}", name, e));
+            }
+        }
+    }
+
+    // runs the pipeline on the selection, or the whole document if nothing is selected, steps
+    // are run one after another without returning to the main loop
+    fn run_pipeline(&self, pipeline: &Pipeline, run_mode: RunMode) {
+        let buffer = self.source_view.get_buffer().expect("failed to get buffer");
+        let selection = buffer
+            .get_selection_bounds()
+            .and_then(|(start, end)| buffer.get_text(&start, &end, false));
+        let text = match &selection {
+            Some(selection) => selection.to_string(),
+            None => buffer
+                .get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false)
+                .expect("failed to get buffer text")
+                .to_string(),
+        };
+
+        let result = {
+            let mut scripts = match self.scripts.try_write() {
+                Ok(scripts) => scripts,
+                Err(_) => {
+                    self.push_error(format!("can't run {} while scripts reload", pipeline.name));
+                    return;
+                }
+            };
+            let config = self.config.borrow();
+            pipeline.run(
+                &mut scripts,
+                &text,
+                |metadata| config.script_timeout(metadata),
+                &Permissions::load(&self.config_dir),
+                &|request| self.host_request(&pipeline.name, &pipeline.name, request),
+            )
+        };
+
+        let output = match result {
+            Ok(output) => output,
+            Err(e) => {
+                error!("pipeline {} failed: {}", pipeline.name, e);
+                self.push_error(format!("{} failed: {}", pipeline.name, e));
+                return;
+            }
+        };
+
+        self.clear_messages();
+        let replacement = if selection.is_some() {
+            TextReplacement::Selection(output)
+        } else {
+            TextReplacement::Full(output)
+        };
+        match run_mode {
+            RunMode::Replace if self.opens_new_tab(&replacement) => {
+                self.open_output_tab(&pipeline.name, replacement, None)
+            }
+            RunMode::Replace => self.do_replacement(&pipeline.name, replacement),
+            RunMode::OutputPane => self.show_output(&pipeline.name, replacement, None),
+            RunMode::NewTab => self.open_output_tab(&pipeline.name, replacement, None),
+            RunMode::Preview => {
+                if self.confirm_replacement(&pipeline.name, &replacement) {
+                    self.do_replacement(&pipeline.name, replacement);
+                }
+            }
+        }
+    }
+
+    // lets the user name a pipeline and choose its steps, or delete an existing one
+    fn edit_pipeline(&self, existing: Option<&Pipeline>) {
+        let mut buttons = vec![
+            ("_Cancel", gtk::ResponseType::Cancel),
+            ("_Save", gtk::ResponseType::Accept),
+        ];
+        if existing.is_some() {
+            buttons.insert(0, ("_Delete", gtk::ResponseType::Reject));
+        }
+        let dialog = gtk::Dialog::with_buttons(
+            Some("Pipeline"),
+            Some(&self.window),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            &buttons,
+        );
+
+        let grid = gtk::Grid::new();
+        grid.set_row_spacing(6);
+        grid.set_column_spacing(12);
+        grid.set_border_width(12);
+
+        let name_entry = gtk::Entry::new();
+        name_entry.set_hexpand(true);
+        let steps = Rc::new(RefCell::new(Vec::new()));
+        let steps_label = Label::new(None);
+        steps_label.set_xalign(0.0);
+        steps_label.set_line_wrap(true);
+        if let Some(pipeline) = existing {
+            name_entry.set_text(&pipeline.name);
+            steps.replace(pipeline.steps.clone());
+        }
+
+        let script_combo = gtk::ComboBoxText::new();
+        for script in self
+            .scripts
+            .read()
+            .expect("scripts lock is poisoned")
+            .iter()
+        {
+            script_combo.append_text(&script.metadata.name);
+        }
+        script_combo.set_active(Some(0));
+        let add_button = Button::with_mnemonic("_Add Step");
+        let remove_button = Button::with_mnemonic("_Remove Last Step");
+
+        grid.attach(&Label::new(Some("Name")), 0, 0, 1, 1);
+        grid.attach(&name_entry, 1, 0, 2, 1);
+        grid.attach(&Label::new(Some("Steps")), 0, 1, 1, 1);
+        grid.attach(&steps_label, 1, 1, 2, 1);
+        grid.attach(&script_combo, 1, 2, 1, 1);
+        grid.attach(&add_button, 2, 2, 1, 1);
+        grid.attach(&remove_button, 2, 3, 1, 1);
+
+        let describe = |steps: &[String]| {
+            if steps.is_empty() {
+                "Add the scripts to run, in order".to_string()
+            } else {
+                steps.join(" → ")
+            }
+        };
+        steps_label.set_text(&describe(&steps.borrow()));
+        {
+            let steps = steps.clone();
+            let steps_label = steps_label.clone();
+            add_button.connect_clicked(move |_| {
+                if let Some(name) = script_combo.get_active_text() {
+                    steps.borrow_mut().push(name.to_string());
+                    steps_label.set_text(&describe(&steps.borrow()));
+                }
+            });
+        }
+        {
+            let steps = steps.clone();
+            remove_button.connect_clicked(move |_| {
+                steps.borrow_mut().pop();
+                steps_label.set_text(&describe(&steps.borrow()));
+            });
+        }
+
+        dialog.get_content_area().pack_start(&grid, true, true, 0);
+        dialog.show_all();
+
+        let response = dialog.run();
+        let name = name_entry.get_text().trim().to_string();
+        dialog.close();
+
+        let mut pipelines = Pipelines::load(&self.config_dir);
+        let existing_name = existing.map(|pipeline| pipeline.name.as_str());
+        match response {
+            gtk::ResponseType::Accept => {
+                if name.is_empty() || steps.borrow().is_empty() {
+                    self.push_error("pipelines need a name and at least one step");
+                    return;
+                }
+                pipelines.insert(
+                    Pipeline {
+                        name,
+                        steps: steps.borrow().clone(),
+                    },
+                    existing_name,
+                );
+            }
+            gtk::ResponseType::Reject => {
+                if let Some(name) = existing_name {
+                    pipelines.remove(name);
+                }
+            }
+            _ => return,
+        }
+
+        if let Err(e) = pipelines.save(&self.config_dir) {
+            error!("could not save pipelines: {}", e);
+            self.push_error("failed to save pipelines");
+        }
+    }
+
+    // opens a script in the editor, or a new script if there's no script id
+    fn edit_script(&self, script_id: Option<usize>) {
+        let (source, path, timeout) = match script_id {
+            Some(script_id) => {
+                let scripts = self.scripts.read().expect("scripts lock is poisoned");
+                let script = &scripts[script_id];
+                // builtin transforms are Rust, there's no script to edit
+                if script.is_native() {
+                    self.push_error(format!(
+                        "{} is built into Boop and can't be edited",
+                        script.metadata.name
+                    ));
+                    return;
+                }
+                let source = match script.source() {
+                    Ok(source) => source.to_string(),
+                    Err(e) => {
+                        self.push_error(format!("could not read {}: {}", script.path.display(), e));
+                        return;
+                    }
+                };
+                // built-in scripts have no path, they're saved as a user script
+                let path = Some(script.path.clone()).filter(|path| !path.as_os_str().is_empty());
+                (
+                    source,
+                    path,
+                    self.config.borrow().script_timeout(&script.metadata),
+                )
+            }
+            None => (
+                editor::TEMPLATE.to_string(),
+                None,
+                Duration::from_secs(self.config.borrow().script_timeout_secs),
+            ),
+        };
+
+        ScriptEditor::new(
+            &self.window,
+            &source,
+            path,
+            self.config_dir.join("scripts"),
+            timeout,
+        )
+        .show();
+    }
+
+    pub fn expand_selection(&self) {
+        let buffer = self.source_view.get_buffer().expect("failed to get buffer");
+        let (start, end) = match buffer.get_selection_bounds() {
+            Some((start, end)) => (start.get_offset(), end.get_offset()),
+            None => {
+                let cursor = buffer.get_property_cursor_position();
+                (cursor, cursor)
+            }
+        };
+
+        let text = buffer
+            .get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false)
+            .expect("failed to get buffer text");
+
+        if let Some((start, end)) = selection::expand(&text, start as usize, end as usize) {
+            buffer.select_range(
+                &buffer.get_iter_at_offset(start as i32),
+                &buffer.get_iter_at_offset(end as i32),
+            );
+        }
+    }
+
+    // runs the script that was run last again, as if it was chosen in the command pallete
+    pub fn rerun_last(&self) {
+        let script_name = match self.recent.borrow().last() {
+            Some(script_name) => script_name.to_string(),
+            None => {
+                self.push_error("no script has been run yet");
+                return;
+            }
+        };
+
+        self.run_script_named(&script_name, RunMode::Replace);
+    }
+
+    // scripts are looked up by name as they may have been reloaded since it was chosen
+    pub fn run_script_named(&self, script_name: &str, run_mode: RunMode) {
+        let script_id = self
+            .scripts
+            .read()
+            .expect("scripts lock is poisoned")
+            .iter()
+            .position(|script| script.metadata.name == script_name);
+
+        match script_id {
+            Some(script_id) => self.run_script(script_id, run_mode),
+            None => self.push_error(format!("{} is no longer installed", script_name)),
+        }
+    }
+
+    // the queue of the tab that's shown
+    fn queue(&self) -> RefMut<'_, ExecutionQueue> {
+        let tab = self.tabs.current_id();
+        RefMut::map(self.queues.borrow_mut(), |queues| {
+            queues.entry(tab).or_default()
+        })
+    }
+
+    // queues a script to run on the document, it starts once the scripts queued before it finish
+    fn run_script(&self, script_id: usize, run_mode: RunMode) {
+        let script_name = self.scripts.read().expect("scripts lock is poisoned")[script_id]
+            .metadata
+            .name
+            .clone();
+        info!("queueing {} ({:?})", script_name, run_mode);
+
+        self.queue().push(QueuedRun {
+            script_name,
+            run_mode,
+        });
+        self.run_next();
+    }
+
+    // starts the next script queued on the tab that's shown unless one is already running, the
+    // document can't be edited until its queue is empty, scripts on other tabs keep running
+    fn run_next(&self) {
+        let tab = self.tabs.current_id();
+        loop {
+            // a script runs on one document at a time, so it waits for it to finish on another tab
+            let busy = self.queue().next().map_or(false, |run| {
+                self.scripts
+                    .read()
+                    .expect("scripts lock is poisoned")
+                    .iter()
+                    .any(|script| script.metadata.name == run.script_name && script.is_running())
+            });
+            if busy {
+                break;
+            }
+
+            let run = match self.queue().start_next() {
+                Some(run) => run,
+                None => break,
+            };
+
+            match self.start_script(&run) {
+                Ok(()) => {
+                    let app = self.clone();
+                    glib::timeout_add_local(POLL_INTERVAL_MS, move || app.poll_script(tab));
+                    break;
+                }
+                Err(e) => {
+                    self.queue().finish();
+                    self.push_error(e);
+                }
+            }
+        }
+
+        let queue = self.queue();
+        self.source_view.set_editable(queue.running().is_none());
+        self.tabs
+            .set_busy(tab, queue.running().is_some() || queue.queued() > 0);
+        self.cancel_queue_button
+            .set_label(&format!("Cancel {} queued", queue.queued()));
+        self.cancel_queue_button.set_visible(queue.queued() > 0);
+        self.cancel_script_button
+            .set_visible(queue.running().is_some());
+        // scripts run on their own thread, so the window stays responsive while they do
+        self.run_spinner.set_visible(queue.running().is_some());
+        self.run_spinner
+            .set_property_active(queue.running().is_some());
+    }
+
+    // stops the running script, scripts queued after it still run, returns false if no script
+    // was running
+    fn cancel_script(&self) -> bool {
+        let run = match self.queue().running() {
+            Some(run) => run.clone(),
+            None => return false,
+        };
+
+        // locked while a dialog answering one of the script's requests is open, which is modal
+        let mut scripts = match self.scripts.try_write() {
+            Ok(scripts) => scripts,
+            Err(_) => return false,
+        };
+        if let Some(script) = scripts
+            .iter_mut()
+            .find(|script| script.metadata.name == run.script_name)
+        {
+            script.cancel();
+        }
+
+        self.status_bar
+            .push(self.context_id, &format!("Stopping {}...", run.script_name));
+        true
+    }
+
+    fn start_script(&self, run: &QueuedRun) -> Result<(), SimpleError> {
+        info!("executing {} ({:?})", run.script_name, run.run_mode);
+
+        let buffer = &self.source_view.get_buffer().expect("failed to get buffer");
+
+        let buffer_text = buffer
+            .get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false)
+            .expect("failed to get buffer text");
+
+        let selection_text = buffer
+            .get_selection_bounds()
+            .map(|(start, end)| buffer.get_text(&start, &end, false))
+            .flatten()
+            .map(|s| s.to_string());
+
+        if self
+            .config
+            .borrow()
+            .is_document_too_large(buffer_text.len())
+            && !self.confirm_large_document(&run.script_name, buffer_text.len())
+        {
+            bail!("{} was not run on the large document", run.script_name);
+        }
+
+        let mut scripts = self.scripts.write().expect("scripts lock is poisoned");
+        let script_id = scripts
+            .iter()
+            .position(|script| script.metadata.name == run.script_name)
+            .ok_or_else(|| SimpleError::new(format!("no script named {}", run.script_name)))?;
+
+        script::enforce_memory_budget(
+            &mut scripts,
+            self.config.borrow().max_script_memory(),
+            script_id,
+        );
+        let timeout = self
+            .config
+            .borrow()
+            .script_timeout(&scripts[script_id].metadata);
+        scripts[script_id].set_heap_limit(self.config.borrow().script_heap_limit());
+        scripts[script_id].set_state(script_state::load(&self.config_dir, &run.script_name));
+        scripts[script_id].set_permissions(&Permissions::load(&self.config_dir));
+        scripts[script_id].start(
+            buffer_text.as_str(),
+            selection_text.as_deref(),
+            &self.document_context(),
+            timeout,
+        )?;
+
+        self.clear_messages();
+        self.status_bar
+            .push(self.context_id, &format!("Running {}...", run.script_name));
+
+        Ok(())
+    }
+
+    // warns that running a script on a large document may take a while, true if the user chose to
+    // run it anyway
+    fn confirm_large_document(&self, script_name: &str, size: usize) -> bool {
+        let dialog = gtk::MessageDialog::new(
+            Some(&self.window),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Warning,
+            gtk::ButtonsType::None,
+            &format!("Run {} on a large document?", script_name),
+        );
+        // the text is copied to the script and back, and scripts see it as UTF-16
+        dialog.set_property_secondary_text(Some(&format!(
+            "The document is {}, running a script on it may use around {} of memory and \
+             the application may stop responding until it finishes.",
+            format_size(size),
+            format_size(size * 6)
+        )));
+        dialog.add_buttons(&[
+            ("_Cancel", gtk::ResponseType::Cancel),
+            ("_Run Anyway", gtk::ResponseType::Accept),
+        ]);
+
+        let run_anyway = dialog.run() == gtk::ResponseType::Accept;
+        dialog.close();
+
+        run_anyway
+    }
+
+    // checks on the script running on the tab, called from a timeout until it has finished
+    fn poll_script(&self, tab: usize) -> glib::Continue {
+        let run = match self
+            .queues
+            .borrow()
+            .get(&tab)
+            .and_then(ExecutionQueue::running)
+        {
+            Some(run) => run.clone(),
+            None => return glib::Continue(false),
+        };
+
+        let result = {
+            // the scripts are already locked if this is called while a dialog answering one of
+            // the script's requests is open, check again once it's closed
+            let mut scripts = match self.scripts.try_write() {
+                Ok(scripts) => scripts,
+                Err(_) => return glib::Continue(true),
+            };
+
+            match scripts
+                .iter_mut()
+                .find(|script| script.metadata.name == run.script_name)
+            {
+                Some(script) => {
+                    let permission_key = script.permission_key();
+                    script.poll(&|request| {
+                        self.host_request(&run.script_name, &permission_key, request)
+                    })
+                }
+                None => Some(Err(SimpleError::new(format!(
+                    "{} was removed while running",
+                    run.script_name
+                )))),
+            }
+        };
+
+        match result {
+            Some(result) => {
+                let running_for = self
+                    .queues
+                    .borrow()
+                    .get(&tab)
+                    .and_then(ExecutionQueue::running_for);
+                if let Some(running_for) = running_for {
+                    self.last_run
+                        .replace(Some((run.script_name.clone(), running_for)));
+                }
+                let succeeded = match &result {
+                    Ok(status) => status.exception().is_none() && status.error().is_none(),
+                    Err(_) => false,
+                };
+                self.notify_finished(&run.script_name, succeeded, running_for);
+
+                // the tab stays busy until its result has been applied to it
+                self.finished
+                    .borrow_mut()
+                    .insert(tab, (run.clone(), result));
+                if tab == self.tabs.current_id() {
+                    self.finish_waiting();
+                } else {
+                    self.status_bar.push(
+                        self.context_id,
+                        &format!(
+                            "{} finished on {}",
+                            run.script_name,
+                            self.tabs.title(tab).unwrap_or_default()
+                        ),
+                    );
+                    // the same script may be waiting to run on the tab that's shown
+                    self.run_next();
+                }
+                glib::Continue(false)
+            }
+            None => glib::Continue(true),
+        }
+    }
+
+    // applies the result of the script that finished on the tab that's shown, then starts the
+    // next one queued on it
+    fn finish_waiting(&self) {
+        let tab = self.tabs.current_id();
+        let finished = self.finished.borrow_mut().remove(&tab);
+        if let Some((run, result)) = finished {
+            self.queue().finish();
+            self.finish_script(&run, result);
+            self.update_stats();
+        }
+        self.run_next();
+    }
+
+    fn finish_script(&self, run: &QueuedRun, status_result: Result<ExecutionStatus, SimpleError>) {
+        let script_name = &run.script_name;
+        let run_mode = if run.run_mode == RunMode::Replace && self.config.borrow().preview_changes {
+            RunMode::Preview
+        } else {
+            run.run_mode
+        };
+
+        self.clear_messages();
+
+        match status_result {
+            Ok(status) if !status.permission_requests().is_empty() => {
+                if self.ask_remote_require(script_name, status.permission_requests()) {
+                    // restart the script so its requires are run again
+                    if let Some(script) = self
+                        .scripts
+                        .write()
+                        .expect("scripts lock is poisoned")
+                        .iter_mut()
+                        .find(|script| &script.metadata.name == script_name)
+                    {
+                        script.kill_thread();
+                    }
+                    self.queue().push_front(run.clone());
+                } else {
+                    self.push_error(format!(
+                        "{} was not allowed to load remote code",
+                        script_name
+                    ));
+                }
+            }
+            Ok(status) => {
+                self.record_run(script_name, &status, run_mode);
+
+                // TODO: how to handle multiple messages?
+                if let Some(exception) = status.exception() {
+                    self.push_script_error(script_name, exception);
+                } else if let Some(error) = status.error() {
+                    self.push_markdown(error);
+                } else if let Some(info) = status.info() {
+                    self.push_markdown(info);
+                }
+                let save_as = status.save_as().cloned();
+                let open_url = status.open_url().cloned();
+                let copy = status.copy().cloned();
+                let notifications = status.notifications().to_vec();
+                let artifacts = status.artifacts().to_vec();
+                let highlights = status.highlights().to_vec();
+                let preview = status.preview().cloned();
+                let cursor = status.cursor();
+                // scripts that always give the same kind of text can declare its language
+                let language = status.language().cloned().or_else(|| {
+                    if status.exception().is_none() && status.error().is_none() {
+                        self.output_language(script_name)
+                    } else {
+                        None
+                    }
+                });
+                self.show_console_output(script_name, status.console_output());
+                let message = status.error().or_else(|| status.info()).cloned();
+                match run_mode {
+                    RunMode::Replace => {
+                        let replacement = status.into_replacement();
+                        if self.opens_new_tab(&replacement) {
+                            self.open_output_tab(script_name, replacement, message);
+                        } else {
+                            self.do_replacement(script_name, replacement);
+                            if let Some(cursor) = cursor {
+                                self.move_cursor(cursor);
+                            }
+                        }
+                    }
+                    RunMode::OutputPane => {
+                        self.show_output(script_name, status.into_replacement(), message)
+                    }
+                    RunMode::NewTab => {
+                        self.open_output_tab(script_name, status.into_replacement(), message)
+                    }
+                    RunMode::Preview => {
+                        let replacement = status.into_replacement();
+                        if self.confirm_replacement(script_name, &replacement) {
+                            self.do_replacement(script_name, replacement);
+                            if let Some(cursor) = cursor {
+                                self.move_cursor(cursor);
+                            }
+                        }
+                    }
+                }
+                self.show_artifacts(script_name, artifacts, run_mode);
+                if let Some(preview) = preview {
+                    self.show_preview(script_name, &preview, run_mode);
+                }
+                match run_mode {
+                    RunMode::Replace | RunMode::Preview | RunMode::NewTab => {
+                        self.show_highlights(&self.source_view, &highlights)
+                    }
+                    RunMode::OutputPane => self.show_highlights(&self.output_view, &highlights),
+                }
+                // a new tab is shown in the editor
+                let view = match run_mode {
+                    RunMode::Replace | RunMode::Preview | RunMode::NewTab => &self.source_view,
+                    RunMode::OutputPane => &self.output_view,
+                };
+                self.detect_language(
+                    &view
+                        .get_buffer()
+                        .expect("failed to get buffer")
+                        .downcast::<sourceview::Buffer>()
+                        .expect("faild to downcast TextBuffer to sourceview Buffer"),
+                    language.as_deref(),
+                );
+                if let Some(save_as) = save_as {
+                    self.save_as(save_as);
+                }
+                if let Some(url) = open_url {
+                    self.open_url(script_name, &url);
+                }
+                if let Some(text) = copy {
+                    gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD).set_text(&text);
+                }
+                for notification in &notifications {
+                    self.send_notification(None, notification);
+                }
+            }
+            Err(e) => {
+                self.status_bar.push(self.context_id, e.as_str());
+            }
+        }
+    }
+
+    // lets the user know a script that ran for a while has finished, if they're using another
+    // window
+    fn notify_finished(&self, script_name: &str, succeeded: bool, running_for: Option<Duration>) {
+        let notify_after = self.config.borrow().notify_after_secs;
+        let long_running =
+            running_for.map_or(false, |running_for| running_for.as_secs() >= notify_after);
+        if notify_after == 0 || !long_running || self.window.is_active() {
+            return;
+        }
+
+        let title = if succeeded {
+            format!("{} finished", script_name)
+        } else {
+            format!("{} failed", script_name)
+        };
+        self.send_notification(Some("script-finished"), &Notification { title, body: None });
+    }
+
+    // a desktop notification, a notification with the same id replaces the last one, clicking it
+    // brings the window to the front
+    fn send_notification(&self, id: Option<&str>, notification: &Notification) {
+        let application = match self.window.get_application() {
+            Some(application) => application,
+            None => {
+                warn!("not notifying, {} has no application", notification.title);
+                return;
+            }
+        };
+
+        let desktop_notification = gio::Notification::new(&notification.title);
+        desktop_notification.set_body(notification.body.as_deref());
+        desktop_notification.set_default_action("app.present");
+        application.send_notification(id, &desktop_notification);
+    }
+
+    fn output_language(&self, script_name: &str) -> Option<String> {
+        self.scripts
+            .read()
+            .expect("scripts lock is poisoned")
+            .iter()
+            .find(|script| script.metadata.name == script_name)
+            .and_then(|script| script.metadata.output_language.clone())
+    }
+
+    // lists what the script wrote to the console, hidden if it wrote nothing
+    fn show_console_output(&self, script_name: &str, output: &[(ConsoleLevel, String)]) {
+        let text = output
+            .iter()
+            .map(|(level, line)| match level {
+                ConsoleLevel::Log => format!("{}\n", line),
+                _ => format!("[{}] {}\n", level.name(), line),
+            })
+            .collect::<String>();
+
+        self.console_view
+            .get_buffer()
+            .expect("failed to get console buffer")
+            .set_text(text.trim_end());
+        self.console_expander.set_label(Some(&format!(
+            "Script output from {} ({} lines)",
+            script_name,
+            output.len()
+        )));
+        self.console_expander.set_visible(!output.is_empty());
+    }
+
+    // marks the ranges a script highlighted, using the theme's colors for each kind
+    fn show_highlights(&self, view: &sourceview::View, highlights: &[Highlight]) {
+        if highlights.is_empty() {
+            return;
+        }
+
+        let buffer = view.get_buffer().expect("failed to get buffer");
+        let text = buffer
+            .get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false)
+            .expect("failed to get buffer text");
+        let tag_table = buffer.get_tag_table().expect("failed to get tag table");
+        let style_context = view.get_style_context();
+
+        for kind in &[
+            HighlightKind::Error,
+            HighlightKind::Warning,
+            HighlightKind::Info,
+        ] {
+            let (color_name, fallback) = match kind {
+                HighlightKind::Error => ("error_color", "#cc0000"),
+                HighlightKind::Warning => ("warning_color", "#f57900"),
+                HighlightKind::Info => ("theme_selected_bg_color", "#3465a4"),
+            };
+            let color = style_context
+                .lookup_color(color_name)
+                .unwrap_or_else(|| fallback.parse().expect("invalid fallback color"));
+
+            let tag_name = highlight_tag_name(*kind);
+            let tag = tag_table.lookup(&tag_name).unwrap_or_else(|| {
+                let tag = gtk::TextTag::new(Some(&tag_name));
+                tag.set_property_underline(pango::Underline::Error);
+                tag_table.add(&tag);
+                tag
+            });
+
+            // colors are set each time in case the theme changed
+            tag.set_property_underline_rgba(Some(&color));
+            tag.set_property_background_rgba(Some(&gdk::RGBA {
+                alpha: 0.25,
+                ..color
+            }));
+        }
+
+        for highlight in highlights {
+            let (start, end) = highlight.char_range(&text);
+            buffer.apply_tag_by_name(
+                &highlight_tag_name(highlight.kind),
+                &buffer.get_iter_at_offset(start as i32),
+                &buffer.get_iter_at_offset(end as i32),
+            );
+        }
+
+        self.highlighted.set(true);
+    }
+
+    fn clear_highlights(&self) {
+        if !self.highlighted.replace(false) {
+            return;
+        }
+
+        for view in &[&self.source_view, &self.output_view] {
+            let buffer = view.get_buffer().expect("failed to get buffer");
+            for kind in &[
+                HighlightKind::Error,
+                HighlightKind::Warning,
+                HighlightKind::Info,
+            ] {
+                buffer.remove_tag_by_name(
+                    &highlight_tag_name(*kind),
+                    &buffer.get_start_iter(),
+                    &buffer.get_end_iter(),
+                );
+            }
+        }
+    }
+
+    // remembers the run and any state the script wants to keep
+    fn record_run(&self, script_name: &str, status: &ExecutionStatus, run_mode: RunMode) {
+        if self.config.borrow().usage_metrics {
+            if let Some(category) = self
+                .scripts
+                .read()
+                .expect("scripts lock is poisoned")
+                .iter()
+                .find(|script| script.metadata.name == script_name)
+                .map(metrics::category)
+            {
+                self.store.borrow_mut().metrics.record_run(&category);
+            }
+
+            let features = [
+                ("output_pane", run_mode == RunMode::OutputPane),
+                ("preview", run_mode == RunMode::Preview),
+                ("new_tab", run_mode == RunMode::NewTab),
+                ("save_as", status.save_as().is_some()),
+                ("open_url", status.open_url().is_some()),
+                ("copy", status.copy().is_some()),
+                ("artifacts", !status.artifacts().is_empty()),
+                ("post_preview", status.preview().is_some()),
+            ];
+            for (feature, used) in features.iter() {
+                if *used {
+                    self.record_feature(feature);
+                }
+            }
+        }
+
+        let mut store = self.store.borrow_mut();
+        store.record_run(script_name);
+        if let Some(state) = status.state() {
+            if let Err(e) =
+                script_state::save(&self.config_dir, script_name, state.map(String::as_str))
+            {
+                error!("could not save state of {}: {}", script_name, e);
+                self.push_error("failed to save script state");
+            }
+        }
+
+        if let Err(e) = store.save(&self.data_dir) {
+            error!("could not save stored data: {}", e);
+            self.push_error("failed to save stored data");
+        }
+
+        let mut recent = self.recent.borrow_mut();
+        recent.push(script_name);
+        if let Err(e) = recent.save(&self.config_dir) {
+            error!("could not save recent scripts: {}", e);
+            self.push_error("failed to save recent scripts");
+        }
+    }
+
+    // counts a use of a feature if the user opted in to usage metrics, saved with the next run
+    pub fn record_feature(&self, feature: &str) {
+        if self.config.borrow().usage_metrics {
+            self.store.borrow_mut().metrics.record_feature(feature);
+        }
+    }
+
+    // asks for the url of a script, shows it and installs it into the scripts directory, where
+    // it's loaded by the watcher
+    fn install_script(&self) {
+        let dialog = gtk::Dialog::with_buttons(
+            Some("Install Script"),
+            Some(&self.window),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            &[
+                ("_Cancel", gtk::ResponseType::Cancel),
+                ("_Download", gtk::ResponseType::Accept),
+            ],
+        );
+        dialog.set_default_response(gtk::ResponseType::Accept);
+
+        let content = gtk::Box::new(gtk::Orientation::Vertical, 6);
+        content.set_border_width(12);
+        let help = Label::new(Some("Link to a script or a GitHub gist:"));
+        help.set_xalign(0.0);
+        content.pack_start(&help, false, true, 0);
+        let entry = gtk::Entry::new();
+        entry.set_activates_default(true);
+        entry.set_width_chars(48);
+        content.pack_start(&entry, false, true, 0);
+        dialog
+            .get_content_area()
+            .pack_start(&content, true, true, 0);
+        dialog.show_all();
+
+        let response = dialog.run();
+        let url = entry.get_text().to_string();
+        dialog.close();
+        if response != gtk::ResponseType::Accept || url.trim().is_empty() {
+            return;
+        }
+
+        let checked = installer::download(&url).and_then(|source| {
+            let (metadata, warnings) = installer::check(&source)?;
+            Ok((source, metadata, warnings))
+        });
+        let (source, metadata, warnings) = match checked {
+            Ok(checked) => checked,
+            Err(e) => {
+                error!("could not install script: {}", e);
+                self.push_error(e);
+                return;
+            }
+        };
+
+        let scripts_dir = self.config_dir.join("scripts");
+        let replaces = installer::install_path(&scripts_dir, &metadata).exists();
+        let dialog = gtk::Dialog::with_buttons(
+            Some(&format!("Install {}?", metadata.name)),
+            Some(&self.window),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            &[
+                ("_Cancel", gtk::ResponseType::Cancel),
+                (
+                    if replaces { "_Replace" } else { "_Install" },
+                    gtk::ResponseType::Accept,
+                ),
+            ],
+        );
+        dialog.set_default_size(560, 420);
+
+        let content = gtk::Box::new(gtk::Orientation::Vertical, 6);
+        content.set_border_width(12);
+        let mut details = format!(
+            "{}\nby {}\n\nScripts can read and change your text, only install scripts you trust.",
+            metadata.description,
+            metadata.author.as_deref().unwrap_or("an unknown author")
+        );
+        if replaces {
+            details.push_str(&format!(
+                "\n\nThis replaces the installed {} script.",
+                metadata.name
+            ));
+        }
+        for warning in &warnings {
+            details.push_str(&format!("\nWarning: {}", warning));
+        }
+        let help = Label::new(Some(&details));
+        help.set_line_wrap(true);
+        help.set_xalign(0.0);
+        content.pack_start(&help, false, true, 0);
+
+        let view = gtk::TextView::new();
+        view.set_editable(false);
+        view.set_monospace(true);
+        view.get_buffer()
+            .expect("failed to get buffer")
+            .set_text(&source);
+        let scrolled_window =
+            gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        scrolled_window.add(&view);
+        content.pack_start(&scrolled_window, true, true, 0);
+
+        dialog
+            .get_content_area()
+            .pack_start(&content, true, true, 0);
+        dialog.show_all();
+
+        let response = dialog.run();
+        dialog.close();
+        if response != gtk::ResponseType::Accept {
+            return;
+        }
+
+        match installer::install(&scripts_dir, &source, replaces) {
+            Ok(_) => self.status_bar.push(
+                self.context_id,
+                &format!("Installed script {}", metadata.name),
+            ),
+            Err(e) => {
+                error!("could not install script: {}", e);
+                self.push_error(e);
+            }
+        };
+    }
+
+    // copies scripts written for Boop on macOS into the scripts directory, from its scripts folder
+    // or a zip of it, and says which might not work
+    fn import_scripts(&self) {
+        let dialog = gtk::FileChooserNative::new(
+            Some("Import Boop Scripts"),
+            Some(&self.window),
+            gtk::FileChooserAction::Open,
+            Some("_Import"),
+            Some("_Cancel"),
+        );
+        dialog.set_select_multiple(true);
+        let filter = gtk::FileFilter::new();
+        filter.set_name(Some("Boop scripts"));
+        filter.add_pattern("*.js");
+        filter.add_pattern("*.zip");
+        dialog.add_filter(&filter);
+
+        if dialog.run() != gtk::ResponseType::Accept {
+            return;
+        }
+        let paths = dialog.get_filenames();
+        if paths.is_empty() {
+            return;
+        }
+
+        let names = self
+            .scripts
+            .read()
+            .expect("scripts lock is poisoned")
+            .iter()
+            .map(|script| script.metadata.name.clone())
+            .collect();
+        let report = match importer::import(&paths, &self.config_dir.join("scripts"), &names) {
+            Ok(report) => report,
+            Err(e) => {
+                error!("could not import scripts: {}", e);
+                self.push_error(e);
+                return;
+            }
+        };
+
+        let dialog = gtk::MessageDialog::new(
+            Some(&self.window),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            if report
+                .imported
+                .iter()
+                .all(|script| script.unsupported.is_empty())
+            {
+                gtk::MessageType::Info
+            } else {
+                gtk::MessageType::Warning
+            },
+            gtk::ButtonsType::Close,
+            "Import Boop Scripts",
+        );
+        dialog.set_property_secondary_text(Some(&report.to_string()));
+        dialog.run();
+        dialog.close();
+    }
+
+    // shows the usage metrics that have been recorded, they are only sent if the user chooses to
+    fn show_usage_metrics(&self) {
+        let dialog = gtk::Dialog::with_buttons(
+            Some("Usage Metrics"),
+            Some(&self.window),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            &[
+                ("_Reset", gtk::ResponseType::Reject),
+                ("_Close", gtk::ResponseType::Close),
+                ("_Send", gtk::ResponseType::Accept),
+            ],
+        );
+        dialog.set_default_size(480, 360);
+
+        let (enabled, url) = {
+            let config = self.config.borrow();
+            (config.usage_metrics, config.usage_metrics_url.clone())
+        };
+        let report = self.store.borrow().metrics.report();
+
+        let content = gtk::Box::new(gtk::Orientation::Vertical, 6);
+        content.set_border_width(12);
+
+        let help = Label::new(Some(&match (enabled, &url) {
+            (false, _) => "Usage metrics are off. To help decide which scripts and features to \
+                           improve, set \"usage_metrics\" to true in config.json."
+                .to_string(),
+            (true, None) => "Only these counts are recorded. Set \"usage_metrics_url\" in \
+                             config.json to be able to send them."
+                .to_string(),
+            (true, Some(url)) => format!(
+                "Only these counts are recorded, pressing Send sends them to {}.",
+                url
+            ),
+        }));
+        help.set_line_wrap(true);
+        help.set_xalign(0.0);
+        content.pack_start(&help, false, true, 0);
+
+        let view = gtk::TextView::new();
+        view.set_editable(false);
+        view.set_monospace(true);
+        view.get_buffer()
+            .expect("failed to get buffer")
+            .set_text(&report);
+        let scrolled_window =
+            gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        scrolled_window.add(&view);
+        content.pack_start(&scrolled_window, true, true, 0);
+
+        dialog
+            .get_content_area()
+            .pack_start(&content, true, true, 0);
+        dialog.set_response_sensitive(
+            gtk::ResponseType::Accept,
+            enabled && url.is_some() && !self.store.borrow().metrics.is_empty(),
+        );
+        dialog.show_all();
+
+        let response = dialog.run();
+        dialog.close();
+
+        let cleared = match (response, url) {
+            (gtk::ResponseType::Reject, _) => true,
+            (gtk::ResponseType::Accept, Some(url)) => {
+                match self.store.borrow().metrics.send(&url) {
+                    Ok(()) => true,
+                    Err(e) => {
+                        error!("could not send usage metrics: {}", e);
+                        self.push_error("failed to send usage metrics");
+                        false
+                    }
+                }
+            }
+            _ => false,
+        };
+
+        // start counting again once the counts have been sent or reset
+        if cleared {
+            let mut store = self.store.borrow_mut();
+            store.metrics = Default::default();
+            if let Err(e) = store.save(&self.data_dir) {
+                error!("could not save stored data: {}", e);
+                self.push_error("failed to save stored data");
+            }
+        }
+    }
+
+    fn document_context(&self) -> DocumentContext {
+        let buffer: sourceview::Buffer = self
+            .source_view
+            .get_buffer()
+            .expect("failed to get buffer")
+            .downcast::<sourceview::Buffer>()
+            .expect("faild to downcast TextBuffer to sourceview Buffer");
+
+        let selection_range = buffer
+            .get_selection_bounds()
+            .map(|(start, end)| (start.get_offset() as usize, end.get_offset() as usize));
+
+        DocumentContext {
+            filename: None,
+            language: buffer
+                .get_language()
+                .and_then(|language| language.get_id())
+                .map(|id| id.to_string()),
+            encoding: self.tabs.current_encoding().to_string(),
+            cursor: buffer.get_property_cursor_position() as usize,
+            selection_range,
+            selection_ranges: if self.carets.has_extra() {
+                self.carets.ranges()
+            } else {
+                Vec::new()
+            },
+        }
+    }
+
+    // places the cursor where a script moved it, `offset` is in UTF-16 code units like JS strings
+    fn move_cursor(&self, offset: usize) {
+        let buffer = &self.source_view.get_buffer().expect("failed to get buffer");
+        let text = buffer
+            .get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false)
+            .expect("failed to get buffer text");
+
+        let mut iter = buffer.get_iter_at_offset(utf16_to_char_offset(&text, offset) as i32);
+        buffer.place_cursor(&iter);
+        self.source_view
+            .scroll_to_iter(&mut iter, 0.0, false, 0.0, 0.0);
+    }
+
+    // show the result of a script in the output pane instead of the editor, scripts that don't
+    // change the text show their message instead
+    fn show_output(
+        &self,
+        script_name: &str,
+        replacement: TextReplacement,
+        message: Option<String>,
+    ) {
+        self.output_view
+            .get_buffer()
+            .expect("failed to get output buffer")
+            .set_text(&output_text(replacement, message));
+        self.output_label
+            .set_text(&format!("Output of {}", script_name));
+        self.preview_window.hide();
+        self.output_window.show();
+        self.output_pane.show();
+    }
+
+    // renders what a script posted with `payload.postPreview` in the output pane, below its
+    // output if it was run with RunMode::OutputPane
+    fn show_preview(&self, script_name: &str, content: &str, run_mode: RunMode) {
+        self.preview_label.set_markup(&preview::to_markup(content));
+        self.output_label
+            .set_text(&format!("Preview from {}", script_name));
+        self.output_window
+            .set_visible(run_mode == RunMode::OutputPane);
+        self.preview_window.show();
+        self.output_pane.show();
+    }
+
+    // true if a script run with RunMode::Replace should open its result in a new tab instead
+    fn opens_new_tab(&self, replacement: &TextReplacement) -> bool {
+        self.config.borrow().full_output_in_new_tab
+            && matches!(replacement, TextReplacement::Full(_))
+    }
+
+    // opens the result of a script in a new tab, leaving the document it was run on untouched
+    fn open_output_tab(
+        &self,
+        script_name: &str,
+        replacement: TextReplacement,
+        message: Option<String>,
+    ) {
+        let buffer = self.tabs.open(
+            Some(&format!("{} Output", script_name)),
+            None,
+            &output_text(replacement, message),
+            None,
+        );
+        self.watch_buffer(&buffer);
+    }
+
+    // lists the artifacts of the last script run in the output pane, each can be opened in the
+    // output view or saved
+    fn show_artifacts(&self, script_name: &str, artifacts: Vec<Artifact>, run_mode: RunMode) {
+        for row in self.output_artifacts.get_children() {
+            self.output_artifacts.remove(&row);
+        }
+
+        if artifacts.is_empty() {
+            self.output_artifacts.hide();
+            return;
+        }
+
+        // the output view still shows a previous run, show the first artifact instead
+        if run_mode != RunMode::OutputPane {
+            self.open_artifact(script_name, &artifacts[0]);
+        }
+
+        for artifact in artifacts {
+            let row = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+            row.set_margin_start(6);
+            row.set_margin_end(2);
+
+            let label = Label::new(Some(&format!(
+                "{} ({} bytes)",
+                artifact.name,
+                artifact.text.len()
+            )));
+            label.set_xalign(0.0);
+            label.set_ellipsize(pango::EllipsizeMode::End);
+            row.pack_start(&label, true, true, 0);
+
+            let open_button = Button::with_label("Open");
+            open_button.set_relief(gtk::ReliefStyle::None);
+            {
+                let app = self.clone();
+                let script_name = script_name.to_string();
+                let artifact = artifact.clone();
+                open_button.connect_clicked(move |_| app.open_artifact(&script_name, &artifact));
+            }
+            row.pack_start(&open_button, false, true, 0);
+
+            let save_button = Button::with_label("Save");
+            save_button.set_relief(gtk::ReliefStyle::None);
+            {
+                let app = self.clone();
+                save_button.connect_clicked(move |_| {
+                    app.save_as(SaveAs {
+                        suggested_name: artifact.name.clone(),
+                        text: artifact.text.clone(),
+                    })
+                });
+            }
+            row.pack_start(&save_button, false, true, 0);
+
+            self.output_artifacts.add(&row);
+        }
+
+        self.output_artifacts.show_all();
+        self.output_pane.show();
+    }
+
+    fn open_artifact(&self, script_name: &str, artifact: &Artifact) {
+        self.output_view
+            .get_buffer()
+            .expect("failed to get output buffer")
+            .set_text(&artifact.text);
+        self.output_label
+            .set_text(&format!("{} from {}", artifact.name, script_name));
+        self.preview_window.hide();
+        self.output_window.show();
+        self.output_pane.show();
+    }
+
+    // the document as it would be after the replacement
+    fn replaced_text(&self, replacement: &TextReplacement) -> String {
+        let buffer = self.source_view.get_buffer().expect("failed to get buffer");
+        let text = buffer
+            .get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false)
+            .expect("failed to get buffer text")
+            .to_string();
+        let selection = buffer
+            .get_selection_bounds()
+            .map(|(start, end)| (start.get_offset() as usize, end.get_offset() as usize));
+
+        let cursor = buffer.get_property_cursor_position() as usize;
+
+        // a selection replacement without a selection isn't made in the editor
+        if let (TextReplacement::Selection(_), None) = (replacement, selection) {
+            return text;
+        }
+        if self.carets.has_extra() {
+            if let Some(change_set) = replacement.selections_change_set(&self.carets.ranges()) {
+                return change_set.apply(&text);
+            }
+        }
+        replacement
+            .change_set(&text, selection, cursor)
+            .apply(&text)
+    }
+
+    // shows the lines the replacement would change, true if the user wants it applied
+    fn confirm_replacement(&self, script_name: &str, replacement: &TextReplacement) -> bool {
+        let buffer = self.source_view.get_buffer().expect("failed to get buffer");
+        let before = buffer
+            .get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false)
+            .expect("failed to get buffer text");
+        let after = self.replaced_text(replacement);
+
+        let lines = diff::diff_lines(&before, &after);
+        let (added, removed) = diff::count_changes(&lines);
+        if added == 0 && removed == 0 {
+            self.status_bar.push(
+                self.context_id,
+                &format!("{} didn't change the text", script_name),
+            );
+            return false;
+        }
+
+        let dialog = gtk::Dialog::with_buttons(
+            Some(&format!("Changes by {}", script_name)),
+            Some(&self.window),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            &[
+                ("_Discard", gtk::ResponseType::Reject),
+                ("_Apply", gtk::ResponseType::Accept),
+            ],
+        );
+        dialog.set_default_size(PREVIEW_WIDTH, PREVIEW_HEIGHT);
+        dialog.set_default_response(gtk::ResponseType::Accept);
+
+        let summary = Label::new(Some(&format!(
+            "{} line(s) added, {} line(s) removed",
+            added, removed
+        )));
+        summary.set_xalign(0.0);
+        summary.set_margin_start(6);
+        summary.set_margin_top(6);
+        summary.set_margin_bottom(6);
+
+        let view = gtk::TextView::new();
+        view.set_monospace(true);
+        view.set_editable(false);
+        view.set_cursor_visible(false);
+        let diff_buffer = view.get_buffer().expect("failed to get buffer");
+        let style_context = view.get_style_context();
+        for &(tag_name, color_name, fallback) in &[
+            ("added", "success_color", "#4e9a06"),
+            ("removed", "error_color", "#cc0000"),
+        ] {
+            let color = style_context
+                .lookup_color(color_name)
+                .unwrap_or_else(|| fallback.parse().expect("invalid fallback color"));
+            let tag = gtk::TextTag::new(Some(tag_name));
+            tag.set_property_paragraph_background_rgba(Some(&gdk::RGBA {
+                alpha: 0.25,
+                ..color
+            }));
+            diff_buffer
+                .get_tag_table()
+                .expect("failed to get tag table")
+                .add(&tag);
+        }
+
+        for line in lines {
+            let (prefix, text, tag_name) = match line {
+                DiffLine::Same(text) => ("  ", text, None),
+                DiffLine::Removed(text) => ("- ", text, Some("removed")),
+                DiffLine::Added(text) => ("+ ", text, Some("added")),
+            };
+            let start = diff_buffer.get_end_iter().get_offset();
+            diff_buffer.insert(
+                &mut diff_buffer.get_end_iter(),
+                &format!("{}{}\n", prefix, text),
+            );
+            if let Some(tag_name) = tag_name {
+                diff_buffer.apply_tag_by_name(
+                    tag_name,
+                    &diff_buffer.get_iter_at_offset(start),
+                    &diff_buffer.get_end_iter(),
+                );
+            }
+        }
+
+        let scrolled_window =
+            gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+        scrolled_window.add(&view);
+        let content_area = dialog.get_content_area();
+        content_area.pack_start(&summary, false, true, 0);
+        content_area.pack_start(&scrolled_window, true, true, 0);
+        dialog.show_all();
+
+        let apply = dialog.run() == gtk::ResponseType::Accept;
+        dialog.close();
+        apply
+    }
+
+    // applies the replacement as a single undo step, and adds it to the history as a change made
+    // by `script_name`
+    fn do_replacement(&self, script_name: &str, replacement: TextReplacement) {
+        let buffer = &self.source_view.get_buffer().expect("failed to get buffer");
+
+        if let TextReplacement::None = replacement {
+            info!("no text to replace");
+            return;
+        }
+
+        let before = buffer
+            .get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false)
+            .expect("failed to get buffer text");
+
+        buffer.begin_user_action();
+        match replacement {
+            TextReplacement::Full(text) => {
+                // only what changed is replaced, so the cursor, marks and scroll position are kept
+                let edits = diff::edits(&before, &text);
+                info!("replacing full text with {} edits", edits.len());
+
+                for (start, end, replacement) in edits {
+                    let mut start = buffer.get_iter_at_offset(start as i32);
+                    let mut end = buffer.get_iter_at_offset(end as i32);
+                    buffer.delete(&mut start, &mut end);
+                    buffer.insert(&mut start, &replacement);
+                }
+            }
+            TextReplacement::Selection(text) => {
+                info!("replacing selection");
                 match &mut buffer.get_selection_bounds() {
                     Some((start, end)) => {
                         buffer.delete(start, end);
-                        buffer.insert(start, &insert_text);
+                        buffer.insert(start, &text);
                     }
                     None => {
-                        let mut insert_point =
-                            buffer.get_iter_at_offset(buffer.get_property_cursor_position());
-                        buffer.insert(&mut insert_point, &insert_text)
+                        error!("tried to do a selection replacement, but no text is selected!");
+                    }
+                }
+            }
+            TextReplacement::Selections(texts) if self.carets.has_extra() => {
+                let ranges = self.carets.ranges();
+                info!("replacing {} selections", texts.len().min(ranges.len()));
+                // last first, so no replacement moves the text of a later one
+                for ((start, end), text) in ranges.iter().zip(&texts).rev() {
+                    let mut start = buffer.get_iter_at_offset(*start as i32);
+                    let mut end = buffer.get_iter_at_offset(*end as i32);
+                    buffer.delete(&mut start, &mut end);
+                    buffer.insert(&mut start, text);
+                }
+            }
+            TextReplacement::Selections(texts) => {
+                info!("replacing {} selections", texts.len());
+                if texts.len() > 1 {
+                    warn!("ignoring {} extra selections", texts.len() - 1);
+                }
+                match (&mut buffer.get_selection_bounds(), texts.first()) {
+                    (Some((start, end)), Some(text)) => {
+                        buffer.delete(start, end);
+                        buffer.insert(start, text);
+                    }
+                    _ => {
+                        error!("tried to do a selections replacement, but no text is selected!");
+                    }
+                }
+            }
+            TextReplacement::Insert(insertions) => {
+                info!("inserting {} pieces of text", insertions.len());
+
+                let selection = buffer
+                    .get_selection_bounds()
+                    .map(|(start, end)| (start.get_offset() as usize, end.get_offset() as usize));
+                let cursor = buffer.get_property_cursor_position() as usize;
+
+                for (start, end, replacement) in
+                    insertion_edits(&insertions, &before, cursor, selection)
+                {
+                    let mut start = buffer.get_iter_at_offset(start as i32);
+                    let mut end = buffer.get_iter_at_offset(end as i32);
+                    buffer.delete(&mut start, &mut end);
+                    buffer.insert(&mut start, &replacement);
+                }
+            }
+            TextReplacement::None => {}
+        }
+        buffer.end_user_action();
+
+        let mut store = self.store.borrow_mut();
+        store.record_change(self.tabs.current_id(), script_name, &before);
+        if let Err(e) = store.save(&self.data_dir) {
+            error!("could not save stored data: {}", e);
+            self.push_error("failed to save stored data");
+        }
+        drop(store);
+        self.show_history();
+    }
+
+    // lists the changes scripts made to the current tab's document, most recent first, each can be
+    // reverted
+    fn show_history(&self) {
+        for row in self.history_list.get_children() {
+            self.history_list.remove(&row);
+        }
+
+        let tab = self.tabs.current_id();
+        let store = self.store.borrow();
+        if store.history.iter().all(|entry| entry.tab != tab) {
+            let label = Label::new(Some("Changes made by scripts are listed here"));
+            label.set_line_wrap(true);
+            label.set_margin_top(12);
+            self.history_list.add(&label);
+        }
+
+        for (index, entry) in store
+            .history
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, entry)| entry.tab == tab)
+        {
+            let row = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+            row.set_margin_start(6);
+            row.set_margin_end(2);
+
+            let time = glib::DateTime::from_unix_local(entry.time as i64)
+                .format("%x %X")
+                .map(|time| time.to_string())
+                .unwrap_or_default();
+            let label = Label::new(None);
+            label.set_markup(&format!(
+                "<b>{}</b>\n<span size=\"smaller\" alpha=\"60%\">{}</span>",
+                glib::markup_escape_text(&entry.script),
+                time
+            ));
+            label.set_xalign(0.0);
+            label.set_ellipsize(pango::EllipsizeMode::End);
+            row.pack_start(&label, true, true, 0);
+
+            let revert_button = Button::with_label("Revert");
+            revert_button.set_relief(gtk::ReliefStyle::None);
+            if entry.before.is_some() {
+                revert_button.set_tooltip_text(Some(&format!(
+                    "Go back to the text from before {} ran",
+                    entry.script
+                )));
+            } else {
+                revert_button.set_sensitive(false);
+                revert_button.set_tooltip_text(Some("The text was too large to keep"));
+            }
+            {
+                let app = self.clone();
+                revert_button.connect_clicked(move |_| app.revert_to(index));
+            }
+            row.pack_start(&revert_button, false, true, 0);
+
+            self.history_list.add(&row);
+        }
+
+        self.history_list.show_all();
+    }
+
+    // restores the text from before a change, as a single undo step so it can itself be undone
+    fn revert_to(&self, index: usize) {
+        let (script_name, before) = {
+            let mut store = self.store.borrow_mut();
+            // only the current tab's changes are listed, but it may have been switched since
+            let script_name = match store.history.get(index) {
+                Some(entry) if entry.tab == self.tabs.current_id() => entry.script.clone(),
+                _ => return,
+            };
+            match store.revert(index) {
+                Some(before) => (script_name, before),
+                None => return,
+            }
+        };
+        info!("reverting to before {}", script_name);
+
+        let buffer = self.source_view.get_buffer().expect("failed to get buffer");
+        buffer.begin_user_action();
+        buffer.set_text(&before);
+        buffer.end_user_action();
+
+        if let Err(e) = self.store.borrow().save(&self.data_dir) {
+            error!("could not save stored data: {}", e);
+            self.push_error("failed to save stored data");
+        }
+        self.show_history();
+        self.status_bar.push(
+            self.context_id,
+            &format!("Reverted to before {} ran", script_name),
+        );
+    }
+
+    // asks the user whether a script may load code from the given urls, the answer is remembered
+    fn ask_remote_require(&self, script_name: &str, urls: &[String]) -> bool {
+        let allowed = self.ask_permission(
+            &format!("Allow {} to load code from the internet?", script_name),
+            &format!(
+                "Code loaded from these addresses can read and change your text:\n\n{}",
+                urls.join("\n")
+            ),
+        );
+
+        let mut permissions = Permissions::load(&self.config_dir);
+        for url in urls {
+            info!("remote require of {} allowed: {}", url, allowed);
+            permissions.remote_require.insert(url.clone(), allowed);
+        }
+        if let Err(e) = permissions.save(&self.config_dir) {
+            error!("could not save permissions: {}", e);
+            self.push_error("failed to save permissions");
+        }
+
+        allowed
+    }
+
+    // looks up an answer the user gave before, otherwise asks them and remembers their answer
+    fn permission(
+        &self,
+        answers: impl Fn(&mut Permissions) -> &mut HashMap<String, bool>,
+        key: &str,
+        question: &str,
+        details: &str,
+    ) -> bool {
+        let mut permissions = Permissions::load(&self.config_dir);
+        if let Some(allowed) = answers(&mut permissions).get(key) {
+            return *allowed;
+        }
+
+        let allowed = self.ask_permission(question, details);
+        answers(&mut permissions).insert(key.to_string(), allowed);
+        if let Err(e) = permissions.save(&self.config_dir) {
+            error!("could not save permissions: {}", e);
+            self.push_error("failed to save permissions");
+        }
+
+        allowed
+    }
+
+    // shows a yes/no question, true if the user answered yes
+    fn ask_permission(&self, question: &str, details: &str) -> bool {
+        let dialog = gtk::MessageDialog::new(
+            Some(&self.window),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::YesNo,
+            question,
+        );
+        dialog.set_property_secondary_text(Some(details));
+
+        let allowed = dialog.run() == gtk::ResponseType::Yes;
+        dialog.close();
+
+        allowed
+    }
+
+    // answers a request made by a script while it's running
+    // `permission_key` is what the user's answers to the script are remembered by
+    fn host_request(
+        &self,
+        script_name: &str,
+        permission_key: &str,
+        request: HostRequest,
+    ) -> HostResponse {
+        match request {
+            HostRequest::ChooseFile(filters) => {
+                HostResponse::ChosenFile(self.choose_file(script_name, permission_key, &filters))
+            }
+            HostRequest::PickColor(initial) => {
+                HostResponse::PickedColor(self.pick_color(initial.as_deref()))
+            }
+            HostRequest::Prompt(label, default) => {
+                HostResponse::Prompted(self.prompt(script_name, &label, default.as_deref()))
+            }
+            HostRequest::ReadClipboard => {
+                HostResponse::ClipboardText(self.read_clipboard(script_name, permission_key))
+            }
+            HostRequest::AllowNetwork(url) => {
+                HostResponse::NetworkAllowed(self.allow_network(script_name, permission_key, &url))
+            }
+        }
+    }
+
+    // asks for a value a script needs, None if the user cancelled
+    fn prompt(&self, script_name: &str, label: &str, default: Option<&str>) -> Option<String> {
+        let dialog = gtk::Dialog::with_buttons(
+            Some(script_name),
+            Some(&self.window),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            &[
+                ("_Cancel", gtk::ResponseType::Cancel),
+                ("_OK", gtk::ResponseType::Ok),
+            ],
+        );
+        dialog.set_default_response(gtk::ResponseType::Ok);
+
+        let label = gtk::Label::new(Some(label));
+        label.set_halign(gtk::Align::Start);
+        let entry = gtk::Entry::new();
+        entry.set_text(default.unwrap_or_default());
+        entry.set_activates_default(true);
+
+        let content_area = dialog.get_content_area();
+        content_area.set_spacing(6);
+        content_area.set_border_width(12);
+        content_area.add(&label);
+        content_area.add(&entry);
+        content_area.show_all();
+
+        let value = if dialog.run() == gtk::ResponseType::Ok {
+            Some(entry.get_text().to_string())
+        } else {
+            None
+        };
+
+        dialog.close();
+
+        value
+    }
+
+    fn pick_color(&self, initial: Option<&str>) -> Option<PickedColor> {
+        let dialog = gtk::ColorChooserDialog::new(Some("Pick a Color"), Some(&self.window));
+        dialog.set_use_alpha(true);
+        if let Some(initial) = initial {
+            match initial.parse::<gdk::RGBA>() {
+                Ok(rgba) => dialog.set_rgba(&rgba),
+                Err(_) => warn!("could not parse initial color {}", initial),
+            }
+        }
+
+        let picked_color = if dialog.run() == gtk::ResponseType::Ok {
+            let rgba = dialog.get_rgba();
+            let (red, green, blue) = (
+                (rgba.red * 255.0).round() as u8,
+                (rgba.green * 255.0).round() as u8,
+                (rgba.blue * 255.0).round() as u8,
+            );
+
+            Some(PickedColor {
+                hex: format!("#{:02x}{:02x}{:02x}", red, green, blue),
+                rgba: format!("rgba({}, {}, {}, {})", red, green, blue, rgba.alpha),
+            })
+        } else {
+            None
+        };
+
+        dialog.close();
+
+        picked_color
+    }
+
+    fn choose_file(
+        &self,
+        script_name: &str,
+        permission_key: &str,
+        filters: &[FileFilter],
+    ) -> Option<ChosenFile> {
+        let allowed = self.permission(
+            |permissions| permissions.answers_mut(Permission::Filesystem),
+            permission_key,
+            &format!("Allow {} to read files?", script_name),
+            "The script will only be able to read files you choose.",
+        );
+
+        if !allowed {
+            warn!("{} is not allowed to read files", script_name);
+            return None;
+        }
+
+        let dialog = gtk::FileChooserDialog::with_buttons(
+            Some("Choose a File"),
+            Some(&self.window),
+            gtk::FileChooserAction::Open,
+            &[
+                ("_Cancel", gtk::ResponseType::Cancel),
+                ("_Open", gtk::ResponseType::Accept),
+            ],
+        );
+        for filter in filters {
+            let file_filter = gtk::FileFilter::new();
+            file_filter.set_name(Some(&filter.name));
+            for pattern in &filter.patterns {
+                file_filter.add_pattern(pattern);
+            }
+            dialog.add_filter(&file_filter);
+        }
+
+        let mut chosen_file = None;
+        if dialog.run() == gtk::ResponseType::Accept {
+            if let Some(path) = dialog.get_filename() {
+                info!("{} chose {}", script_name, path.display());
+                match fs::read(&path) {
+                    Ok(contents) => {
+                        chosen_file = Some(ChosenFile {
+                            path: path.display().to_string(),
+                            contents,
+                        })
+                    }
+                    Err(e) => {
+                        error!("could not read {}: {}", path.display(), e);
+                        self.push_error(format!("failed to read {}", path.display()));
                     }
                 }
             }
-            TextReplacement::None => {
-                info!("no text to replace");
+        }
+
+        dialog.close();
+
+        chosen_file
+    }
+
+    fn read_clipboard(&self, script_name: &str, permission_key: &str) -> Option<String> {
+        let allowed = self.permission(
+            |permissions| permissions.answers_mut(Permission::Clipboard),
+            permission_key,
+            &format!("Allow {} to read the clipboard?", script_name),
+            "The script will be able to read whatever you copy.",
+        );
+
+        if !allowed {
+            warn!("{} is not allowed to read the clipboard", script_name);
+            return None;
+        }
+
+        gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD)
+            .wait_for_text()
+            .map(|text| text.to_string())
+    }
+
+    fn allow_network(&self, script_name: &str, permission_key: &str, url: &str) -> bool {
+        let allowed = self.permission(
+            |permissions| permissions.answers_mut(Permission::Network),
+            permission_key,
+            &format!("Allow {} to access the network?", script_name),
+            &format!("The script wants to fetch {}.", url),
+        );
+
+        if !allowed {
+            warn!("{} is not allowed to fetch {}", script_name, url);
+        }
+
+        allowed
+    }
+
+    fn open_url(&self, script_name: &str, url: &str) {
+        // the script has finished, so the scripts aren't locked
+        let permission_key = self
+            .scripts
+            .read()
+            .expect("scripts lock is poisoned")
+            .iter()
+            .find(|script| script.metadata.name == script_name)
+            .map_or_else(|| script_name.to_string(), Script::permission_key);
+        let allowed = self.permission(
+            |permissions| &mut permissions.open_urls,
+            &permission_key,
+            &format!("Allow {} to open web pages?", script_name),
+            &format!("The script wants to open {} in your browser.", url),
+        );
+
+        if !allowed {
+            warn!("{} is not allowed to open {}", script_name, url);
+            return;
+        }
+
+        if let Err(open_err) = open::that(url) {
+            error!("could not launch {}: {}", url, open_err);
+            self.push_error(format!("failed to open {}", url));
+        }
+    }
+
+    // shows a save dialog for a file requested by a script with `payload.saveAs`
+    fn save_as(&self, save_as: SaveAs) {
+        let dialog = gtk::FileChooserDialog::with_buttons(
+            Some("Save As"),
+            Some(&self.window),
+            gtk::FileChooserAction::Save,
+            &[
+                ("_Cancel", gtk::ResponseType::Cancel),
+                ("_Save", gtk::ResponseType::Accept),
+            ],
+        );
+        dialog.set_do_overwrite_confirmation(true);
+        dialog.set_current_name(&save_as.suggested_name);
+
+        if dialog.run() == gtk::ResponseType::Accept {
+            if let Some(path) = dialog.get_filename() {
+                info!("saving {} bytes to {}", save_as.text.len(), path.display());
+                if let Err(e) = fs::write(&path, save_as.text.as_bytes()) {
+                    error!("could not save {}: {}", path.display(), e);
+                    self.push_error(format!("failed to save {}", path.display()));
+                }
             }
         }
+
+        dialog.close();
+    }
+}
+
+// the text a script gave, shown on its own rather than replacing part of the document
+fn output_text(replacement: TextReplacement, message: Option<String>) -> String {
+    match replacement {
+        TextReplacement::Full(text) | TextReplacement::Selection(text) => text,
+        TextReplacement::Selections(texts) => texts.join("\n"),
+        TextReplacement::Insert(insertions) => insertions
+            .iter()
+            .map(|insertion| insertion.text.as_str())
+            .collect(),
+        TextReplacement::None => message.unwrap_or_default(),
+    }
+}
+
+fn highlight_tag_name(kind: HighlightKind) -> String {
+    format!("boop-highlight-{}", kind.name())
+}
+
+// a size in bytes, e.g. "12.3 MB"
+fn format_size(bytes: usize) -> String {
+    match bytes {
+        0..=1023 => format!("{} bytes", bytes),
+        1024..=1_048_575 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / (1024.0 * 1024.0)),
     }
 }
@@ -0,0 +1,245 @@
+// lists scripts grouped by their tags, for finding scripts without knowing what they're called,
+// scripts are listed under each tag they share with another script
+
+use crate::script::Script;
+use glib::Type;
+use gtk::prelude::*;
+use std::collections::BTreeMap;
+
+const ICON_COLUMN: u32 = 0;
+const TEXT_COLUMN: u32 = 1;
+// id of the script, or -1 for categories
+const ID_COLUMN: u32 = 2;
+
+const COLUMNS: [u32; 3] = [ICON_COLUMN, TEXT_COLUMN, ID_COLUMN];
+const COLUMN_TYPES: [Type; 3] = [Type::String, Type::String, Type::I64];
+
+// tags of fewer scripts don't make a category
+const MIN_CATEGORY_SIZE: usize = 2;
+// scripts that don't share a tag with any other script
+const OTHER_CATEGORY: &str = "Other";
+
+const DIALOG_WIDTH: i32 = 420;
+const DIALOG_HEIGHT: i32 = 480;
+
+#[derive(Debug, PartialEq)]
+pub struct Category {
+    pub name: String,
+    // indices of the scripts, sorted by name
+    pub scripts: Vec<usize>,
+}
+
+// categories of the scripts from their names and comma separated tags, sorted by name with other
+// scripts last
+pub fn categories(scripts: &[(&str, Option<&str>)]) -> Vec<Category> {
+    let mut by_tag: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+    for (index, (_, tags)) in scripts.iter().enumerate() {
+        for tag in tags.unwrap_or_default().split(',') {
+            let tag = tag.trim().to_lowercase();
+            if tag.is_empty() {
+                continue;
+            }
+            let indices = by_tag.entry(tag).or_default();
+            if !indices.contains(&index) {
+                indices.push(index);
+            }
+        }
+    }
+
+    let sort_by_name = |indices: &mut Vec<usize>| {
+        indices.sort_by_key(|index| scripts[*index].0.to_lowercase());
+    };
+
+    let mut categories: Vec<Category> = by_tag
+        .into_iter()
+        .filter(|(_, indices)| indices.len() >= MIN_CATEGORY_SIZE)
+        .map(|(tag, mut indices)| {
+            sort_by_name(&mut indices);
+            Category {
+                name: title_case(&tag),
+                scripts: indices,
+            }
+        })
+        .collect();
+
+    let mut other: Vec<usize> = (0..scripts.len())
+        .filter(|index| {
+            !categories
+                .iter()
+                .any(|category| category.scripts.contains(index))
+        })
+        .collect();
+    if !other.is_empty() {
+        sort_by_name(&mut other);
+        categories.push(Category {
+            name: OTHER_CATEGORY.to_string(),
+            scripts: other,
+        });
+    }
+
+    categories
+}
+
+// "url encode" to "Url Encode"
+fn title_case(text: &str) -> String {
+    text.split(' ')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+#[derive(Shrinkwrap)]
+pub struct ScriptBrowser {
+    #[shrinkwrap(main_field)]
+    dialog: gtk::Dialog,
+}
+
+impl ScriptBrowser {
+    // the dialog responds with the id of the chosen script
+    pub fn new(parent: &gtk::ApplicationWindow, scripts: &[Script]) -> Self {
+        let dialog = gtk::Dialog::with_buttons(
+            Some("Browse Scripts"),
+            Some(parent),
+            gtk::DialogFlags::MODAL
+                | gtk::DialogFlags::DESTROY_WITH_PARENT
+                | gtk::DialogFlags::USE_HEADER_BAR,
+            &[],
+        );
+        dialog.set_default_size(DIALOG_WIDTH, DIALOG_HEIGHT);
+
+        let store = gtk::TreeStore::new(&COLUMN_TYPES);
+        let names: Vec<(&str, Option<&str>)> = scripts
+            .iter()
+            .map(|script| {
+                (
+                    script.metadata.name.as_str(),
+                    script.metadata.tags.as_deref(),
+                )
+            })
+            .collect();
+        for category in categories(&names) {
+            let text = format!(
+                "<b>{}</b> <span alpha=\"60%\">{}</span>",
+                glib::markup_escape_text(&category.name),
+                category.scripts.len()
+            );
+            let values: [&dyn ToValue; 3] = [&None::<&str>, &text, &-1i64];
+            let parent = store.insert_with_values(None, None, &COLUMNS, &values);
+
+            for index in category.scripts {
+                let metadata = &scripts[index].metadata;
+                let icon_name = format!("boop-gtk-{}-symbolic", metadata.icon.to_lowercase());
+                let text = format!(
+                    "<b>{}</b>\n<span size=\"smaller\">{}</span>",
+                    glib::markup_escape_text(&metadata.name),
+                    glib::markup_escape_text(&metadata.description)
+                );
+                let values: [&dyn ToValue; 3] = [&icon_name, &text, &(index as i64)];
+                store.insert_with_values(Some(&parent), None, &COLUMNS, &values);
+            }
+        }
+
+        let tree_view = gtk::TreeView::with_model(&store);
+        tree_view.set_headers_visible(false);
+        tree_view.set_enable_search(false);
+        {
+            let column = gtk::TreeViewColumn::new();
+
+            let icon_renderer = gtk::CellRendererPixbuf::new();
+            icon_renderer.set_padding(4, 4);
+            column.pack_start(&icon_renderer, false);
+            column.add_attribute(&icon_renderer, "icon-name", ICON_COLUMN as i32);
+
+            let text_renderer = gtk::CellRendererText::new();
+            text_renderer.set_property_wrap_mode(pango::WrapMode::Word);
+            text_renderer.set_property_wrap_width(DIALOG_WIDTH - 96);
+            column.pack_start(&text_renderer, true);
+            column.add_attribute(&text_renderer, "markup", TEXT_COLUMN as i32);
+
+            tree_view.append_column(&column);
+        }
+
+        // categories open and close, scripts are chosen
+        {
+            let dialog = dialog.clone();
+            tree_view.connect_row_activated(move |tree_view, path, _| {
+                let model = match tree_view.get_model() {
+                    Some(model) => model,
+                    None => return,
+                };
+                let id = model
+                    .get_iter(path)
+                    .and_then(|iter| model.get_value(&iter, ID_COLUMN as i32).get::<i64>().ok())
+                    .flatten();
+
+                match id {
+                    Some(id) if id >= 0 => dialog.response(gtk::ResponseType::Other(id as u16)),
+                    _ if tree_view.row_expanded(path) => {
+                        tree_view.collapse_row(path);
+                    }
+                    _ => {
+                        tree_view.expand_row(path, false);
+                    }
+                }
+            });
+        }
+
+        let scrolled_window = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+        scrolled_window.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+        scrolled_window.set_vexpand(true);
+        scrolled_window.add(&tree_view);
+        dialog.get_content_area().add(&scrolled_window);
+
+        ScriptBrowser { dialog }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_categories() {
+        let scripts = [
+            ("URL Encode", Some("url,encode")),
+            ("Base64 Encode", Some("base64, Encode")),
+            ("Base64 Decode", Some("base64,decode")),
+            ("URL Decode", Some("url,decode")),
+            ("Shuffle Lines", None),
+            ("Count Words", Some("count")),
+        ];
+
+        assert_eq!(
+            categories(&scripts),
+            vec![
+                Category {
+                    name: "Base64".to_string(),
+                    scripts: vec![2, 1],
+                },
+                Category {
+                    name: "Decode".to_string(),
+                    scripts: vec![2, 3],
+                },
+                Category {
+                    name: "Encode".to_string(),
+                    scripts: vec![1, 0],
+                },
+                Category {
+                    name: "Url".to_string(),
+                    scripts: vec![3, 0],
+                },
+                Category {
+                    name: "Other".to_string(),
+                    scripts: vec![5, 4],
+                },
+            ]
+        );
+        assert!(categories(&[]).is_empty());
+    }
+}
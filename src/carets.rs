@@ -0,0 +1,505 @@
+// extra cursors and selections in the editor, GTK 3 text views only have one so the others are
+// kept as pairs of marks, drawn over the editor, and typing is repeated at each of them
+//
+// Ctrl+click adds a cursor, Alt+drag selects a block of columns, Escape or a click goes back to a
+// single cursor
+
+use gdk::keys::{self, Key};
+use gtk::prelude::*;
+use sourceview::prelude::*;
+use std::{cell::RefCell, rc::Rc};
+
+// selections longer than this are only drawn at their ends, each character is drawn separately
+const MAX_DRAWN_CHARS: usize = 10_000;
+const CARET_WIDTH: f64 = 1.5;
+const SELECTION_ALPHA: f64 = 0.25;
+
+// the columns selected on each line of a block between two (line, column) corners, as (line,
+// first column, last column), columns past the end of a line are moved to its end
+pub fn block(
+    from: (i32, i32),
+    to: (i32, i32),
+    line_length: impl Fn(i32) -> i32,
+) -> Vec<(i32, i32, i32)> {
+    let (left, right) = (from.1.min(to.1), from.1.max(to.1));
+    (from.0.min(to.0)..=from.0.max(to.0))
+        .map(|line| {
+            let length = line_length(line);
+            (line, left.min(length), right.min(length))
+        })
+        .collect()
+}
+
+// ranges of characters that touch, or are the same cursor, become one caret
+pub fn overlaps(a: (usize, usize), b: (usize, usize)) -> bool {
+    a.0 <= b.1 && b.0 <= a.1
+}
+
+#[derive(Clone)]
+pub struct Carets {
+    view: sourceview::View,
+    area: gtk::DrawingArea,
+    // start and end of each caret besides the editor's own, in the buffer that's shown, the same
+    // mark twice for a cursor
+    extra: Rc<RefCell<Vec<(gtk::TextMark, gtk::TextMark)>>>,
+    // the (line, column) an Alt+drag started at
+    block_start: Rc<RefCell<Option<(i32, i32)>>>,
+}
+
+impl Carets {
+    pub fn new(view: &sourceview::View) -> Self {
+        let carets = Carets {
+            view: view.clone(),
+            area: gtk::DrawingArea::new(),
+            extra: Rc::new(RefCell::new(Vec::new())),
+            block_start: Rc::new(RefCell::new(None)),
+        };
+
+        {
+            let carets_ = carets.clone();
+            carets.area.connect_draw(move |area, cr| {
+                let color = carets_
+                    .view
+                    .get_style_context()
+                    .get_color(gtk::StateFlags::NORMAL);
+                for (x, y, width, height, alpha) in carets_.shapes(area) {
+                    cr.set_source_rgba(color.red, color.green, color.blue, color.alpha * alpha);
+                    cr.rectangle(x, y, width, height);
+                    cr.fill();
+                }
+                gtk::Inhibit(false)
+            });
+        }
+        {
+            let carets_ = carets.clone();
+            view.connect_button_press_event(move |_, event| carets_.button_pressed(event));
+        }
+        {
+            let carets_ = carets.clone();
+            view.connect_motion_notify_event(move |_, event| carets_.pointer_moved(event));
+        }
+        {
+            let carets_ = carets.clone();
+            view.connect_button_release_event(move |_, _| {
+                carets_.block_start.replace(None);
+                carets_.dedupe();
+                gtk::Inhibit(false)
+            });
+        }
+        {
+            let carets_ = carets.clone();
+            view.connect_key_press_event(move |_, event| carets_.key_pressed(event));
+        }
+        if let Some(adjustment) = view.get_vadjustment() {
+            let area = carets.area.clone();
+            adjustment.connect_value_changed(move |_| area.queue_draw());
+        }
+        {
+            let area = carets.area.clone();
+            view.connect_size_allocate(move |_, _| area.queue_draw());
+        }
+
+        carets
+    }
+
+    // shown over the editor, clicks go through to it
+    pub fn widget(&self) -> &gtk::DrawingArea {
+        &self.area
+    }
+
+    // buffers of new tabs are redrawn as they change
+    pub fn watch(&self, buffer: &gtk::TextBuffer) {
+        let area = self.area.clone();
+        buffer.connect_changed(move |_| area.queue_draw());
+    }
+
+    pub fn has_extra(&self) -> bool {
+        !self.extra.borrow().is_empty()
+    }
+
+    // back to the editor's own cursor, when the tab changes or the user clicks somewhere
+    pub fn clear(&self) {
+        for (start, end) in self.extra.borrow_mut().drain(..) {
+            if let Some(buffer) = start.get_buffer() {
+                buffer.delete_mark(&start);
+                if end != start {
+                    buffer.delete_mark(&end);
+                }
+            }
+        }
+        self.area.queue_draw();
+    }
+
+    // every caret's selection in characters in the order they appear, cursors are empty ranges
+    pub fn ranges(&self) -> Vec<(usize, usize)> {
+        let buffer = self.view.get_buffer().expect("failed to get buffer");
+        let mut ranges: Vec<(usize, usize)> = self
+            .marks(&buffer)
+            .iter()
+            .map(|(start, end)| {
+                let (start, end) = Carets::iters(&buffer, start, end);
+                (start.get_offset() as usize, end.get_offset() as usize)
+            })
+            .collect();
+        ranges.sort_unstable();
+        ranges
+    }
+
+    // the editor's own cursor first
+    fn marks(&self, buffer: &gtk::TextBuffer) -> Vec<(gtk::TextMark, gtk::TextMark)> {
+        let mut marks = Vec::new();
+        if let (Some(insert), Some(bound)) = (buffer.get_insert(), buffer.get_selection_bound()) {
+            marks.push((insert, bound));
+        }
+        marks.extend(self.extra.borrow().iter().cloned());
+        marks
+    }
+
+    fn iters(
+        buffer: &gtk::TextBuffer,
+        start: &gtk::TextMark,
+        end: &gtk::TextMark,
+    ) -> (gtk::TextIter, gtk::TextIter) {
+        let mut start = buffer.get_iter_at_mark(start);
+        let mut end = buffer.get_iter_at_mark(end);
+        start.order(&mut end);
+        (start, end)
+    }
+
+    fn add(&self, buffer: &gtk::TextBuffer, start: &gtk::TextIter, end: &gtk::TextIter) {
+        let start_mark = match buffer.create_mark(None, start, true) {
+            Some(mark) => mark,
+            None => return,
+        };
+        let end_mark = if start == end {
+            start_mark.clone()
+        } else {
+            match buffer.create_mark(None, end, true) {
+                Some(mark) => mark,
+                None => return,
+            }
+        };
+        self.extra.borrow_mut().push((start_mark, end_mark));
+    }
+
+    // carets that ran into each other, or into the editor's own, are merged
+    fn dedupe(&self) {
+        let buffer = self.view.get_buffer().expect("failed to get buffer");
+        let mut seen = Vec::new();
+        if let Some((start, end)) = buffer.get_selection_bounds() {
+            seen.push((start.get_offset() as usize, end.get_offset() as usize));
+        } else if let Some(insert) = buffer.get_insert() {
+            let offset = buffer.get_iter_at_mark(&insert).get_offset() as usize;
+            seen.push((offset, offset));
+        }
+
+        self.extra.borrow_mut().retain(|(start, end)| {
+            let (start_iter, end_iter) = Carets::iters(&buffer, start, end);
+            let range = (
+                start_iter.get_offset() as usize,
+                end_iter.get_offset() as usize,
+            );
+            if seen.iter().any(|other| overlaps(*other, range)) {
+                buffer.delete_mark(start);
+                if end != start {
+                    buffer.delete_mark(end);
+                }
+                false
+            } else {
+                seen.push(range);
+                true
+            }
+        });
+        self.area.queue_draw();
+    }
+
+    fn iter_at_event(&self, x: f64, y: f64) -> Option<gtk::TextIter> {
+        let (x, y) =
+            self.view
+                .window_to_buffer_coords(gtk::TextWindowType::Text, x as i32, y as i32);
+        self.view.get_iter_at_location(x, y)
+    }
+
+    fn button_pressed(&self, event: &gdk::EventButton) -> gtk::Inhibit {
+        if event.get_button() != 1 {
+            return gtk::Inhibit(false);
+        }
+        let buffer = self.view.get_buffer().expect("failed to get buffer");
+        let state = event.get_state();
+
+        if state.contains(gdk::ModifierType::CONTROL_MASK) {
+            // the editor's cursor becomes an extra one and moves to where was clicked
+            let (start, end) = buffer.get_selection_bounds().unwrap_or_else(|| {
+                let cursor = buffer.get_iter_at_offset(buffer.get_property_cursor_position());
+                (cursor.clone(), cursor)
+            });
+            self.add(&buffer, &start, &end);
+            return gtk::Inhibit(false);
+        }
+
+        self.clear();
+        if state.contains(gdk::ModifierType::MOD1_MASK) {
+            if let Some(iter) = self.iter_at_event(event.get_position().0, event.get_position().1) {
+                self.block_start
+                    .replace(Some((iter.get_line(), iter.get_line_offset())));
+                buffer.place_cursor(&iter);
+                self.view.grab_focus();
+                return gtk::Inhibit(true);
+            }
+        }
+        gtk::Inhibit(false)
+    }
+
+    fn pointer_moved(&self, event: &gdk::EventMotion) -> gtk::Inhibit {
+        let from = match *self.block_start.borrow() {
+            Some(from) => from,
+            None => return gtk::Inhibit(false),
+        };
+        let to = match self.iter_at_event(event.get_position().0, event.get_position().1) {
+            Some(iter) => (iter.get_line(), iter.get_line_offset()),
+            None => return gtk::Inhibit(true),
+        };
+
+        let buffer = self.view.get_buffer().expect("failed to get buffer");
+        let line_length = |line| {
+            let mut end = buffer.get_iter_at_line(line);
+            if !end.ends_line() {
+                end.forward_to_line_end();
+            }
+            end.get_line_offset()
+        };
+
+        self.clear();
+        for (line, first, last) in block(from, to, line_length) {
+            let start = buffer.get_iter_at_line_offset(line, first);
+            let end = buffer.get_iter_at_line_offset(line, last);
+            // the line under the pointer is the editor's own selection
+            if line == to.0 {
+                buffer.select_range(&end, &start);
+            } else {
+                self.add(&buffer, &start, &end);
+            }
+        }
+        gtk::Inhibit(true)
+    }
+
+    fn key_pressed(&self, event: &gdk::EventKey) -> gtk::Inhibit {
+        if !self.has_extra() {
+            return gtk::Inhibit(false);
+        }
+
+        let key = event.get_keyval();
+        let modifiers = event.get_state() & gtk::accelerator_get_default_mod_mask();
+        if !(modifiers - gdk::ModifierType::SHIFT_MASK).is_empty() {
+            return gtk::Inhibit(false);
+        }
+
+        if key == keys::constants::Escape {
+            self.clear();
+        } else if key == keys::constants::BackSpace {
+            self.edit(|buffer, mut start, mut end| {
+                if start == end {
+                    start.backward_char();
+                }
+                buffer.delete(&mut start, &mut end);
+                start
+            });
+        } else if key == keys::constants::Delete {
+            self.edit(|buffer, mut start, mut end| {
+                if start == end {
+                    end.forward_char();
+                }
+                buffer.delete(&mut start, &mut end);
+                start
+            });
+        } else if let Some(text) = self.typed_text(&key) {
+            self.edit(|buffer, mut start, mut end| {
+                buffer.delete(&mut start, &mut end);
+                buffer.insert(&mut start, &text);
+                start
+            });
+        } else if let Some(movement) = Movement::from_key(&key) {
+            self.edit(|_, start, end| movement.apply(start, end));
+        } else {
+            return gtk::Inhibit(false);
+        }
+        gtk::Inhibit(true)
+    }
+
+    // what a key types at each caret, None for keys that don't type anything
+    fn typed_text(&self, key: &Key) -> Option<String> {
+        if *key == keys::constants::Return || *key == keys::constants::KP_Enter {
+            return Some("\n".to_string());
+        }
+        if *key == keys::constants::Tab {
+            return Some(if self.view.get_insert_spaces_instead_of_tabs() {
+                " ".repeat(self.view.get_tab_width() as usize)
+            } else {
+                "\t".to_string()
+            });
+        }
+        key.to_unicode()
+            .filter(|c| !c.is_control())
+            .map(|c| c.to_string())
+    }
+
+    // makes the edit at every caret as one undo step, `edit` is given the caret's start and end
+    // and returns where the caret goes
+    fn edit(&self, edit: impl Fn(&gtk::TextBuffer, gtk::TextIter, gtk::TextIter) -> gtk::TextIter) {
+        let buffer = self.view.get_buffer().expect("failed to get buffer");
+
+        buffer.begin_user_action();
+        // marks keep their place as the text before them changes
+        for (start, end) in self.marks(&buffer) {
+            let (start_iter, end_iter) = Carets::iters(&buffer, &start, &end);
+            let caret = edit(&buffer, start_iter, end_iter);
+            buffer.move_mark(&start, &caret);
+            buffer.move_mark(&end, &caret);
+        }
+        buffer.end_user_action();
+
+        if let Some(insert) = buffer.get_insert() {
+            self.view.scroll_mark_onscreen(&insert);
+        }
+        self.dedupe();
+    }
+
+    // (x, y, width, height, alpha) of what's drawn for each extra caret, in the drawing area's
+    // coordinates, a rectangle for each selected character and a line for the cursor
+    fn shapes(&self, area: &gtk::DrawingArea) -> Vec<(f64, f64, f64, f64, f64)> {
+        let buffer = match self.view.get_buffer() {
+            Some(buffer) => buffer,
+            None => return Vec::new(),
+        };
+
+        let rectangle = |iter: &gtk::TextIter| {
+            let location = self.view.get_iter_location(iter);
+            let (x, y) = self.view.buffer_to_window_coords(
+                gtk::TextWindowType::Widget,
+                location.x,
+                location.y,
+            );
+            self.view.translate_coordinates(area, x, y).map(|(x, y)| {
+                (
+                    f64::from(x),
+                    f64::from(y),
+                    f64::from(location.width.max(1)),
+                    f64::from(location.height),
+                )
+            })
+        };
+
+        let mut shapes = Vec::new();
+        for (start, end) in self.extra.borrow().iter() {
+            let (mut iter, end) = Carets::iters(&buffer, start, end);
+            let mut drawn = 0;
+            while iter < end && drawn < MAX_DRAWN_CHARS {
+                if let Some((x, y, width, height)) = rectangle(&iter) {
+                    shapes.push((x, y, width, height, SELECTION_ALPHA));
+                }
+                iter.forward_char();
+                drawn += 1;
+            }
+            if let Some((x, y, _, height)) = rectangle(&end) {
+                shapes.push((x, y, CARET_WIDTH, height, 1.0));
+            }
+        }
+        shapes
+    }
+}
+
+// how the arrow keys, Home and End move each caret
+#[derive(Clone, Copy)]
+enum Movement {
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+}
+
+impl Movement {
+    fn from_key(key: &Key) -> Option<Self> {
+        match *key {
+            keys::constants::Left => Some(Movement::Left),
+            keys::constants::Right => Some(Movement::Right),
+            keys::constants::Up => Some(Movement::Up),
+            keys::constants::Down => Some(Movement::Down),
+            keys::constants::Home => Some(Movement::Home),
+            keys::constants::End => Some(Movement::End),
+            _ => None,
+        }
+    }
+
+    // selections collapse to the side moved towards
+    fn apply(self, mut start: gtk::TextIter, mut end: gtk::TextIter) -> gtk::TextIter {
+        let selected = start != end;
+        match self {
+            Movement::Left => {
+                if !selected {
+                    start.backward_char();
+                }
+                start
+            }
+            Movement::Right => {
+                if !selected {
+                    end.forward_char();
+                }
+                end
+            }
+            Movement::Up | Movement::Down => {
+                let column = end.get_line_offset();
+                let moved = if let Movement::Up = self {
+                    end.backward_line()
+                } else {
+                    end.forward_line()
+                };
+                if moved {
+                    let mut line_end = end.clone();
+                    if !line_end.ends_line() {
+                        line_end.forward_to_line_end();
+                    }
+                    end.set_line_offset(column.min(line_end.get_line_offset()));
+                }
+                end
+            }
+            Movement::Home => {
+                start.set_line_offset(0);
+                start
+            }
+            Movement::End => {
+                if !end.ends_line() {
+                    end.forward_to_line_end();
+                }
+                end
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_block() {
+        let lengths = [5, 1, 8];
+        let line_length = |line: i32| lengths[line as usize];
+
+        assert_eq!(
+            vec![(0, 2, 4), (1, 1, 1), (2, 2, 4)],
+            block((2, 4), (0, 2), line_length)
+        );
+        assert_eq!(vec![(1, 0, 1)], block((1, 0), (1, 3), line_length));
+    }
+
+    #[test]
+    fn test_overlaps() {
+        assert!(overlaps((3, 3), (3, 3)));
+        assert!(overlaps((2, 5), (4, 4)));
+        assert!(overlaps((2, 5), (5, 7)));
+        assert!(!overlaps((3, 3), (4, 4)));
+        assert!(!overlaps((0, 2), (3, 6)));
+    }
+}
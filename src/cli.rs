@@ -0,0 +1,374 @@
+// command line arguments, scripts can be run on stdin without opening a window so they can be used
+// in shell pipelines
+
+use crate::{
+    config::Config,
+    executor::{
+        find_selection, headless_host, DocumentContext, ExecutionStatus, HostRequest, HostResponse,
+    },
+    permissions::Permissions,
+    script::Script,
+    script_state,
+};
+use serde::Serialize;
+use simple_error::{bail, SimpleError};
+use std::path::{Path, PathBuf};
+
+pub const USAGE: &str = "Usage: boop-gtk [OPTIONS]
+
+Options:
+  -s, --script <NAME>  run a script on stdin and write the result to stdout, e.g. \"Format JSON\"
+                       or format_json
+      --clipboard      run the script on the clipboard instead, replacing its text with the result
+      --list-scripts   list the name, description, tags and path of each script, separated by
+                       tabs, built-in scripts have the path \"internal\"
+      --json           list scripts as JSON
+      --test-scripts   check every script's metadata and that it loads, then run the tests
+                       declared by scripts, exits with 1 if any fail
+      --dbus-service   run scripts for other applications over D-Bus, as org.boop.GTK
+      --import-scripts <PATH>
+                       import scripts written for Boop on macOS from a folder or zip into the
+                       scripts directory
+      --select-script <NAME>
+                       open the window with the command pallete searching for NAME
+  -h, --help           show this message";
+
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    // open the window
+    Gui,
+    // open the window with the command pallete searching for a script
+    SelectScript(String),
+    RunScript(String),
+    // run a script on the clipboard
+    TransformClipboard(String),
+    // list scripts, as JSON if true
+    ListScripts(bool),
+    TestScripts,
+    DBusService,
+    // import Boop scripts from a folder or zip
+    ImportScripts(PathBuf),
+    Help,
+}
+
+// arguments don't include the program name
+pub fn parse(args: impl IntoIterator<Item = String>) -> Result<Command, SimpleError> {
+    let mut args = args.into_iter();
+    let mut command = Command::Gui;
+    let mut json = false;
+    let mut clipboard = false;
+
+    while let Some(arg) = args.next() {
+        let next = match arg.as_str() {
+            "-s" | "--script" => match args.next() {
+                Some(name) => Command::RunScript(name),
+                None => bail!("{} needs the name of a script", arg),
+            },
+            "--list-scripts" => Command::ListScripts(false),
+            "--json" => {
+                json = true;
+                continue;
+            }
+            "--clipboard" => {
+                clipboard = true;
+                continue;
+            }
+            "--test-scripts" => Command::TestScripts,
+            "--dbus-service" => Command::DBusService,
+            "--import-scripts" => match args.next() {
+                Some(path) => Command::ImportScripts(PathBuf::from(path)),
+                None => bail!("{} needs a folder or zip of scripts", arg),
+            },
+            "--select-script" => match args.next() {
+                Some(query) => Command::SelectScript(query),
+                None => bail!("{} needs the name of a script", arg),
+            },
+            "-h" | "--help" => return Ok(Command::Help),
+            _ => match arg.strip_prefix("--script=") {
+                Some(name) => Command::RunScript(name.to_string()),
+                None => bail!("unknown argument {}", arg),
+            },
+        };
+
+        if command != Command::Gui {
+            bail!("only one of --script, --list-scripts, --test-scripts, --dbus-service, --import-scripts and --select-script can be given");
+        }
+        command = next;
+    }
+
+    match command {
+        Command::ListScripts(_) => Ok(Command::ListScripts(json)),
+        _ if json => bail!("--json can only be given with --list-scripts"),
+        Command::RunScript(name) if clipboard => Ok(Command::TransformClipboard(name)),
+        _ if clipboard => bail!("--clipboard can only be given with --script"),
+        command => Ok(command),
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Listing<'a> {
+    name: &'a str,
+    description: &'a str,
+    tags: Vec<&'a str>,
+    // None for built-in scripts
+    path: Option<String>,
+}
+
+// one line per script, or a JSON array for launchers and shell completions
+pub fn list_scripts(scripts: &[Script], json: bool) -> String {
+    let listings: Vec<Listing> = scripts
+        .iter()
+        .map(|script| Listing {
+            name: &script.metadata.name,
+            description: &script.metadata.description,
+            tags: script
+                .metadata
+                .tags
+                .as_deref()
+                .map(|tags| {
+                    tags.split(',')
+                        .map(str::trim)
+                        .filter(|tag| !tag.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            path: Some(script.path.display().to_string()).filter(|path| !path.is_empty()),
+        })
+        .collect();
+
+    if json {
+        return serde_jsonrc::to_string_pretty(&listings).expect("failed to serialize scripts");
+    }
+
+    // tabs and newlines would split a field
+    let field = |text: &str| text.replace(|c| c == '\t' || c == '\n', " ");
+    listings
+        .iter()
+        .map(|listing| {
+            format!(
+                "{}\t{}\t{}\t{}",
+                field(listing.name),
+                field(listing.description),
+                field(&listing.tags.join(",")),
+                field(listing.path.as_deref().unwrap_or("internal"))
+            )
+        })
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// "Format JSON", "format json" and format_json all name the same script
+fn normalize(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+// index of the script called `name`, an exact match is preferred
+pub fn find_script<'a>(names: impl Iterator<Item = &'a str> + Clone, name: &str) -> Option<usize> {
+    names.clone().position(|n| n == name).or_else(|| {
+        let name = normalize(name);
+        names.clone().position(|n| normalize(n) == name)
+    })
+}
+
+// runs the script called `name` as if it was chosen in the window, keeping the same state, the
+// selection is the first occurrence of `selection` in `text`
+pub fn execute_script(
+    scripts: &mut [Script],
+    name: &str,
+    text: &str,
+    selection: Option<&str>,
+    config: &Config,
+    config_dir: &Path,
+    host: &dyn Fn(HostRequest) -> HostResponse,
+) -> Result<ExecutionStatus, SimpleError> {
+    let index = match find_script(
+        scripts.iter().map(|script| script.metadata.name.as_str()),
+        name,
+    ) {
+        Some(index) => index,
+        None => bail!("no script named {}", name),
+    };
+    let script = &mut scripts[index];
+    let script_name = script.metadata.name.clone();
+
+    script.set_heap_limit(config.script_heap_limit());
+    script.set_state(script_state::load(config_dir, &script_name));
+    script.set_permissions(&Permissions::load(config_dir));
+    let selection_range = find_selection(text, selection);
+    let document = DocumentContext {
+        cursor: selection_range
+            .map(|(_, end)| end)
+            .unwrap_or_else(|| text.chars().count()),
+        selection_range,
+        ..Default::default()
+    };
+    let result = script.execute(
+        text,
+        selection,
+        &document,
+        config.script_timeout(&script.metadata),
+        host,
+    );
+    let status = result.map_err(|e| SimpleError::new(format!("could not run {}: {}", name, e)))?;
+
+    if let Some(exception) = status.exception() {
+        bail!("{} threw {}", script_name, exception);
+    }
+    if let Some(state) = status.state() {
+        script_state::save(config_dir, &script_name, state.map(String::as_str))?;
+    }
+
+    Ok(status)
+}
+
+// runs the script on `input`, as if the whole document was chosen in the window, any message the
+// script shows is written to stderr
+pub fn run_script(
+    scripts: &mut [Script],
+    name: &str,
+    input: &str,
+    config: &Config,
+    config_dir: &Path,
+) -> Result<String, SimpleError> {
+    let status = execute_script(
+        scripts,
+        name,
+        input,
+        None,
+        config,
+        config_dir,
+        &headless_host,
+    )?;
+
+    for (level, line) in status.console_output() {
+        eprintln!("[{}] {}", level.name(), line);
+    }
+    if let Some(info) = status.info() {
+        eprintln!("{}", info);
+    }
+    if let Some(error) = status.error() {
+        bail!("{}: {}", name, error);
+    }
+
+    Ok(status.into_replacement().apply(input, None))
+}
+
+// runs the script on the text of the clipboard and replaces it with the result, GTK must be
+// initialized
+pub fn transform_clipboard(
+    scripts: &mut [Script],
+    name: &str,
+    config: &Config,
+    config_dir: &Path,
+) -> Result<(), SimpleError> {
+    let clipboard = gtk::Clipboard::get(&gdk::SELECTION_CLIPBOARD);
+    let input = match clipboard.wait_for_text() {
+        Some(input) => input.to_string(),
+        None => bail!("the clipboard has no text"),
+    };
+
+    let output = run_script(scripts, name, &input, config, config_dir)?;
+    clipboard.set_text(&output);
+    // the text is lost when this process exits unless a clipboard manager keeps it
+    clipboard.store();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Result<Command, SimpleError> {
+        parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Command::Gui, args(&[]).unwrap());
+        assert_eq!(
+            Command::RunScript("Format JSON".to_string()),
+            args(&["--script", "Format JSON"]).unwrap()
+        );
+        assert_eq!(
+            Command::RunScript("format_json".to_string()),
+            args(&["--script=format_json"]).unwrap()
+        );
+        assert_eq!(Command::TestScripts, args(&["--test-scripts"]).unwrap());
+        assert_eq!(Command::Help, args(&["-s", "a", "-h"]).unwrap());
+
+        assert!(args(&["-s"]).is_err());
+        assert!(args(&["--frobnicate"]).is_err());
+        assert!(args(&["-s", "a", "--test-scripts"]).is_err());
+
+        assert_eq!(
+            Command::ListScripts(true),
+            args(&["--json", "--list-scripts"]).unwrap()
+        );
+        assert_eq!(
+            Command::ListScripts(false),
+            args(&["--list-scripts"]).unwrap()
+        );
+        assert!(args(&["--json"]).is_err());
+        assert_eq!(
+            Command::TransformClipboard("Format JSON".to_string()),
+            args(&["--clipboard", "-s", "Format JSON"]).unwrap()
+        );
+        assert!(args(&["--clipboard"]).is_err());
+        assert!(args(&["--clipboard", "--list-scripts"]).is_err());
+        assert_eq!(Command::DBusService, args(&["--dbus-service"]).unwrap());
+        assert_eq!(
+            Command::SelectScript("json".to_string()),
+            args(&["--select-script", "json"]).unwrap()
+        );
+        assert!(args(&["--select-script", "json", "--dbus-service"]).is_err());
+        assert_eq!(
+            Command::ImportScripts(PathBuf::from("Boop.zip")),
+            args(&["--import-scripts", "Boop.zip"]).unwrap()
+        );
+        assert!(args(&["--import-scripts"]).is_err());
+    }
+
+    #[test]
+    fn test_list_scripts() {
+        let script = |tags: &str, path: &str| {
+            Script::from_source(
+                format!(
+                    "/** {{ \"api\": 1, \"name\": \"Format JSON\", \"description\": \"Tidies\\tJSON\",
+                    \"icon\": \"html\", \"tags\": \"{}\" }} **/
+                    function main(state) {{}}",
+                    tags
+                ),
+                path.into(),
+            )
+            .unwrap()
+        };
+        let scripts = vec![script("json, format", ""), script("", "/a.js")];
+
+        assert_eq!(
+            "Format JSON\tTidies JSON\tjson,format\tinternal\n\
+             Format JSON\tTidies JSON\t\t/a.js",
+            list_scripts(&scripts, false)
+        );
+
+        let listing: serde_jsonrc::Value =
+            serde_jsonrc::from_str(&list_scripts(&scripts, true)).unwrap();
+        assert_eq!("format", listing[0]["tags"][1]);
+        assert!(listing[0]["path"].is_null());
+        assert_eq!("/a.js", listing[1]["path"]);
+    }
+
+    #[test]
+    fn test_find_script() {
+        let names = ["Format JSON", "format json", "Sort lines"];
+        let find = |name| find_script(names.iter().copied(), name);
+
+        assert_eq!(Some(1), find("format json"));
+        assert_eq!(Some(0), find("format_json"));
+        assert_eq!(Some(2), find("SORT-LINES"));
+        assert_eq!(None, find("Reverse"));
+    }
+}
@@ -0,0 +1,141 @@
+// whether the desktop prefers dark or light applications, read from the settings portal so Boop
+// can switch along with it, themes with "dark" in their name are taken as dark where there's no
+// portal
+
+#[cfg(target_os = "linux")]
+use simple_error::SimpleError;
+#[cfg(target_os = "linux")]
+use std::thread;
+#[cfg(target_os = "linux")]
+use zvariant::{OwnedValue, Value};
+
+#[cfg(target_os = "linux")]
+const PORTAL_BUS_NAME: &str = "org.freedesktop.portal.Desktop";
+#[cfg(target_os = "linux")]
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+#[cfg(target_os = "linux")]
+const PORTAL_INTERFACE: &str = "org.freedesktop.portal.Settings";
+#[cfg(target_os = "linux")]
+const NAMESPACE: &str = "org.freedesktop.appearance";
+#[cfg(target_os = "linux")]
+const KEY: &str = "color-scheme";
+
+// the portal's color-scheme setting, 0 for no preference, 1 for dark and 2 for light
+const PREFER_DARK: u32 = 1;
+
+pub fn prefers_dark(color_scheme: u32) -> bool {
+    color_scheme == PREFER_DARK
+}
+
+// e.g. "Adwaita-dark"
+pub fn theme_name_is_dark(theme_name: &str) -> bool {
+    theme_name.to_lowercase().contains("dark")
+}
+
+// sends whether the desktop prefers dark applications, once straight away and again each time it
+// changes, nothing is sent if there's no settings portal
+#[cfg(target_os = "linux")]
+pub fn watch(sender: glib::Sender<bool>) {
+    thread::spawn(move || {
+        if let Err(e) = listen_portal(&sender) {
+            info!(
+                "settings portal isn't available, not following the desktop: {}",
+                e
+            );
+        }
+    });
+}
+
+// values are wrapped in another variant by some versions of the portal
+#[cfg(target_os = "linux")]
+fn color_scheme(value: &Value) -> Option<u32> {
+    match value {
+        Value::U32(color_scheme) => Some(*color_scheme),
+        Value::Value(value) => color_scheme(value),
+        _ => None,
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn listen_portal(sender: &glib::Sender<bool>) -> Result<(), SimpleError> {
+    let connection =
+        zbus::Connection::new_session().map_err(|e| SimpleError::with("no session bus", e))?;
+
+    let bus = zbus::fdo::DBusProxy::new(&connection)
+        .map_err(|e| SimpleError::with("no session bus", e))?;
+    bus.add_match(
+        "type='signal',interface='org.freedesktop.portal.Settings',member='SettingChanged'",
+    )
+    .map_err(|e| SimpleError::with("could not listen to the portal", e))?;
+
+    let reply = connection
+        .call_method(
+            Some(PORTAL_BUS_NAME),
+            PORTAL_PATH,
+            Some(PORTAL_INTERFACE),
+            "Read",
+            &(NAMESPACE, KEY),
+        )
+        .map_err(|e| SimpleError::with("could not read the color scheme", e))?;
+    let value = reply
+        .body::<OwnedValue>()
+        .map_err(|e| SimpleError::with("invalid color scheme", e))?;
+    let initial =
+        color_scheme(&value).ok_or_else(|| SimpleError::new("the color scheme isn't a number"))?;
+    info!("desktop color scheme is {}", initial);
+    if sender.send(prefers_dark(initial)).is_err() {
+        return Ok(());
+    }
+
+    loop {
+        let message = match connection.receive_message() {
+            Ok(message) => message,
+            Err(e) => {
+                error!("settings portal connection closed: {}", e);
+                return Ok(());
+            }
+        };
+        let is_setting_changed = message
+            .header()
+            .ok()
+            .and_then(|header| header.member().ok().flatten().map(|m| m.to_string()))
+            .as_deref()
+            == Some("SettingChanged");
+        if !is_setting_changed {
+            continue;
+        }
+
+        let (namespace, key, value) = match message.body::<(String, String, OwnedValue)>() {
+            Ok(body) => body,
+            Err(e) => {
+                warn!("invalid setting change: {}", e);
+                continue;
+            }
+        };
+        if namespace != NAMESPACE || key != KEY {
+            continue;
+        }
+        if let Some(changed) = color_scheme(&value) {
+            info!("desktop color scheme changed to {}", changed);
+            if sender.send(prefers_dark(changed)).is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefers_dark() {
+        assert!(prefers_dark(1));
+        assert!(!prefers_dark(0));
+        assert!(!prefers_dark(2));
+
+        assert!(theme_name_is_dark("Adwaita-dark"));
+        assert!(theme_name_is_dark("Yaru-Dark"));
+        assert!(!theme_name_is_dark("Adwaita"));
+    }
+}
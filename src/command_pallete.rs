@@ -4,12 +4,22 @@ use gladis::Gladis;
 use gtk::prelude::*;
 use gtk::{Dialog, Entry, TreePath, TreeView, Window};
 use shrinkwraprs::Shrinkwrap;
-use sublime_fuzzy::FuzzySearch;
 
-use crate::{script::Script, SEARCH_CONFIG};
+use crate::{
+    config::DisabledScripts,
+    favorites::Favorites,
+    matcher::{self, Candidate},
+    pipeline::Pipeline,
+    script::{self, Fixture, Script},
+    search::SearchIndex,
+    shortcuts::Shortcuts,
+    suggestions,
+};
 use glib::Type;
 use std::{
+    cell::{Cell, RefCell},
     collections::HashMap,
+    rc::Rc,
     sync::{Arc, RwLock},
 };
 
@@ -18,27 +28,263 @@ const TEXT_COLUMN: u32 = 1;
 const ID_COLUMN: u32 = 2;
 const SCORE_COLUMN: u32 = 3;
 const VISIBLE_COLUMN: u32 = 4;
+const BADGE_COLUMN: u32 = 5;
+const ERROR_COLUMN: u32 = 6;
+const SHORTCUT_COLUMN: u32 = 7;
+const SUGGESTED_COLUMN: u32 = 8;
+const RECENT_COLUMN: u32 = 9;
+const STAR_COLUMN: u32 = 10;
 
-const COLUMNS: [u32; 5] = [
+const COLUMNS: [u32; 11] = [
     ICON_COLUMN,
     TEXT_COLUMN,
     ID_COLUMN,
     SCORE_COLUMN,
     VISIBLE_COLUMN,
+    BADGE_COLUMN,
+    ERROR_COLUMN,
+    SHORTCUT_COLUMN,
+    SUGGESTED_COLUMN,
+    RECENT_COLUMN,
+    STAR_COLUMN,
+];
+const COLUMN_TYPES: [Type; 11] = [
+    Type::String,
+    Type::String,
+    Type::U64,
+    Type::I64,
+    Type::Bool,
+    Type::String,
+    Type::String,
+    Type::String,
+    Type::Bool,
+    Type::Bool,
+    Type::String,
 ];
-const COLUMN_TYPES: [Type; 5] = [Type::String, Type::String, Type::U64, Type::I64, Type::Bool];
+
+// pipelines are listed after scripts, with ids starting here, ids have to fit in the u16 of a
+// dialog response
+pub const PIPELINE_ID_BASE: u64 = 0x8000;
+const PIPELINE_ICON: &str = "boop-gtk-filtration-symbolic";
+
+// shown next to scripts that failed to compile or threw on their last run
+const ERROR_BADGE_ICON: &str = "dialog-warning-symbolic";
+
+// shown next to scripts suggested for the text, while nothing has been searched for
+const SUGGESTED_MARKUP: &str = "<span size=\"smaller\" alpha=\"60%\">Suggested</span>";
+// suggested scripts are sorted above the rest, which are scored by their negated index
+const SUGGESTED_SCORE: i64 = 1000;
+
+// shown next to recently run scripts, which are listed first while nothing has been searched for
+const RECENT_MARKUP: &str = "<span size=\"smaller\" alpha=\"60%\">Recent</span>";
+const RECENT_SCORE: i64 = 2000;
+// recent scripts listed first, the rest are in their usual place
+const MAX_RECENT_SHOWN: usize = 5;
+
+// starred scripts are pinned above recent ones while nothing has been searched for
+const FAVORITE_SCORE: i64 = 3000;
+const STARRED_ICON: &str = "starred-symbolic";
+const UNSTARRED_ICON: &str = "non-starred-symbolic";
+
+// rows Page Up and Page Down move the cursor by
+const PAGE_ROWS: i32 = 5;
+// longer examples are cut short in the preview
+const MAX_EXAMPLE_CHARS: usize = 200;
+
+// how long the cursor has to rest on a script before its executor is warmed up
+const WARM_UP_DELAY_MS: u32 = 200;
 
 const DIALOG_WIDTH: i32 = 300;
 const ICON_COLUMN_PADDING: i32 = 8;
 const ICON_COLUMN_WIDTH: i32 = ICON_COLUMN_PADDING + 32 + ICON_COLUMN_PADDING; // IconSize::Dnd = 32
 const TEXT_COLUMN_WIDTH: i32 = DIALOG_WIDTH - ICON_COLUMN_WIDTH;
 
+// what to do with the result of the chosen script
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RunMode {
+    // replace the text in the editor (Enter)
+    Replace,
+    // show the result in the output pane, leaving the text untouched (Shift+Enter)
+    OutputPane,
+    // show what would change and ask before replacing the text (Alt+Enter)
+    Preview,
+    // open the result in a new tab, leaving the text untouched (Ctrl+Enter)
+    NewTab,
+}
+
+impl RunMode {
+    fn from_modifiers(modifiers: gdk::ModifierType) -> Self {
+        if modifiers.contains(gdk::ModifierType::SHIFT_MASK) {
+            RunMode::OutputPane
+        } else if modifiers.contains(gdk::ModifierType::MOD1_MASK) {
+            RunMode::Preview
+        } else if modifiers.contains(gdk::ModifierType::CONTROL_MASK) {
+            RunMode::NewTab
+        } else {
+            RunMode::Replace
+        }
+    }
+}
+
 #[derive(Shrinkwrap, Gladis)]
 pub struct CommandPalleteDialogWidgets {
     #[shrinkwrap(main_field)]
     dialog: Dialog,
     dialog_tree_view: TreeView,
     search_bar: Entry,
+    preview_revealer: gtk::Revealer,
+    preview_label: gtk::Label,
+}
+
+// pane next to the results describing the script under the cursor, toggled with Tab, so scripts can
+// be discovered without running them
+#[derive(Clone)]
+struct Preview {
+    revealer: gtk::Revealer,
+    label: gtk::Label,
+    scripts: Arc<RwLock<Vec<Script>>>,
+    pipelines: Rc<Vec<(String, String)>>,
+}
+
+impl Preview {
+    fn toggle(&self, dialog_tree_view: &TreeView) {
+        self.revealer
+            .set_reveal_child(!self.revealer.get_reveal_child());
+        self.update(dialog_tree_view);
+    }
+
+    // describes the script under the cursor, while the pane is shown
+    fn update(&self, dialog_tree_view: &TreeView) {
+        if !self.revealer.get_reveal_child() {
+            return;
+        }
+
+        let markup = match CommandPalleteDialog::selected_script_id(dialog_tree_view) {
+            Some(id) if id >= PIPELINE_ID_BASE => self
+                .pipelines
+                .get((id - PIPELINE_ID_BASE) as usize)
+                .map(|(name, description)| preview_markup(name, description, None, None)),
+            Some(id) => self
+                .scripts
+                .read()
+                .expect("scripts lock is poisoned")
+                .get(id as usize)
+                .map(|script| {
+                    preview_markup(
+                        &script.metadata.name,
+                        &script.metadata.description,
+                        script.metadata.author.as_deref(),
+                        script.metadata.tests.first(),
+                    )
+                }),
+            None => None,
+        };
+        self.label.set_markup(&markup.unwrap_or_default());
+    }
+}
+
+// the name, author and full description, with the script's first test as an example of what it does
+fn preview_markup(
+    name: &str,
+    description: &str,
+    author: Option<&str>,
+    example: Option<&Fixture>,
+) -> String {
+    let mut markup = format!("<big><b>{}</b></big>", glib::markup_escape_text(name));
+    if let Some(author) = author {
+        markup.push_str(&format!(
+            "\n<span size=\"smaller\" alpha=\"60%\">by {}</span>",
+            glib::markup_escape_text(author)
+        ));
+    }
+    markup.push_str(&format!("\n\n{}", glib::markup_escape_text(description)));
+
+    match example {
+        Some(example) => markup.push_str(&format!(
+            "\n\n<b>Before</b>\n<tt>{}</tt>\n\n<b>After</b>\n<tt>{}</tt>",
+            glib::markup_escape_text(&truncate(&example.input)),
+            glib::markup_escape_text(&truncate(&example.expected))
+        )),
+        None => markup.push_str(
+            "\n\n<span size=\"smaller\" alpha=\"60%\">Press Alt+Enter to see what it would \
+             change before replacing the text</span>",
+        ),
+    }
+
+    markup
+}
+
+// stars scripts with Ctrl+D or by clicking their star, starred scripts are pinned above the rest
+#[derive(Clone)]
+struct Pins {
+    favorites: Rc<RefCell<Favorites>>,
+    scripts: Arc<RwLock<Vec<Script>>>,
+    search_bar: Entry,
+    // saves the favorites, set by whoever opened the pallete
+    on_change: Rc<RefCell<Option<Box<dyn Fn()>>>>,
+}
+
+impl Pins {
+    // rank of each starred script that's still loaded, in the order they were starred, by script id
+    fn ranks(&self) -> HashMap<u64, i64> {
+        let scripts = self.scripts.read().expect("scripts lock is poisoned");
+        self.favorites
+            .borrow()
+            .scripts
+            .iter()
+            .filter_map(|name| scripts.iter().position(|s| s.metadata.name == *name))
+            .enumerate()
+            .map(|(rank, index)| (index as u64, rank as i64))
+            .collect()
+    }
+
+    fn toggle(&self, dialog_tree_view: &TreeView, script_id: u64) {
+        let name = match self
+            .scripts
+            .read()
+            .expect("scripts lock is poisoned")
+            .get(script_id as usize)
+        {
+            Some(script) if script_id < PIPELINE_ID_BASE => script.metadata.name.clone(),
+            _ => return,
+        };
+
+        let starred = self.favorites.borrow_mut().toggle(&name);
+        info!("{} {}", if starred { "starred" } else { "unstarred" }, name);
+        if let Some(on_change) = self.on_change.borrow().as_ref() {
+            on_change();
+        }
+
+        // rank the results again, pinning or unpinning the script, keeping the cursor on it
+        self.search_bar
+            .emit("changed", &[])
+            .expect("failed to emit changed");
+        let model = match dialog_tree_view.get_model() {
+            Some(model) => model,
+            None => return,
+        };
+        for i in 0..model.iter_n_children(None) {
+            let path = TreePath::from_indicesv(&[i]);
+            let id = model
+                .get_iter(&path)
+                .and_then(|iter| model.get_value(&iter, ID_COLUMN as i32).get::<u64>().ok())
+                .flatten();
+            if id == Some(script_id) {
+                dialog_tree_view.set_cursor(&path, gtk::NONE_TREE_VIEW_COLUMN, false);
+                break;
+            }
+        }
+    }
+}
+
+fn truncate(text: &str) -> String {
+    if text.chars().count() > MAX_EXAMPLE_CHARS {
+        let mut truncated: String = text.chars().take(MAX_EXAMPLE_CHARS).collect();
+        truncated.push('…');
+        truncated
+    } else {
+        text.to_string()
+    }
 }
 
 #[derive(Shrinkwrap)]
@@ -47,18 +293,93 @@ pub struct CommandPalleteDialog {
     widgets: CommandPalleteDialogWidgets,
 
     scripts: Arc<RwLock<Vec<Script>>>,
+    search_index: Rc<RefCell<SearchIndex>>,
+    // names and descriptions of the pipelines listed, by id - PIPELINE_ID_BASE
+    pipelines: Rc<Vec<(String, String)>>,
+    // times each script was run, by name, scripts run often are ranked higher
+    usage: Rc<HashMap<String, u64>>,
+    run_mode: Rc<Cell<RunMode>>,
+    // the chosen script should be opened in the editor instead of run (Ctrl+E)
+    edit: Rc<Cell<bool>>,
+    // rank of each script suggested for the text, by script id
+    suggested: Rc<HashMap<u64, i64>>,
+    // rank of each recently run script, most recent first, by script id
+    recent: Rc<HashMap<u64, i64>>,
+    // bytes all executors may use before idle ones are killed
+    memory_budget: usize,
+    preview: Preview,
+    pins: Pins,
 }
 
 impl CommandPalleteDialog {
-    pub fn new<P: IsA<Window>>(window: &P, scripts: Arc<RwLock<Vec<Script>>>) -> Self {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new<P: IsA<Window>>(
+        window: &P,
+        scripts: Arc<RwLock<Vec<Script>>>,
+        search_index: Rc<RefCell<SearchIndex>>,
+        shortcuts: &Shortcuts,
+        pipelines: &[Pipeline],
+        recent: &[String],
+        usage: &HashMap<String, u64>,
+        favorites: Rc<RefCell<Favorites>>,
+        disabled: &DisabledScripts,
+        memory_budget: usize,
+        text: &str,
+    ) -> Self {
         let widgets = CommandPalleteDialogWidgets::from_resource(
             "/co/uk/mrbenshef/Boop-GTK/command-pallete.glade",
         )
         .unwrap_or_else(|e| panic!("failed to load command-pallete.glade: {}", e)); // TODO: don't debug print once gladis errors implement display
 
+        // scripts may have changed since the pallete was last opened
+        search_index.borrow_mut().update(
+            scripts
+                .read()
+                .expect("scripts lock is poisoned")
+                .iter()
+                .map(|script| {
+                    (
+                        script.metadata.name.as_str(),
+                        script.metadata.tags.as_deref(),
+                    )
+                }),
+        );
+
+        let suggested = CommandPalleteDialog::suggest(&scripts, text);
+        let recent = CommandPalleteDialog::rank_recent(&scripts, recent);
+
+        let pipeline_descriptions: Rc<Vec<(String, String)>> = Rc::new(
+            pipelines
+                .iter()
+                .map(|pipeline| (pipeline.name.clone(), pipeline.description()))
+                .collect(),
+        );
+        let preview = Preview {
+            revealer: widgets.preview_revealer.clone(),
+            label: widgets.preview_label.clone(),
+            scripts: scripts.clone(),
+            pipelines: pipeline_descriptions.clone(),
+        };
+        let pins = Pins {
+            favorites,
+            scripts: scripts.clone(),
+            search_bar: widgets.search_bar.clone(),
+            on_change: Rc::new(RefCell::new(None)),
+        };
+
         let command_pallete_dialog = CommandPalleteDialog {
             widgets,
             scripts: scripts.clone(),
+            search_index,
+            pipelines: pipeline_descriptions,
+            usage: Rc::new(usage.clone()),
+            run_mode: Rc::new(Cell::new(RunMode::Replace)),
+            edit: Rc::new(Cell::new(false)),
+            suggested: Rc::new(suggested),
+            recent: Rc::new(recent),
+            memory_budget,
+            preview,
+            pins,
         };
 
         command_pallete_dialog.set_transient_for(Some(window));
@@ -102,9 +423,67 @@ impl CommandPalleteDialog {
                 column.set_max_width(TEXT_COLUMN_WIDTH);
                 column.add_attribute(&renderer, "markup", TEXT_COLUMN as i32);
 
+                // error badge, the error itself is shown as the row's tooltip
+                let badge_renderer = gtk::CellRendererPixbuf::new();
+                column.pack_end(&badge_renderer, false);
+                column.add_attribute(&badge_renderer, "icon-name", BADGE_COLUMN as i32);
+
+                let shortcut_renderer = gtk::CellRendererText::new();
+                column.pack_end(&shortcut_renderer, false);
+                column.add_attribute(&shortcut_renderer, "markup", SHORTCUT_COLUMN as i32);
+
+                let suggested_renderer = gtk::CellRendererText::new();
+                suggested_renderer.set_property_markup(Some(SUGGESTED_MARKUP));
+                column.pack_end(&suggested_renderer, false);
+                column.add_attribute(&suggested_renderer, "visible", SUGGESTED_COLUMN as i32);
+
+                let recent_renderer = gtk::CellRendererText::new();
+                recent_renderer.set_property_markup(Some(RECENT_MARKUP));
+                column.pack_end(&recent_renderer, false);
+                column.add_attribute(&recent_renderer, "visible", RECENT_COLUMN as i32);
+
                 command_pallete_dialog
                     .dialog_tree_view
                     .append_column(&column);
+                command_pallete_dialog
+                    .dialog_tree_view
+                    .set_tooltip_column(ERROR_COLUMN as i32);
+            }
+
+            // star column, clicking the star stars the script instead of running it
+            {
+                let renderer = gtk::CellRendererPixbuf::new();
+                renderer.set_padding(ICON_COLUMN_PADDING, 0);
+
+                let column = gtk::TreeViewColumn::new();
+                column.pack_start(&renderer, false);
+                column.add_attribute(&renderer, "icon-name", STAR_COLUMN as i32);
+
+                command_pallete_dialog
+                    .dialog_tree_view
+                    .append_column(&column);
+
+                let pins = command_pallete_dialog.pins.clone();
+                command_pallete_dialog
+                    .dialog_tree_view
+                    .connect_button_press_event(move |tv, event| {
+                        let (x, y) = event.get_position();
+                        let script_id = match tv.get_path_at_pos(x as i32, y as i32) {
+                            Some((Some(path), Some(clicked), _, _)) if clicked == column => tv
+                                .get_model()
+                                .and_then(|model| model.get_iter(&path).map(|iter| (model, iter)))
+                                .and_then(|(model, iter)| {
+                                    model.get_value(&iter, ID_COLUMN as i32).get::<u64>().ok()
+                                })
+                                .flatten(),
+                            _ => return Inhibit(false),
+                        };
+
+                        if let Some(script_id) = script_id {
+                            pins.toggle(tv, script_id);
+                        }
+                        Inhibit(true)
+                    });
             }
 
             #[cfg(debug_assertions)]
@@ -122,28 +501,102 @@ impl CommandPalleteDialog {
                 }
             }
 
+            let favorites = command_pallete_dialog.pins.ranks();
             for (index, script) in scripts
                 .read()
                 .expect("scripts lock is poisoned")
                 .iter()
                 .enumerate()
             {
+                // turned off in the scripts manager
+                if disabled.contains(&script.metadata.name) {
+                    continue;
+                }
+
                 let mut icon_name = script.metadata.icon.to_lowercase();
                 icon_name.insert_str(0, "boop-gtk-");
                 icon_name.push_str("-symbolic");
 
-                let entry_text = format!(
-                    "<b>{}</b>\n<span size=\"smaller\">{}</span>",
-                    script.metadata.name.to_string(),
-                    script.metadata.description.to_string()
+                let entry_text = CommandPalleteDialog::entry_markup(
+                    &script.metadata.name,
+                    &script.metadata.description,
+                    &[],
                 );
 
-                let values: [&dyn ToValue; 5] = [
+                let last_error = script.last_error();
+                let badge = last_error.as_ref().map(|_| ERROR_BADGE_ICON);
+                let error_markup = last_error.map(|error| {
+                    format!(
+                        "<b>Error:</b> {}\n<small>Press Ctrl+R to reset the script</small>",
+                        glib::markup_escape_text(&error)
+                    )
+                });
+
+                let shortcut_markup = shortcuts.label_for(&script.metadata.name).map(|label| {
+                    format!(
+                        "<span size=\"smaller\" alpha=\"60%\">{}</span>",
+                        glib::markup_escape_text(&label)
+                    )
+                });
+
+                // starred scripts aren't marked as recent or suggested too, nor recent ones as
+                // suggested
+                let favorite = favorites.get(&(index as u64));
+                let recent = command_pallete_dialog
+                    .recent
+                    .get(&(index as u64))
+                    .filter(|_| favorite.is_none());
+                let suggestion = command_pallete_dialog
+                    .suggested
+                    .get(&(index as u64))
+                    .filter(|_| favorite.is_none() && recent.is_none());
+                let star_icon = if favorite.is_some() {
+                    STARRED_ICON
+                } else {
+                    UNSTARRED_ICON
+                };
+
+                let values: [&dyn ToValue; 11] = [
                     &icon_name,
                     &entry_text,
                     &(index as u64),
-                    &(-(index as i64)),
+                    &CommandPalleteDialog::empty_query_score(
+                        index as u64,
+                        favorite,
+                        suggestion,
+                        recent,
+                    ),
                     &true,
+                    &badge,
+                    &error_markup,
+                    &shortcut_markup,
+                    &suggestion.is_some(),
+                    &recent.is_some(),
+                    &star_icon,
+                ];
+                store.set(&store.append(), &COLUMNS, &values);
+            }
+
+            for (index, pipeline) in pipelines.iter().enumerate() {
+                let id = PIPELINE_ID_BASE + index as u64;
+                let entry_text = CommandPalleteDialog::entry_markup(
+                    &pipeline.name,
+                    &pipeline.description(),
+                    &[],
+                );
+
+                let values: [&dyn ToValue; 11] = [
+                    &PIPELINE_ICON,
+                    &entry_text,
+                    &id,
+                    &CommandPalleteDialog::empty_query_score(id, None, None, None),
+                    &true,
+                    &None::<&str>,
+                    &None::<String>,
+                    &None::<String>,
+                    &false,
+                    &false,
+                    &None::<&str>,
                 ];
                 store.set(&store.append(), &COLUMNS, &values);
             }
@@ -164,29 +617,204 @@ impl CommandPalleteDialog {
         command_pallete_dialog
     }
 
+    // ranks the scripts suggested for the text, in the order the suggestions are listed
+    fn suggest(scripts: &RwLock<Vec<Script>>, text: &str) -> HashMap<u64, i64> {
+        let kind = match suggestions::detect(text) {
+            Some(kind) => kind,
+            None => return HashMap::new(),
+        };
+        info!("text looks like {:?}", kind);
+
+        let scripts = scripts.read().expect("scripts lock is poisoned");
+        kind.scripts()
+            .iter()
+            .filter_map(|name| scripts.iter().position(|s| s.metadata.name == *name))
+            .enumerate()
+            .map(|(rank, index)| (index as u64, rank as i64))
+            .collect()
+    }
+
+    // ranks the most recently run scripts that are still loaded
+    fn rank_recent(scripts: &RwLock<Vec<Script>>, recent: &[String]) -> HashMap<u64, i64> {
+        let scripts = scripts.read().expect("scripts lock is poisoned");
+        recent
+            .iter()
+            .filter_map(|name| scripts.iter().position(|s| s.metadata.name == *name))
+            .take(MAX_RECENT_SHOWN)
+            .enumerate()
+            .map(|(rank, index)| (index as u64, rank as i64))
+            .collect()
+    }
+
+    // the name with the characters matching the search underlined, above the description
+    fn entry_markup(name: &str, description: &str, positions: &[usize]) -> String {
+        format!(
+            "<b>{}</b>\n<span size=\"smaller\">{}</span>",
+            matcher::highlight(name, positions),
+            glib::markup_escape_text(description)
+        )
+    }
+
+    fn empty_query_score(
+        script_id: u64,
+        favorite: Option<&i64>,
+        suggestion: Option<&i64>,
+        recent: Option<&i64>,
+    ) -> i64 {
+        match (favorite, recent, suggestion) {
+            (Some(rank), _, _) => FAVORITE_SCORE - rank,
+            (None, Some(rank), _) => RECENT_SCORE - rank,
+            (None, None, Some(rank)) => SUGGESTED_SCORE - rank,
+            (None, None, None) => -(script_id as i64), // alphabetical sort
+        }
+    }
+
+    // called each time a script is starred or unstarred
+    pub fn connect_favorites_changed(&self, f: impl Fn() + 'static) {
+        *self.pins.on_change.borrow_mut() = Some(Box::new(f));
+    }
+
+    // searches for `query` as if it was typed
+    pub fn set_query(&self, query: &str) {
+        self.search_bar.set_text(query);
+        self.search_bar.set_position(-1);
+    }
+
+    // how the chosen script should be run, valid once the dialog has responded
+    pub fn run_mode(&self) -> RunMode {
+        self.run_mode.get()
+    }
+
+    // the chosen script should be edited instead of run, valid once the dialog has responded
+    pub fn edit_requested(&self) -> bool {
+        self.edit.get()
+    }
+
     fn register_handlers(&self) {
+        // Ctrl+D stars the script under the cursor
+        let lb = self.dialog_tree_view.clone();
+        let pins = self.pins.clone();
+        self.dialog.connect_key_press_event(move |_, k| {
+            let modifiers = k.get_state();
+            if k.get_keyval() != keys::constants::d
+                || !modifiers.contains(gdk::ModifierType::CONTROL_MASK)
+            {
+                return Inhibit(false);
+            }
+            if let Some(script_id) = CommandPalleteDialog::selected_script_id(&lb) {
+                pins.toggle(&lb, script_id);
+            }
+            Inhibit(true)
+        });
+
         let lb = self.dialog_tree_view.clone();
         let dialog = self.dialog.clone();
+        let run_mode = self.run_mode.clone();
+        let edit = self.edit.clone();
+        let scripts = self.scripts.clone();
+        let preview = self.preview.clone();
         self.dialog.connect_key_press_event(move |_, k| {
-            CommandPalleteDialog::on_key_press(k, &lb, &dialog)
+            CommandPalleteDialog::on_key_press(
+                k, &lb, &dialog, &run_mode, &edit, &scripts, &preview,
+            )
         });
 
         let lb = self.dialog_tree_view.clone();
         let scripts = self.scripts.clone();
-        self.search_bar
-            .connect_changed(move |s| CommandPalleteDialog::on_changed(s, &lb, scripts.clone()));
+        let search_index = self.search_index.clone();
+        let suggested = self.suggested.clone();
+        let recent = self.recent.clone();
+        let pipelines = self.pipelines.clone();
+        let usage = self.usage.clone();
+        let pins = self.pins.clone();
+        self.search_bar.connect_changed(move |s| {
+            CommandPalleteDialog::on_changed(
+                s,
+                &lb,
+                scripts.clone(),
+                &search_index.borrow(),
+                &pins.ranks(),
+                &suggested,
+                &recent,
+                &pipelines,
+                &usage,
+            )
+        });
 
         let dialog = self.dialog.clone();
+        let run_mode = self.run_mode.clone();
         self.dialog_tree_view
-            .connect_row_activated(move |tv, _, _| CommandPalleteDialog::on_click(tv, &dialog));
+            .connect_row_activated(move |tv, _, _| {
+                CommandPalleteDialog::on_click(tv, &dialog, &run_mode)
+            });
+
+        // warm up the executor of the script the cursor rests on, so it runs without delay
+        let scripts = self.scripts.clone();
+        let memory_budget = self.memory_budget;
+        let preview = self.preview.clone();
+        self.dialog_tree_view.connect_cursor_changed(move |tv| {
+            preview.update(tv);
+
+            let script_id = match CommandPalleteDialog::selected_script_id(tv) {
+                Some(script_id) if script_id < PIPELINE_ID_BASE => script_id,
+                _ => return,
+            };
+
+            let tv = tv.clone();
+            let scripts = scripts.clone();
+            glib::timeout_add_local(WARM_UP_DELAY_MS, move || {
+                // the cursor has moved on
+                if CommandPalleteDialog::selected_script_id(&tv) != Some(script_id) {
+                    return glib::Continue(false);
+                }
+
+                if let Ok(mut scripts) = scripts.try_write() {
+                    let index = script_id as usize;
+                    scripts[index].warm_up();
+                    script::enforce_memory_budget(&mut scripts, memory_budget, index);
+                }
+                glib::Continue(false)
+            });
+        });
     }
 
-    fn on_key_press(key: &EventKey, dialog_tree_view: &TreeView, dialog: &Dialog) -> Inhibit {
+    // id of the script under the cursor
+    fn selected_script_id(dialog_tree_view: &TreeView) -> Option<u64> {
+        let model = dialog_tree_view.get_model()?;
+        let path = dialog_tree_view.get_cursor().0?;
+        let iter = model.get_iter(&path)?;
+
+        model
+            .get_value(&iter, ID_COLUMN as i32)
+            .get::<u64>()
+            .ok()
+            .flatten()
+    }
+
+    fn on_key_press(
+        key: &EventKey,
+        dialog_tree_view: &TreeView,
+        dialog: &Dialog,
+        run_mode: &Rc<Cell<RunMode>>,
+        edit: &Cell<bool>,
+        scripts: &RwLock<Vec<Script>>,
+        preview: &Preview,
+    ) -> Inhibit {
         let model: gtk::TreeModelFilter = dialog_tree_view.get_model().unwrap().downcast().unwrap();
         let result_count: i32 = model.iter_n_children(None);
 
+        let modifiers = key.get_state();
         let key = key.get_keyval();
-        if key == keys::constants::Up || key == keys::constants::Down {
+        if key == keys::constants::r && modifiers.contains(gdk::ModifierType::CONTROL_MASK) {
+            CommandPalleteDialog::reset_selected(dialog_tree_view, scripts);
+            return Inhibit(true);
+        } else if key == keys::constants::e && modifiers.contains(gdk::ModifierType::CONTROL_MASK) {
+            if let Some(script_id) = CommandPalleteDialog::selected_script_id(dialog_tree_view) {
+                edit.set(true);
+                dialog.response(gtk::ResponseType::Other(script_id as u16));
+            }
+            return Inhibit(true);
+        } else if key == keys::constants::Up || key == keys::constants::Down {
             if let (Some(mut path), _) = dialog_tree_view.get_cursor() {
                 let index: i32 = path.get_indices()[0];
 
@@ -211,9 +839,27 @@ impl CommandPalleteDialog {
                 dialog_tree_view.set_cursor(&path, gtk::NONE_TREE_VIEW_COLUMN, false);
             }
 
+            return Inhibit(true);
+        } else if key == keys::constants::Page_Up || key == keys::constants::Page_Down {
+            // pages stop at the first and last results instead of wrapping around
+            if let (Some(path), _) = dialog_tree_view.get_cursor() {
+                let index: i32 = path.get_indices()[0];
+                let index = if key == keys::constants::Page_Up {
+                    (index - PAGE_ROWS).max(0)
+                } else {
+                    (index + PAGE_ROWS).min(result_count - 1).max(0)
+                };
+
+                let path = TreePath::from_indicesv(&[index]);
+                dialog_tree_view.set_cursor(&path, gtk::NONE_TREE_VIEW_COLUMN, false);
+            }
+
+            return Inhibit(true);
+        } else if key == keys::constants::Tab {
+            preview.toggle(dialog_tree_view);
             return Inhibit(true);
         } else if key == keys::constants::Return {
-            CommandPalleteDialog::on_click(dialog_tree_view, dialog);
+            CommandPalleteDialog::on_click(dialog_tree_view, dialog, run_mode);
         } else if key == keys::constants::Escape {
             dialog.close();
         }
@@ -221,28 +867,67 @@ impl CommandPalleteDialog {
         Inhibit(false)
     }
 
-    fn on_click(dialog_tree_view: &TreeView, dialog: &Dialog) {
-        let model: gtk::TreeModelFilter = dialog_tree_view.get_model().unwrap().downcast().unwrap();
+    // gives the script under the cursor a fresh context, forgetting its globals and last error
+    fn reset_selected(dialog_tree_view: &TreeView, scripts: &RwLock<Vec<Script>>) {
+        let script_id = match CommandPalleteDialog::selected_script_id(dialog_tree_view) {
+            Some(script_id) if script_id < PIPELINE_ID_BASE => script_id,
+            _ => return,
+        };
+
+        {
+            let mut scripts = match scripts.try_write() {
+                Ok(scripts) => scripts,
+                Err(_) => return,
+            };
+            let script = &mut scripts[script_id as usize];
+            if script.is_running() {
+                warn!("{} is running, not resetting it", script.metadata.name);
+                return;
+            }
+            info!("resetting {}", script.metadata.name);
+            script.reset();
+        }
 
-        if let (Some(path), _) = dialog_tree_view.get_cursor() {
-            let value = model.get_value(
-                &model
-                    .get_iter(&path)
-                    .unwrap_or_else(|| panic!("failed to get iter for path: {:?}", path)),
-                ID_COLUMN as i32,
+        // the error badge no longer applies
+        let filter_store: gtk::TreeModelFilter =
+            dialog_tree_view.get_model().unwrap().downcast().unwrap();
+        let store: gtk::ListStore = filter_store.get_model().unwrap().downcast().unwrap();
+        if let Some(iter) = dialog_tree_view
+            .get_cursor()
+            .0
+            .and_then(|path| filter_store.get_iter(&path))
+        {
+            let values: [&dyn ToValue; 2] = [&None::<&str>, &None::<String>];
+            store.set(
+                &filter_store.convert_iter_to_child_iter(&iter),
+                &[BADGE_COLUMN, ERROR_COLUMN],
+                &values,
             );
-            let v = value
-                .downcast::<u64>()
-                .expect("cannot downcast value to u64")
-                .get_some();
-            dialog.response(gtk::ResponseType::Other(v as u16));
         }
     }
 
+    fn on_click(dialog_tree_view: &TreeView, dialog: &Dialog, run_mode: &Rc<Cell<RunMode>>) {
+        // modifiers held while pressing enter or clicking the row
+        run_mode.set(RunMode::from_modifiers(
+            gtk::get_current_event_state().unwrap_or_else(gdk::ModifierType::empty),
+        ));
+
+        if let Some(script_id) = CommandPalleteDialog::selected_script_id(dialog_tree_view) {
+            dialog.response(gtk::ResponseType::Other(script_id as u16));
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
     fn on_changed(
         searchbar: &Entry,
         dialog_tree_view: &TreeView,
         scripts: Arc<RwLock<Vec<Script>>>,
+        search_index: &SearchIndex,
+        favorites: &HashMap<u64, i64>,
+        suggested: &HashMap<u64, i64>,
+        recent: &HashMap<u64, i64>,
+        pipelines: &[(String, String)],
+        usage: &HashMap<String, u64>,
     ) {
         let filter_store: gtk::TreeModelFilter =
             dialog_tree_view.get_model().unwrap().downcast().unwrap();
@@ -254,20 +939,53 @@ impl CommandPalleteDialog {
 
         let searchbar_text = searchbar.get_text().to_owned();
 
-        // score each script using search text
-        let script_to_score = scripts
+        // scripts within a few typos of the search text, for when nothing else matches
+        let typo_scores = search_index.search(&searchbar_text);
+        let query_len = searchbar_text.trim().chars().count();
+
+        // score each script using search text, along with its markup showing what matched
+        let mut script_to_score = scripts
             .read()
             .expect("scripts lock is poisoned")
             .iter()
             .enumerate()
             .map(|(index, script)| {
-                let mut search = FuzzySearch::new(&searchbar_text, &script.metadata.name, true);
-                search.set_score_config(SEARCH_CONFIG);
+                let candidate = Candidate {
+                    name: &script.metadata.name,
+                    tags: script.metadata.tags.as_deref(),
+                    description: &script.metadata.description,
+                    bias: script.metadata.bias,
+                    runs: usage.get(&script.metadata.name).cloned().unwrap_or(0),
+                };
 
-                let score = search.best_match().map(|m| m.score()).unwrap_or(-1000);
-                (index as u64, score)
+                let (score, positions) = match matcher::score(&searchbar_text, &candidate) {
+                    Some(m) => (m.score, m.positions),
+                    None => match typo_scores.get(&script.metadata.name) {
+                        Some(score) => (matcher::weigh(*score, query_len, &candidate), vec![]),
+                        None => (-1000, vec![]),
+                    },
+                };
+                let markup = CommandPalleteDialog::entry_markup(
+                    candidate.name,
+                    candidate.description,
+                    &positions,
+                );
+                (index as u64, (score, markup))
             })
-            .collect::<HashMap<u64, isize>>();
+            .collect::<HashMap<u64, (isize, String)>>();
+        for (index, (name, description)) in pipelines.iter().enumerate() {
+            let candidate = Candidate {
+                name,
+                description,
+                ..Candidate::default()
+            };
+
+            let (score, positions) = matcher::score(&searchbar_text, &candidate)
+                .map(|m| (m.score, m.positions))
+                .unwrap_or((-1000, vec![]));
+            let markup = CommandPalleteDialog::entry_markup(name, description, &positions);
+            script_to_score.insert(PIPELINE_ID_BASE + index as u64, (score, markup));
+        }
 
         let script_count = store.iter_n_children(None);
         for i in 0..script_count {
@@ -285,10 +1003,25 @@ impl CommandPalleteDialog {
                 .unwrap()
                 .unwrap();
 
+            let favorite = favorites.get(&script_id);
+            let star_icon = match favorite {
+                _ if script_id >= PIPELINE_ID_BASE => None,
+                Some(_) => Some(STARRED_ICON),
+                None => Some(UNSTARRED_ICON),
+            };
+            let favorite = favorite.filter(|_| searchbar_text.is_empty());
+            let recent = recent
+                .get(&script_id)
+                .filter(|_| searchbar_text.is_empty() && favorite.is_none());
+            let suggestion = suggested
+                .get(&script_id)
+                .filter(|_| searchbar_text.is_empty() && favorite.is_none() && recent.is_none());
+
+            let (match_score, markup) = &script_to_score[&script_id];
             let score = if searchbar_text.is_empty() {
-                -(script_id as i64) // alphabetical sort
+                CommandPalleteDialog::empty_query_score(script_id, favorite, suggestion, recent)
             } else {
-                script_to_score[&script_id] as i64
+                *match_score as i64
             };
 
             let is_visible = if searchbar_text.is_empty() {
@@ -297,8 +1030,26 @@ impl CommandPalleteDialog {
                 score > 0
             };
 
-            let values: [&dyn ToValue; 2] = [&score, &is_visible];
-            store.set(&iter, &[SCORE_COLUMN, VISIBLE_COLUMN], &values);
+            let values: [&dyn ToValue; 6] = [
+                markup,
+                &score,
+                &is_visible,
+                &suggestion.is_some(),
+                &recent.is_some(),
+                &star_icon,
+            ];
+            store.set(
+                &iter,
+                &[
+                    TEXT_COLUMN,
+                    SCORE_COLUMN,
+                    VISIBLE_COLUMN,
+                    SUGGESTED_COLUMN,
+                    RECENT_COLUMN,
+                    STAR_COLUMN,
+                ],
+                &values,
+            );
         }
 
         // start sorting again
@@ -311,3 +1062,28 @@ impl CommandPalleteDialog {
         dialog_tree_view.set_cursor(&TreePath::new_first(), gtk::NONE_TREE_VIEW_COLUMN, false);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_preview_markup() {
+        assert_eq!(
+            preview_markup("Trim", "Trims <space>", None, None),
+            "<big><b>Trim</b></big>\n\nTrims &lt;space&gt;\n\n<span size=\"smaller\" \
+             alpha=\"60%\">Press Alt+Enter to see what it would change before replacing the \
+             text</span>"
+        );
+
+        let example = Fixture {
+            input: " a ".to_string(),
+            selection: None,
+            expected: "a".repeat(MAX_EXAMPLE_CHARS + 1),
+        };
+        let markup = preview_markup("Trim", "Trims", Some("Ivan"), Some(&example));
+        assert!(markup.contains("by Ivan"));
+        assert!(markup.contains("<b>Before</b>\n<tt> a </tt>"));
+        assert!(markup.contains(&format!("<tt>{}…</tt>", "a".repeat(MAX_EXAMPLE_CHARS))));
+    }
+}
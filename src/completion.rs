@@ -0,0 +1,321 @@
+// completion and hover docs for the script API, active while the document is a boop script
+
+use gtk::prelude::*;
+use sourceview::prelude::*;
+use std::{cell::Cell, rc::Rc};
+
+// names scripts use, with the description shown when hovering over them
+const API_DOCS: &[(&str, &str)] = &[
+    // payload
+    ("fullText", "Text of the whole document, setting it replaces the document"),
+    (
+        "fullTextBytes",
+        "UTF-8 bytes of the whole document as a <tt>Uint8Array</tt>, setting it replaces the document",
+    ),
+    (
+        "text",
+        "Selected text, or the whole document if nothing is selected, setting it replaces that text",
+    ),
+    ("selection", "Selected text, setting it replaces the selection"),
+    (
+        "selections",
+        "Array of every selected text, changing an element replaces that selection",
+    ),
+    (
+        "persistent",
+        "Value kept between runs and sessions, anything that can be converted to JSON",
+    ),
+    (
+        "state",
+        "Object kept between runs and sessions, e.g. <tt>state.state.count = (state.state.count || 0) + 1</tt>",
+    ),
+    ("postInfo", "<tt>postInfo(message)</tt>\nShows a message in the status bar, supports markdown"),
+    ("postError", "<tt>postError(message)</tt>\nShows an error in the status bar, supports markdown"),
+    (
+        "insert",
+        "<tt>insert(text, offset)</tt>\nInserts text at an offset, or at the cursor replacing the selection",
+    ),
+    (
+        "cursorOffset",
+        "Offset of the cursor in the document, like the index of a character in a string",
+    ),
+    (
+        "selectionRange",
+        "<tt>{ start, end }</tt> offsets of the selection in the document, or null",
+    ),
+    ("moveCursor", "<tt>moveCursor(offset)</tt>\nMoves the cursor once the script finishes"),
+    ("saveAs", "<tt>saveAs(name, text)</tt>\nAsks the user where to save text once the script finishes"),
+    (
+        "chooseFile",
+        "<tt>chooseFile(filters)</tt>\nAsks the user for a file, returns <tt>{ path, text, bytes }</tt> or null",
+    ),
+    (
+        "readFile",
+        "<tt>readFile(path)</tt>\nReads a file in the script's directory, or one chosen with <tt>chooseFile</tt>",
+    ),
+    (
+        "writeFile",
+        "<tt>writeFile(path, text)</tt>\nWrites a file in the script's directory, or one chosen with <tt>chooseFile</tt>",
+    ),
+    (
+        "pickColor",
+        "<tt>pickColor(initial)</tt>\nAsks the user for a color, returns <tt>{ hex, rgba }</tt> or null",
+    ),
+    (
+        "prompt",
+        "<tt>prompt(label, default)</tt>\nAsks the user for a value, returns the text entered or null",
+    ),
+    ("openUrl", "<tt>openUrl(url)</tt>\nOpens a web page once the script finishes"),
+    ("copy", "<tt>copy(text)</tt>\nCopies text to the clipboard once the script finishes"),
+    (
+        "notify",
+        "<tt>notify(title, body)</tt>\nSends a desktop notification once the script finishes",
+    ),
+    (
+        "readClipboard",
+        "<tt>readClipboard()</tt>\nReturns the text on the clipboard, or null",
+    ),
+    (
+        "addArtifact",
+        "<tt>addArtifact(name, text)</tt>\nAdds a named output that can be opened or saved",
+    ),
+    (
+        "postPreview",
+        "<tt>postPreview(markdownOrHtml)</tt>\nRenders markdown or HTML in the preview panel once the script finishes",
+    ),
+    (
+        "highlight",
+        "<tt>highlight(start, end, kind)</tt>\nMarks text once the script finishes, kind is error, warning or info",
+    ),
+    (
+        "parseJSON",
+        "<tt>parseJSON(text)</tt>\nParses the text, or the payload's text, as JSON, posting where it's invalid and returning null if it is",
+    ),
+    (
+        "stringifyJSON",
+        "<tt>stringifyJSON(value, indent)</tt>\nConverts a value to JSON indented by 2 spaces, or <tt>indent</tt>",
+    ),
+    ("document", "<tt>{ filename, language, lineEnding, encoding }</tt> of the document"),
+    ("lineEnding", "The characters most lines of the document end with, e.g. <tt>\"\\r\\n\"</tt>"),
+    ("env", "<tt>{ locale, timezone, platform, version }</tt> of the application"),
+    // globals
+    ("main", "<tt>function main(state)</tt>\nCalled with the payload each time the script runs, it may be async"),
+    (
+        "processLine",
+        "<tt>function processLine(line, index)</tt>\nCalled with each line by scripts that stream, returns the new line, or null to remove it",
+    ),
+    ("require", "<tt>require(path)</tt>\nLoads a module, e.g. <tt>@boop/base64</tt>, <tt>@boop/native</tt> or an https url"),
+    (
+        "fetch",
+        "<tt>fetch(url, { method, headers, body })</tt>\nFetches a url, returns <tt>{ ok, status, headers, text(), json() }</tt>",
+    ),
+    // metadata
+    ("api", "Version of the script API, always 1"),
+    ("name", "Name shown in the command pallete"),
+    ("description", "Description shown in the command pallete"),
+    ("author", "Who wrote the script"),
+    ("icon", "Icon shown in the command pallete"),
+    ("tags", "Comma separated words the script can be searched by"),
+    (
+        "bias",
+        "Number that raises the script in search results, or lowers it if negative, e.g. <tt>0.5</tt>",
+    ),
+    ("shortcut", "Suggested shortcut, e.g. <tt>&lt;Primary&gt;K F</tt>"),
+    ("timeout", "Seconds the script may run for before it's stopped"),
+    (
+        "pure",
+        "The script always gives the same result for the same text, so results can be reused",
+    ),
+    ("tests", "Fixtures run by the self-tests, <tt>[{ input, selection, expected }]</tt>"),
+    ("network", "The script may <tt>fetch</tt> urls, once the user allows it"),
+    (
+        "permissions",
+        "What the script may ask for, <tt>[\"filesystem\", \"network\", \"clipboard\"]</tt>",
+    ),
+    (
+        "stream",
+        "The script's <tt>processLine</tt> is called with each line instead of <tt>main</tt>, for large texts",
+    ),
+];
+
+// completion for a view, the buffers it shows have to be watched so it's only active while the
+// document is a script
+#[derive(Clone)]
+pub struct Completion {
+    view: sourceview::View,
+    provider: sourceview::CompletionWords,
+    enabled: Rc<Cell<bool>>,
+}
+
+impl Completion {
+    pub fn watch(&self, buffer: &sourceview::Buffer) {
+        let completion = self.clone();
+        buffer.connect_changed(move |buffer| {
+            let buffer = buffer.upcast_ref::<gtk::TextBuffer>();
+            if completion.view.get_buffer().as_ref() == Some(buffer) {
+                completion.update(buffer);
+            }
+        });
+    }
+
+    // only complete while editing a script, so other documents aren't interrupted
+    fn update(&self, buffer: &gtk::TextBuffer) {
+        let head = buffer
+            .get_text(
+                &buffer.get_start_iter(),
+                &buffer.get_iter_at_offset(2048),
+                false,
+            )
+            .map(|head| head.to_string())
+            .unwrap_or_default();
+
+        let is_script = is_script(&head);
+        if is_script == self.enabled.get() {
+            return;
+        }
+
+        info!("script API completion enabled: {}", is_script);
+        self.enabled.set(is_script);
+        let completion = self
+            .view
+            .get_completion()
+            .expect("failed to get completion");
+        let result = if is_script {
+            completion.add_provider(&self.provider)
+        } else {
+            completion.remove_provider(&self.provider)
+        };
+        if let Err(e) = result {
+            error!("could not change completion providers: {}", e);
+        }
+    }
+}
+
+pub fn setup(view: &sourceview::View) -> Completion {
+    let buffer = view
+        .get_buffer()
+        .expect("failed to get buffer")
+        .downcast::<sourceview::Buffer>()
+        .expect("faild to downcast TextBuffer to sourceview Buffer");
+
+    // words are completed from a buffer that's never shown
+    let words = gtk::TextBuffer::new(None::<&gtk::TextTagTable>);
+    words.set_text(
+        &API_DOCS
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<&str>>()
+            .join("\n"),
+    );
+    let provider = sourceview::CompletionWords::new(Some("Boop"), None::<&gdk_pixbuf::Pixbuf>);
+    provider.register(&words);
+
+    let completion = Completion {
+        view: view.clone(),
+        provider,
+        enabled: Rc::new(Cell::new(false)),
+    };
+    completion.watch(&buffer);
+    // the buffer is replaced when another tab is shown
+    {
+        let completion = completion.clone();
+        view.connect_property_buffer_notify(move |view| {
+            if let Some(buffer) = view.get_buffer() {
+                completion.update(&buffer);
+            }
+        });
+    }
+
+    let enabled = completion.enabled.clone();
+    view.set_has_tooltip(true);
+    view.connect_query_tooltip(move |view, x, y, keyboard_mode, tooltip| {
+        if !enabled.get() || keyboard_mode {
+            return false;
+        }
+
+        let (x, y) = view.window_to_buffer_coords(gtk::TextWindowType::Widget, x, y);
+        let iter = match view.get_iter_at_location(x, y) {
+            Some(iter) => iter,
+            None => return false,
+        };
+
+        let mut start = iter.clone();
+        start.set_line_offset(0);
+        let mut end = iter.clone();
+        if !end.ends_line() {
+            end.forward_to_line_end();
+        }
+        let line = match start.get_text(&end) {
+            Some(line) => line,
+            None => return false,
+        };
+
+        match word_at(&line, iter.get_line_offset() as usize).and_then(docs_for) {
+            Some(docs) => {
+                tooltip.set_markup(Some(docs));
+                true
+            }
+            None => false,
+        }
+    });
+
+    completion
+}
+
+// true if the start of a document looks like the metadata header of a script
+fn is_script(head: &str) -> bool {
+    head.trim_start().starts_with("/**") && head.contains("\"api\"")
+}
+
+// the identifier containing the character at `offset` (in chars)
+fn word_at(line: &str, offset: usize) -> Option<&str> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+
+    let index = line.char_indices().nth(offset).map(|(index, _)| index)?;
+    if !line[index..].starts_with(is_word) {
+        return None;
+    }
+
+    let start = line[..index]
+        .char_indices()
+        .rev()
+        .find(|(_, c)| !is_word(*c))
+        .map(|(start, c)| start + c.len_utf8())
+        .unwrap_or(0);
+    let end = line[index..]
+        .find(|c: char| !is_word(c))
+        .map(|end| index + end)
+        .unwrap_or_else(|| line.len());
+
+    Some(&line[start..end])
+}
+
+fn docs_for(word: &str) -> Option<&'static str> {
+    API_DOCS
+        .iter()
+        .find(|(name, _)| *name == word)
+        .map(|(_, docs)| *docs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_script() {
+        assert!(is_script("\n/**\n  {\n    \"api\":1,\n"));
+        assert!(!is_script("/** just a comment */"));
+        assert!(!is_script("some text with \"api\" in it"));
+    }
+
+    #[test]
+    fn test_word_at() {
+        let line = "    state.postInfo(\"done\");";
+        assert_eq!(Some("state"), word_at(line, 4));
+        assert_eq!(Some("postInfo"), word_at(line, 12));
+        assert_eq!(Some("postInfo"), word_at(line, 17));
+        assert_eq!(None, word_at(line, 18));
+        assert_eq!(None, word_at(line, 100));
+        assert_eq!(Some("fullText"), word_at("é.fullText", 5));
+    }
+}
@@ -0,0 +1,159 @@
+// D-Bus service so other applications can run scripts without opening a window, started with
+// `boop-gtk --dbus-service`
+
+use crate::{
+    cli,
+    config::Config,
+    executor::{headless_host, TextReplacement},
+    script::Script,
+    search_provider::{self, SearchProvider},
+};
+use simple_error::SimpleError;
+use std::path::PathBuf;
+use zbus::{dbus_interface, fdo};
+
+pub const BUS_NAME: &str = "org.boop.GTK";
+const OBJECT_PATH: &str = "/org/boop/GTK";
+
+struct Service {
+    scripts: Vec<Script>,
+    config: Config,
+    config_dir: PathBuf,
+}
+
+#[dbus_interface(name = "org.boop.GTK")]
+impl Service {
+    // returns the kind of replacement, "full", "selection" or "none", and the replacing text, an
+    // empty selection is no selection
+    fn execute_script(
+        &mut self,
+        name: &str,
+        text: &str,
+        selection: &str,
+    ) -> fdo::Result<(String, String)> {
+        info!("{} requested over D-Bus", name);
+
+        let selection = Some(selection).filter(|selection| !selection.is_empty());
+        let status = cli::execute_script(
+            &mut self.scripts,
+            name,
+            text,
+            selection,
+            &self.config,
+            &self.config_dir,
+            &headless_host,
+        )
+        .map_err(|e| fdo::Error::Failed(e.to_string()))?;
+
+        if let Some(error) = status.error() {
+            return Err(fdo::Error::Failed(format!("{}: {}", name, error)));
+        }
+
+        let (kind, text) = replacement_result(status.into_replacement(), text, selection);
+        Ok((kind.to_string(), text))
+    }
+
+    // the name, description and comma separated tags of each script
+    fn list_scripts(&self) -> Vec<(String, String, String)> {
+        self.scripts
+            .iter()
+            .map(|script| {
+                (
+                    script.metadata.name.clone(),
+                    script.metadata.description.clone(),
+                    script.metadata.tags.clone().unwrap_or_default(),
+                )
+            })
+            .collect()
+    }
+}
+
+// insertions are made to the text, so callers only have to handle whole texts and selections
+fn replacement_result(
+    replacement: TextReplacement,
+    text: &str,
+    selection: Option<&str>,
+) -> (&'static str, String) {
+    match replacement {
+        TextReplacement::Full(text) => ("full", text),
+        TextReplacement::Selection(text) => ("selection", text),
+        TextReplacement::Selections(texts) => match texts.into_iter().next() {
+            Some(text) => ("selection", text),
+            None => ("none", String::new()),
+        },
+        TextReplacement::Insert(_) => ("full", replacement.apply(text, selection)),
+        TextReplacement::None => ("none", String::new()),
+    }
+}
+
+// serves requests until the process is killed
+pub fn serve(scripts: Vec<Script>, config: Config, config_dir: PathBuf) -> Result<(), SimpleError> {
+    let connection = zbus::Connection::new_session()
+        .map_err(|e| SimpleError::with("could not connect to the session bus", e))?;
+
+    fdo::DBusProxy::new(&connection)
+        .map_err(|e| SimpleError::with("could not connect to the session bus", e))?
+        .request_name(BUS_NAME, fdo::RequestNameFlags::DoNotQueue.into())
+        .map_err(|e| SimpleError::with(&format!("could not own {}", BUS_NAME), e))?;
+
+    let metadata = scripts
+        .iter()
+        .map(|script| script.metadata.clone())
+        .collect();
+    let mut object_server = zbus::ObjectServer::new(&connection);
+    object_server
+        .at(search_provider::OBJECT_PATH, SearchProvider::new(metadata))
+        .map_err(|e| SimpleError::with("could not serve the search provider", e))?;
+    object_server
+        .at(
+            OBJECT_PATH,
+            Service {
+                scripts,
+                config,
+                config_dir,
+            },
+        )
+        .map_err(|e| SimpleError::with(&format!("could not serve {}", OBJECT_PATH), e))?;
+
+    info!("serving {} at {}", BUS_NAME, OBJECT_PATH);
+    loop {
+        if let Err(e) = object_server.try_handle_next() {
+            error!("D-Bus error: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::Insertion;
+
+    #[test]
+    fn test_replacement_result() {
+        assert_eq!(
+            ("selection", "B".to_string()),
+            replacement_result(TextReplacement::Selection("B".to_string()), "ab", Some("b"))
+        );
+        assert_eq!(
+            ("full", "[ab]".to_string()),
+            replacement_result(
+                TextReplacement::Insert(vec![
+                    Insertion {
+                        text: "[".to_string(),
+                        at: Some(0)
+                    },
+                    Insertion {
+                        text: "]".to_string(),
+                        at: Some(2)
+                    }
+                ]),
+                "ab",
+                None
+            )
+        );
+        assert_eq!(
+            ("none", String::new()),
+            replacement_result(TextReplacement::Selections(Vec::new()), "ab", None)
+        );
+    }
+}
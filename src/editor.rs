@@ -0,0 +1,311 @@
+// window for writing scripts, the script being edited can be run against sample text without
+// saving it, using its own executor so scripts that are loaded aren't affected
+
+use crate::{
+    executor::{headless_host, TextReplacement},
+    installer,
+    script::Script,
+};
+use gtk::prelude::*;
+use sourceview::prelude::*;
+use std::{cell::RefCell, fs, path::PathBuf, rc::Rc, time::Duration};
+
+// source of a new script
+pub const TEMPLATE: &str = "/**
+    {
+        \"api\":1,
+        \"name\":\"New Script\",
+        \"description\":\"Describe what your script does\",
+        \"author\":\"\",
+        \"icon\":\"html\",
+        \"tags\":\"\"
+    }
+**/
+
+function main(state) {
+    state.text = state.text.toUpperCase();
+}
+";
+
+const WINDOW_WIDTH: i32 = 900;
+const WINDOW_HEIGHT: i32 = 600;
+const SAMPLE_INPUT: &str = "Hello, World!";
+
+// what running the script against the sample input did
+#[derive(Debug, PartialEq)]
+pub struct TestRun {
+    // the sample input after the script ran
+    pub output: String,
+    pub summary: String,
+}
+
+// runs `source` against `input`, the selection is the first occurrence of `selection` in `input`
+pub fn test_run(
+    source: &str,
+    input: &str,
+    selection: Option<&str>,
+    timeout: Duration,
+) -> Result<TestRun, String> {
+    let mut script =
+        Script::from_source(source.to_string(), PathBuf::new()).map_err(|e| e.to_string())?;
+
+    let result = script.execute(
+        input,
+        selection,
+        &Default::default(),
+        timeout,
+        &headless_host,
+    );
+    script.kill_thread();
+    let status = result.map_err(|e| e.to_string())?;
+
+    if let Some(exception) = status.exception() {
+        return Err(match &exception.stack_trace {
+            Some(stack_trace) => format!("threw {}\n{}", exception, stack_trace),
+            None => format!("threw {}", exception),
+        });
+    }
+
+    let mut messages: Vec<String> = status
+        .console_output()
+        .iter()
+        .map(|(level, line)| format!("{}: {}", level.name(), line))
+        .collect();
+    if let Some(info) = status.info() {
+        messages.push(format!("info: {}", info));
+    }
+    if let Some(error) = status.error() {
+        messages.push(format!("error: {}", error));
+    }
+
+    let replacement = status.into_replacement();
+    let mut summary = match &replacement {
+        TextReplacement::Full(_) => "Replaced the whole text",
+        TextReplacement::Selection(_) | TextReplacement::Selections(_) => "Replaced the selection",
+        TextReplacement::Insert(_) => "Inserted text",
+        TextReplacement::None => "Didn't change the text",
+    }
+    .to_string();
+    for message in messages {
+        summary.push('\n');
+        summary.push_str(&message);
+    }
+
+    Ok(TestRun {
+        output: replacement.apply(input, selection),
+        summary,
+    })
+}
+
+#[derive(Clone)]
+pub struct ScriptEditor {
+    window: gtk::Window,
+    source_view: sourceview::View,
+    input_view: gtk::TextView,
+    output_view: gtk::TextView,
+    result_label: gtk::Label,
+    // where the script is saved, None until a new script is saved
+    path: Rc<RefCell<Option<PathBuf>>>,
+    scripts_dir: PathBuf,
+    timeout: Duration,
+}
+
+impl ScriptEditor {
+    // built-in scripts have no path, saving them creates a user script
+    pub fn new(
+        parent: &gtk::ApplicationWindow,
+        source: &str,
+        path: Option<PathBuf>,
+        scripts_dir: PathBuf,
+        timeout: Duration,
+    ) -> Self {
+        let window = gtk::Window::new(gtk::WindowType::Toplevel);
+        window.set_transient_for(Some(parent));
+        window.set_default_size(WINDOW_WIDTH, WINDOW_HEIGHT);
+
+        let header_bar = gtk::HeaderBar::new();
+        header_bar.set_show_close_button(true);
+        header_bar.set_title(Some("Script Editor"));
+        header_bar.set_subtitle(
+            path.as_ref()
+                .map(|path| path.display().to_string())
+                .as_deref()
+                .or(Some("not saved")),
+        );
+        let run_button = gtk::Button::with_mnemonic("_Run");
+        run_button.get_style_context().add_class("suggested-action");
+        run_button.set_tooltip_text(Some("Run the script on the sample input (Ctrl+Enter)"));
+        let save_button = gtk::Button::with_mnemonic("_Save");
+        header_bar.pack_start(&run_button);
+        header_bar.pack_end(&save_button);
+        window.set_titlebar(Some(&header_bar));
+
+        let buffer = sourceview::Buffer::new(None::<&gtk::TextTagTable>);
+        buffer.set_language(
+            sourceview::LanguageManager::get_default()
+                .and_then(|manager| manager.get_language("js"))
+                .as_ref(),
+        );
+        buffer.set_text(source);
+        let source_view = sourceview::View::new_with_buffer(&buffer);
+        source_view.set_monospace(true);
+        source_view.set_show_line_numbers(true);
+        source_view.set_auto_indent(true);
+        source_view.set_tab_width(4);
+
+        let input_view = gtk::TextView::new();
+        input_view.set_monospace(true);
+        input_view
+            .get_buffer()
+            .expect("failed to get buffer")
+            .set_text(SAMPLE_INPUT);
+        let output_view = gtk::TextView::new();
+        output_view.set_monospace(true);
+        output_view.set_editable(false);
+        let result_label = gtk::Label::new(Some(
+            "Select part of the sample input to run the script on a selection",
+        ));
+        result_label.set_xalign(0.0);
+        result_label.set_line_wrap(true);
+        result_label.set_selectable(true);
+
+        let scrolled = |widget: &gtk::TextView| {
+            let scrolled_window =
+                gtk::ScrolledWindow::new(None::<&gtk::Adjustment>, None::<&gtk::Adjustment>);
+            scrolled_window.add(widget);
+            scrolled_window
+        };
+        let heading = |text: &str| {
+            let label = gtk::Label::new(None);
+            label.set_markup(&format!("<b>{}</b>", text));
+            label.set_xalign(0.0);
+            label
+        };
+
+        let sample = gtk::Box::new(gtk::Orientation::Vertical, 6);
+        sample.set_border_width(6);
+        sample.pack_start(&heading("Sample Input"), false, true, 0);
+        sample.pack_start(&scrolled(&input_view), true, true, 0);
+        sample.pack_start(&heading("Result"), false, true, 0);
+        sample.pack_start(&scrolled(&output_view), true, true, 0);
+        sample.pack_start(&result_label, false, true, 0);
+
+        let paned = gtk::Paned::new(gtk::Orientation::Horizontal);
+        paned.pack1(
+            &scrolled(source_view.upcast_ref::<gtk::TextView>()),
+            true,
+            false,
+        );
+        paned.pack2(&sample, true, false);
+        paned.set_position(WINDOW_WIDTH * 3 / 5);
+        window.add(&paned);
+
+        let editor = ScriptEditor {
+            window,
+            source_view,
+            input_view,
+            output_view,
+            result_label,
+            path: Rc::new(RefCell::new(path)),
+            scripts_dir,
+            timeout,
+        };
+
+        {
+            let editor_ = editor.clone();
+            run_button.connect_clicked(move |_| editor_.run());
+        }
+        {
+            let editor_ = editor.clone();
+            save_button.connect_clicked(move |_| {
+                if let Some(path) = editor_.save() {
+                    header_bar.set_subtitle(Some(&path.display().to_string()));
+                }
+            });
+        }
+        {
+            let editor_ = editor.clone();
+            editor.window.connect_key_press_event(move |_, event| {
+                let control = event.get_state().contains(gdk::ModifierType::CONTROL_MASK);
+                if control && event.get_keyval() == gdk::keys::constants::Return {
+                    editor_.run();
+                    gtk::Inhibit(true)
+                } else {
+                    gtk::Inhibit(false)
+                }
+            });
+        }
+
+        editor
+    }
+
+    pub fn show(&self) {
+        self.window.show_all();
+        self.source_view.grab_focus();
+    }
+
+    fn source(&self) -> String {
+        let buffer = self.source_view.get_buffer().expect("failed to get buffer");
+        buffer
+            .get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false)
+            .expect("failed to get buffer text")
+            .to_string()
+    }
+
+    fn run(&self) {
+        let buffer = self.input_view.get_buffer().expect("failed to get buffer");
+        let input = buffer
+            .get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false)
+            .expect("failed to get buffer text");
+        let selection = buffer
+            .get_selection_bounds()
+            .and_then(|(start, end)| buffer.get_text(&start, &end, false));
+
+        let output_buffer = self.output_view.get_buffer().expect("failed to get buffer");
+        match test_run(&self.source(), &input, selection.as_deref(), self.timeout) {
+            Ok(run) => {
+                output_buffer.set_text(&run.output);
+                self.result_label.set_text(&run.summary);
+            }
+            Err(e) => {
+                output_buffer.set_text("");
+                self.result_label.set_text(&format!("Error: {}", e));
+            }
+        }
+    }
+
+    // user scripts are saved in place, other scripts are installed into the scripts directory
+    // where they're loaded by the watcher
+    fn save(&self) -> Option<PathBuf> {
+        let source = self.source();
+        if let Err(e) = installer::check(&source) {
+            self.result_label.set_text(&format!("Not saved: {}", e));
+            return None;
+        }
+
+        let path = self.path.borrow().clone();
+        let saved = match path {
+            Some(path) => fs::write(&path, &source)
+                .map(|_| path.clone())
+                .map_err(|e| format!("could not write {}: {}", path.display(), e)),
+            None => {
+                installer::install(&self.scripts_dir, &source, false).map_err(|e| e.to_string())
+            }
+        };
+
+        match saved {
+            Ok(path) => {
+                info!("saved script to {}", path.display());
+                self.result_label
+                    .set_text(&format!("Saved to {}", path.display()));
+                self.path.replace(Some(path.clone()));
+                Some(path)
+            }
+            Err(e) => {
+                error!("could not save script: {}", e);
+                self.result_label.set_text(&format!("Not saved: {}", e));
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,67 @@
+// names of the scripts starred in the command pallete, kept in `favorites` in config.json, they're
+// pinned above the other scripts and can be shown as buttons in the header bar
+
+use crate::config::Config;
+use simple_error::SimpleError;
+use std::path::Path;
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Favorites {
+    // in the order they were starred, each script is listed once
+    pub scripts: Vec<String>,
+}
+
+impl Favorites {
+    pub fn load(config_dir: &Path) -> Self {
+        Favorites {
+            scripts: Config::load(config_dir).favorites,
+        }
+    }
+
+    pub fn save(&self, config_dir: &Path) -> Result<(), SimpleError> {
+        Config::update(config_dir, |config| config.favorites = self.scripts.clone())
+    }
+
+    pub fn contains(&self, script_name: &str) -> bool {
+        self.scripts.iter().any(|name| name == script_name)
+    }
+
+    // stars the script, or unstars it if it's already starred, returning whether it's now starred
+    pub fn toggle(&mut self, script_name: &str) -> bool {
+        if self.contains(script_name) {
+            self.scripts.retain(|name| name != script_name);
+            false
+        } else {
+            self.scripts.push(script_name.to_string());
+            true
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_favorites() {
+        let config_dir =
+            std::env::temp_dir().join(format!("boop-gtk-favorites-{}", std::process::id()));
+        std::fs::create_dir_all(&config_dir).unwrap();
+
+        let mut favorites = Favorites::load(&config_dir);
+        assert!(favorites.scripts.is_empty());
+
+        assert!(favorites.toggle("Format JSON"));
+        assert!(favorites.toggle("Sort lines"));
+        assert!(favorites.toggle("Base64 Encode"));
+        assert!(!favorites.toggle("Sort lines"));
+        favorites.save(&config_dir).unwrap();
+
+        let favorites = Favorites::load(&config_dir);
+        assert_eq!(vec!["Format JSON", "Base64 Encode"], favorites.scripts);
+        assert!(favorites.contains("Format JSON"));
+        assert!(!favorites.contains("Sort lines"));
+
+        std::fs::remove_dir_all(&config_dir).unwrap();
+    }
+}
@@ -0,0 +1,194 @@
+// reading files into the editor, whatever encoding they were written in, as long as they're text
+// and small enough for the editor to cope with, files are saved in the encoding they were read as
+
+use simple_error::{bail, SimpleError};
+use std::{fs, path::Path};
+
+// larger files make the editor and scripts crawl
+pub const MAX_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+// every encoding files are read and written in
+pub const ENCODINGS: &[&str] = &["UTF-8", "UTF-16LE", "UTF-16BE", "Windows-1252"];
+
+const UTF8_BOM: &[u8] = &[0xEF, 0xBB, 0xBF];
+const UTF16LE_BOM: &[u8] = &[0xFF, 0xFE];
+const UTF16BE_BOM: &[u8] = &[0xFE, 0xFF];
+
+// characters windows-1252 puts in 0x80 to 0x9F, where latin-1 has control characters
+const WINDOWS_1252: [char; 32] = [
+    '\u{20AC}', '\u{81}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{8D}', '\u{017D}', '\u{8F}',
+    '\u{90}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{9D}', '\u{017E}', '\u{0178}',
+];
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct TextFile {
+    pub text: String,
+    pub encoding: &'static str,
+}
+
+pub fn read_text(path: &Path) -> Result<TextFile, SimpleError> {
+    let metadata = fs::metadata(path)
+        .map_err(|e| SimpleError::with(&format!("could not read {}", path.display()), e))?;
+    if metadata.is_dir() {
+        bail!("{} is a folder", path.display());
+    }
+    if metadata.len() > MAX_FILE_SIZE {
+        bail!(
+            "{} is too large to open, files can be up to {} MB",
+            path.display(),
+            MAX_FILE_SIZE / 1024 / 1024
+        );
+    }
+
+    let bytes = fs::read(path)
+        .map_err(|e| SimpleError::with(&format!("could not read {}", path.display()), e))?;
+    match decode(&bytes) {
+        Some(file) => Ok(file),
+        None => bail!("{} isn't a text file", path.display()),
+    }
+}
+
+// guesses the encoding from the byte order mark, falling back to windows-1252 for anything that
+// isn't utf-8, none if the bytes look binary
+pub fn decode(bytes: &[u8]) -> Option<TextFile> {
+    if bytes.starts_with(UTF8_BOM) {
+        let rest = &bytes[UTF8_BOM.len()..];
+        return String::from_utf8(rest.to_vec()).ok().map(|text| TextFile {
+            text,
+            encoding: "UTF-8",
+        });
+    }
+    if bytes.starts_with(UTF16LE_BOM) {
+        let rest = &bytes[UTF16LE_BOM.len()..];
+        return decode_utf16(rest, u16::from_le_bytes).map(|text| TextFile {
+            text,
+            encoding: "UTF-16LE",
+        });
+    }
+    if bytes.starts_with(UTF16BE_BOM) {
+        let rest = &bytes[UTF16BE_BOM.len()..];
+        return decode_utf16(rest, u16::from_be_bytes).map(|text| TextFile {
+            text,
+            encoding: "UTF-16BE",
+        });
+    }
+
+    // text doesn't have nul bytes, binary files almost always do
+    if bytes.contains(&0) {
+        return None;
+    }
+
+    match std::str::from_utf8(bytes) {
+        Ok(text) => Some(TextFile {
+            text: text.to_string(),
+            encoding: "UTF-8",
+        }),
+        Err(_) => Some(TextFile {
+            text: bytes
+                .iter()
+                .map(|&byte| match byte {
+                    0x80..=0x9F => WINDOWS_1252[(byte - 0x80) as usize],
+                    _ => byte as char,
+                })
+                .collect(),
+            encoding: "Windows-1252",
+        }),
+    }
+}
+
+pub fn write_text(path: &Path, text: &str, encoding: &str) -> Result<(), SimpleError> {
+    let bytes = encode(text, encoding)
+        .map_err(|e| SimpleError::with(&format!("could not save {}", path.display()), e))?;
+
+    fs::write(path, bytes)
+        .map_err(|e| SimpleError::with(&format!("could not write {}", path.display()), e))
+}
+
+// the bytes of `text` in an encoding `decode` reports, utf-16 gets a byte order mark so it's
+// decoded the same way again
+pub fn encode(text: &str, encoding: &str) -> Result<Vec<u8>, SimpleError> {
+    let utf16 = |bom: &[u8], to_bytes: fn(u16) -> [u8; 2]| {
+        let mut bytes = bom.to_vec();
+        for unit in text.encode_utf16() {
+            bytes.extend_from_slice(&to_bytes(unit));
+        }
+        bytes
+    };
+
+    match encoding {
+        "UTF-8" => Ok(text.as_bytes().to_vec()),
+        "UTF-16LE" => Ok(utf16(UTF16LE_BOM, u16::to_le_bytes)),
+        "UTF-16BE" => Ok(utf16(UTF16BE_BOM, u16::to_be_bytes)),
+        "Windows-1252" => text
+            .chars()
+            .map(|c| match c as u32 {
+                0x00..=0x7F | 0xA0..=0xFF => Ok(c as u8),
+                _ => match WINDOWS_1252.iter().position(|&other| other == c) {
+                    Some(index) => Ok(0x80 + index as u8),
+                    None => bail!(
+                        "\"{}\" can't be written in Windows-1252, convert the document to UTF-8",
+                        c
+                    ),
+                },
+            })
+            .collect(),
+        _ => bail!("unknown encoding {}", encoding),
+    }
+}
+
+fn decode_utf16(bytes: &[u8], from_bytes: fn([u8; 2]) -> u16) -> Option<String> {
+    if bytes.len() % 2 != 0 {
+        return None;
+    }
+    let units: Vec<u16> = bytes
+        .chunks(2)
+        .map(|pair| from_bytes([pair[0], pair[1]]))
+        .collect();
+    String::from_utf16(&units).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let decoded = |bytes: &[u8]| decode(bytes).map(|file| (file.text, file.encoding));
+
+        assert_eq!(
+            decoded("caf\u{e9}".as_bytes()),
+            Some(("caf\u{e9}".to_string(), "UTF-8"))
+        );
+        assert_eq!(
+            decoded(b"\xEF\xBB\xBFhi"),
+            Some(("hi".to_string(), "UTF-8"))
+        );
+        assert_eq!(
+            decoded(b"\xFF\xFEh\0i\0"),
+            Some(("hi".to_string(), "UTF-16LE"))
+        );
+        assert_eq!(
+            decoded(b"\xFE\xFF\0h\0i"),
+            Some(("hi".to_string(), "UTF-16BE"))
+        );
+        assert_eq!(
+            decoded(b"caf\xE9 \x80"),
+            Some(("caf\u{e9} \u{20AC}".to_string(), "Windows-1252"))
+        );
+        assert_eq!(decoded(b"\x89PNG\r\n\x1A\n\0\0\0\rIHDR"), None);
+    }
+
+    #[test]
+    fn test_encode() {
+        for encoding in ENCODINGS {
+            let bytes = encode("caf\u{e9} \u{20AC}", encoding).unwrap();
+            assert_eq!(
+                Some(("caf\u{e9} \u{20AC}".to_string(), *encoding)),
+                decode(&bytes).map(|file| (file.text, file.encoding))
+            );
+        }
+        assert!(encode("\u{1F600}", "Windows-1252").is_err());
+    }
+}
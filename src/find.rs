@@ -0,0 +1,412 @@
+// the find and replace bar above the editor, it searches the current tab with a sourceview search
+// context, which highlights every match while the bar is open
+
+use gtk::prelude::*;
+use sourceview::prelude::*;
+use std::{cell::RefCell, rc::Rc};
+
+// longer selections aren't what the user wants to search for
+const MAX_SELECTION_SEARCH: usize = 200;
+
+// e.g. "3 of 12" when a match is selected, `count` is -1 while the buffer is still being searched
+// and `position` is 0 when no match is selected
+pub fn count_label(count: i32, position: i32) -> String {
+    match count {
+        c if c < 0 => String::new(),
+        0 => "No matches".to_string(),
+        _ if position > 0 => format!("{} of {}", position, count),
+        1 => "1 match".to_string(),
+        _ => format!("{} matches", count),
+    }
+}
+
+#[derive(Clone)]
+pub struct FindBar {
+    revealer: gtk::Revealer,
+    find_entry: gtk::SearchEntry,
+    replace_entry: gtk::Entry,
+    replace_box: gtk::Box,
+    count_label: gtk::Label,
+    source_view: sourceview::View,
+    settings: sourceview::SearchSettings,
+    // search of the buffer that's shown, replaced when the tab changes and dropped when the bar
+    // is closed
+    context: Rc<RefCell<Option<sourceview::SearchContext>>>,
+}
+
+impl FindBar {
+    pub fn new(source_view: &sourceview::View) -> Self {
+        let settings = sourceview::SearchSettings::new();
+        settings.set_wrap_around(true);
+
+        let find_bar = FindBar {
+            revealer: gtk::Revealer::new(),
+            find_entry: gtk::SearchEntry::new(),
+            replace_entry: gtk::Entry::new(),
+            replace_box: gtk::Box::new(gtk::Orientation::Horizontal, 6),
+            count_label: gtk::Label::new(None),
+            source_view: source_view.clone(),
+            settings,
+            context: Rc::new(RefCell::new(None)),
+        };
+
+        find_bar
+            .find_entry
+            .set_placeholder_text(Some("Find (Ctrl+F)"));
+        find_bar.find_entry.set_hexpand(true);
+        {
+            let find_bar_ = find_bar.clone();
+            find_bar.find_entry.connect_search_changed(move |entry| {
+                let text = entry.get_text();
+                find_bar_
+                    .settings
+                    .set_search_text(Some(text.as_str()).filter(|text| !text.is_empty()));
+                find_bar_.search_from_selection();
+            });
+        }
+        {
+            let find_bar_ = find_bar.clone();
+            find_bar
+                .find_entry
+                .connect_activate(move |_| find_bar_.find(true));
+        }
+        {
+            let find_bar_ = find_bar.clone();
+            find_bar
+                .find_entry
+                .connect_next_match(move |_| find_bar_.find(true));
+        }
+        {
+            let find_bar_ = find_bar.clone();
+            find_bar
+                .find_entry
+                .connect_previous_match(move |_| find_bar_.find(false));
+        }
+        {
+            let find_bar_ = find_bar.clone();
+            find_bar
+                .find_entry
+                .connect_stop_search(move |_| find_bar_.hide());
+        }
+
+        let previous_button = icon_button("go-up-symbolic", "Previous Match (Shift+Ctrl+G)");
+        {
+            let find_bar_ = find_bar.clone();
+            previous_button.connect_clicked(move |_| find_bar_.find(false));
+        }
+        let next_button = icon_button("go-down-symbolic", "Next Match (Ctrl+G)");
+        {
+            let find_bar_ = find_bar.clone();
+            next_button.connect_clicked(move |_| find_bar_.find(true));
+        }
+
+        let case_button = gtk::ToggleButton::with_label("Aa");
+        case_button.set_tooltip_text(Some("Match Case"));
+        {
+            let settings = find_bar.settings.clone();
+            case_button.connect_toggled(move |button| {
+                settings.set_case_sensitive(button.get_active());
+            });
+        }
+        let word_button = gtk::ToggleButton::with_label("Word");
+        word_button.set_tooltip_text(Some("Match Whole Words"));
+        {
+            let settings = find_bar.settings.clone();
+            word_button.connect_toggled(move |button| {
+                settings.set_at_word_boundaries(button.get_active());
+            });
+        }
+        let regex_button = gtk::ToggleButton::with_label(".*");
+        regex_button.set_tooltip_text(Some(
+            "Regular Expression, \\1 in the replacement is the first group",
+        ));
+        {
+            let settings = find_bar.settings.clone();
+            regex_button.connect_toggled(move |button| {
+                settings.set_regex_enabled(button.get_active());
+            });
+        }
+
+        find_bar.count_label.set_width_chars(10);
+        find_bar.count_label.set_xalign(1.0);
+        find_bar
+            .count_label
+            .get_style_context()
+            .add_class("dim-label");
+
+        let close_button = icon_button("window-close-symbolic", "Close (Escape)");
+        {
+            let find_bar_ = find_bar.clone();
+            close_button.connect_clicked(move |_| find_bar_.hide());
+        }
+
+        let find_box = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        find_box.pack_start(&find_bar.find_entry, true, true, 0);
+        find_box.pack_start(&previous_button, false, false, 0);
+        find_box.pack_start(&next_button, false, false, 0);
+        find_box.pack_start(&case_button, false, false, 0);
+        find_box.pack_start(&word_button, false, false, 0);
+        find_box.pack_start(&regex_button, false, false, 0);
+        find_box.pack_start(&find_bar.count_label, false, false, 0);
+        find_box.pack_end(&close_button, false, false, 0);
+
+        find_bar
+            .replace_entry
+            .set_placeholder_text(Some("Replace (Ctrl+H)"));
+        {
+            let find_bar_ = find_bar.clone();
+            find_bar
+                .replace_entry
+                .connect_activate(move |_| find_bar_.replace());
+        }
+        let replace_button = gtk::Button::with_label("Replace");
+        {
+            let find_bar_ = find_bar.clone();
+            replace_button.connect_clicked(move |_| find_bar_.replace());
+        }
+        let replace_all_button = gtk::Button::with_label("Replace All");
+        {
+            let find_bar_ = find_bar.clone();
+            replace_all_button.connect_clicked(move |_| find_bar_.replace_all());
+        }
+        find_bar
+            .replace_box
+            .pack_start(&find_bar.replace_entry, true, true, 0);
+        find_bar
+            .replace_box
+            .pack_start(&replace_button, false, false, 0);
+        find_bar
+            .replace_box
+            .pack_start(&replace_all_button, false, false, 0);
+
+        let bar = gtk::Box::new(gtk::Orientation::Vertical, 6);
+        bar.set_border_width(6);
+        bar.pack_start(&find_box, false, false, 0);
+        bar.pack_start(&find_bar.replace_box, false, false, 0);
+        find_bar.revealer.add(&bar);
+        find_bar.revealer.show_all();
+
+        find_bar
+    }
+
+    pub fn widget(&self) -> &gtk::Revealer {
+        &self.revealer
+    }
+
+    // opens the bar searching for the selection, with the replace row if `replace`
+    pub fn show(&self, replace: bool) {
+        let buffer = self.source_view.get_buffer().expect("failed to get buffer");
+        if let Some(selected) = buffer
+            .get_selection_bounds()
+            .and_then(|(start, end)| buffer.get_text(&start, &end, false))
+            .filter(|selected| !selected.contains('\n') && selected.len() <= MAX_SELECTION_SEARCH)
+        {
+            self.find_entry.set_text(&selected);
+        }
+
+        self.replace_box.set_visible(replace);
+        self.revealer.set_reveal_child(true);
+        self.attach();
+        if replace && !self.find_entry.get_text().is_empty() {
+            self.replace_entry.grab_focus();
+        } else {
+            self.find_entry.grab_focus();
+        }
+    }
+
+    pub fn hide(&self) {
+        self.revealer.set_reveal_child(false);
+        let context = self.context.borrow_mut().take();
+        if let Some(context) = context {
+            context.set_highlight(false);
+        }
+        self.source_view.grab_focus();
+    }
+
+    // searches the buffer that's shown, called when the tab changes
+    pub fn attach(&self) {
+        if !self.revealer.get_reveal_child() {
+            return;
+        }
+        let buffer: sourceview::Buffer = self
+            .source_view
+            .get_buffer()
+            .expect("failed to get buffer")
+            .downcast::<sourceview::Buffer>()
+            .expect("faild to downcast TextBuffer to sourceview Buffer");
+
+        let mut context = self.context.borrow_mut();
+        if let Some(current) = context.as_ref() {
+            if current.get_buffer().as_ref() == Some(&buffer) {
+                return;
+            }
+            current.set_highlight(false);
+        }
+
+        let search_context = sourceview::SearchContext::new(&buffer, Some(&self.settings));
+        {
+            let find_bar = self.clone();
+            search_context.connect_property_occurrences_count_notify(move |_| {
+                find_bar.update_count();
+            });
+        }
+        {
+            let find_bar = self.clone();
+            search_context.connect_property_regex_error_notify(move |_| {
+                find_bar.update_count();
+            });
+        }
+        context.replace(search_context);
+        drop(context);
+
+        self.update_count();
+    }
+
+    fn search_context(&self) -> Option<sourceview::SearchContext> {
+        self.context.borrow().clone()
+    }
+
+    // the selection, or the cursor if nothing is selected
+    fn selection_bounds(buffer: &gtk::TextBuffer) -> Option<(gtk::TextIter, gtk::TextIter)> {
+        buffer.get_selection_bounds().or_else(|| {
+            let cursor = buffer.get_iter_at_mark(&buffer.get_insert()?);
+            Some((cursor.clone(), cursor))
+        })
+    }
+
+    fn select(&self, buffer: &gtk::TextBuffer, start: &gtk::TextIter, end: &gtk::TextIter) {
+        buffer.select_range(start, end);
+        if let Some(insert) = buffer.get_insert() {
+            self.source_view.scroll_mark_onscreen(&insert);
+        }
+        self.update_count();
+    }
+
+    // moves to the next or previous match from the selection
+    fn find(&self, forward: bool) {
+        let context = match self.search_context() {
+            Some(context) => context,
+            None => return,
+        };
+        let buffer = self.source_view.get_buffer().expect("failed to get buffer");
+        let (start, end) = match FindBar::selection_bounds(&buffer) {
+            Some(bounds) => bounds,
+            None => return,
+        };
+
+        let found = if forward {
+            context.forward(&end)
+        } else {
+            context.backward(&start)
+        };
+        if let Some((start, end)) = found {
+            self.select(&buffer, &start, &end);
+        }
+    }
+
+    // as the search changes, the match at or after the selection is selected
+    fn search_from_selection(&self) {
+        let context = match self.search_context() {
+            Some(context) => context,
+            None => return,
+        };
+        let buffer = self.source_view.get_buffer().expect("failed to get buffer");
+        if let Some((start, _)) = FindBar::selection_bounds(&buffer) {
+            if let Some((start, end)) = context.forward(&start) {
+                self.select(&buffer, &start, &end);
+            }
+        }
+    }
+
+    // replaces the selected match and selects the next one, or just selects the next one if a
+    // match isn't selected
+    fn replace(&self) {
+        let context = match self.search_context() {
+            Some(context) => context,
+            None => return,
+        };
+        let buffer = self.source_view.get_buffer().expect("failed to get buffer");
+
+        if let Some((start, end)) = buffer.get_selection_bounds() {
+            if context.get_occurrence_position(&start, &end) > 0 {
+                if let Err(e) = context.replace(&start, &end, &self.replace_entry.get_text()) {
+                    error!("could not replace match: {}", e);
+                    self.count_label.set_text("Failed to replace");
+                    return;
+                }
+            }
+        }
+        self.find(true);
+    }
+
+    fn replace_all(&self) {
+        let context = match self.search_context() {
+            Some(context) => context,
+            None => return,
+        };
+
+        let count = context.get_occurrences_count();
+        match context.replace_all(&self.replace_entry.get_text()) {
+            Ok(_) => {
+                info!("replaced {} matches", count);
+                self.count_label.set_text(&match count {
+                    1 => "Replaced 1 match".to_string(),
+                    count => format!("Replaced {} matches", count.max(0)),
+                });
+            }
+            Err(e) => {
+                error!("could not replace matches: {}", e);
+                self.count_label.set_text("Failed to replace");
+            }
+        }
+    }
+
+    // the number of matches and which is selected, or what's wrong with the regex
+    fn update_count(&self) {
+        let context = match self.search_context() {
+            Some(context) => context,
+            None => return,
+        };
+
+        let style_context = self.find_entry.get_style_context();
+        if let Some(e) = context.get_regex_error() {
+            style_context.add_class("error");
+            self.find_entry.set_tooltip_text(Some(&e.to_string()));
+            self.count_label.set_text("Invalid regex");
+            return;
+        }
+        style_context.remove_class("error");
+        self.find_entry.set_tooltip_text(None);
+
+        if self.find_entry.get_text().is_empty() {
+            self.count_label.set_text("");
+            return;
+        }
+        let buffer = self.source_view.get_buffer().expect("failed to get buffer");
+        let position = buffer.get_selection_bounds().map_or(0, |(start, end)| {
+            context.get_occurrence_position(&start, &end)
+        });
+        self.count_label
+            .set_text(&count_label(context.get_occurrences_count(), position));
+    }
+}
+
+fn icon_button(icon_name: &str, tooltip: &str) -> gtk::Button {
+    let button = gtk::Button::from_icon_name(Some(icon_name), gtk::IconSize::Button);
+    button.set_tooltip_text(Some(tooltip));
+    button
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_label() {
+        assert_eq!("", count_label(-1, 0));
+        assert_eq!("No matches", count_label(0, 0));
+        assert_eq!("1 match", count_label(1, 0));
+        assert_eq!("12 matches", count_label(12, 0));
+        assert_eq!("3 of 12", count_label(12, 3));
+    }
+}
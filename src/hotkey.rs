@@ -0,0 +1,293 @@
+// global shortcut that summons the quick pop-up from any application, bound through the
+// GlobalShortcuts desktop portal where it's available, otherwise grabbed from the X server
+
+use gdk::ModifierType;
+use simple_error::{bail, SimpleError};
+use std::{collections::HashMap, convert::TryFrom, thread};
+use x11rb::{
+    connection::Connection as _,
+    protocol::{
+        xproto::{ConnectionExt as _, GrabMode},
+        Event,
+    },
+};
+use zvariant::{ObjectPath, OwnedObjectPath, OwnedValue, Value};
+
+const PORTAL_BUS_NAME: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const PORTAL_INTERFACE: &str = "org.freedesktop.portal.GlobalShortcuts";
+const SHORTCUT_ID: &str = "quick-pop-up";
+const SHORTCUT_DESCRIPTION: &str = "Open the Boop quick pop-up";
+
+// X11 modifier masks
+const X_SHIFT: u16 = 1;
+const X_LOCK: u16 = 2;
+const X_CONTROL: u16 = 4;
+const X_MOD1: u16 = 8;
+// num lock
+const X_MOD2: u16 = 16;
+const X_MOD4: u16 = 64;
+
+// sends on `sender` each time `accelerator`, e.g. "<Super>B", is pressed, the shortcut is bound on
+// another thread as the portal may ask the user to confirm it
+pub fn bind(accelerator: &str, sender: glib::Sender<()>) -> Result<(), SimpleError> {
+    let (key, modifiers) = gtk::accelerator_parse(accelerator);
+    let key_name = match gdk::keyval_name(key) {
+        Some(key_name) if key != 0 => key_name.to_string(),
+        _ => bail!("{} is not a valid shortcut", accelerator),
+    };
+    let trigger = portal_trigger(modifiers, &key_name);
+    // keyvals are X keysyms
+    let keysym = gdk::keyval_to_lower(key);
+    let x_modifiers = x_modifiers(modifiers);
+
+    thread::spawn(move || {
+        if let Err(portal_error) = listen_portal(&trigger, &sender) {
+            info!(
+                "global shortcuts portal isn't available, grabbing {} from X: {}",
+                trigger, portal_error
+            );
+            if let Err(e) = listen_x11(keysym, x_modifiers, &sender) {
+                error!("could not bind {}: {}, {}", trigger, portal_error, e);
+            }
+        }
+    });
+
+    Ok(())
+}
+
+// e.g. "CTRL+SHIFT+b", as described by the XDG shortcuts specification
+fn portal_trigger(modifiers: ModifierType, key_name: &str) -> String {
+    let mut trigger: Vec<&str> = [
+        (ModifierType::CONTROL_MASK, "CTRL"),
+        (ModifierType::MOD1_MASK, "ALT"),
+        (ModifierType::SHIFT_MASK, "SHIFT"),
+        (ModifierType::SUPER_MASK | ModifierType::MOD4_MASK, "LOGO"),
+    ]
+    .iter()
+    .filter(|(mask, _)| modifiers.intersects(*mask))
+    .map(|(_, name)| *name)
+    .collect();
+    trigger.push(key_name);
+    trigger.join("+")
+}
+
+fn x_modifiers(modifiers: ModifierType) -> u16 {
+    [
+        (ModifierType::SHIFT_MASK, X_SHIFT),
+        (ModifierType::CONTROL_MASK, X_CONTROL),
+        (ModifierType::MOD1_MASK, X_MOD1),
+        (ModifierType::SUPER_MASK | ModifierType::MOD4_MASK, X_MOD4),
+    ]
+    .iter()
+    .filter(|(mask, _)| modifiers.intersects(*mask))
+    .fold(0, |x_modifiers, (_, x_mask)| x_modifiers | x_mask)
+}
+
+// the object the portal answers a request on, e.g. ":1.42" and "boop1" give
+// /org/freedesktop/portal/desktop/request/1_42/boop1
+fn request_path(unique_name: &str, token: &str) -> String {
+    format!(
+        "{}/request/{}/{}",
+        PORTAL_PATH,
+        unique_name.trim_start_matches(':').replace('.', "_"),
+        token
+    )
+}
+
+// waits for the portal to answer the request at `path`, returning its results
+fn wait_for_response(
+    connection: &zbus::Connection,
+    path: &str,
+) -> Result<HashMap<String, OwnedValue>, SimpleError> {
+    loop {
+        let message = connection
+            .receive_message()
+            .map_err(|e| SimpleError::with("portal connection closed", e))?;
+        let header = message
+            .header()
+            .map_err(|e| SimpleError::with("invalid message", e))?;
+        let member = header.member().ok().flatten().map(|m| m.to_string());
+        let object = header.path().ok().flatten().map(|p| p.to_string());
+        let is_response = member.as_deref() == Some("Response") && object.as_deref() == Some(path);
+        if !is_response {
+            continue;
+        }
+
+        let (response, results) = message
+            .body::<(u32, HashMap<String, OwnedValue>)>()
+            .map_err(|e| SimpleError::with("invalid portal response", e))?;
+        // 1 is cancelled by the user, 2 is any other error
+        if response != 0 {
+            bail!("the portal refused the request ({})", response);
+        }
+        return Ok(results);
+    }
+}
+
+// binds the shortcut through the portal and sends each time it's activated, returns only if the
+// shortcut couldn't be bound
+fn listen_portal(trigger: &str, sender: &glib::Sender<()>) -> Result<(), SimpleError> {
+    let connection =
+        zbus::Connection::new_session().map_err(|e| SimpleError::with("no session bus", e))?;
+    let unique_name = connection
+        .unique_name()
+        .ok_or_else(|| SimpleError::new("no unique name on the session bus"))?
+        .to_string();
+
+    let bus = zbus::fdo::DBusProxy::new(&connection)
+        .map_err(|e| SimpleError::with("no session bus", e))?;
+    for rule in &[
+        "type='signal',interface='org.freedesktop.portal.Request',member='Response'",
+        "type='signal',interface='org.freedesktop.portal.GlobalShortcuts',member='Activated'",
+    ] {
+        bus.add_match(rule)
+            .map_err(|e| SimpleError::with("could not listen to the portal", e))?;
+    }
+
+    let token = format!("boop{}", std::process::id());
+    let mut options: HashMap<&str, Value> = HashMap::new();
+    options.insert("handle_token", Value::from(token.as_str()));
+    options.insert("session_handle_token", Value::from(token.as_str()));
+    connection
+        .call_method(
+            Some(PORTAL_BUS_NAME),
+            PORTAL_PATH,
+            Some(PORTAL_INTERFACE),
+            "CreateSession",
+            &(options,),
+        )
+        .map_err(|e| SimpleError::with("could not create a global shortcuts session", e))?;
+    let results = wait_for_response(&connection, &request_path(&unique_name, &token))?;
+    let session_handle = results
+        .get("session_handle")
+        .and_then(|handle| <&str>::try_from(handle).ok())
+        .ok_or_else(|| SimpleError::new("the portal didn't create a session"))?
+        .to_string();
+    let session_path = ObjectPath::try_from(session_handle.as_str())
+        .map_err(|e| SimpleError::with("invalid session handle", e))?;
+
+    let bind_token = format!("{}bind", token);
+    let mut shortcut: HashMap<&str, Value> = HashMap::new();
+    shortcut.insert("description", Value::from(SHORTCUT_DESCRIPTION));
+    shortcut.insert("preferred_trigger", Value::from(trigger));
+    let mut options: HashMap<&str, Value> = HashMap::new();
+    options.insert("handle_token", Value::from(bind_token.as_str()));
+    connection
+        .call_method(
+            Some(PORTAL_BUS_NAME),
+            PORTAL_PATH,
+            Some(PORTAL_INTERFACE),
+            "BindShortcuts",
+            &(session_path, vec![(SHORTCUT_ID, shortcut)], "", options),
+        )
+        .map_err(|e| SimpleError::with("could not bind the shortcut", e))?;
+    wait_for_response(&connection, &request_path(&unique_name, &bind_token))?;
+    info!("bound {} through the global shortcuts portal", trigger);
+
+    loop {
+        let message = match connection.receive_message() {
+            Ok(message) => message,
+            Err(e) => {
+                error!("global shortcuts portal connection closed: {}", e);
+                return Ok(());
+            }
+        };
+        let is_activated = message
+            .header()
+            .ok()
+            .and_then(|header| header.member().ok().flatten().map(|m| m.to_string()))
+            .as_deref()
+            == Some("Activated");
+        if !is_activated {
+            continue;
+        }
+
+        if let Ok((_, shortcut_id, _, _)) =
+            message.body::<(OwnedObjectPath, String, u64, HashMap<String, OwnedValue>)>()
+        {
+            if shortcut_id == SHORTCUT_ID && sender.send(()).is_err() {
+                return Ok(());
+            }
+        }
+    }
+}
+
+// grabs the key on the root window and sends each time it's pressed, returns only if the key
+// couldn't be grabbed
+fn listen_x11(keysym: u32, modifiers: u16, sender: &glib::Sender<()>) -> Result<(), SimpleError> {
+    let (connection, screen) = x11rb::connect(None)
+        .map_err(|e| SimpleError::with("could not connect to the X server", e))?;
+    let setup = connection.setup();
+    let root = setup.roots[screen].root;
+    let (min_keycode, max_keycode) = (setup.min_keycode, setup.max_keycode);
+
+    let mapping = connection
+        .get_keyboard_mapping(min_keycode, max_keycode - min_keycode + 1)
+        .map_err(|e| SimpleError::with("could not read the keyboard mapping", e))?
+        .reply()
+        .map_err(|e| SimpleError::with("could not read the keyboard mapping", e))?;
+    let per_keycode = usize::from(mapping.keysyms_per_keycode).max(1);
+    let keycode = match mapping
+        .keysyms
+        .chunks(per_keycode)
+        .position(|keysyms| keysyms.contains(&keysym))
+    {
+        Some(index) => min_keycode + index as u8,
+        None => bail!("no key produces {}", keysym),
+    };
+
+    // the shortcut should work whether or not caps lock and num lock are on
+    for extra in &[0, X_LOCK, X_MOD2, X_LOCK | X_MOD2] {
+        connection
+            .grab_key(
+                true,
+                root,
+                modifiers | extra,
+                keycode,
+                GrabMode::Async,
+                GrabMode::Async,
+            )
+            .map_err(|e| SimpleError::with("could not grab the key", e))?
+            .check()
+            .map_err(|e| {
+                SimpleError::with("could not grab the key, another application may have", e)
+            })?;
+    }
+    connection
+        .flush()
+        .map_err(|e| SimpleError::with("could not grab the key", e))?;
+
+    loop {
+        match connection.wait_for_event() {
+            Ok(Event::KeyPress(_)) => {
+                if sender.send(()).is_err() {
+                    return Ok(());
+                }
+            }
+            Ok(_) => {}
+            Err(e) => {
+                error!("X server connection closed: {}", e);
+                return Ok(());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shortcut() {
+        let modifiers = ModifierType::SUPER_MASK | ModifierType::SHIFT_MASK;
+        assert_eq!("SHIFT+LOGO+b", portal_trigger(modifiers, "b"));
+        assert_eq!("Return", portal_trigger(ModifierType::empty(), "Return"));
+        assert_eq!(X_SHIFT | X_MOD4, x_modifiers(modifiers));
+
+        assert_eq!(
+            "/org/freedesktop/portal/desktop/request/1_42/boop1",
+            request_path(":1.42", "boop1")
+        );
+    }
+}
@@ -0,0 +1,491 @@
+// imports scripts written for Boop on macOS, from its scripts folder or a zip of it, scripts are
+// renamed if one with the same name is already loaded, and ones using APIs Boop-GTK doesn't have
+// are imported but reported
+
+use crate::{installer, native, script::Metadata, Scripts};
+use simple_error::{bail, SimpleError};
+use std::{
+    collections::HashSet,
+    fmt,
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+// scripts are small, anything larger is most likely not a script
+const MAX_SCRIPT_SIZE: u64 = 1024 * 1024;
+
+// globals JavaScriptCore or browsers have that scripts don't, and what's used instead
+const UNSUPPORTED_GLOBALS: &[(&str, &str)] = &[
+    (
+        "setTimeout",
+        "scripts run synchronously or return a promise",
+    ),
+    (
+        "setInterval",
+        "scripts run synchronously or return a promise",
+    ),
+    ("XMLHttpRequest", "use fetch with \"network\": true"),
+    ("atob", "use require(\"@boop/base64\")"),
+    ("btoa", "use require(\"@boop/base64\")"),
+];
+
+#[derive(Debug)]
+pub struct ImportedScript {
+    pub name: String,
+    // the script's own name, if it was renamed as another script already has it
+    pub renamed_from: Option<String>,
+    pub path: PathBuf,
+    // why the script might not work, e.g. "uses setTimeout, scripts run synchronously or ..."
+    pub unsupported: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct SkippedScript {
+    pub file: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub imported: Vec<ImportedScript>,
+    pub skipped: Vec<SkippedScript>,
+}
+
+impl fmt::Display for ImportReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for script in &self.imported {
+            let file = script
+                .path
+                .file_name()
+                .map(|name| name.to_string_lossy())
+                .unwrap_or_default();
+            match &script.renamed_from {
+                Some(name) => writeln!(f, "Imported {} as {} to {}", name, script.name, file)?,
+                None => writeln!(f, "Imported {} to {}", script.name, file)?,
+            }
+            for reason in &script.unsupported {
+                writeln!(f, "  {}", reason)?;
+            }
+        }
+        for script in &self.skipped {
+            writeln!(f, "Skipped {}: {}", script.file, script.reason)?;
+        }
+
+        write!(
+            f,
+            "{} scripts imported, {} skipped",
+            self.imported.len(),
+            self.skipped.len()
+        )
+    }
+}
+
+// imports the scripts in each of `paths`, which can be folders, zips or scripts, into
+// `scripts_dir`, `names` are the names of the scripts already loaded
+pub fn import(
+    paths: &[PathBuf],
+    scripts_dir: &Path,
+    names: &HashSet<String>,
+) -> Result<ImportReport, SimpleError> {
+    let mut files = Vec::new();
+    for path in paths {
+        files.extend(read_scripts(path)?);
+    }
+
+    fs::create_dir_all(scripts_dir).map_err(|e| {
+        SimpleError::with(&format!("could not create {}", scripts_dir.display()), e)
+    })?;
+
+    // scripts can require other files that are imported alongside them
+    let file_names: HashSet<&str> = files.iter().map(|(file, _)| file.as_str()).collect();
+    let mut installed = installed_sources(scripts_dir);
+    let mut names = names.clone();
+    let mut report = ImportReport::default();
+
+    for (file, source) in &files {
+        let metadata = match installer::check(source) {
+            Ok((metadata, _)) => metadata,
+            Err(e) => {
+                report.skipped.push(SkippedScript {
+                    file: file.clone(),
+                    reason: e.to_string(),
+                });
+                continue;
+            }
+        };
+
+        // importing the same folder again doesn't make copies, even of scripts that were renamed
+        let already_imported = installed.contains(source)
+            || names
+                .iter()
+                .filter(|name| name.starts_with(&format!("{} (Boop", metadata.name)))
+                .filter_map(|name| rename(source, &metadata.name, name))
+                .any(|renamed| installed.contains(&renamed));
+        if already_imported {
+            report.skipped.push(SkippedScript {
+                file: file.clone(),
+                reason: "already imported".to_string(),
+            });
+            continue;
+        }
+
+        let (name, source) = if names.contains(&metadata.name) {
+            let name = unique_name(&metadata.name, &names);
+            match rename(source, &metadata.name, &name) {
+                Some(source) => (name, source),
+                None => {
+                    report.skipped.push(SkippedScript {
+                        file: file.clone(),
+                        reason: format!("{} is already a script and could not be renamed", name),
+                    });
+                    continue;
+                }
+            }
+        } else {
+            (metadata.name.clone(), source.clone())
+        };
+
+        let path = unique_path(scripts_dir, &name);
+        fs::write(&path, &source)
+            .map_err(|e| SimpleError::with(&format!("could not write {}", path.display()), e))?;
+        info!("imported {} at {}", name, path.display());
+
+        let unsupported = unsupported_apis(&source, &file_names, scripts_dir);
+        names.insert(name.clone());
+        installed.insert(source);
+        report.imported.push(ImportedScript {
+            renamed_from: Some(metadata.name).filter(|original| *original != name),
+            name,
+            path,
+            unsupported,
+        });
+    }
+
+    Ok(report)
+}
+
+fn installed_sources(scripts_dir: &Path) -> HashSet<String> {
+    fs::read_dir(scripts_dir)
+        .map(|entries| {
+            entries
+                .filter_map(Result::ok)
+                .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// the file name and source of each script in a folder, zip or script
+fn read_scripts(path: &Path) -> Result<Vec<(String, String)>, SimpleError> {
+    let is_zip = path
+        .extension()
+        .map_or(false, |extension| extension.eq_ignore_ascii_case("zip"));
+
+    if path.is_dir() {
+        let entries = fs::read_dir(path)
+            .map_err(|e| SimpleError::with(&format!("could not read {}", path.display()), e))?;
+        let mut paths: Vec<PathBuf> = entries
+            .filter_map(Result::ok)
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file() && is_script(&path.to_string_lossy()))
+            .collect();
+        paths.sort();
+
+        paths.iter().map(|path| read_script(path)).collect()
+    } else if is_zip {
+        read_zip(path)
+    } else {
+        Ok(vec![read_script(path)?])
+    }
+}
+
+fn read_script(path: &Path) -> Result<(String, String), SimpleError> {
+    let file = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+    if fs::metadata(path).map_or(false, |metadata| metadata.len() > MAX_SCRIPT_SIZE) {
+        bail!("{} is too large to be a script", file);
+    }
+
+    let source = fs::read_to_string(path)
+        .map_err(|e| SimpleError::with(&format!("could not read {}", path.display()), e))?;
+    Ok((file, source))
+}
+
+// scripts anywhere in the zip, so zips of the folder as well as its contents can be imported
+fn read_zip(path: &Path) -> Result<Vec<(String, String)>, SimpleError> {
+    let file = File::open(path)
+        .map_err(|e| SimpleError::with(&format!("could not open {}", path.display()), e))?;
+    let mut archive = zip::ZipArchive::new(file)
+        .map_err(|e| SimpleError::with(&format!("could not read {}", path.display()), e))?;
+
+    let mut scripts = Vec::new();
+    for index in 0..archive.len() {
+        let mut entry = archive
+            .by_index(index)
+            .map_err(|e| SimpleError::with(&format!("could not read {}", path.display()), e))?;
+        if entry.is_dir() || !is_script(entry.name()) || entry.size() > MAX_SCRIPT_SIZE {
+            continue;
+        }
+
+        // the size in the entry's header can be wrong, so this stops reading just past the limit
+        let name = entry.name().to_string();
+        let mut source = String::new();
+        entry
+            .by_ref()
+            .take(MAX_SCRIPT_SIZE + 1)
+            .read_to_string(&mut source)
+            .map_err(|e| SimpleError::with(&format!("could not read {}", name), e))?;
+        if source.len() as u64 > MAX_SCRIPT_SIZE {
+            continue;
+        }
+        let file = name.rsplit('/').next().unwrap_or_default().to_string();
+        scripts.push((file, source));
+    }
+
+    Ok(scripts)
+}
+
+// JavaScript files, but not the resource forks macOS adds to zips
+fn is_script(path: &str) -> bool {
+    let file = path
+        .rsplit(|c| c == '/' || c == '\\')
+        .next()
+        .unwrap_or(path);
+    path.ends_with(".js") && !file.starts_with("._") && !path.starts_with("__MACOSX/")
+}
+
+// e.g. "Format JSON (Boop)", or "Format JSON (Boop 2)" if that's taken too
+fn unique_name(name: &str, names: &HashSet<String>) -> String {
+    let mut unique = format!("{} (Boop)", name);
+    let mut count = 1;
+    while names.contains(&unique) {
+        count += 1;
+        unique = format!("{} (Boop {})", name, count);
+    }
+
+    unique
+}
+
+// names with the same letters share a file name, so numbers are added to keep both
+fn unique_path(scripts_dir: &Path, name: &str) -> PathBuf {
+    let file_name = installer::file_name(name);
+    let stem = file_name.trim_end_matches(".js");
+    let mut path = scripts_dir.join(&file_name);
+    let mut count = 1;
+    while path.exists() {
+        count += 1;
+        path = scripts_dir.join(format!("{}-{}.js", stem, count));
+    }
+
+    path
+}
+
+// changes the name in the script's metadata, None if it couldn't be found
+fn rename(source: &str, name: &str, new_name: &str) -> Option<String> {
+    let header_end = source.find("**/")?;
+    let key = source[..header_end].find("\"name\"")?;
+    let quoted = serde_jsonrc::to_string(name).ok()?;
+    let start = key + source[key..header_end].find(&quoted)?;
+
+    let renamed = format!(
+        "{}{}{}",
+        &source[..start],
+        serde_jsonrc::to_string(new_name).ok()?,
+        &source[start + quoted.len()..]
+    );
+
+    // the header is checked again in case the name was found somewhere else
+    match Metadata::from_source(&renamed) {
+        Ok((metadata, _)) if metadata.name == new_name => Some(renamed),
+        _ => None,
+    }
+}
+
+// APIs the script uses that Boop-GTK doesn't have, and libraries it requires that aren't there
+fn unsupported_apis(source: &str, file_names: &HashSet<&str>, scripts_dir: &Path) -> Vec<String> {
+    let mut unsupported: Vec<String> = UNSUPPORTED_GLOBALS
+        .iter()
+        .filter(|(global, _)| uses_global(source, global))
+        .map(|(global, instead)| format!("uses {}, {}", global, instead))
+        .collect();
+
+    for path in required_paths(source) {
+        let available = if path == native::MODULE_NAME || path.contains("://") {
+            true
+        } else {
+            let file = if path.ends_with(".js") {
+                path.clone()
+            } else {
+                format!("{}.js", path)
+            };
+            match file.strip_prefix("@boop/") {
+                Some(library) => Scripts::get(&format!("lib/{}", library)).is_some(),
+                None => file_names.contains(file.as_str()) || scripts_dir.join(&file).is_file(),
+            }
+        };
+
+        if !available {
+            unsupported.push(format!("requires {}, which isn't available", path));
+        }
+    }
+
+    unsupported
+}
+
+// whether `global` is used, but not defined, by the script
+fn uses_global(source: &str, global: &str) -> bool {
+    let is_identifier = |c: char| c.is_alphanumeric() || c == '_' || c == '$';
+
+    if source.contains(&format!("function {}", global))
+        || source.contains(&format!("var {}", global))
+        || source.contains(&format!("let {}", global))
+        || source.contains(&format!("const {}", global))
+    {
+        return false;
+    }
+
+    source.match_indices(global).any(|(index, _)| {
+        let before = source[..index].chars().next_back();
+        let after = source[index + global.len()..].chars().next();
+        !before.map_or(false, |c| is_identifier(c) || c == '.')
+            && !after.map_or(false, is_identifier)
+    })
+}
+
+// the paths passed to `require` as string literals
+fn required_paths(source: &str) -> Vec<String> {
+    source
+        .match_indices("require(")
+        .filter_map(|(index, call)| {
+            let argument = source[index + call.len()..].trim_start();
+            let quote = argument
+                .chars()
+                .next()
+                .filter(|c| *c == '"' || *c == '\'')?;
+            let argument = &argument[1..];
+            Some(argument[..argument.find(quote)?].to_string())
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn script(name: &str, body: &str) -> String {
+        format!(
+            "/**\n    {{\n        \"api\": 1,\n        \"name\": \"{}\",\n        \"icon\": \"html\"\n    }}\n**/\n\nfunction main(state) {{ {} }}\n",
+            name, body
+        )
+    }
+
+    #[test]
+    fn test_import() {
+        let dir = std::env::temp_dir().join(format!("boop-gtk-importer-{}", std::process::id()));
+        let boop_dir = dir.join("Boop");
+        let scripts_dir = dir.join("scripts");
+        fs::create_dir_all(&boop_dir).unwrap();
+        fs::write(boop_dir.join("shout.js"), script("Shout", "")).unwrap();
+        fs::write(
+            boop_dir.join("later.js"),
+            script(
+                "Later",
+                "setTimeout(() => {}, 10); require('@boop/missing')",
+            ),
+        )
+        .unwrap();
+        fs::write(boop_dir.join("notes.js"), "// not a script").unwrap();
+        fs::write(boop_dir.join("._shout.js"), "").unwrap();
+
+        let names: HashSet<String> = vec!["Shout".to_string()].into_iter().collect();
+        let report = import(&[boop_dir.clone()], &scripts_dir, &names).unwrap();
+
+        let imported: Vec<(&str, Option<&str>)> = report
+            .imported
+            .iter()
+            .map(|script| (script.name.as_str(), script.renamed_from.as_deref()))
+            .collect();
+        assert_eq!(
+            vec![("Later", None), ("Shout (Boop)", Some("Shout"))],
+            imported
+        );
+        assert_eq!(
+            vec![
+                "uses setTimeout, scripts run synchronously or return a promise",
+                "requires @boop/missing, which isn't available"
+            ],
+            report.imported[0].unsupported
+        );
+        assert!(report.imported[1].unsupported.is_empty());
+        assert_eq!(1, report.skipped.len());
+        assert_eq!("notes.js", report.skipped[0].file);
+
+        let (metadata, _) =
+            Metadata::from_source(&fs::read_to_string(&report.imported[1].path).unwrap()).unwrap();
+        assert_eq!("Shout (Boop)", metadata.name);
+
+        // already imported scripts aren't copied again
+        let names: HashSet<String> = vec!["Shout", "Shout (Boop)", "Later"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let report = import(&[boop_dir.clone()], &scripts_dir, &names).unwrap();
+        assert!(report.imported.is_empty());
+        assert_eq!(3, report.skipped.len());
+        assert_eq!("already imported", report.skipped[0].reason);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_zip_forged_size() {
+        use std::io::Write;
+
+        let path =
+            std::env::temp_dir().join(format!("boop-gtk-importer-{}.zip", std::process::id()));
+        let mut zip = zip::ZipWriter::new(File::create(&path).unwrap());
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        zip.start_file("small.js", options).unwrap();
+        zip.write_all(b"small").unwrap();
+        zip.start_file("large.js", options).unwrap();
+        zip.write_all(&vec![b'a'; MAX_SCRIPT_SIZE as usize + 1])
+            .unwrap();
+        zip.finish().unwrap();
+
+        // claim large.js is 5 bytes, in its local header and in the central directory
+        let mut bytes = fs::read(&path).unwrap();
+        let headers = [(b"PK\x03\x04", 22, 30), (b"PK\x01\x02", 24, 46)];
+        for (signature, size_offset, name_offset) in headers.iter() {
+            let start = (0..bytes.len())
+                .find(|&start| {
+                    bytes[start..].starts_with(&signature[..])
+                        && bytes[start + name_offset..].starts_with(b"large.js")
+                })
+                .unwrap();
+            bytes[start + size_offset..start + size_offset + 4]
+                .copy_from_slice(&5u32.to_le_bytes());
+        }
+        fs::write(&path, bytes).unwrap();
+
+        let scripts = read_zip(&path).unwrap();
+        assert_eq!(vec![("small.js".to_string(), "small".to_string())], scripts);
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_uses_global() {
+        assert!(uses_global("setTimeout(f, 0)", "setTimeout"));
+        assert!(!uses_global("Base64.atob(text)", "atob"));
+        assert!(!uses_global("function atob(text) {}\natob(x)", "atob"));
+        assert!(!uses_global("let mySetTimeout = 1", "setTimeout"));
+        assert_eq!(
+            vec!["@boop/base64", "./other"],
+            required_paths("require(\"@boop/base64\"); require( './other')")
+        );
+    }
+}
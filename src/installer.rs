@@ -0,0 +1,173 @@
+// downloads scripts from a url or gist so they can be installed without copying them into the
+// config directory by hand
+
+use crate::script::Metadata;
+use simple_error::{bail, SimpleError};
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+const DOWNLOAD_TIMEOUT_MS: u64 = 10000;
+// scripts are small, anything larger is most likely not a script
+const MAX_SCRIPT_SIZE: usize = 1024 * 1024;
+
+// links to gists and files on github are to web pages, the raw file is downloaded instead
+pub fn raw_url(url: &str) -> String {
+    let url = url.trim();
+
+    if let Some(path) = url.strip_prefix("https://gist.github.com/") {
+        let path = path.trim_end_matches('/');
+        // raw links to gists without a user redirect to the right place
+        return format!("https://gist.githubusercontent.com/{}/raw", path);
+    }
+
+    if let Some(path) = url.strip_prefix("https://github.com/") {
+        let parts: Vec<&str> = path.splitn(4, '/').collect();
+        if let [user, repo, "blob", file] = parts.as_slice() {
+            return format!(
+                "https://raw.githubusercontent.com/{}/{}/{}",
+                user, repo, file
+            );
+        }
+    }
+
+    url.to_string()
+}
+
+pub fn download(url: &str) -> Result<String, SimpleError> {
+    let url = raw_url(url);
+    if !url.starts_with("https://") {
+        bail!("only https urls can be installed from");
+    }
+
+    info!("downloading script from {}", url);
+
+    let response = ureq::get(&url)
+        .timeout_connect(DOWNLOAD_TIMEOUT_MS)
+        .timeout_read(DOWNLOAD_TIMEOUT_MS)
+        .call();
+
+    let source = match response.synthetic_error() {
+        Some(e) => bail!("could not download {}: {}", url, e),
+        None if !response.ok() => bail!(
+            "could not download {}: {} {}",
+            url,
+            response.status(),
+            response.status_text()
+        ),
+        None => {
+            // one byte more than a script may have is enough to know it's too large
+            let mut source = String::new();
+            response
+                .into_reader()
+                .take(MAX_SCRIPT_SIZE as u64 + 1)
+                .read_to_string(&mut source)
+                .map_err(|e| SimpleError::with(&format!("could not download {}", url), e))?;
+            source
+        }
+    };
+
+    if source.len() > MAX_SCRIPT_SIZE {
+        bail!("{} is too large to be a script", url);
+    }
+
+    Ok(source)
+}
+
+// the script's metadata and warnings about it, shown before it's installed
+pub fn check(source: &str) -> Result<(Metadata, Vec<String>), SimpleError> {
+    Metadata::from_source(source).map_err(|e| SimpleError::new(format!("not a Boop script, {}", e)))
+}
+
+// e.g. "format-json.js" for "Format JSON"
+pub fn file_name(script_name: &str) -> String {
+    let name: String = script_name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '-' })
+        .collect();
+    let name: Vec<&str> = name.split('-').filter(|part| !part.is_empty()).collect();
+
+    if name.is_empty() {
+        "script.js".to_string()
+    } else {
+        format!("{}.js", name.join("-"))
+    }
+}
+
+// where a script will be installed
+pub fn install_path(scripts_dir: &Path, metadata: &Metadata) -> PathBuf {
+    scripts_dir.join(file_name(&metadata.name))
+}
+
+// written to the scripts directory, where it's loaded by the watcher, a script already installed
+// at the same path is only replaced if `replace` is set, once the user has agreed to it
+pub fn install(scripts_dir: &Path, source: &str, replace: bool) -> Result<PathBuf, SimpleError> {
+    let (metadata, _) = check(source)?;
+    let path = install_path(scripts_dir, &metadata);
+    if !replace && path.exists() {
+        bail!(
+            "{} is already installed at {}",
+            metadata.name,
+            path.display()
+        );
+    }
+
+    fs::create_dir_all(scripts_dir).map_err(|e| {
+        SimpleError::with(&format!("could not create {}", scripts_dir.display()), e)
+    })?;
+    fs::write(&path, source)
+        .map_err(|e| SimpleError::with(&format!("could not write {}", path.display()), e))?;
+
+    info!("installed {} at {}", metadata.name, path.display());
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_raw_url() {
+        assert_eq!(
+            "https://gist.githubusercontent.com/user/abc123/raw",
+            raw_url(" https://gist.github.com/user/abc123/ ")
+        );
+        assert_eq!(
+            "https://raw.githubusercontent.com/user/repo/main/scripts/a.js",
+            raw_url("https://github.com/user/repo/blob/main/scripts/a.js")
+        );
+        assert_eq!(
+            "https://example.com/a.js",
+            raw_url("https://example.com/a.js")
+        );
+    }
+
+    #[test]
+    fn test_install() {
+        assert_eq!("format-json.js", file_name("Format JSON"));
+        assert_eq!("a-b.js", file_name(" A / B "));
+        assert_eq!("script.js", file_name("!!"));
+
+        assert!(check("<html>not found</html>").is_err());
+
+        let scripts_dir =
+            std::env::temp_dir().join(format!("boop-gtk-installer-{}", std::process::id()));
+        let source = "/** { \"api\": 1, \"name\": \"Format JSON\", \"icon\": \"html\" } **/
+            function main(state) {}";
+        let path = install(&scripts_dir, source, false).unwrap();
+        assert_eq!(scripts_dir.join("format-json.js"), path);
+        assert_eq!(source, fs::read_to_string(&path).unwrap());
+
+        // installed scripts are only replaced when asked to
+        let newer = source.replace("{}", "{ state.text = state.text.trim(); }");
+        assert!(install(&scripts_dir, &newer, false).is_err());
+        assert_eq!(source, fs::read_to_string(&path).unwrap());
+        assert_eq!(path, install(&scripts_dir, &newer, true).unwrap());
+        assert_eq!(newer, fs::read_to_string(&path).unwrap());
+
+        fs::remove_dir_all(&scripts_dir).unwrap();
+    }
+}
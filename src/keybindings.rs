@@ -0,0 +1,632 @@
+// Vim and Emacs keys for the editor, picked in the preferences, keys are handled before the
+// window's shortcuts while the editor has focus and anything they don't use is left to GTK
+//
+// Vim has normal, insert and visual mode, counts and the common motions, Emacs has the movement,
+// mark and kill keys, both keep what they delete or copy in a register of their own
+
+use crate::{
+    config::InputMode,
+    vim::{self, Command, Mode, Motion, Operator, Parser},
+};
+use gdk::keys;
+use gtk::prelude::*;
+use sourceview::prelude::*;
+use std::{
+    cell::{Cell, RefCell},
+    rc::Rc,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Emacs {
+    Move(Motion),
+    // M-f, to the end of the word
+    ForwardWord,
+    // M-< and M->
+    DocumentStart,
+    DocumentEnd,
+    // C-space and C-g
+    SetMark,
+    Cancel,
+    // C-d, M-d and C-k
+    DeleteChar,
+    KillWord,
+    KillLine,
+    // C-w, M-w and C-y
+    KillRegion,
+    CopyRegion,
+    Yank,
+    Undo,
+}
+
+// the command typed with `c` and Control or Alt
+pub fn emacs_command(c: char, control: bool, alt: bool) -> Option<Emacs> {
+    let command = match (control, alt, c) {
+        (true, false, 'f') => Emacs::Move(Motion::Right),
+        (true, false, 'b') => Emacs::Move(Motion::Left),
+        (true, false, 'n') => Emacs::Move(Motion::Down),
+        (true, false, 'p') => Emacs::Move(Motion::Up),
+        (true, false, 'a') => Emacs::Move(Motion::LineStart),
+        (true, false, 'e') => Emacs::Move(Motion::LineEnd),
+        (false, true, 'f') => Emacs::ForwardWord,
+        (false, true, 'b') => Emacs::Move(Motion::WordBackward),
+        (false, true, '<') => Emacs::DocumentStart,
+        (false, true, '>') => Emacs::DocumentEnd,
+        (true, false, ' ') => Emacs::SetMark,
+        (true, false, 'g') => Emacs::Cancel,
+        (true, false, 'd') => Emacs::DeleteChar,
+        (false, true, 'd') => Emacs::KillWord,
+        (true, false, 'k') => Emacs::KillLine,
+        (true, false, 'w') => Emacs::KillRegion,
+        (false, true, 'w') => Emacs::CopyRegion,
+        (true, false, 'y') => Emacs::Yank,
+        (true, false, '/') | (true, false, '_') => Emacs::Undo,
+        _ => return None,
+    };
+    Some(command)
+}
+
+#[derive(Clone)]
+pub struct Keybindings {
+    view: sourceview::View,
+    // the Vim mode and keys typed so far, in the status bar
+    mode_label: gtk::Label,
+    input_mode: Rc<Cell<InputMode>>,
+    mode: Rc<Cell<Mode>>,
+    parser: Rc<RefCell<Parser>>,
+    // where visual mode started, and its cursor, the selection shown includes the character
+    // under the cursor
+    visual: Rc<Cell<(usize, usize)>>,
+    // the text last deleted or copied, and whether it's whole lines
+    register: Rc<RefCell<(String, bool)>>,
+    // the Emacs mark is set, so moving selects
+    mark_active: Rc<Cell<bool>>,
+}
+
+impl Keybindings {
+    pub fn new(view: &sourceview::View) -> Self {
+        let keybindings = Keybindings {
+            view: view.clone(),
+            mode_label: gtk::Label::new(None),
+            input_mode: Rc::new(Cell::new(InputMode::Default)),
+            mode: Rc::new(Cell::new(Mode::Insert)),
+            parser: Rc::new(RefCell::new(Parser::default())),
+            visual: Rc::new(Cell::new((0, 0))),
+            register: Rc::new(RefCell::new((String::new(), false))),
+            mark_active: Rc::new(Cell::new(false)),
+        };
+
+        // a click ends visual mode, it moves the cursor away from the selection
+        {
+            let keybindings_ = keybindings.clone();
+            view.connect_button_press_event(move |_, _| {
+                if keybindings_.is_visual() {
+                    keybindings_.set_mode(Mode::Normal);
+                }
+                gtk::Inhibit(false)
+            });
+        }
+
+        keybindings
+    }
+
+    pub fn widget(&self) -> &gtk::Label {
+        &self.mode_label
+    }
+
+    pub fn set_input_mode(&self, input_mode: InputMode) {
+        if input_mode == self.input_mode.get() {
+            return;
+        }
+        self.input_mode.set(input_mode);
+        self.mark_active.set(false);
+        self.set_mode(if input_mode == InputMode::Vim {
+            Mode::Normal
+        } else {
+            Mode::Insert
+        });
+    }
+
+    // handles a key pressed in the window, true if it was used
+    pub fn key_pressed(&self, event: &gdk::EventKey) -> bool {
+        if !self.view.has_focus() {
+            return false;
+        }
+
+        match self.input_mode.get() {
+            InputMode::Default => false,
+            InputMode::Vim => self.vim_key_pressed(event),
+            InputMode::Emacs => self.emacs_key_pressed(event),
+        }
+    }
+
+    fn buffer(&self) -> sourceview::Buffer {
+        self.view
+            .get_buffer()
+            .expect("failed to get buffer")
+            .downcast::<sourceview::Buffer>()
+            .expect("faild to downcast TextBuffer to sourceview Buffer")
+    }
+
+    fn chars(buffer: &sourceview::Buffer) -> Vec<char> {
+        buffer
+            .get_text(&buffer.get_start_iter(), &buffer.get_end_iter(), false)
+            .expect("failed to get buffer text")
+            .chars()
+            .collect()
+    }
+
+    fn is_visual(&self) -> bool {
+        matches!(self.mode.get(), Mode::Visual | Mode::VisualLine)
+    }
+
+    fn cursor(&self, buffer: &sourceview::Buffer) -> usize {
+        if self.is_visual() {
+            self.visual.get().1
+        } else {
+            buffer.get_property_cursor_position() as usize
+        }
+    }
+
+    fn set_mode(&self, mode: Mode) {
+        let buffer = self.buffer();
+        if self.is_visual() && !matches!(mode, Mode::Visual | Mode::VisualLine) {
+            let cursor = buffer.get_iter_at_offset(self.visual.get().1 as i32);
+            buffer.place_cursor(&cursor);
+        }
+        self.mode.set(mode);
+        self.parser.borrow_mut().clear();
+
+        // normal and visual mode show a block cursor
+        let vim = self.input_mode.get() == InputMode::Vim;
+        self.view.set_overwrite(vim && mode != Mode::Insert);
+        self.update_label();
+    }
+
+    fn update_label(&self) {
+        let text = match (self.input_mode.get(), self.mode.get()) {
+            (InputMode::Vim, Mode::Normal) => self.parser.borrow().pending().to_string(),
+            (InputMode::Vim, Mode::Insert) => "-- INSERT --".to_string(),
+            (InputMode::Vim, Mode::Visual) => "-- VISUAL --".to_string(),
+            (InputMode::Vim, Mode::VisualLine) => "-- VISUAL LINE --".to_string(),
+            (InputMode::Emacs, _) if self.mark_active.get() => "Mark set".to_string(),
+            _ => String::new(),
+        };
+        self.mode_label.set_text(&text);
+    }
+
+    fn vim_key_pressed(&self, event: &gdk::EventKey) -> bool {
+        let key = event.get_keyval();
+        let state = event.get_state();
+
+        if self.mode.get() == Mode::Insert {
+            if key != keys::constants::Escape {
+                return false;
+            }
+            // like Vim, the cursor goes back onto the last character typed
+            let buffer = self.buffer();
+            let chars = Self::chars(&buffer);
+            let cursor = buffer.get_property_cursor_position() as usize;
+            self.set_mode(Mode::Normal);
+            self.move_to(&buffer, vim::target(&chars, cursor, Motion::Left, 1));
+            return true;
+        }
+
+        // Escape in normal mode is left to the rest of the window, e.g. to remove extra cursors
+        if key == keys::constants::Escape {
+            if self.mode.get() == Mode::Normal && self.parser.borrow().pending().is_empty() {
+                return false;
+            }
+            self.set_mode(Mode::Normal);
+            return true;
+        }
+        if state.contains(gdk::ModifierType::CONTROL_MASK) {
+            if key == keys::constants::r {
+                self.vim_command(Command::Redo(1));
+                return true;
+            }
+            return false;
+        }
+        if state.contains(gdk::ModifierType::MOD1_MASK) {
+            return false;
+        }
+
+        let c = match key.to_unicode() {
+            Some(c) if !c.is_control() => c,
+            // keys that would change the text aren't typed outside of insert mode
+            _ => {
+                return [
+                    keys::constants::BackSpace,
+                    keys::constants::Delete,
+                    keys::constants::Return,
+                    keys::constants::KP_Enter,
+                    keys::constants::Tab,
+                ]
+                .contains(&key)
+            }
+        };
+
+        let command = self.parser.borrow_mut().feed(c, self.mode.get());
+        match command {
+            Some(command) => self.vim_command(command),
+            None => self.update_label(),
+        }
+        true
+    }
+
+    fn vim_command(&self, command: Command) {
+        let buffer = self.buffer();
+        let chars = Self::chars(&buffer);
+        let cursor = self.cursor(&buffer);
+
+        match command {
+            Command::Move(motion, count) => {
+                self.move_to(&buffer, vim::target(&chars, cursor, motion, count))
+            }
+            Command::Operate(operator, motion, count) => {
+                // cw changes to the end of the word, leaving the space after it
+                let motion = if operator == Operator::Change && motion == Motion::WordForward {
+                    Motion::WordEnd
+                } else {
+                    motion
+                };
+                let mut target = vim::target(&chars, cursor, motion, count);
+                if motion.is_inclusive() {
+                    target = (target + 1).min(chars.len());
+                }
+                self.operate(
+                    &buffer,
+                    &chars,
+                    operator,
+                    (cursor.min(target), cursor.max(target)),
+                    false,
+                );
+            }
+            Command::OperateLines(operator, count) => {
+                let range = vim::line_range(&chars, cursor, count);
+                self.operate(&buffer, &chars, operator, range, true);
+            }
+            Command::DeleteChars(count) => {
+                let end = (cursor + count).min(vim::line_end(&chars, cursor));
+                self.operate(&buffer, &chars, Operator::Delete, (cursor, end), false);
+            }
+            Command::Insert => self.set_mode(Mode::Insert),
+            Command::Append => {
+                self.move_to(&buffer, vim::target(&chars, cursor, Motion::Right, 1));
+                self.set_mode(Mode::Insert);
+            }
+            Command::InsertAtLineStart => {
+                self.move_to(
+                    &buffer,
+                    vim::target(&chars, cursor, Motion::FirstNonBlank, 1),
+                );
+                self.set_mode(Mode::Insert);
+            }
+            Command::AppendAtLineEnd => {
+                self.move_to(&buffer, vim::line_end(&chars, cursor));
+                self.set_mode(Mode::Insert);
+            }
+            Command::OpenBelow | Command::OpenAbove => {
+                let at = if command == Command::OpenBelow {
+                    vim::line_end(&chars, cursor)
+                } else {
+                    vim::line_start(&chars, cursor)
+                };
+                let mut iter = buffer.get_iter_at_offset(at as i32);
+                buffer.begin_user_action();
+                buffer.insert(&mut iter, "\n");
+                buffer.end_user_action();
+                let line = if command == Command::OpenBelow {
+                    at + 1
+                } else {
+                    at
+                };
+                self.set_mode(Mode::Insert);
+                self.move_to(&buffer, line);
+            }
+            Command::Paste { before, count } => self.paste(&buffer, &chars, before, count),
+            Command::Undo(count) => {
+                for _ in 0..count {
+                    if buffer.can_undo() {
+                        buffer.undo();
+                    }
+                }
+            }
+            Command::Redo(count) => {
+                for _ in 0..count {
+                    if buffer.can_redo() {
+                        buffer.redo();
+                    }
+                }
+            }
+            Command::Visual | Command::VisualLine => {
+                let mode = if command == Command::Visual {
+                    Mode::Visual
+                } else {
+                    Mode::VisualLine
+                };
+                if self.mode.get() == mode {
+                    self.set_mode(Mode::Normal);
+                } else {
+                    if !self.is_visual() {
+                        self.visual.set((cursor, cursor));
+                    }
+                    self.set_mode(mode);
+                    self.select(&buffer, &chars);
+                }
+            }
+            Command::OperateSelection(operator) => {
+                let range = self.visual_range(&chars);
+                let lines = self.mode.get() == Mode::VisualLine;
+                self.set_mode(Mode::Normal);
+                self.operate(&buffer, &chars, operator, range, lines);
+            }
+        }
+        self.update_label();
+    }
+
+    // the text visual mode selects
+    fn visual_range(&self, chars: &[char]) -> (usize, usize) {
+        let (anchor, cursor) = self.visual.get();
+        let (start, end) = (anchor.min(cursor), anchor.max(cursor));
+        if self.mode.get() == Mode::VisualLine {
+            (
+                vim::line_start(chars, start),
+                (vim::line_end(chars, end) + 1).min(chars.len()),
+            )
+        } else {
+            (start, (end + 1).min(chars.len()))
+        }
+    }
+
+    fn select(&self, buffer: &sourceview::Buffer, chars: &[char]) {
+        let (start, end) = self.visual_range(chars);
+        let start = buffer.get_iter_at_offset(start as i32);
+        let end = buffer.get_iter_at_offset(end as i32);
+        // the end the cursor is at scrolls into view
+        let (anchor, cursor) = self.visual.get();
+        if cursor < anchor {
+            buffer.select_range(&start, &end);
+        } else {
+            buffer.select_range(&end, &start);
+        }
+        if let Some(insert) = buffer.get_insert() {
+            self.view.scroll_mark_onscreen(&insert);
+        }
+    }
+
+    fn move_to(&self, buffer: &sourceview::Buffer, offset: usize) {
+        if self.is_visual() {
+            let (anchor, _) = self.visual.get();
+            self.visual.set((anchor, offset));
+            self.select(buffer, &Self::chars(buffer));
+            return;
+        }
+
+        let iter = buffer.get_iter_at_offset(offset as i32);
+        if self.mark_active.get() {
+            // the selection bound stays at the Emacs mark
+            if let Some(insert) = buffer.get_insert() {
+                buffer.move_mark(&insert, &iter);
+            }
+        } else {
+            buffer.place_cursor(&iter);
+        }
+        if let Some(insert) = buffer.get_insert() {
+            self.view.scroll_mark_onscreen(&insert);
+        }
+    }
+
+    // deletes, changes or copies the characters from `range.0` to `range.1`
+    fn operate(
+        &self,
+        buffer: &sourceview::Buffer,
+        chars: &[char],
+        operator: Operator,
+        (start, end): (usize, usize),
+        lines: bool,
+    ) {
+        let mut text: String = chars[start..end].iter().collect();
+        if lines && !text.ends_with('\n') {
+            text.push('\n');
+        }
+        *self.register.borrow_mut() = (text, lines);
+
+        let (mut start, mut end) = (start, end);
+        match operator {
+            Operator::Yank => {
+                self.move_to(buffer, start);
+                return;
+            }
+            // deleting the last lines deletes the line break before them
+            Operator::Delete if lines && end == chars.len() && start > 0 => start -= 1,
+            // changing lines keeps an empty line to type on
+            Operator::Change if lines && end > start && chars[end - 1] == '\n' => end -= 1,
+            _ => (),
+        }
+
+        let mut start_iter = buffer.get_iter_at_offset(start as i32);
+        let mut end_iter = buffer.get_iter_at_offset(end as i32);
+        buffer.begin_user_action();
+        buffer.delete(&mut start_iter, &mut end_iter);
+        buffer.end_user_action();
+
+        if operator == Operator::Change {
+            self.set_mode(Mode::Insert);
+            self.move_to(buffer, start);
+        } else if lines {
+            let chars = Self::chars(buffer);
+            let line = vim::line_start(&chars, (start + 1).min(chars.len()));
+            self.move_to(buffer, vim::target(&chars, line, Motion::FirstNonBlank, 1));
+        } else {
+            self.move_to(buffer, start);
+        }
+    }
+
+    // puts the register after or before the cursor, or the line it's on if it's whole lines
+    fn paste(&self, buffer: &sourceview::Buffer, chars: &[char], before: bool, count: usize) {
+        let (text, lines) = self.register.borrow().clone();
+        if text.is_empty() {
+            return;
+        }
+        let cursor = self.cursor(buffer);
+        let mut text = text.repeat(count);
+
+        let at = if !lines {
+            if before {
+                cursor
+            } else {
+                (cursor + 1).min(vim::line_end(chars, cursor))
+            }
+        } else if before {
+            vim::line_start(chars, cursor)
+        } else {
+            let end = vim::line_end(chars, cursor);
+            if end == chars.len() {
+                // the last line has no line break to paste after
+                text.pop();
+                text.insert(0, '\n');
+                end
+            } else {
+                end + 1
+            }
+        };
+
+        let mut iter = buffer.get_iter_at_offset(at as i32);
+        buffer.begin_user_action();
+        buffer.insert(&mut iter, &text);
+        buffer.end_user_action();
+
+        let pasted = text.chars().count();
+        if lines {
+            let line = if text.starts_with('\n') { at + 1 } else { at };
+            self.move_to(buffer, line);
+        } else {
+            self.move_to(buffer, at + pasted - 1);
+        }
+    }
+
+    fn emacs_key_pressed(&self, event: &gdk::EventKey) -> bool {
+        let state = event.get_state();
+        let command = event.get_keyval().to_unicode().and_then(|c| {
+            emacs_command(
+                c,
+                state.contains(gdk::ModifierType::CONTROL_MASK),
+                state.contains(gdk::ModifierType::MOD1_MASK),
+            )
+        });
+        let command = match command {
+            Some(command) => command,
+            None => return false,
+        };
+
+        let buffer = self.buffer();
+        let chars = Self::chars(&buffer);
+        let cursor = buffer.get_property_cursor_position() as usize;
+        let selection = buffer
+            .get_selection_bounds()
+            .map(|(start, end)| (start.get_offset() as usize, end.get_offset() as usize));
+
+        match command {
+            Emacs::Move(motion) => self.move_to(&buffer, vim::target(&chars, cursor, motion, 1)),
+            Emacs::ForwardWord => {
+                let end = vim::target(&chars, cursor, Motion::WordEnd, 1);
+                self.move_to(&buffer, (end + 1).min(chars.len()));
+            }
+            Emacs::DocumentStart => self.move_to(&buffer, 0),
+            Emacs::DocumentEnd => self.move_to(&buffer, chars.len()),
+            Emacs::SetMark => {
+                let iter = buffer.get_iter_at_offset(cursor as i32);
+                buffer.place_cursor(&iter);
+                self.mark_active.set(true);
+            }
+            Emacs::Cancel => {
+                let iter = buffer.get_iter_at_offset(cursor as i32);
+                self.mark_active.set(false);
+                buffer.place_cursor(&iter);
+            }
+            Emacs::DeleteChar => {
+                let end = (cursor + 1).min(chars.len());
+                self.kill(&buffer, &chars, (cursor, end), false);
+            }
+            Emacs::KillWord => {
+                let end = vim::target(&chars, cursor, Motion::WordEnd, 1);
+                self.kill(&buffer, &chars, (cursor, (end + 1).min(chars.len())), true);
+            }
+            Emacs::KillLine => {
+                // at the end of a line, the line break is killed
+                let end = match vim::line_end(&chars, cursor) {
+                    end if end == cursor => (end + 1).min(chars.len()),
+                    end => end,
+                };
+                self.kill(&buffer, &chars, (cursor, end), true);
+            }
+            Emacs::KillRegion | Emacs::CopyRegion => {
+                if let Some(range) = selection {
+                    if command == Emacs::KillRegion {
+                        self.kill(&buffer, &chars, range, true);
+                    } else {
+                        *self.register.borrow_mut() =
+                            (chars[range.0..range.1].iter().collect(), false);
+                    }
+                }
+                self.mark_active.set(false);
+                let iter = buffer.get_iter_at_offset(buffer.get_property_cursor_position());
+                buffer.place_cursor(&iter);
+            }
+            Emacs::Yank => {
+                let text = self.register.borrow().0.clone();
+                buffer.begin_user_action();
+                buffer.delete_selection(true, true);
+                buffer.insert_at_cursor(&text);
+                buffer.end_user_action();
+            }
+            Emacs::Undo => {
+                if buffer.can_undo() {
+                    buffer.undo();
+                }
+            }
+        }
+        self.update_label();
+        true
+    }
+
+    // deletes the characters from `range.0` to `range.1`, keeping them in the register unless
+    // it's a single character
+    fn kill(
+        &self,
+        buffer: &sourceview::Buffer,
+        chars: &[char],
+        (start, end): (usize, usize),
+        keep: bool,
+    ) {
+        if start == end {
+            return;
+        }
+        if keep {
+            *self.register.borrow_mut() = (chars[start..end].iter().collect(), false);
+        }
+        let mut start = buffer.get_iter_at_offset(start as i32);
+        let mut end = buffer.get_iter_at_offset(end as i32);
+        buffer.begin_user_action();
+        buffer.delete(&mut start, &mut end);
+        buffer.end_user_action();
+        self.mark_active.set(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_emacs_command() {
+        assert_eq!(
+            Some(Emacs::Move(Motion::LineEnd)),
+            emacs_command('e', true, false)
+        );
+        assert_eq!(Some(Emacs::KillWord), emacs_command('d', false, true));
+        assert_eq!(Some(Emacs::Undo), emacs_command('_', true, false));
+        assert_eq!(None, emacs_command('e', false, false));
+        assert_eq!(None, emacs_command('y', true, true));
+    }
+}
@@ -1,152 +1,74 @@
 #![forbid(unsafe_code)]
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")] // don't spawn command line on windows
 
-#[macro_use]
-extern crate lazy_static;
 #[macro_use]
 extern crate shrinkwraprs;
 #[macro_use]
 extern crate log;
 
-mod executor;
-mod script;
-use script::Script;
+// the script engine is in the boop-core crate, these let the rest of the app refer to it as
+// `crate::script` and so on
+use boop_core::{
+    catalog::{self, ScriptCatalog},
+    config, diff, executor, line_ending, native, permissions, pool, script, snapshot, testing,
+    Scripts, PROJECT_DIRS,
+};
+
 mod app;
+mod browser;
+mod carets;
+mod cli;
+mod color_scheme;
 mod command_pallete;
-
-use rusty_v8 as v8;
+mod completion;
+#[cfg(target_os = "linux")]
+mod dbus;
+mod editor;
+mod favorites;
+mod files;
+mod find;
+#[cfg(target_os = "linux")]
+mod hotkey;
+mod importer;
+mod installer;
+mod keybindings;
+mod manager;
+mod markdown;
+mod matcher;
+mod metrics;
+mod pipeline;
+mod preferences;
+mod preview;
+mod queue;
+mod recent;
+mod script_state;
+mod search;
+#[cfg(target_os = "linux")]
+mod search_provider;
+mod selection;
+mod shortcuts;
+mod stats;
+mod store;
+mod suggestions;
+mod sync;
+mod tabs;
+mod vim;
+mod watcher;
 
 use gio::prelude::*;
 use gtk::prelude::*;
 use gtk::Application;
 
-use rust_embed::RustEmbed;
-use std::{
-    borrow::Cow,
-    fmt,
-    path::{Path, PathBuf},
-};
-
-use sublime_fuzzy::ScoreConfig;
-
 use app::App;
-use directories::ProjectDirs;
-use fmt::Display;
+use command_pallete::RunMode;
+use line_ending::LineEnding;
 use std::{
-    error::Error,
     fs,
     io::prelude::*,
     sync::{Arc, RwLock},
     thread,
 };
 
-lazy_static! {
-    static ref PROJECT_DIRS: directories::ProjectDirs =
-        ProjectDirs::from("uk.co", "mrbenshef", "boop-gtk")
-            .expect("Unable to find a configuration location for your platform");
-}
-
-const SEARCH_CONFIG: ScoreConfig = ScoreConfig {
-    bonus_consecutive: 12,
-    bonus_word_start: 0,
-    bonus_coverage: 64,
-    penalty_distance: 4,
-};
-
-#[derive(RustEmbed)]
-#[folder = "submodules/Boop/Boop/Boop/scripts/"]
-struct Scripts;
-
-#[derive(Debug)]
-enum LoadScriptError {
-    FailedToCreateScriptDirectory,
-    FailedToReadScriptDirectory,
-}
-
-impl Display for LoadScriptError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            LoadScriptError::FailedToCreateScriptDirectory => {
-                write!(f, "Can't create scripts directory, check your permissions")
-            }
-            LoadScriptError::FailedToReadScriptDirectory => {
-                write!(f, "Can't read scripts directory, check your premissions")
-            }
-        }
-    }
-}
-
-#[derive(Debug)]
-enum ScriptError {
-    LoadError(LoadScriptError),
-}
-
-impl Display for ScriptError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            ScriptError::LoadError(e) => write!(f, "{}", e),
-        }
-    }
-}
-
-impl Error for ScriptError {}
-
-fn load_user_scripts(config_dir: &Path) -> Result<Vec<Script>, LoadScriptError> {
-    let scripts_dir: PathBuf = config_dir.join("scripts");
-
-    fs::create_dir_all(&scripts_dir).map_err(|_| LoadScriptError::FailedToCreateScriptDirectory)?;
-
-    let paths =
-        fs::read_dir(&scripts_dir).map_err(|_| LoadScriptError::FailedToReadScriptDirectory)?;
-
-    Ok(paths
-        .filter_map(Result::ok)
-        .map(|f| f.path())
-        .filter(|path| path.is_file())
-        .map(Script::from_file)
-        .filter_map(Result::ok)
-        .collect())
-}
-
-fn load_internal_scripts() -> Vec<Script> {
-    let mut scripts: Vec<Script> = Vec::with_capacity(Scripts::iter().count());
-
-    // scripts are internal, so we can unwrap "safely"
-    for file in Scripts::iter() {
-        let file: Cow<'_, str> = file;
-        let source: Cow<'static, [u8]> = Scripts::get(&file)
-            .unwrap_or_else(|| panic!("failed to get file: {}", file.to_string()));
-        let script_source = String::from_utf8(source.to_vec())
-            .unwrap_or_else(|e| panic!("{} is not UTF8: {}", file, e));
-        if let Ok(script) = Script::from_source(script_source, PathBuf::new()) {
-            scripts.push(script);
-        }
-    }
-
-    scripts
-}
-
-fn load_all_scripts(config_dir: &Path) -> (Vec<Script>, Option<ScriptError>) {
-    let mut scripts = load_internal_scripts();
-
-    let internal_script_count = scripts.len();
-
-    match load_user_scripts(&config_dir) {
-        Ok(mut user_scripts) => {
-            scripts.append(&mut user_scripts);
-        }
-        Err(e) => return (scripts, Some(ScriptError::LoadError(e))),
-    }
-
-    info!(
-        "found {} scripts ({} internal scripts)",
-        scripts.len(),
-        internal_script_count,
-    );
-
-    (scripts, None)
-}
-
 // extract language file, ideally we would use GResource for this but sourceview doesn't support that
 fn extract_language_file() {
     let config_dir = PROJECT_DIRS.config_dir().to_path_buf();
@@ -170,117 +92,176 @@ fn extract_language_file() {
     file.write_all(include_bytes!("../boop.lang"))
         .expect("Failed to write language file");
     info!("language file written at: {}", lang_file_path.display());
+
+    // light and dark style schemes, switched between as the desktop does
+    for (name, contents) in &[
+        ("boop-light.xml", &include_bytes!("../boop-light.xml")[..]),
+        ("boop-dark.xml", &include_bytes!("../boop-dark.xml")[..]),
+    ] {
+        let path = PROJECT_DIRS.config_dir().join(name);
+        fs::write(&path, contents).expect("Failed to write style scheme");
+        info!("style scheme written at: {}", path.display());
+    }
 }
 
-fn watch_scripts_folder(scripts: Arc<RwLock<Vec<Script>>>) {
-    use notify::{RecommendedWatcher, RecursiveMode, Result, Watcher};
-
-    trace!("watch_scripts_folder");
-
-    // watch for changes to script folder
-    let watcher: Result<RecommendedWatcher> = Watcher::new_immediate(move |res| {
-        debug!("res: {:?}", res);
-        match res {
-            Ok(event) => {
-                let event: notify::Event = event;
-
-                for file in event.paths {
-                    debug!("file: {}", file.display());
-
-                    match file.extension() {
-                        Some(s) => {
-                            if s == "js" {
-                            } else {
-                                break;
-                            }
-                        }
-                        None => break,
-                    }
+fn main() {
+    env_logger::init();
+
+    let command = match cli::parse(std::env::args().skip(1)) {
+        Ok(cli::Command::Help) => {
+            println!("{}", cli::USAGE);
+            return;
+        }
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
 
-                    info!("{} changed, reloading", file.display());
+    // the command pallete is opened searching for this once the window is shown
+    let select_script = match &command {
+        cli::Command::SelectScript(query) => Some(query.clone()),
+        _ => None,
+    };
 
-                    // remove scripts, if they where modified we create a new instance bellow
-                    let mut scripts = scripts.write().expect("script lock is poisoned");
-                    for i in 0..scripts.len() {
-                        if scripts[i].path == file {
-                            scripts.remove(i);
-                            break;
-                        }
-                    }
+    if command == cli::Command::Gui || select_script.is_some() {
+        debug!(
+            "found {} pixbuf loaders",
+            gdk_pixbuf::Pixbuf::get_formats().len()
+        );
 
-                    // .drain_filter(|script| script.path == file);
+        extract_language_file();
+    }
 
-                    if !file.exists() {
-                        break;
-                    }
+    boop_core::init();
+
+    snapshot::load(PROJECT_DIRS.data_dir());
+
+    let config_dir = PROJECT_DIRS.config_dir().to_path_buf();
+
+    let (catalog, script_error) = ScriptCatalog::load_from(&config_dir);
+    let mut scripts = catalog.into_scripts();
 
-                    match Script::from_file(file.clone()) {
-                        Ok(script) => {
-                            scripts.push(script);
-                            scripts.sort_by_key(|s| s.metadata.name.clone());
-                        }
-                        Err(e) => {
-                            error!("error parsing {}: {}", file.display(), e);
-                        }
+    match command {
+        // run the fixtures declared by scripts and exit without starting the UI
+        cli::Command::TestScripts => {
+            if let Some(error) = &script_error {
+                eprintln!("{}", error);
+            }
+
+            let report = testing::run_tests(
+                &mut scripts,
+                &config::Config::load(&config_dir),
+                &config_dir.join("scripts"),
+            );
+            println!("{}", report);
+            std::process::exit(if report.is_success() && script_error.is_none() {
+                0
+            } else {
+                1
+            });
+        }
+        // run a script on stdin and exit without starting the UI
+        cli::Command::RunScript(name) => {
+            let mut input = String::new();
+            if let Err(e) = std::io::stdin().read_to_string(&mut input) {
+                eprintln!("could not read stdin: {}", e);
+                std::process::exit(1);
+            }
+
+            let config = config::Config::load(&config_dir);
+            match cli::run_script(&mut scripts, &name, &input, &config, &config_dir) {
+                Ok(output) => {
+                    let mut stdout = std::io::stdout();
+                    if let Err(e) = stdout
+                        .write_all(output.as_bytes())
+                        .and_then(|_| stdout.flush())
+                    {
+                        eprintln!("could not write stdout: {}", e);
+                        std::process::exit(1);
                     }
+                    std::process::exit(0);
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
                 }
             }
-            Err(e) => error!("watch error: {:?}", e),
         }
-    });
+        // run a script on the clipboard and exit without starting the UI
+        cli::Command::TransformClipboard(name) => {
+            if let Err(e) = gtk::init() {
+                eprintln!("could not initialize GTK: {}", e);
+                std::process::exit(1);
+            }
 
-    // configure and start watcher
-    match watcher {
-        Ok(mut watcher) => {
-            let mut config_dir = PROJECT_DIRS.config_dir().to_path_buf();
-            config_dir.push("scripts");
+            let config = config::Config::load(&config_dir);
+            if let Err(e) = cli::transform_clipboard(&mut scripts, &name, &config, &config_dir) {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+            std::process::exit(0);
+        }
+        cli::Command::ListScripts(json) => {
+            if let Some(error) = &script_error {
+                eprintln!("{}", error);
+            }
 
-            info!("watching {}", config_dir.display());
+            println!("{}", cli::list_scripts(&scripts, json));
+            return;
+        }
+        // serve requests from other applications until killed, without starting the UI
+        cli::Command::DBusService => {
+            if let Some(error) = &script_error {
+                eprintln!("{}", error);
+            }
 
-            loop {
-                if let Err(watch_error) = watcher.watch(&config_dir, RecursiveMode::Recursive) {
-                    error!("watch start error: {}", watch_error);
-                    break;
+            #[cfg(target_os = "linux")]
+            {
+                let config = config::Config::load(&config_dir);
+                if let Err(e) = dbus::serve(scripts, config, config_dir) {
+                    eprintln!("{}", e);
                 }
             }
+            #[cfg(not(target_os = "linux"))]
+            eprintln!("--dbus-service is only supported on Linux");
+
+            std::process::exit(1);
         }
-        Err(watcher_error) => {
-            error!("couldn't create watcher: {}", watcher_error);
+        // copy scripts from Boop on macOS into the scripts directory
+        cli::Command::ImportScripts(path) => {
+            let names = scripts
+                .iter()
+                .map(|script| script.metadata.name.clone())
+                .collect();
+            match importer::import(&[path], &config_dir.join("scripts"), &names) {
+                Ok(report) => {
+                    println!("{}", report);
+                    std::process::exit(0);
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    std::process::exit(1);
+                }
+            }
         }
+        cli::Command::Gui | cli::Command::SelectScript(_) | cli::Command::Help => {}
     }
-}
-
-fn main() {
-    env_logger::init();
-
-    debug!(
-        "found {} pixbuf loaders",
-        gdk_pixbuf::Pixbuf::get_formats().len()
-    );
-
-    extract_language_file();
-
-    // initalize V8
-    let platform = v8::new_default_platform().unwrap();
-    v8::V8::initialize_platform(platform);
-    v8::V8::initialize();
-    info!("V8 initialized");
-
-    let config_dir = PROJECT_DIRS.config_dir().to_path_buf();
-
-    let (mut scripts, script_error) = load_all_scripts(&config_dir);
-
-    // sort alphabetically
-    scripts.sort_by_key(|s| s.metadata.name.clone());
 
     // watch scripts folder for changes
     let scripts = Arc::new(RwLock::new(scripts));
+    let (script_event_sender, script_event_receiver) =
+        glib::MainContext::channel(glib::PRIORITY_DEFAULT);
     {
         let scripts = scripts.clone();
+        let scripts_dir = config_dir.join("scripts");
         thread::spawn(move || {
-            watch_scripts_folder(scripts);
+            watcher::watch(scripts_dir, scripts, script_event_sender);
         });
     }
+    // the app is only activated once, but the receiver has to be moved into it
+    let script_event_receiver = std::cell::Cell::new(Some(script_event_receiver));
 
     // needed on windows
     sourceview::View::static_type();
@@ -288,6 +269,64 @@ fn main() {
     let application = Application::new(Some("uk.co.mrbenshef.Boop-GTK"), Default::default())
         .expect("failed to initialize GTK application");
 
+    // a window that's already open selects the script instead of opening another
+    if let Some(query) = &select_script {
+        match application.register(None::<&gio::Cancellable>) {
+            Ok(()) if application.get_is_remote() => {
+                application.activate_action("select_script", Some(&query.to_variant()));
+                if let Some(connection) = application.get_dbus_connection() {
+                    if let Err(e) = connection.flush_sync(None::<&gio::Cancellable>) {
+                        error!("could not select {} in the open window: {}", query, e);
+                    }
+                }
+                return;
+            }
+            Ok(()) => {}
+            Err(e) => error!("could not register application: {}", e),
+        }
+    }
+    let select_script = std::cell::Cell::new(select_script);
+
+    // isolates are ready before the first script is run
+    pool::warm_up(config::Config::load(&config_dir).script_heap_limit());
+
+    // a small window with the command pallete open can be summoned from any application
+    #[cfg(target_os = "linux")]
+    {
+        let config_dir = config_dir.clone();
+        let scripts = scripts.clone();
+        application.connect_startup(move |application| {
+            let accelerator = match config::Config::load(&config_dir).quick_pop_up_shortcut {
+                Some(accelerator) => accelerator,
+                None => return,
+            };
+            let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+            if let Err(e) = hotkey::bind(&accelerator, sender) {
+                error!("could not bind the quick pop-up shortcut: {}", e);
+                return;
+            }
+
+            // created the first time it's summoned, then hidden and shown again
+            let pop_up: std::cell::RefCell<Option<App>> = Default::default();
+            let application = application.clone();
+            let config_dir = config_dir.clone();
+            let scripts = scripts.clone();
+            receiver.attach(None, move |()| {
+                let pop_up = pop_up
+                    .borrow_mut()
+                    .get_or_insert_with(|| {
+                        let pop_up = App::new(&config_dir, scripts.clone());
+                        pop_up.set_application(Some(&application));
+                        pop_up.make_pop_up();
+                        pop_up
+                    })
+                    .clone();
+                pop_up.show_pop_up();
+                glib::Continue(true)
+            });
+        });
+    }
+
     application.connect_activate(move |application| {
         // resources.gresources is created by build.rs
         // it includes all the files in the resources directory
@@ -302,17 +341,221 @@ fn main() {
 
         let app = App::new(&config_dir, scripts.clone());
         app.set_application(Some(application));
+        app.restore_session();
         app.show_all();
 
+        if sync::Repositories::load(&config_dir).sync_on_startup {
+            app.sync_repositories();
+        }
+
+        // switch between light and dark with the desktop
+        #[cfg(target_os = "linux")]
+        {
+            let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+            color_scheme::watch(sender);
+            let app = app.clone();
+            receiver.attach(None, move |dark| {
+                app.set_system_dark(dark);
+                glib::Continue(true)
+            });
+        }
+
         if let Some(error) = &script_error {
             app.push_error(error);
         }
 
+        if let Some(receiver) = script_event_receiver.take() {
+            let app = app.clone();
+            receiver.attach(None, move |event| {
+                app.on_script_event(event);
+                glib::Continue(true)
+            });
+        }
+
+        // activated by clicking a notification
+        let present_action = gio::SimpleAction::new("present", None);
+        application.add_action(&present_action);
+        {
+            let app = app.clone();
+            present_action.connect_activate(move |_, _| app.present());
+        }
+
         // add keyboard shortcut for opening command pallete
         let command_pallete_action = gio::SimpleAction::new("command_pallete", None);
         application.add_action(&command_pallete_action);
         application.set_accels_for_action("app.command_pallete", &["<Primary><Shift>P"]);
-        command_pallete_action.connect_activate(move |_, _| app.open_command_pallete());
+        {
+            let app = app.clone();
+            command_pallete_action.connect_activate(move |_, _| app.open_command_pallete());
+        }
+
+        // run the last script again without opening the command pallete
+        let rerun_last_action = gio::SimpleAction::new("rerun_last", None);
+        application.add_action(&rerun_last_action);
+        application.set_accels_for_action("app.rerun_last", &["<Primary><Shift>B"]);
+        {
+            let app = app.clone();
+            rerun_last_action.connect_activate(move |_, _| app.rerun_last());
+        }
+
+        // find and replace in the current tab
+        let find_action = gio::SimpleAction::new("find", None);
+        application.add_action(&find_action);
+        application.set_accels_for_action("app.find", &["<Primary>F"]);
+        {
+            let app = app.clone();
+            find_action.connect_activate(move |_, _| app.find(false));
+        }
+        let replace_action = gio::SimpleAction::new("replace", None);
+        application.add_action(&replace_action);
+        application.set_accels_for_action("app.replace", &["<Primary>H"]);
+        {
+            let app = app.clone();
+            replace_action.connect_activate(move |_, _| app.find(true));
+        }
+
+        // scratchpads in tabs
+        let new_tab_action = gio::SimpleAction::new("new_tab", None);
+        application.add_action(&new_tab_action);
+        application.set_accels_for_action("app.new_tab", &["<Primary>T"]);
+        {
+            let app = app.clone();
+            new_tab_action.connect_activate(move |_, _| app.new_tab());
+        }
+        let close_tab_action = gio::SimpleAction::new("close_tab", None);
+        application.add_action(&close_tab_action);
+        application.set_accels_for_action("app.close_tab", &["<Primary>W"]);
+        {
+            let app = app.clone();
+            close_tab_action.connect_activate(move |_, _| app.close_tab());
+        }
+
+        let preferences_action = gio::SimpleAction::new("preferences", None);
+        application.add_action(&preferences_action);
+        application.set_accels_for_action("app.preferences", &["<Primary>comma"]);
+        {
+            let app = app.clone();
+            preferences_action.connect_activate(move |_, _| app.show_preferences());
+        }
+
+        let browse_scripts_action = gio::SimpleAction::new("browse_scripts", None);
+        application.add_action(&browse_scripts_action);
+        application.set_accels_for_action("app.browse_scripts", &["<Primary><Shift>B"]);
+        {
+            let app = app.clone();
+            browse_scripts_action.connect_activate(move |_, _| app.browse_scripts());
+        }
+
+        // files open in tabs
+        let open_file_action = gio::SimpleAction::new("open_file", None);
+        application.add_action(&open_file_action);
+        application.set_accels_for_action("app.open_file", &["<Primary>O"]);
+        {
+            let app = app.clone();
+            open_file_action.connect_activate(move |_, _| app.open_file());
+        }
+        let save_file_action = gio::SimpleAction::new("save_file", None);
+        application.add_action(&save_file_action);
+        application.set_accels_for_action("app.save_file", &["<Primary>S"]);
+        {
+            let app = app.clone();
+            save_file_action.connect_activate(move |_, _| {
+                app.save_file();
+            });
+        }
+        let save_file_as_action = gio::SimpleAction::new("save_file_as", None);
+        application.add_action(&save_file_as_action);
+        application.set_accels_for_action("app.save_file_as", &["<Primary><Shift>S"]);
+        {
+            let app = app.clone();
+            save_file_as_action.connect_activate(move |_, _| {
+                app.save_file_as();
+            });
+        }
+
+        // run a script on the clipboard without changing the document
+        let transform_clipboard_action = gio::SimpleAction::new("transform_clipboard", None);
+        application.add_action(&transform_clipboard_action);
+        application.set_accels_for_action("app.transform_clipboard", &["<Primary><Shift>V"]);
+        {
+            let app = app.clone();
+            transform_clipboard_action.connect_activate(move |_, _| app.transform_clipboard());
+        }
+
+        // grow the selection to the next syntactic unit
+        let expand_selection_action = gio::SimpleAction::new("expand_selection", None);
+        application.add_action(&expand_selection_action);
+        application.set_accels_for_action("app.expand_selection", &["<Alt><Shift>Right"]);
+        {
+            let app = app.clone();
+            expand_selection_action.connect_activate(move |_, _| app.expand_selection());
+        }
+
+        // line endings and encoding of the current tab, from the menu by the status bar's counts
+        let convert_line_endings_action = gio::SimpleAction::new(
+            "convert_line_endings",
+            Some(glib::VariantTy::new("s").expect("invalid variant type")),
+        );
+        application.add_action(&convert_line_endings_action);
+        {
+            let app = app.clone();
+            convert_line_endings_action.connect_activate(move |_, id| {
+                if let Some(line_ending) =
+                    id.and_then(|id| id.get_str()).and_then(LineEnding::from_id)
+                {
+                    app.convert_line_endings(line_ending);
+                }
+            });
+        }
+        let convert_to_utf8_action = gio::SimpleAction::new("convert_to_utf8", None);
+        application.add_action(&convert_to_utf8_action);
+        {
+            let app = app.clone();
+            convert_to_utf8_action.connect_activate(move |_, _| app.convert_to_utf8());
+        }
+
+        // scripts run by their shortcuts, single keys are bound here and chords activate it once
+        // they're typed
+        let run_script_action = gio::SimpleAction::new(
+            "run_script",
+            Some(glib::VariantTy::new("s").expect("invalid variant type")),
+        );
+        application.add_action(&run_script_action);
+        {
+            let app = app.clone();
+            run_script_action.connect_activate(move |_, script_name| {
+                if let Some(script_name) = script_name.and_then(|script_name| script_name.get_str())
+                {
+                    app.record_feature("shortcut");
+                    app.run_script_named(script_name, RunMode::Replace);
+                }
+            });
+        }
+        app.set_shortcut_accels(&[]);
+
+        // open the command pallete searching for a script, used by the search provider
+        let select_script_action = gio::SimpleAction::new(
+            "select_script",
+            Some(glib::VariantTy::new("s").expect("invalid variant type")),
+        );
+        application.add_action(&select_script_action);
+        {
+            let app = app.clone();
+            select_script_action.connect_activate(move |_, query| {
+                if let Some(query) = query.and_then(|query| query.get_str()) {
+                    app.select_script(query);
+                }
+            });
+        }
+
+        if let Some(query) = select_script.take() {
+            let app = app.clone();
+            // once the window is mapped, the pallete is modal to it
+            glib::idle_add_local(move || {
+                app.select_script(&query);
+                glib::Continue(false)
+            });
+        }
     });
 
     application.run(&[]);
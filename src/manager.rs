@@ -0,0 +1,299 @@
+// window listing every script with where it came from, scripts can be turned off so they're hidden
+// from the command pallete, and the user's own scripts can be deleted
+
+use crate::{
+    config::DisabledScripts,
+    script::{Script, ScriptOrigin},
+};
+use gtk::prelude::*;
+use std::{
+    cell::RefCell,
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+    sync::{Arc, RwLock},
+};
+
+const WINDOW_WIDTH: i32 = 560;
+const WINDOW_HEIGHT: i32 = 520;
+const SOURCE_WIDTH: i32 = 640;
+const SOURCE_HEIGHT: i32 = 480;
+
+const MANAGER_HELP: &str = "Scripts that are turned off are hidden from the command pallete.";
+
+// e.g. "Built in", "Your script" or "From team-scripts"
+pub fn origin_label(origin: &ScriptOrigin) -> String {
+    match origin {
+        ScriptOrigin::Builtin => "Built in".to_string(),
+        ScriptOrigin::User => "Your script".to_string(),
+        ScriptOrigin::Remote(name) => format!("From {}", name),
+    }
+}
+
+#[derive(Clone)]
+pub struct ScriptManager {
+    window: gtk::Window,
+    scripts: Arc<RwLock<Vec<Script>>>,
+    disabled: Rc<RefCell<DisabledScripts>>,
+    config_dir: PathBuf,
+    status: gtk::Label,
+}
+
+impl ScriptManager {
+    pub fn new(
+        parent: &gtk::ApplicationWindow,
+        scripts: Arc<RwLock<Vec<Script>>>,
+        disabled: Rc<RefCell<DisabledScripts>>,
+        config_dir: PathBuf,
+    ) -> Self {
+        let window = gtk::Window::new(gtk::WindowType::Toplevel);
+        window.set_transient_for(Some(parent));
+        window.set_destroy_with_parent(true);
+        window.set_default_size(WINDOW_WIDTH, WINDOW_HEIGHT);
+
+        let header_bar = gtk::HeaderBar::new();
+        header_bar.set_show_close_button(true);
+        header_bar.set_title(Some("Manage Scripts"));
+        window.set_titlebar(Some(&header_bar));
+
+        let manager = ScriptManager {
+            window,
+            scripts,
+            disabled,
+            config_dir,
+            status: gtk::Label::new(Some(MANAGER_HELP)),
+        };
+
+        let list = gtk::ListBox::new();
+        list.set_selection_mode(gtk::SelectionMode::None);
+        for script in manager
+            .scripts
+            .read()
+            .expect("scripts lock is poisoned")
+            .iter()
+        {
+            list.add(&manager.row(script));
+        }
+
+        let scrolled_window = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+        scrolled_window.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+        scrolled_window.set_shadow_type(gtk::ShadowType::In);
+        scrolled_window.add(&list);
+
+        manager.status.set_line_wrap(true);
+        manager.status.set_xalign(0.0);
+
+        let page = gtk::Box::new(gtk::Orientation::Vertical, 12);
+        page.set_border_width(18);
+        page.pack_start(&scrolled_window, true, true, 0);
+        page.pack_start(&manager.status, false, false, 0);
+        manager.window.add(&page);
+
+        manager
+    }
+
+    pub fn show(&self) {
+        self.window.show_all();
+    }
+
+    fn row(&self, script: &Script) -> gtk::Box {
+        let name = script.metadata.name.clone();
+        let origin = script.origin();
+
+        let icon = gtk::Image::from_icon_name(
+            Some(&format!(
+                "boop-gtk-{}-symbolic",
+                script.metadata.icon.to_lowercase()
+            )),
+            gtk::IconSize::LargeToolbar,
+        );
+
+        let label = gtk::Label::new(None);
+        label.set_markup(&format!(
+            "<b>{}</b>\n<span size=\"smaller\">{}</span>\n<span size=\"smaller\" alpha=\"60%\">{}</span>",
+            glib::markup_escape_text(&name),
+            glib::markup_escape_text(&script.metadata.description),
+            glib::markup_escape_text(&origin_label(&origin))
+        ));
+        label.set_xalign(0.0);
+        label.set_hexpand(true);
+        label.set_line_wrap(true);
+
+        let enabled_switch = gtk::Switch::new();
+        enabled_switch.set_active(!self.disabled.borrow().contains(&name));
+        enabled_switch.set_valign(gtk::Align::Center);
+        {
+            let manager = self.clone();
+            let name = name.clone();
+            enabled_switch.connect_property_active_notify(move |switch| {
+                manager.set_enabled(&name, switch.get_active());
+            });
+        }
+
+        let folder_button = icon_button("folder-open-symbolic", "Open Containing Folder");
+        folder_button.set_sensitive(origin != ScriptOrigin::Builtin);
+        {
+            let manager = self.clone();
+            let path = script.path.clone();
+            folder_button.connect_clicked(move |_| manager.open_folder(&path));
+        }
+
+        let source_button = icon_button("text-x-generic-symbolic", "View Source");
+        source_button.set_sensitive(!script.is_native());
+        {
+            let manager = self.clone();
+            let name = name.clone();
+            source_button.connect_clicked(move |_| manager.show_source(&name));
+        }
+
+        // scripts from repositories would come back when they're next synced
+        let delete_button = icon_button("user-trash-symbolic", "Delete Script");
+        delete_button.set_sensitive(origin == ScriptOrigin::User);
+
+        let row = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+        row.set_border_width(6);
+        row.pack_start(&icon, false, false, 0);
+        row.pack_start(&label, true, true, 0);
+        row.pack_end(&delete_button, false, false, 0);
+        row.pack_end(&source_button, false, false, 0);
+        row.pack_end(&folder_button, false, false, 0);
+        row.pack_end(&enabled_switch, false, false, 0);
+        {
+            let manager = self.clone();
+            let path = script.path.clone();
+            let row = row.clone();
+            delete_button.connect_clicked(move |_| {
+                if manager.delete(&name, &path) {
+                    if let Some(list_row) = row.get_parent() {
+                        list_row.destroy();
+                    }
+                }
+            });
+        }
+
+        row
+    }
+
+    fn set_enabled(&self, script_name: &str, enabled: bool) {
+        self.disabled.borrow_mut().set_enabled(script_name, enabled);
+        if let Err(e) = self.disabled.borrow().save(&self.config_dir) {
+            error!("could not save disabled scripts: {}", e);
+            self.status
+                .set_text("Failed to save which scripts are off.");
+        }
+    }
+
+    fn open_folder(&self, path: &Path) {
+        let folder = match path.parent() {
+            Some(folder) => folder,
+            None => return,
+        };
+
+        if let Err(e) = open::that(folder) {
+            error!("could not open {}: {}", folder.display(), e);
+            self.status
+                .set_text(&format!("Failed to open {}.", folder.display()));
+        }
+    }
+
+    fn show_source(&self, script_name: &str) {
+        let source = {
+            let scripts = self.scripts.read().expect("scripts lock is poisoned");
+            match scripts
+                .iter()
+                .find(|script| script.metadata.name == script_name)
+            {
+                Some(script) => script.source().map(|source| source.to_string()),
+                None => return,
+            }
+        };
+        let source = match source {
+            Ok(source) => source,
+            Err(e) => {
+                error!("could not read {}: {}", script_name, e);
+                self.status
+                    .set_text(&format!("Failed to read {}.", script_name));
+                return;
+            }
+        };
+
+        let dialog = gtk::Dialog::with_buttons(
+            Some(script_name),
+            Some(&self.window),
+            gtk::DialogFlags::DESTROY_WITH_PARENT,
+            &[("_Close", gtk::ResponseType::Close)],
+        );
+        dialog.set_default_size(SOURCE_WIDTH, SOURCE_HEIGHT);
+
+        let view = gtk::TextView::new();
+        view.set_editable(false);
+        view.set_monospace(true);
+        view.get_buffer()
+            .expect("failed to get buffer")
+            .set_text(&source);
+        let scrolled_window = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+        scrolled_window.add(&view);
+        dialog
+            .get_content_area()
+            .pack_start(&scrolled_window, true, true, 0);
+
+        dialog.connect_response(|dialog, _| dialog.close());
+        dialog.show_all();
+    }
+
+    // asks first, the watcher unloads the script once its file is gone, true if it was deleted
+    fn delete(&self, script_name: &str, path: &Path) -> bool {
+        let dialog = gtk::MessageDialog::new(
+            Some(&self.window),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::None,
+            &format!("Delete {}?", script_name),
+        );
+        dialog.set_property_secondary_text(Some(&format!(
+            "{} will be deleted, this can't be undone.",
+            path.display()
+        )));
+        dialog.add_button("_Cancel", gtk::ResponseType::Cancel);
+        dialog.add_button("_Delete", gtk::ResponseType::Accept);
+        let response = dialog.run();
+        dialog.close();
+        if response != gtk::ResponseType::Accept {
+            return false;
+        }
+
+        if let Err(e) = fs::remove_file(path) {
+            error!("could not delete {}: {}", path.display(), e);
+            self.status
+                .set_text(&format!("Failed to delete {}.", script_name));
+            return false;
+        }
+        info!("deleted {}", path.display());
+
+        self.set_enabled(script_name, true);
+        true
+    }
+}
+
+fn icon_button(icon_name: &str, tooltip: &str) -> gtk::Button {
+    let button = gtk::Button::from_icon_name(Some(icon_name), gtk::IconSize::Button);
+    button.set_tooltip_text(Some(tooltip));
+    button.set_relief(gtk::ReliefStyle::None);
+    button.set_valign(gtk::Align::Center);
+    button
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_origin_label() {
+        assert_eq!("Built in", origin_label(&ScriptOrigin::Builtin));
+        assert_eq!("Your script", origin_label(&ScriptOrigin::User));
+        assert_eq!(
+            "From team-scripts",
+            origin_label(&ScriptOrigin::Remote("team-scripts".to_string()))
+        );
+    }
+}
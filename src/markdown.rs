@@ -0,0 +1,209 @@
+// messages longer than this are shortened in the status bar, the rest can be expanded
+const SUMMARY_LENGTH: usize = 120;
+
+// converts the small subset of markdown scripts use in messages (headings, lists, bold, italics,
+// code spans and links) into pango markup, anything else is escaped and shown as is
+
+pub fn to_pango_markup(markdown: &str) -> String {
+    markdown
+        .lines()
+        .map(line_to_markup)
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+// shows text as is in a monospace font, for data that markdown would mangle
+pub fn to_monospace_markup(text: &str) -> String {
+    format!("<tt>{}</tt>", escape(text))
+}
+
+// the first line of a message, shortened if needed, or None if the message is short enough to be
+// shown in full
+pub fn summary(message: &str) -> Option<String> {
+    let message = message.trim();
+    let first_line = message.lines().next().unwrap_or("");
+
+    if first_line.len() == message.len() && first_line.chars().count() <= SUMMARY_LENGTH {
+        return None;
+    }
+
+    let summary: String = first_line.chars().take(SUMMARY_LENGTH).collect();
+    Some(format!("{}…", summary.trim_end()))
+}
+
+// true if a message contains an excerpt of data (JSON, indented or tabular text) rather than
+// prose, so it's shown in a monospace font instead of being rendered as markdown
+pub fn looks_like_data(message: &str) -> bool {
+    message.contains('\t')
+        || message.lines().skip(1).any(|line| {
+            let trimmed = line.trim_start();
+            (trimmed.len() < line.len() && !trimmed.starts_with(&['-', '*'][..]))
+                || trimmed.starts_with(&['{', '}', '[', ']'][..])
+        })
+}
+
+fn line_to_markup(line: &str) -> String {
+    let trimmed = line.trim_start();
+    let indent = &line[..line.len() - trimmed.len()];
+
+    let heading = trimmed.trim_start_matches('#');
+    if heading.len() < trimmed.len() && heading.starts_with(' ') {
+        return format!("<b>{}</b>", inline_to_markup(heading.trim_start()));
+    }
+
+    if let Some(item) = trimmed
+        .strip_prefix("- ")
+        .or_else(|| trimmed.strip_prefix("* "))
+    {
+        return format!("{}• {}", indent, inline_to_markup(item));
+    }
+
+    inline_to_markup(line)
+}
+
+fn inline_to_markup(text: &str) -> String {
+    let mut markup = String::with_capacity(text.len());
+    let mut previous: Option<char> = None;
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        // code span, contents are not formatted
+        if c == '`' {
+            if let Some(end) = rest[1..].find('`') {
+                markup.push_str("<tt>");
+                markup.push_str(&escape(&rest[1..1 + end]));
+                markup.push_str("</tt>");
+                previous = Some('`');
+                rest = &rest[end + 2..];
+                continue;
+            }
+        }
+
+        // bold
+        if rest.starts_with("**") {
+            if let Some(end) = rest[2..].find("**").filter(|end| *end > 0) {
+                markup.push_str("<b>");
+                markup.push_str(&inline_to_markup(&rest[2..2 + end]));
+                markup.push_str("</b>");
+                previous = Some('*');
+                rest = &rest[end + 4..];
+                continue;
+            }
+        }
+
+        // italics, underscores inside words (snake_case) are left alone
+        if c == '*' || (c == '_' && !previous.is_some_and(char::is_alphanumeric)) {
+            if let Some(end) = rest[1..].find(c).filter(|end| *end > 0) {
+                markup.push_str("<i>");
+                markup.push_str(&inline_to_markup(&rest[1..1 + end]));
+                markup.push_str("</i>");
+                previous = Some(c);
+                rest = &rest[end + 2..];
+                continue;
+            }
+        }
+
+        // link
+        if c == '[' {
+            if let Some(label_end) = rest.find("](") {
+                if let Some(url_end) = rest[label_end + 2..].find(')') {
+                    let label = &rest[1..label_end];
+                    let url = &rest[label_end + 2..label_end + 2 + url_end];
+                    markup.push_str(&format!(
+                        "<a href=\"{}\">{}</a>",
+                        escape(url),
+                        inline_to_markup(label)
+                    ));
+                    previous = Some(')');
+                    rest = &rest[label_end + 3 + url_end..];
+                    continue;
+                }
+            }
+        }
+
+        markup.push_str(&escape(&rest[..c.len_utf8()]));
+        previous = Some(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    markup
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_text_is_escaped() {
+        assert_eq!(
+            "1 &lt; 2 &amp;&amp; &quot;a&quot; &gt; 0",
+            to_pango_markup("1 < 2 && \"a\" > 0")
+        );
+    }
+
+    #[test]
+    fn test_inline_formatting() {
+        assert_eq!(
+            "<b>42</b> matches in <i>input</i>, see <tt>a &lt; b</tt>",
+            to_pango_markup("**42** matches in _input_, see `a < b`")
+        );
+        assert_eq!(
+            "<b>bold <i>and italic</i></b>",
+            to_pango_markup("**bold _and italic_**")
+        );
+    }
+
+    #[test]
+    fn test_unmatched_delimiters() {
+        assert_eq!("2 * 3 = 6", to_pango_markup("2 * 3 = 6"));
+        assert_eq!("snake_case_name", to_pango_markup("snake_case_name"));
+        assert_eq!("a ** b", to_pango_markup("a ** b"));
+        assert_eq!("`unclosed", to_pango_markup("`unclosed"));
+    }
+
+    #[test]
+    fn test_links() {
+        assert_eq!(
+            "see <a href=\"https://example.com/?a=1&amp;b=2\">the <b>docs</b></a>",
+            to_pango_markup("see [the **docs**](https://example.com/?a=1&b=2)")
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(None, summary("Formatted 3 lines"));
+        assert_eq!(
+            Some("Invalid JSON…".to_string()),
+            summary("Invalid JSON\n  at line 2")
+        );
+        assert_eq!(
+            Some(format!("{}…", "a".repeat(SUMMARY_LENGTH))),
+            summary(&"a".repeat(SUMMARY_LENGTH + 1))
+        );
+    }
+
+    #[test]
+    fn test_looks_like_data() {
+        assert!(looks_like_data("Unexpected token:\n{\n  \"a\": 1,\n}"));
+        assert!(looks_like_data("name\tvalue"));
+        assert!(!looks_like_data("Converted **3** values"));
+        assert!(!looks_like_data("## Report\n- one\n  * two"));
+        assert_eq!("<tt>a &lt; b</tt>", to_monospace_markup("a < b"));
+    }
+
+    #[test]
+    fn test_block_formatting() {
+        assert_eq!(
+            "<b>Report</b>\n• one\n  • <tt>two</tt>\n#hashtag",
+            to_pango_markup("## Report\n- one\n  * `two`\n#hashtag")
+        );
+    }
+}
@@ -0,0 +1,353 @@
+// ranks scripts against what's typed in the command pallete, by their name, then their tags and
+// description, so "fj", "format js" and "prettify" all find "Format JSON", scripts that are run
+// often and have a higher bias are ranked higher
+
+// how well the query matches the name, better matches score higher whatever the usage and bias
+const EXACT_SCORE: isize = 1000;
+const PREFIX_SCORE: isize = 800;
+// the query is the first letters of the name's words, e.g. "fj" for "Format JSON"
+const ACRONYM_SCORE: isize = 700;
+const WORD_SUBSTRING_SCORE: isize = 600;
+const SUBSTRING_SCORE: isize = 500;
+// the query's characters are in the name in order, less the further apart they are
+const FUZZY_SCORE: isize = 400;
+const MIN_FUZZY_SCORE: isize = 150;
+const FUZZY_GAP_PENALTY: isize = 8;
+const FUZZY_WORD_START_BONUS: isize = 10;
+// each word of the query is in the name, tags or description
+const WORDS_SCORE: isize = 300;
+const WORDS_OUTSIDE_NAME_PENALTY: isize = 20;
+const TAG_SCORE: isize = 250;
+const EXACT_TAG_BONUS: isize = 50;
+const DESCRIPTION_SCORE: isize = 100;
+// shorter queries match too many descriptions
+const MIN_DESCRIPTION_QUERY_LEN: usize = 3;
+
+// added for each doubling of the number of times a script was run
+const USAGE_SCORE: f64 = 20.0;
+// added to the score of a script with a bias of 1 for a single character query, divided by the
+// length of longer queries as they say more about which script is wanted
+const BIAS_SCORE: f64 = 100.0;
+
+// what a query is matched against
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Candidate<'a> {
+    pub name: &'a str,
+    // comma separated
+    pub tags: Option<&'a str>,
+    pub description: &'a str,
+    pub bias: f64,
+    // times the script was run
+    pub runs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Match {
+    pub score: isize,
+    // indices of the characters of the name that matched, to highlight them
+    pub positions: Vec<usize>,
+}
+
+// none if the query doesn't match the candidate at all
+pub fn score(query: &str, candidate: &Candidate) -> Option<Match> {
+    let query: Vec<char> = lowercase(query.trim());
+    if query.is_empty() {
+        return None;
+    }
+
+    let name: Vec<char> = candidate.name.chars().collect();
+    let (base, positions) = name_match(&query, &name)
+        .or_else(|| words_match(&query, &name, candidate))
+        .or_else(|| {
+            let query: String = query.iter().collect();
+            tags_match(&query, candidate.tags).or_else(|| description_match(&query, candidate))
+        })?;
+
+    Some(Match {
+        score: weigh(base, query.len(), candidate),
+        positions,
+    })
+}
+
+// adds the candidate's usage and bias to how well it matched a query `query_len` characters long,
+// scripts stay visible however negative their bias is, as they match the query
+pub fn weigh(score: isize, query_len: usize, candidate: &Candidate) -> isize {
+    let usage = ((candidate.runs as f64 + 1.0).log2() * USAGE_SCORE).round() as isize;
+    let bias = (candidate.bias * BIAS_SCORE / query_len.max(1) as f64).round() as isize;
+    (score + usage + bias).max(1)
+}
+
+// pango markup of `text` with the characters at `positions` underlined
+pub fn highlight(text: &str, positions: &[usize]) -> String {
+    let mut markup = String::new();
+    let mut run = String::new();
+    let mut in_match = false;
+
+    for (i, c) in text.chars().enumerate() {
+        let matched = positions.contains(&i);
+        if matched != in_match {
+            markup.push_str(&close_run(&run, in_match));
+            run.clear();
+            in_match = matched;
+        }
+        run.push(c);
+    }
+    markup.push_str(&close_run(&run, in_match));
+
+    markup
+}
+
+fn close_run(run: &str, matched: bool) -> String {
+    let escaped = glib::markup_escape_text(run).to_string();
+    if matched && !run.is_empty() {
+        format!("<u>{}</u>", escaped)
+    } else {
+        escaped
+    }
+}
+
+fn lowercase(text: &str) -> Vec<char> {
+    // one character for each, so indices line up with the original
+    text.chars()
+        .map(|c| c.to_lowercase().next().unwrap_or(c))
+        .collect()
+}
+
+// indices of the first character of each word, words are split by anything that isn't a letter or
+// a digit and where lowercase turns to uppercase, e.g. "toUpperCase"
+fn word_starts(text: &[char]) -> Vec<usize> {
+    (0..text.len())
+        .filter(|&i| {
+            text[i].is_alphanumeric()
+                && (i == 0
+                    || !text[i - 1].is_alphanumeric()
+                    || (text[i - 1].is_lowercase() && text[i].is_uppercase()))
+        })
+        .collect()
+}
+
+fn name_match(query: &[char], name: &[char]) -> Option<(isize, Vec<usize>)> {
+    let lower = lowercase(&name.iter().collect::<String>());
+    let starts = word_starts(name);
+
+    if lower == query {
+        return Some((EXACT_SCORE, (0..name.len()).collect()));
+    }
+    if lower.starts_with(query) {
+        return Some((PREFIX_SCORE, (0..query.len()).collect()));
+    }
+
+    let initials: Vec<char> = starts.iter().map(|&i| lower[i]).collect();
+    if query.len() > 1 && initials.starts_with(query) {
+        return Some((ACRONYM_SCORE, starts[..query.len()].to_vec()));
+    }
+
+    // occurrences at the start of a word are better than ones in the middle of one
+    let occurrences: Vec<usize> = (0..=lower.len().saturating_sub(query.len()))
+        .filter(|&i| lower[i..].starts_with(query))
+        .collect();
+    if let Some(&start) = occurrences
+        .iter()
+        .find(|i| starts.contains(i))
+        .or_else(|| occurrences.first())
+    {
+        let score = if starts.contains(&start) {
+            WORD_SUBSTRING_SCORE
+        } else {
+            SUBSTRING_SCORE
+        };
+        return Some((score, (start..start + query.len()).collect()));
+    }
+
+    fuzzy_match(query, &lower, &starts)
+}
+
+// the query's characters in order, each as early as it can be
+fn fuzzy_match(query: &[char], lower: &[char], starts: &[usize]) -> Option<(isize, Vec<usize>)> {
+    if query.iter().any(|c| c.is_whitespace()) {
+        return None;
+    }
+
+    let mut positions = Vec::with_capacity(query.len());
+    let mut from = 0;
+    for c in query {
+        let position = from + lower[from..].iter().position(|l| l == c)?;
+        positions.push(position);
+        from = position + 1;
+    }
+
+    let span = positions[positions.len() - 1] - positions[0] + 1;
+    let gaps = (span - positions.len()) as isize;
+    let word_starts = positions.iter().filter(|p| starts.contains(p)).count() as isize;
+    let score = (FUZZY_SCORE - gaps * FUZZY_GAP_PENALTY + word_starts * FUZZY_WORD_START_BONUS)
+        .max(MIN_FUZZY_SCORE)
+        .min(FUZZY_SCORE);
+
+    Some((score, positions))
+}
+
+// every word of the query starts a word of the name, a tag or a word of the description
+fn words_match(
+    query: &[char],
+    name: &[char],
+    candidate: &Candidate,
+) -> Option<(isize, Vec<usize>)> {
+    let query: String = query.iter().collect();
+    let query_words: Vec<&str> = query.split_whitespace().collect();
+    if query_words.len() < 2 {
+        return None;
+    }
+
+    let lower = lowercase(&name.iter().collect::<String>());
+    let starts = word_starts(name);
+    let mut positions = Vec::new();
+    let mut outside_name = 0;
+    for word in query_words {
+        let word: Vec<char> = word.chars().collect();
+        match starts.iter().find(|&&i| lower[i..].starts_with(&word)) {
+            Some(&start) => positions.extend(start..start + word.len()),
+            None => {
+                let word: String = word.iter().collect();
+                tags_match(&word, candidate.tags)
+                    .or_else(|| description_match(&word, candidate))?;
+                outside_name += 1;
+            }
+        }
+    }
+
+    Some((
+        WORDS_SCORE - outside_name * WORDS_OUTSIDE_NAME_PENALTY,
+        positions,
+    ))
+}
+
+fn tags_match(query: &str, tags: Option<&str>) -> Option<(isize, Vec<usize>)> {
+    tags?
+        .split(',')
+        .map(|tag| tag.trim().to_lowercase())
+        .filter(|tag| tag.starts_with(query))
+        .map(|tag| {
+            if tag == query {
+                TAG_SCORE + EXACT_TAG_BONUS
+            } else {
+                TAG_SCORE
+            }
+        })
+        .max()
+        .map(|score| (score, Vec::new()))
+}
+
+fn description_match(query: &str, candidate: &Candidate) -> Option<(isize, Vec<usize>)> {
+    if query.chars().count() < MIN_DESCRIPTION_QUERY_LEN {
+        return None;
+    }
+
+    if candidate
+        .description
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .any(|word| word.starts_with(query))
+    {
+        Some((DESCRIPTION_SCORE, Vec::new()))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SCRIPTS: &[Candidate] = &[
+        Candidate {
+            name: "Format JSON",
+            tags: Some("json,prettify"),
+            description: "Cleans and formats JSON documents",
+            bias: 0.0,
+            runs: 0,
+        },
+        Candidate {
+            name: "Minify JSON",
+            tags: Some("json,compress"),
+            description: "Removes whitespace from JSON",
+            bias: 0.0,
+            runs: 0,
+        },
+        Candidate {
+            name: "JSON to CSV",
+            tags: None,
+            description: "Converts an array of objects to CSV",
+            bias: 0.0,
+            runs: 0,
+        },
+        Candidate {
+            name: "Sort lines",
+            tags: None,
+            description: "Sorts lines alphabetically",
+            bias: 0.0,
+            runs: 0,
+        },
+        Candidate {
+            name: "Join Lines",
+            tags: Some("merge"),
+            description: "Joins every line into one",
+            bias: 0.0,
+            runs: 0,
+        },
+    ];
+
+    // names of the scripts that match, best first
+    fn rank<'a>(query: &str, scripts: &[Candidate<'a>]) -> Vec<&'a str> {
+        let mut matches: Vec<(isize, &str)> = scripts
+            .iter()
+            .filter_map(|script| Some((score(query, script)?.score, script.name)))
+            .collect();
+        matches.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(b.1)));
+        matches.into_iter().map(|(_, name)| name).collect()
+    }
+
+    #[test]
+    fn test_ranking() {
+        assert_eq!("Format JSON", rank("fj", SCRIPTS)[0]);
+        assert_eq!("Format JSON", rank("format js", SCRIPTS)[0]);
+        assert_eq!(
+            vec!["JSON to CSV", "Format JSON", "Minify JSON"],
+            rank("json", SCRIPTS)
+        );
+        assert_eq!(vec!["Format JSON"], rank("prettify", SCRIPTS));
+        assert_eq!(vec!["Sort lines"], rank("alphabet", SCRIPTS));
+        assert_eq!(vec!["Minify JSON"], rank("mnfy", SCRIPTS));
+        assert_eq!(vec!["Join Lines"], rank("lines merge", SCRIPTS));
+        assert!(rank("xyz", SCRIPTS).is_empty());
+
+        // exact names beat everything else
+        assert_eq!("Sort lines", rank("sort lines", SCRIPTS)[0]);
+    }
+
+    #[test]
+    fn test_usage_and_bias() {
+        let mut scripts = SCRIPTS.to_vec();
+        assert_eq!("Join Lines", rank("lines", &scripts)[0]);
+
+        scripts[3].runs = 20;
+        assert_eq!("Sort lines", rank("lines", &scripts)[0]);
+
+        scripts[4].bias = 5.0;
+        assert_eq!("Join Lines", rank("lines", &scripts)[0]);
+
+        // a bias counts for less the longer the query is
+        assert_eq!(100, weigh(0, 5, &scripts[4]));
+        assert_eq!(50, weigh(0, 10, &scripts[4]));
+    }
+
+    #[test]
+    fn test_highlight() {
+        let positions = score("fj", &SCRIPTS[0]).unwrap().positions;
+        assert_eq!(vec![0, 7], positions);
+        assert_eq!(
+            "<u>F</u>ormat <u>J</u>SON",
+            highlight("Format JSON", &positions)
+        );
+        assert_eq!("<u>a &amp;</u> b", highlight("a & b", &[0, 1, 2]));
+    }
+}
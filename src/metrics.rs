@@ -0,0 +1,115 @@
+// opt-in usage metrics, only aggregate counts are kept and nothing is sent until the user has
+// reviewed the report and chosen to send it
+
+use crate::script::Script;
+use serde::{Deserialize, Serialize};
+use simple_error::SimpleError;
+use std::collections::BTreeMap;
+
+const SEND_TIMEOUT_MS: u64 = 5000;
+// category of scripts that don't ship with boop, their tags are chosen by the user so aren't used
+const USER_SCRIPT_CATEGORY: &str = "user script";
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Metrics {
+    // runs of built-in scripts by their first tag
+    pub runs_by_category: BTreeMap<String, u64>,
+    // how often each feature was used, e.g. "output_pane" or "shortcut"
+    pub features: BTreeMap<String, u64>,
+}
+
+impl Metrics {
+    pub fn record_run(&mut self, category: &str) {
+        *self
+            .runs_by_category
+            .entry(category.to_string())
+            .or_default() += 1;
+    }
+
+    pub fn record_feature(&mut self, feature: &str) {
+        *self.features.entry(feature.to_string()).or_default() += 1;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.runs_by_category.is_empty() && self.features.is_empty()
+    }
+
+    // exactly what is sent, shown to the user first
+    pub fn report(&self) -> String {
+        serde_jsonrc::to_string_pretty(self).expect("failed to serialize metrics")
+    }
+
+    pub fn send(&self, url: &str) -> Result<(), SimpleError> {
+        info!("sending usage metrics to {}", url);
+
+        let response = ureq::post(url)
+            .timeout_connect(SEND_TIMEOUT_MS)
+            .timeout_read(SEND_TIMEOUT_MS)
+            .set("Content-Type", "application/json")
+            .send_string(&self.report());
+
+        match response.synthetic_error() {
+            Some(e) => Err(SimpleError::new(e.to_string())),
+            None if !response.ok() => Err(SimpleError::new(format!(
+                "{} {}",
+                response.status(),
+                response.status_text()
+            ))),
+            None => Ok(()),
+        }
+    }
+}
+
+// category a run of the script is counted under
+pub fn category(script: &Script) -> String {
+    // built-in scripts are loaded from memory, so have no path
+    if !script.path.as_os_str().is_empty() {
+        return USER_SCRIPT_CATEGORY.to_string();
+    }
+
+    script
+        .metadata
+        .tags
+        .as_deref()
+        .and_then(|tags| tags.split(',').map(str::trim).find(|tag| !tag.is_empty()))
+        .unwrap_or("untagged")
+        .to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_metrics() {
+        let source = "
+            /**
+                {
+                    \"api\":1,
+                    \"name\":\"Format JSON\",
+                    \"description\":\"Formats JSON\",
+                    \"icon\":\"html\",
+                    \"tags\":\" JSON, prettify\"
+                }
+            **/
+
+            function main(state) {}";
+
+        let builtin = Script::from_source(source.to_string(), PathBuf::new()).unwrap();
+        let user = Script::from_source(source.to_string(), PathBuf::from("/home/a/x.js")).unwrap();
+        assert_eq!("json", category(&builtin));
+        assert_eq!(USER_SCRIPT_CATEGORY, category(&user));
+
+        let mut metrics = Metrics::default();
+        assert!(metrics.is_empty());
+        metrics.record_run(&category(&builtin));
+        metrics.record_run(&category(&builtin));
+        metrics.record_feature("shortcut");
+        assert_eq!(Some(&2), metrics.runs_by_category.get("json"));
+
+        let report: Metrics = serde_jsonrc::from_str(&metrics.report()).unwrap();
+        assert_eq!(metrics, report);
+    }
+}
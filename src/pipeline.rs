@@ -0,0 +1,225 @@
+// named sequences of scripts, each script is run on the text the previous one produced, shown in
+// the command pallete alongside scripts
+
+use crate::{
+    executor::{HostRequest, HostResponse},
+    permissions::Permissions,
+    script::{Metadata, Script},
+};
+use serde::{Deserialize, Serialize};
+use simple_error::{bail, SimpleError};
+use std::{fs, path::Path, time::Duration};
+
+const PIPELINES_FILE: &str = "pipelines.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pipeline {
+    pub name: String,
+    // names of the scripts to run, in order, scripts are referred to by name as built-in scripts
+    // have no path
+    pub steps: Vec<String>,
+}
+
+impl Pipeline {
+    // e.g. "URL Decode → Format JSON"
+    pub fn description(&self) -> String {
+        self.steps.join(" → ")
+    }
+
+    // runs each step on the output of the one before, stopping at the first step that fails
+    pub fn run(
+        &self,
+        scripts: &mut [Script],
+        text: &str,
+        timeout: impl Fn(&Metadata) -> Duration,
+        permissions: &Permissions,
+        host: &dyn Fn(HostRequest) -> HostResponse,
+    ) -> Result<String, SimpleError> {
+        let mut text = text.to_string();
+
+        for step in &self.steps {
+            let script = match scripts
+                .iter_mut()
+                .find(|script| script.metadata.name == *step)
+            {
+                Some(script) => script,
+                None => bail!("{} is not installed", step),
+            };
+
+            info!("running {} in {}", step, self.name);
+            let timeout = timeout(&script.metadata);
+            script.set_permissions(permissions);
+            let status = script
+                .execute(&text, None, &Default::default(), timeout, host)
+                .map_err(|e| SimpleError::new(format!("could not run {}: {}", step, e)))?;
+            if let Some(exception) = status.exception() {
+                bail!("{} threw {}", step, exception);
+            }
+
+            text = status.into_replacement().apply(&text, None);
+        }
+
+        Ok(text)
+    }
+}
+
+// stored as JSON in the config directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Pipelines {
+    pub pipelines: Vec<Pipeline>,
+}
+
+impl Pipelines {
+    pub fn load(config_dir: &Path) -> Self {
+        let path = config_dir.join(PIPELINES_FILE);
+
+        match fs::read_to_string(&path) {
+            Ok(source) => serde_jsonrc::from_str(&source).unwrap_or_else(|e| {
+                error!("invalid pipelines file {}: {}", path.display(), e);
+                Pipelines::default()
+            }),
+            Err(_) => Pipelines::default(),
+        }
+    }
+
+    pub fn save(&self, config_dir: &Path) -> Result<(), SimpleError> {
+        let path = config_dir.join(PIPELINES_FILE);
+        let source = serde_jsonrc::to_string_pretty(self)
+            .map_err(|e| SimpleError::with("could not serialize pipelines", e))?;
+
+        fs::write(&path, source)
+            .map_err(|e| SimpleError::with(&format!("could not write {}", path.display()), e))
+    }
+
+    // adds the pipeline, replacing the one named `replacing` or any with the same name
+    pub fn insert(&mut self, pipeline: Pipeline, replacing: Option<&str>) {
+        self.pipelines.retain(|existing| {
+            existing.name != pipeline.name && Some(existing.name.as_str()) != replacing
+        });
+        self.pipelines.push(pipeline);
+        self.pipelines.sort_by(|a, b| a.name.cmp(&b.name));
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.pipelines.retain(|pipeline| pipeline.name != name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::executor::headless_host;
+    use std::path::PathBuf;
+
+    fn script(name: &str, body: &str) -> Script {
+        Script::from_source(
+            format!(
+                "/** {{ \"api\": 1, \"name\": \"{}\", \"icon\": \"html\" }} **/
+                function main(state) {{ {} }}",
+                name, body
+            ),
+            PathBuf::new(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_run() {
+        boop_core::init();
+
+        let mut scripts = vec![
+            script("Trim", "state.text = state.text.trim();"),
+            script("Shout", "state.text = state.text.toUpperCase() + \"!\";"),
+            script("Fail", "throw new Error(\"nope\");"),
+        ];
+        let timeout = |_: &Metadata| Duration::from_secs(10);
+
+        let pipeline = Pipeline {
+            name: "Trim and Shout".to_string(),
+            steps: vec!["Trim".to_string(), "Shout".to_string()],
+        };
+        assert_eq!("Trim → Shout", pipeline.description());
+        assert_eq!(
+            "HELLO!",
+            pipeline
+                .run(
+                    &mut scripts,
+                    " hello ",
+                    timeout,
+                    &Permissions::default(),
+                    &headless_host
+                )
+                .unwrap()
+        );
+
+        let pipeline = Pipeline {
+            name: "Broken".to_string(),
+            steps: vec!["Trim".to_string(), "Fail".to_string()],
+        };
+        let error = pipeline
+            .run(
+                &mut scripts,
+                "a",
+                timeout,
+                &Permissions::default(),
+                &headless_host,
+            )
+            .unwrap_err();
+        assert!(error.as_str().starts_with("Fail threw Error: nope"));
+
+        let pipeline = Pipeline {
+            name: "Missing".to_string(),
+            steps: vec!["Reverse".to_string()],
+        };
+        assert_eq!(
+            "Reverse is not installed",
+            pipeline
+                .run(
+                    &mut scripts,
+                    "a",
+                    timeout,
+                    &Permissions::default(),
+                    &headless_host
+                )
+                .unwrap_err()
+                .as_str()
+        );
+
+        for script in scripts.iter_mut() {
+            script.kill_thread();
+        }
+    }
+
+    #[test]
+    fn test_pipelines() {
+        let config_dir =
+            std::env::temp_dir().join(format!("boop-gtk-pipelines-{}", std::process::id()));
+        fs::create_dir_all(&config_dir).unwrap();
+
+        let mut pipelines = Pipelines::load(&config_dir);
+        assert!(pipelines.pipelines.is_empty());
+
+        let pipeline = |name: &str, steps: &[&str]| Pipeline {
+            name: name.to_string(),
+            steps: steps.iter().map(|step| step.to_string()).collect(),
+        };
+        pipelines.insert(pipeline("B", &["URL Decode"]), None);
+        pipelines.insert(pipeline("A", &["URL Decode", "Format JSON"]), None);
+        pipelines.insert(pipeline("C", &["Format JSON"]), Some("B"));
+        pipelines.save(&config_dir).unwrap();
+
+        let mut pipelines = Pipelines::load(&config_dir);
+        assert_eq!(
+            vec![
+                pipeline("A", &["URL Decode", "Format JSON"]),
+                pipeline("C", &["Format JSON"])
+            ],
+            pipelines.pipelines
+        );
+        pipelines.remove("A");
+        assert_eq!(1, pipelines.pipelines.len());
+
+        fs::remove_dir_all(&config_dir).unwrap();
+    }
+}
@@ -0,0 +1,706 @@
+// window for changing how the editor looks, the shortcuts that run scripts, the repositories
+// scripts are synced from and what scripts are allowed to do, changes are applied as they're made
+// so they can be previewed, and saved straight away
+
+use crate::{
+    config::{Appearance, InputMode, Theme},
+    permissions::{Permission, Permissions},
+    script::Script,
+    shortcuts::Shortcuts,
+    sync::{self, Repositories},
+};
+use gtk::prelude::*;
+use sourceview::prelude::*;
+use std::{
+    cell::RefCell,
+    path::PathBuf,
+    rc::Rc,
+    sync::{Arc, RwLock},
+};
+
+// shipped with Boop and written to the config directory
+pub const DEFAULT_STYLE_SCHEME: &str = "boop-light";
+pub const DEFAULT_DARK_STYLE_SCHEME: &str = "boop-dark";
+const MAX_TAB_WIDTH: f64 = 16.0;
+
+const SHORTCUTS_HELP: &str =
+    "Click a shortcut and press the keys to run the script with, Backspace removes it.";
+const NO_SHORTCUT: &str = "Disabled";
+const SHORTCUTS_HEIGHT: i32 = 320;
+
+const REPOSITORIES_HELP: &str =
+    "Scripts in these git repositories are cloned into the scripts directory and kept up to date.";
+
+const PERMISSIONS_HELP: &str =
+    "Scripts ask the first time they need what they declared, Ask forgets your answer.";
+const NO_PERMISSIONS: &str = "No scripts need permission.";
+
+// CSS giving text views the font, empty to keep the system's monospace font
+pub fn font_css(font: Option<&str>) -> String {
+    let description = match font {
+        Some(font) => pango::FontDescription::from_string(font),
+        None => return String::new(),
+    };
+
+    let mut css = String::from("textview {");
+    if let Some(family) = description.get_family() {
+        css.push_str(&format!(" font-family: \"{}\";", family));
+    }
+    if description.get_size() > 0 {
+        let unit = if description.get_size_is_absolute() {
+            "px"
+        } else {
+            "pt"
+        };
+        css.push_str(&format!(
+            " font-size: {}{};",
+            f64::from(description.get_size()) / f64::from(pango::SCALE),
+            unit
+        ));
+    }
+    if description.get_style() == pango::Style::Italic {
+        css.push_str(" font-style: italic;");
+    }
+    if description.get_weight() == pango::Weight::Bold {
+        css.push_str(" font-weight: bold;");
+    }
+    css.push_str(" }");
+    css
+}
+
+fn theme_id(theme: Theme) -> &'static str {
+    match theme {
+        Theme::System => "system",
+        Theme::Light => "light",
+        Theme::Dark => "dark",
+    }
+}
+
+fn input_mode_id(input_mode: InputMode) -> &'static str {
+    match input_mode {
+        InputMode::Default => "default",
+        InputMode::Vim => "vim",
+        InputMode::Emacs => "emacs",
+    }
+}
+
+// every style scheme, by name, with `current` picked
+fn style_scheme_combo(current: &str) -> gtk::ComboBoxText {
+    let combo = gtk::ComboBoxText::new();
+    if let Some(manager) = sourceview::StyleSchemeManager::get_default() {
+        let mut schemes: Vec<(String, String)> = manager
+            .get_scheme_ids()
+            .iter()
+            .filter_map(|id| manager.get_scheme(id))
+            .filter_map(|scheme| {
+                Some((scheme.get_id()?.to_string(), scheme.get_name()?.to_string()))
+            })
+            .collect();
+        schemes.sort_by_key(|(_, name)| name.to_lowercase());
+        for (id, name) in &schemes {
+            combo.append(Some(id), name);
+        }
+    }
+    combo.set_active_id(Some(current));
+    combo
+}
+
+#[derive(Clone)]
+pub struct Preferences {
+    window: gtk::Window,
+    appearance: Rc<RefCell<Appearance>>,
+    scripts: Arc<RwLock<Vec<Script>>>,
+    shortcuts: Rc<RefCell<Shortcuts>>,
+    config_dir: PathBuf,
+    // applies the appearance to the editor
+    on_change: Rc<dyn Fn()>,
+    // binds the shortcuts saved in the config directory
+    on_shortcuts_change: Rc<dyn Fn()>,
+    // the script whose shortcut is being picked, and its button
+    capturing: Rc<RefCell<Option<(String, gtk::Button)>>>,
+    shortcuts_status: gtk::Label,
+    repositories: Rc<RefCell<Repositories>>,
+    repositories_list: gtk::ListBox,
+    repositories_status: gtk::Label,
+    // pulls the repositories in the background
+    on_sync: Rc<dyn Fn()>,
+    permissions_status: gtk::Label,
+}
+
+impl Preferences {
+    pub fn new(
+        parent: &gtk::ApplicationWindow,
+        appearance: Rc<RefCell<Appearance>>,
+        scripts: Arc<RwLock<Vec<Script>>>,
+        shortcuts: Rc<RefCell<Shortcuts>>,
+        config_dir: PathBuf,
+        on_change: impl Fn() + 'static,
+        on_shortcuts_change: impl Fn() + 'static,
+        on_sync: impl Fn() + 'static,
+    ) -> Self {
+        let window = gtk::Window::new(gtk::WindowType::Toplevel);
+        window.set_transient_for(Some(parent));
+        window.set_destroy_with_parent(true);
+        window.set_resizable(false);
+
+        let stack = gtk::Stack::new();
+        let switcher = gtk::StackSwitcher::new();
+        switcher.set_stack(Some(&stack));
+
+        let header_bar = gtk::HeaderBar::new();
+        header_bar.set_show_close_button(true);
+        header_bar.set_custom_title(Some(&switcher));
+        window.set_titlebar(Some(&header_bar));
+        window.set_title("Preferences");
+        window.add(&stack);
+
+        let grid = gtk::Grid::new();
+        grid.set_border_width(18);
+        grid.set_row_spacing(12);
+        grid.set_column_spacing(24);
+        stack.add_titled(&grid, "editor", "Editor");
+
+        let preferences = Preferences {
+            window,
+            appearance,
+            scripts,
+            shortcuts,
+            config_dir: config_dir.clone(),
+            on_change: Rc::new(on_change),
+            on_shortcuts_change: Rc::new(on_shortcuts_change),
+            capturing: Rc::new(RefCell::new(None)),
+            shortcuts_status: gtk::Label::new(Some(SHORTCUTS_HELP)),
+            repositories: Rc::new(RefCell::new(Repositories::load(&config_dir))),
+            repositories_list: gtk::ListBox::new(),
+            repositories_status: gtk::Label::new(Some(REPOSITORIES_HELP)),
+            on_sync: Rc::new(on_sync),
+            permissions_status: gtk::Label::new(Some(PERMISSIONS_HELP)),
+        };
+        let current = preferences.appearance.borrow().clone();
+
+        let font_button = gtk::FontButton::new();
+        font_button.set_use_font(true);
+        if let Some(font) = &current.font {
+            font_button.set_font(font);
+        }
+        {
+            let preferences_ = preferences.clone();
+            font_button.connect_font_set(move |button| {
+                let font = button.get_font().map(|font| font.to_string());
+                preferences_.update(|appearance| appearance.font = font);
+            });
+        }
+
+        let theme_combo = gtk::ComboBoxText::new();
+        theme_combo.append(Some(theme_id(Theme::System)), "Follow System");
+        theme_combo.append(Some(theme_id(Theme::Light)), "Light");
+        theme_combo.append(Some(theme_id(Theme::Dark)), "Dark");
+        theme_combo.set_active_id(Some(theme_id(current.theme)));
+        {
+            let preferences_ = preferences.clone();
+            theme_combo.connect_changed(move |combo| {
+                let theme = match combo.get_active_id().as_deref() {
+                    Some("light") => Theme::Light,
+                    Some("dark") => Theme::Dark,
+                    _ => Theme::System,
+                };
+                preferences_.update(|appearance| appearance.theme = theme);
+            });
+        }
+
+        // schemes are applied as they're picked, so the editor previews them
+        let scheme_combo = style_scheme_combo(
+            current
+                .style_scheme
+                .as_deref()
+                .unwrap_or(DEFAULT_STYLE_SCHEME),
+        );
+        {
+            let preferences_ = preferences.clone();
+            scheme_combo.connect_changed(move |combo| {
+                let scheme = combo.get_active_id().map(|id| id.to_string());
+                preferences_.update(|appearance| appearance.style_scheme = scheme);
+            });
+        }
+        let dark_scheme_combo = style_scheme_combo(
+            current
+                .dark_style_scheme
+                .as_deref()
+                .unwrap_or(DEFAULT_DARK_STYLE_SCHEME),
+        );
+        {
+            let preferences_ = preferences.clone();
+            dark_scheme_combo.connect_changed(move |combo| {
+                let scheme = combo.get_active_id().map(|id| id.to_string());
+                preferences_.update(|appearance| appearance.dark_style_scheme = scheme);
+            });
+        }
+
+        let line_numbers_switch = gtk::Switch::new();
+        line_numbers_switch.set_active(current.show_line_numbers);
+        {
+            let preferences_ = preferences.clone();
+            line_numbers_switch.connect_property_active_notify(move |switch| {
+                let active = switch.get_active();
+                preferences_.update(|appearance| appearance.show_line_numbers = active);
+            });
+        }
+
+        let wrap_switch = gtk::Switch::new();
+        wrap_switch.set_active(current.wrap_lines);
+        {
+            let preferences_ = preferences.clone();
+            wrap_switch.connect_property_active_notify(move |switch| {
+                let active = switch.get_active();
+                preferences_.update(|appearance| appearance.wrap_lines = active);
+            });
+        }
+
+        let tab_width_button = gtk::SpinButton::with_range(1.0, MAX_TAB_WIDTH, 1.0);
+        tab_width_button.set_value(f64::from(current.tab_width));
+        {
+            let preferences_ = preferences.clone();
+            tab_width_button.connect_value_changed(move |button| {
+                let width = button.get_value_as_int() as u32;
+                preferences_.update(|appearance| appearance.tab_width = width);
+            });
+        }
+
+        let input_mode_combo = gtk::ComboBoxText::new();
+        input_mode_combo.append(Some(input_mode_id(InputMode::Default)), "Default");
+        input_mode_combo.append(Some(input_mode_id(InputMode::Vim)), "Vim");
+        input_mode_combo.append(Some(input_mode_id(InputMode::Emacs)), "Emacs");
+        input_mode_combo.set_active_id(Some(input_mode_id(current.input_mode)));
+        {
+            let preferences_ = preferences.clone();
+            input_mode_combo.connect_changed(move |combo| {
+                let input_mode = match combo.get_active_id().as_deref() {
+                    Some("vim") => InputMode::Vim,
+                    Some("emacs") => InputMode::Emacs,
+                    _ => InputMode::Default,
+                };
+                preferences_.update(|appearance| appearance.input_mode = input_mode);
+            });
+        }
+
+        let rows: [(&str, gtk::Widget); 8] = [
+            ("Editor Font", font_button.upcast()),
+            ("Style", theme_combo.upcast()),
+            ("Color Scheme", scheme_combo.upcast()),
+            ("Dark Color Scheme", dark_scheme_combo.upcast()),
+            ("Line Numbers", line_numbers_switch.upcast()),
+            ("Wrap Lines", wrap_switch.upcast()),
+            ("Tab Width", tab_width_button.upcast()),
+            ("Keybindings", input_mode_combo.upcast()),
+        ];
+        for (row, (label, widget)) in rows.iter().enumerate() {
+            let label = gtk::Label::new(Some(label));
+            label.set_xalign(0.0);
+            widget.set_halign(gtk::Align::End);
+            grid.attach(&label, 0, row as i32, 1, 1);
+            grid.attach(widget, 1, row as i32, 1, 1);
+        }
+
+        stack.add_titled(&preferences.shortcuts_page(), "shortcuts", "Shortcuts");
+        stack.add_titled(
+            &preferences.repositories_page(),
+            "repositories",
+            "Repositories",
+        );
+        stack.add_titled(
+            &preferences.permissions_page(),
+            "permissions",
+            "Permissions",
+        );
+        {
+            let preferences_ = preferences.clone();
+            preferences
+                .window
+                .connect_key_press_event(move |_, event| preferences_.on_key_press(event));
+        }
+
+        preferences
+    }
+
+    // each script with a button showing its shortcut, clicking it waits for a new one
+    fn shortcuts_page(&self) -> gtk::Box {
+        let list = gtk::ListBox::new();
+        list.set_selection_mode(gtk::SelectionMode::None);
+
+        for script in self
+            .scripts
+            .read()
+            .expect("scripts lock is poisoned")
+            .iter()
+        {
+            let name = script.metadata.name.clone();
+
+            let label = gtk::Label::new(Some(&name));
+            label.set_xalign(0.0);
+            label.set_hexpand(true);
+
+            let button = gtk::Button::with_label(&self.shortcut_label(&name));
+            button.set_relief(gtk::ReliefStyle::None);
+            {
+                let preferences = self.clone();
+                button.connect_clicked(move |button| preferences.capture(&name, button));
+            }
+
+            let row = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+            row.set_border_width(6);
+            row.pack_start(&label, true, true, 0);
+            row.pack_end(&button, false, false, 0);
+            list.add(&row);
+        }
+
+        let scrolled_window = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+        scrolled_window.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+        scrolled_window.set_min_content_height(SHORTCUTS_HEIGHT);
+        scrolled_window.set_shadow_type(gtk::ShadowType::In);
+        scrolled_window.add(&list);
+
+        self.shortcuts_status.set_line_wrap(true);
+        self.shortcuts_status.set_xalign(0.0);
+
+        let page = gtk::Box::new(gtk::Orientation::Vertical, 12);
+        page.set_border_width(18);
+        page.pack_start(&scrolled_window, true, true, 0);
+        page.pack_start(&self.shortcuts_status, false, false, 0);
+        page
+    }
+
+    // the repositories scripts are synced from, with an entry to add another
+    fn repositories_page(&self) -> gtk::Box {
+        self.repositories_list
+            .set_selection_mode(gtk::SelectionMode::None);
+        self.fill_repositories();
+
+        let scrolled_window = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+        scrolled_window.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+        scrolled_window.set_min_content_height(SHORTCUTS_HEIGHT);
+        scrolled_window.set_shadow_type(gtk::ShadowType::In);
+        scrolled_window.add(&self.repositories_list);
+
+        let entry = gtk::Entry::new();
+        entry.set_placeholder_text(Some("https://github.com/team/scripts.git"));
+        entry.set_hexpand(true);
+        let add_button = gtk::Button::with_label("Add");
+        {
+            let preferences = self.clone();
+            let entry_ = entry.clone();
+            add_button.connect_clicked(move |_| {
+                preferences.add_repository(&entry_.get_text());
+                entry_.set_text("");
+            });
+        }
+        {
+            let add_button = add_button.clone();
+            entry.connect_activate(move |_| add_button.clicked());
+        }
+        let add_row = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+        add_row.pack_start(&entry, true, true, 0);
+        add_row.pack_start(&add_button, false, false, 0);
+
+        let startup_switch = gtk::Switch::new();
+        startup_switch.set_active(self.repositories.borrow().sync_on_startup);
+        {
+            let preferences = self.clone();
+            startup_switch.connect_property_active_notify(move |switch| {
+                let active = switch.get_active();
+                preferences
+                    .update_repositories(|repositories| repositories.sync_on_startup = active);
+            });
+        }
+        let startup_label = gtk::Label::new(Some("Sync at Startup"));
+        startup_label.set_xalign(0.0);
+        let sync_button = gtk::Button::with_label("Sync Now");
+        {
+            let on_sync = self.on_sync.clone();
+            sync_button.connect_clicked(move |_| on_sync());
+        }
+        let sync_row = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+        sync_row.pack_start(&startup_label, false, false, 0);
+        sync_row.pack_start(&startup_switch, false, false, 0);
+        sync_row.pack_end(&sync_button, false, false, 0);
+
+        self.repositories_status.set_line_wrap(true);
+        self.repositories_status.set_xalign(0.0);
+
+        let page = gtk::Box::new(gtk::Orientation::Vertical, 12);
+        page.set_border_width(18);
+        page.pack_start(&scrolled_window, true, true, 0);
+        page.pack_start(&add_row, false, false, 0);
+        page.pack_start(&sync_row, false, false, 0);
+        page.pack_start(&self.repositories_status, false, false, 0);
+        page
+    }
+
+    fn fill_repositories(&self) {
+        for row in self.repositories_list.get_children() {
+            self.repositories_list.remove(&row);
+        }
+
+        for repository in &self.repositories.borrow().repositories {
+            let label = gtk::Label::new(Some(&format!("{}\n{}", repository.name, repository.url)));
+            label.set_xalign(0.0);
+            label.set_hexpand(true);
+            label.set_ellipsize(pango::EllipsizeMode::Middle);
+
+            let button = gtk::Button::with_label("Remove");
+            button.set_relief(gtk::ReliefStyle::None);
+            {
+                let preferences = self.clone();
+                let name = repository.name.clone();
+                button.connect_clicked(move |_| preferences.remove_repository(&name));
+            }
+
+            let row = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+            row.set_border_width(6);
+            row.pack_start(&label, true, true, 0);
+            row.pack_end(&button, false, false, 0);
+            self.repositories_list.add(&row);
+        }
+        self.repositories_list.show_all();
+    }
+
+    // added repositories are cloned straight away
+    fn add_repository(&self, url: &str) {
+        if url.trim().is_empty() {
+            return;
+        }
+
+        let mut repositories = self.repositories.borrow().clone();
+        if let Err(e) = repositories.add(url) {
+            self.repositories_status
+                .set_text(&format!("Not added, {}.", e));
+            return;
+        }
+        self.update_repositories(|current| *current = repositories);
+        (self.on_sync)();
+    }
+
+    // the repository's clone is removed with it, so its scripts are unloaded
+    fn remove_repository(&self, name: &str) {
+        self.update_repositories(|repositories| repositories.remove(name));
+        if let Err(e) = sync::remove_clone(&self.config_dir.join("scripts"), name) {
+            error!("could not remove repository: {}", e);
+            self.repositories_status
+                .set_text(&format!("Failed to remove the clone of {}.", name));
+        }
+    }
+
+    fn update_repositories(&self, change: impl FnOnce(&mut Repositories)) {
+        change(&mut self.repositories.borrow_mut());
+        if let Err(e) = self.repositories.borrow().save(&self.config_dir) {
+            error!("could not save repositories: {}", e);
+            self.repositories_status
+                .set_text("Failed to save repositories.");
+            return;
+        }
+        self.repositories_status.set_text(REPOSITORIES_HELP);
+        self.fill_repositories();
+    }
+
+    // each permission scripts declared, with the user's answer, which can be changed
+    fn permissions_page(&self) -> gtk::Box {
+        let list = gtk::ListBox::new();
+        list.set_selection_mode(gtk::SelectionMode::None);
+        let placeholder = gtk::Label::new(Some(NO_PERMISSIONS));
+        placeholder.show();
+        list.set_placeholder(Some(&placeholder));
+
+        let permissions = Permissions::load(&self.config_dir);
+        for script in self
+            .scripts
+            .read()
+            .expect("scripts lock is poisoned")
+            .iter()
+        {
+            for permission in &script.metadata.permissions {
+                let name = script.metadata.name.clone();
+                let key = script.permission_key();
+                let permission = *permission;
+
+                let label =
+                    gtk::Label::new(Some(&format!("{}\n{}", name, permission.description())));
+                label.set_xalign(0.0);
+                label.set_hexpand(true);
+
+                let combo = gtk::ComboBoxText::new();
+                combo.append(Some("ask"), "Ask");
+                combo.append(Some("allow"), "Allow");
+                combo.append(Some("deny"), "Deny");
+                combo.set_active_id(Some(match permissions.answers(permission).get(&key) {
+                    Some(true) => "allow",
+                    Some(false) => "deny",
+                    None => "ask",
+                }));
+                {
+                    let preferences = self.clone();
+                    combo.connect_changed(move |combo| {
+                        let answer = match combo.get_active_id().as_deref() {
+                            Some("allow") => Some(true),
+                            Some("deny") => Some(false),
+                            _ => None,
+                        };
+                        preferences.set_permission(permission, &key, answer);
+                    });
+                }
+
+                let row = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+                row.set_border_width(6);
+                row.pack_start(&label, true, true, 0);
+                row.pack_end(&combo, false, false, 0);
+                list.add(&row);
+            }
+        }
+
+        let scrolled_window = gtk::ScrolledWindow::new(gtk::NONE_ADJUSTMENT, gtk::NONE_ADJUSTMENT);
+        scrolled_window.set_policy(gtk::PolicyType::Never, gtk::PolicyType::Automatic);
+        scrolled_window.set_min_content_height(SHORTCUTS_HEIGHT);
+        scrolled_window.set_shadow_type(gtk::ShadowType::In);
+        scrolled_window.add(&list);
+
+        self.permissions_status.set_line_wrap(true);
+        self.permissions_status.set_xalign(0.0);
+
+        let page = gtk::Box::new(gtk::Orientation::Vertical, 12);
+        page.set_border_width(18);
+        page.pack_start(&scrolled_window, true, true, 0);
+        page.pack_start(&self.permissions_status, false, false, 0);
+        page
+    }
+
+    // None forgets the answer, so the user is asked again
+    fn set_permission(&self, permission: Permission, script_key: &str, answer: Option<bool>) {
+        let mut permissions = Permissions::load(&self.config_dir);
+        match answer {
+            Some(allowed) => {
+                permissions
+                    .answers_mut(permission)
+                    .insert(script_key.to_string(), allowed);
+            }
+            None => permissions.forget(permission, script_key),
+        }
+
+        if let Err(e) = permissions.save(&self.config_dir) {
+            error!("could not save permissions: {}", e);
+            self.permissions_status
+                .set_text("Failed to save permissions.");
+            return;
+        }
+        self.permissions_status.set_text(PERMISSIONS_HELP);
+    }
+
+    fn shortcut_label(&self, script_name: &str) -> String {
+        self.shortcuts
+            .borrow()
+            .label_for(script_name)
+            .unwrap_or_else(|| NO_SHORTCUT.to_string())
+    }
+
+    fn capture(&self, script_name: &str, button: &gtk::Button) {
+        // only one shortcut is picked at a time
+        if let Some((name, previous)) = self.capturing.borrow_mut().take() {
+            previous.set_label(&self.shortcut_label(&name));
+        }
+
+        button.set_label("Press a shortcut…");
+        self.shortcuts_status.set_text(SHORTCUTS_HELP);
+        self.capturing
+            .replace(Some((script_name.to_string(), button.clone())));
+    }
+
+    fn on_key_press(&self, event: &gdk::EventKey) -> gtk::Inhibit {
+        if self.capturing.borrow().is_none() || event.get_is_modifier() {
+            return gtk::Inhibit(false);
+        }
+
+        let key = event.get_keyval();
+        let modifiers = event.get_state() & gtk::accelerator_get_default_mod_mask();
+        let shortcut = if key == gdk::keys::constants::Escape {
+            None
+        } else if key == gdk::keys::constants::BackSpace && modifiers.is_empty() {
+            Some(None)
+        } else if (modifiers - gdk::ModifierType::SHIFT_MASK).is_empty() {
+            // they'd be taken from the editor as they're typed
+            self.shortcuts_status
+                .set_text("Shortcuts need Ctrl, Alt or Super, e.g. Ctrl+Alt+J.");
+            return gtk::Inhibit(true);
+        } else {
+            Some(Some(
+                gtk::accelerator_name(*key, modifiers)
+                    .map(|name| name.to_string())
+                    .unwrap_or_default(),
+            ))
+        };
+
+        let (name, button) = match self.capturing.borrow_mut().take() {
+            Some(capturing) => capturing,
+            None => return gtk::Inhibit(false),
+        };
+        if let Some(shortcut) = shortcut {
+            self.bind(&name, shortcut.as_deref());
+        }
+        button.set_label(&self.shortcut_label(&name));
+
+        gtk::Inhibit(true)
+    }
+
+    // binds the shortcut to the script, or removes its shortcut, as long as no script loses its own
+    fn bind(&self, script_name: &str, shortcut: Option<&str>) {
+        let overrides = Shortcuts::rebind(
+            &self.scripts.read().expect("scripts lock is poisoned"),
+            &Shortcuts::load_overrides(&self.config_dir),
+            script_name,
+            shortcut,
+        );
+
+        let overrides = match overrides {
+            Ok(overrides) => overrides,
+            Err(conflict) => {
+                self.shortcuts_status
+                    .set_text(&format!("Not bound, {}.", conflict));
+                return;
+            }
+        };
+        if let Err(e) = Shortcuts::save_overrides(&self.config_dir, &overrides) {
+            error!("could not save shortcuts: {}", e);
+            self.shortcuts_status.set_text("Failed to save shortcuts.");
+            return;
+        }
+
+        (self.on_shortcuts_change)();
+        self.shortcuts_status.set_text(SHORTCUTS_HELP);
+    }
+
+    pub fn show(&self) {
+        self.window.show_all();
+    }
+
+    fn update(&self, change: impl FnOnce(&mut Appearance)) {
+        change(&mut self.appearance.borrow_mut());
+        if let Err(e) = self.appearance.borrow().save(&self.config_dir) {
+            error!("could not save appearance: {}", e);
+        }
+        (self.on_change)();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_font_css() {
+        assert_eq!(font_css(None), "");
+        assert_eq!(
+            font_css(Some("Source Code Pro 11")),
+            "textview { font-family: \"Source Code Pro\"; font-size: 11pt; }"
+        );
+        assert_eq!(
+            font_css(Some("Monospace Bold 10.5")),
+            "textview { font-family: \"Monospace\"; font-size: 10.5pt; font-weight: bold; }"
+        );
+    }
+}
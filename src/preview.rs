@@ -0,0 +1,407 @@
+// renders what scripts post with `payload.postPreview` as pango markup for the preview panel,
+// markdown gets sized headings, code blocks and lined up tables on top of what messages get, the
+// common HTML tags are converted and anything else in HTML is shown as its text
+
+use crate::markdown;
+
+// elements whose contents aren't shown
+const HIDDEN_ELEMENTS: [&str; 4] = ["head", "script", "style", "title"];
+// elements without contents or a closing tag
+const VOID_ELEMENTS: [&str; 12] = [
+    "area", "base", "br", "col", "embed", "hr", "img", "input", "link", "meta", "source", "wbr",
+];
+const RULE: &str = "────────────────────";
+
+// text starting with a tag is HTML, anything else markdown
+pub fn to_markup(content: &str) -> String {
+    if content.trim_start().starts_with('<') {
+        html_to_markup(content)
+    } else {
+        markdown_to_markup(content)
+    }
+}
+
+fn escape(text: &str) -> String {
+    glib::markup_escape_text(text).to_string()
+}
+
+fn markdown_to_markup(markdown: &str) -> String {
+    let mut blocks = Vec::new();
+    let mut lines = markdown.lines().peekable();
+
+    while let Some(line) = lines.next() {
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("```") {
+            let code: Vec<&str> = lines
+                .by_ref()
+                .take_while(|line| !line.trim_start().starts_with("```"))
+                .collect();
+            blocks.push(format!("<tt>{}</tt>", escape(&code.join("\n"))));
+        } else if trimmed.starts_with('|') {
+            let mut rows = vec![line];
+            while let Some(line) = lines.peek() {
+                if !line.trim_start().starts_with('|') {
+                    break;
+                }
+                rows.push(line);
+                lines.next();
+            }
+            blocks.push(table_to_markup(&rows));
+        } else if let Some((level, heading)) = heading(trimmed) {
+            let size = match level {
+                1 => "xx-large",
+                2 => "x-large",
+                3 => "large",
+                _ => "medium",
+            };
+            blocks.push(format!(
+                "<span size=\"{}\"><b>{}</b></span>",
+                size,
+                markdown::to_pango_markup(heading)
+            ));
+        } else {
+            blocks.push(markdown::to_pango_markup(line));
+        }
+    }
+
+    blocks.join("\n")
+}
+
+// the level and text of a heading, e.g. (2, "Usage") for "## Usage"
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let text = line.trim_start_matches('#');
+    let level = line.len() - text.len();
+    if level > 0 && level <= 6 && text.starts_with(' ') {
+        Some((level, text.trim()))
+    } else {
+        None
+    }
+}
+
+// a markdown table in a monospace font with its columns lined up, the header in bold
+fn table_to_markup(rows: &[&str]) -> String {
+    let mut rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| {
+            let row = row.trim();
+            let row = row.strip_prefix('|').unwrap_or(row);
+            let row = row.strip_suffix('|').unwrap_or(row);
+            row.split('|').map(|cell| cell.trim().to_string()).collect()
+        })
+        .collect();
+
+    // the row under the header only says how columns are aligned
+    let is_separator = |row: &Vec<String>| {
+        row.iter()
+            .all(|cell| !cell.is_empty() && cell.chars().all(|c| c == '-' || c == ':'))
+    };
+    let has_header = rows.len() > 1 && is_separator(&rows[1]);
+    if has_header {
+        rows.remove(1);
+    }
+
+    let columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<usize> = (0..columns)
+        .map(|column| {
+            rows.iter()
+                .filter_map(|row| row.get(column))
+                .map(|cell| cell.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    let lines: Vec<String> = rows
+        .iter()
+        .enumerate()
+        .map(|(index, row)| {
+            let line = widths
+                .iter()
+                .enumerate()
+                .map(|(column, width)| {
+                    let cell = row.get(column).map_or("", String::as_str);
+                    format!("{:width$}", cell, width = *width)
+                })
+                .collect::<Vec<String>>()
+                .join("  ");
+            let line = escape(line.trim_end());
+            if index == 0 && has_header {
+                format!("<b>{}</b>", line)
+            } else {
+                line
+            }
+        })
+        .collect();
+
+    format!("<tt>{}</tt>", lines.join("\n"))
+}
+
+fn html_to_markup(html: &str) -> String {
+    let mut markup = String::with_capacity(html.len());
+    // the elements open, with the markup that closes them
+    let mut open: Vec<(String, String)> = Vec::new();
+    let mut rest = html;
+
+    while !rest.is_empty() {
+        let tag_start = rest.find('<').unwrap_or(rest.len());
+        let in_pre = open.iter().any(|(name, _)| name == "pre");
+        push_text(&mut markup, &rest[..tag_start], in_pre);
+        rest = &rest[tag_start..];
+        if rest.is_empty() {
+            break;
+        }
+
+        if rest.starts_with("<!--") {
+            rest = rest.find("-->").map_or("", |end| &rest[end + 3..]);
+            continue;
+        }
+        let tag_end = match rest.find('>') {
+            Some(tag_end) => tag_end,
+            None => {
+                push_text(&mut markup, rest, in_pre);
+                break;
+            }
+        };
+        let tag = &rest[1..tag_end];
+        rest = &rest[tag_end + 1..];
+
+        // doctypes and processing instructions
+        if tag.starts_with('!') || tag.starts_with('?') {
+            continue;
+        }
+
+        let closing = tag.starts_with('/');
+        let tag = tag.trim_start_matches('/');
+        let name_end = tag
+            .find(|c: char| c.is_whitespace() || c == '/')
+            .unwrap_or(tag.len());
+        let name = tag[..name_end].to_ascii_lowercase();
+        let attributes = &tag[name_end..];
+
+        if closing {
+            if let Some(index) = open.iter().rposition(|(open_name, _)| *open_name == name) {
+                for (_, close) in open.drain(index..).rev() {
+                    markup.push_str(&close);
+                }
+            }
+        } else if HIDDEN_ELEMENTS.contains(&name.as_str()) {
+            let end = find_ignoring_case(rest, &format!("</{}", name));
+            rest = end.map_or("", |end| &rest[end..]);
+            rest = rest.find('>').map_or("", |end| &rest[end + 1..]);
+        } else if VOID_ELEMENTS.contains(&name.as_str()) || tag.ends_with('/') {
+            match name.as_str() {
+                "br" => markup.push('\n'),
+                "hr" => {
+                    break_line(&mut markup);
+                    markup.push_str(RULE);
+                    markup.push('\n');
+                }
+                "img" => {
+                    if let Some(alt) = attribute(attributes, "alt") {
+                        markup.push_str(&escape(&alt));
+                    }
+                }
+                _ => (),
+            }
+        } else {
+            let close = open_element(&mut markup, &name, attributes);
+            open.push((name, close));
+        }
+    }
+
+    for (_, close) in open.into_iter().rev() {
+        markup.push_str(&close);
+    }
+    markup.trim_end().to_string()
+}
+
+// adds the markup opening an element, returning the markup that closes it
+fn open_element(markup: &mut String, name: &str, attributes: &str) -> String {
+    let (open, close) = match name {
+        "b" | "strong" => ("<b>".to_string(), "</b>"),
+        "i" | "em" | "cite" | "var" => ("<i>".to_string(), "</i>"),
+        "u" | "ins" => ("<u>".to_string(), "</u>"),
+        "s" | "del" | "strike" => ("<s>".to_string(), "</s>"),
+        "code" | "kbd" | "samp" | "tt" => ("<tt>".to_string(), "</tt>"),
+        "sub" => ("<sub>".to_string(), "</sub>"),
+        "sup" => ("<sup>".to_string(), "</sup>"),
+        "small" => ("<small>".to_string(), "</small>"),
+        "big" => ("<big>".to_string(), "</big>"),
+        "a" => match attribute(attributes, "href") {
+            Some(href) => (format!("<a href=\"{}\">", escape(&href)), "</a>"),
+            None => (String::new(), ""),
+        },
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" => {
+            let size = match name {
+                "h1" => "xx-large",
+                "h2" => "x-large",
+                "h3" => "large",
+                _ => "medium",
+            };
+            break_line(markup);
+            (format!("<span size=\"{}\"><b>", size), "</b></span>\n")
+        }
+        "pre" => {
+            break_line(markup);
+            ("<tt>".to_string(), "</tt>\n")
+        }
+        "li" => {
+            break_line(markup);
+            ("• ".to_string(), "\n")
+        }
+        "dt" => {
+            break_line(markup);
+            ("<b>".to_string(), "</b>\n")
+        }
+        "dd" => {
+            break_line(markup);
+            ("    ".to_string(), "\n")
+        }
+        "th" => ("<b>".to_string(), "</b>\t"),
+        "td" => (String::new(), "\t"),
+        "p" | "div" | "section" | "article" | "header" | "footer" | "main" | "nav" | "aside"
+        | "blockquote" | "ul" | "ol" | "dl" | "table" | "tr" | "figure" | "form" => {
+            break_line(markup);
+            (String::new(), "\n")
+        }
+        _ => (String::new(), ""),
+    };
+
+    markup.push_str(&open);
+    close.to_string()
+}
+
+// starts a new line unless the markup is already at the start of one
+fn break_line(markup: &mut String) {
+    if !markup.is_empty() && !markup.ends_with('\n') {
+        markup.push('\n');
+    }
+}
+
+// text between tags, runs of whitespace are shown as one space outside of <pre>
+fn push_text(markup: &mut String, text: &str, in_pre: bool) {
+    if in_pre {
+        markup.push_str(&escape(&decode_entities(text)));
+        return;
+    }
+
+    let at_line_start = markup.is_empty() || markup.ends_with('\n') || markup.ends_with(' ');
+    let mut collapsed = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if collapsed.is_empty() {
+        if !text.is_empty() && !at_line_start {
+            markup.push(' ');
+        }
+        return;
+    }
+    if text.starts_with(char::is_whitespace) && !at_line_start {
+        collapsed.insert(0, ' ');
+    }
+    if text.ends_with(char::is_whitespace) {
+        collapsed.push(' ');
+    }
+    // decoded after collapsing, so &nbsp; isn't collapsed
+    markup.push_str(&escape(&decode_entities(&collapsed)));
+}
+
+// the value of an attribute in the attributes of a tag, e.g. ` href="https://example.com"`
+fn attribute(attributes: &str, name: &str) -> Option<String> {
+    let mut rest = attributes;
+    while let Some(start) = find_ignoring_case(rest, name) {
+        let preceded_by_space = start > 0 && rest.as_bytes()[start - 1].is_ascii_whitespace();
+        let after = rest[start + name.len()..].trim_start();
+        if let (true, Some(value)) = (preceded_by_space, after.strip_prefix('=')) {
+            let value = value.trim_start();
+            let value = match value.chars().next() {
+                Some(quote @ '"') | Some(quote @ '\'') => value[1..].split(quote).next(),
+                _ => value.split(char::is_whitespace).next(),
+            };
+            return Some(decode_entities(value.unwrap_or("")));
+        }
+        rest = &rest[start + name.len()..];
+    }
+    None
+}
+
+fn find_ignoring_case(text: &str, pattern: &str) -> Option<usize> {
+    text.as_bytes()
+        .windows(pattern.len())
+        .position(|window| window.eq_ignore_ascii_case(pattern.as_bytes()))
+}
+
+// replaces character references like "&amp;" and "&#39;" with the characters they stand for
+fn decode_entities(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 10)
+            .map(|end| &rest[1..1 + end]);
+        let c = entity.and_then(|entity| match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => {
+                let number = entity.strip_prefix('#')?;
+                let code = match number.strip_prefix(&['x', 'X'][..]) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => number.parse().ok()?,
+                };
+                std::char::from_u32(code)
+            }
+        });
+
+        match (entity, c) {
+            (Some(entity), Some(c)) => {
+                decoded.push(c);
+                rest = &rest[entity.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_markdown() {
+        assert_eq!(
+            "<span size=\"xx-large\"><b>Report</b></span>\n<b>3</b> rows\n<tt>a &lt; b\n  c</tt>",
+            to_markup("# Report\n**3** rows\n```js\na < b\n  c\n```")
+        );
+        assert_eq!(
+            "<tt><b>Name    Size</b>\nboop    1\nkernel  120</tt>",
+            to_markup("| Name | Size |\n|------|-----:|\n| boop | 1 |\n| kernel | 120 |")
+        );
+    }
+
+    #[test]
+    fn test_html() {
+        assert_eq!(
+            "<span size=\"x-large\"><b>Claims</b></span>\n<b>sub</b>: 1234 &amp; <i>more</i>\n\
+             • one\n• <a href=\"https://example.com/?a=1&amp;b=2\">two</a>",
+            to_markup(
+                "<html><head><title>JWT</title><style>b { color: red }</style></head>\n\
+                 <body><h2>Claims</h2>\n  <p><strong>sub</strong>:   1234 &amp; <em>more</p>\n\
+                 <ul><li>one</li><li><a href='https://example.com/?a=1&amp;b=2'>two</a></li>\
+                 </ul><!-- end --></body></html>"
+            )
+        );
+        assert_eq!("a&amp;#1 \u{a0}&lt;", to_markup("<p>a&#1 &nbsp;&#x3C;"));
+    }
+}
@@ -0,0 +1,115 @@
+use crate::command_pallete::RunMode;
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueuedRun {
+    pub script_name: String,
+    pub run_mode: RunMode,
+}
+
+// scripts launched on a document, run one at a time in the order they were launched so each sees
+// the result of the one before, every document has its own queue so documents don't wait on
+// each other
+#[derive(Debug, Default)]
+pub struct ExecutionQueue {
+    running: Option<QueuedRun>,
+    // when the running script was started
+    started: Option<Instant>,
+    queued: VecDeque<QueuedRun>,
+}
+
+impl ExecutionQueue {
+    pub fn push(&mut self, run: QueuedRun) {
+        self.queued.push_back(run);
+    }
+
+    // queues a run ahead of everything else, e.g. to restart a script
+    pub fn push_front(&mut self, run: QueuedRun) {
+        self.queued.push_front(run);
+    }
+
+    // takes the next queued run, unless a script is already running
+    pub fn start_next(&mut self) -> Option<QueuedRun> {
+        if self.running.is_some() {
+            return None;
+        }
+
+        self.running = self.queued.pop_front();
+        self.started = self.running.as_ref().map(|_| Instant::now());
+        self.running.clone()
+    }
+
+    // the run that starts next
+    pub fn next(&self) -> Option<&QueuedRun> {
+        self.queued.front()
+    }
+
+    pub fn running(&self) -> Option<&QueuedRun> {
+        self.running.as_ref()
+    }
+
+    // how long the running script has been running for
+    pub fn running_for(&self) -> Option<Duration> {
+        self.started.map(|started| started.elapsed())
+    }
+
+    // marks the running script as finished, returning it
+    pub fn finish(&mut self) -> Option<QueuedRun> {
+        self.started = None;
+        self.running.take()
+    }
+
+    // drops every run that hasn't started yet, returns how many were dropped
+    pub fn cancel_queued(&mut self) -> usize {
+        let cancelled = self.queued.len();
+        self.queued.clear();
+        cancelled
+    }
+
+    // number of runs waiting for the running script to finish
+    pub fn queued(&self) -> usize {
+        self.queued.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(script_name: &str) -> QueuedRun {
+        QueuedRun {
+            script_name: script_name.to_string(),
+            run_mode: RunMode::Replace,
+        }
+    }
+
+    #[test]
+    fn test_queue() {
+        let mut queue = ExecutionQueue::default();
+        queue.push(run("a"));
+        queue.push(run("b"));
+
+        assert_eq!(None, queue.running_for());
+        assert_eq!(Some(&run("a")), queue.next());
+        assert_eq!(Some(run("a")), queue.start_next());
+        assert!(queue.running_for().is_some());
+        assert_eq!(None, queue.start_next()); // "a" is still running
+        assert_eq!(1, queue.queued());
+        assert_eq!(Some(&run("b")), queue.next());
+
+        queue.push(run("c"));
+        queue.push_front(run("a"));
+        assert_eq!(Some(run("a")), queue.finish());
+        assert_eq!(Some(run("a")), queue.start_next());
+        assert_eq!(Some(&run("a")), queue.running());
+
+        assert_eq!(2, queue.cancel_queued());
+        assert_eq!(Some(run("a")), queue.finish());
+        assert_eq!(None, queue.start_next());
+        assert_eq!(None, queue.running());
+        assert_eq!(None, queue.running_for());
+    }
+}
@@ -0,0 +1,80 @@
+// names of the scripts run most recently, stored in the config directory so the last script can
+// be run again and recent scripts are listed first in the command pallete
+
+use serde::{Deserialize, Serialize};
+use simple_error::SimpleError;
+use std::{collections::VecDeque, fs, path::Path};
+
+const RECENT_FILE: &str = "recent.json";
+// scripts remembered, the least recently run are forgotten first
+const MAX_RECENT: usize = 10;
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Recent {
+    // most recent first, each script is listed once
+    pub scripts: VecDeque<String>,
+}
+
+impl Recent {
+    pub fn load(config_dir: &Path) -> Self {
+        let path = config_dir.join(RECENT_FILE);
+
+        match fs::read_to_string(&path) {
+            Ok(source) => serde_jsonrc::from_str(&source).unwrap_or_else(|e| {
+                error!("invalid recent scripts file {}: {}", path.display(), e);
+                Recent::default()
+            }),
+            Err(_) => Recent::default(),
+        }
+    }
+
+    pub fn save(&self, config_dir: &Path) -> Result<(), SimpleError> {
+        let path = config_dir.join(RECENT_FILE);
+        let source = serde_jsonrc::to_string(self)
+            .map_err(|e| SimpleError::with("could not serialize recent scripts", e))?;
+
+        fs::write(&path, source)
+            .map_err(|e| SimpleError::with(&format!("could not write {}", path.display()), e))
+    }
+
+    // moves the script to the front, running a script again doesn't list it twice
+    pub fn push(&mut self, script_name: &str) {
+        self.scripts.retain(|name| name != script_name);
+        self.scripts.push_front(script_name.to_string());
+        self.scripts.truncate(MAX_RECENT);
+    }
+
+    pub fn last(&self) -> Option<&str> {
+        self.scripts.front().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_recent() {
+        let config_dir =
+            std::env::temp_dir().join(format!("boop-gtk-recent-{}", std::process::id()));
+        fs::create_dir_all(&config_dir).unwrap();
+
+        let mut recent = Recent::load(&config_dir);
+        assert_eq!(None, recent.last());
+
+        for index in 0..MAX_RECENT + 2 {
+            recent.push(&index.to_string());
+        }
+        recent.push("5");
+        recent.save(&config_dir).unwrap();
+
+        let recent = Recent::load(&config_dir);
+        assert_eq!(Some("5"), recent.last());
+        assert_eq!(MAX_RECENT, recent.scripts.len());
+        assert_eq!(1, recent.scripts.iter().filter(|name| *name == "5").count());
+        assert!(!recent.scripts.contains(&"1".to_string()));
+
+        fs::remove_dir_all(&config_dir).unwrap();
+    }
+}
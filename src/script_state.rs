@@ -0,0 +1,100 @@
+// state each script keeps in `payload.state` (or `payload.persistent`, its older name), stored as
+// JSON in the config directory with a file per script so it can be inspected or reset by hand
+
+use simple_error::SimpleError;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+const STATE_DIR: &str = "scripts/state";
+
+// script names can contain anything, characters that aren't safe in file names are replaced
+fn path(config_dir: &Path, script_name: &str) -> PathBuf {
+    let file_name: String = script_name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == ' ' || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    config_dir
+        .join(STATE_DIR)
+        .join(format!("{}.json", file_name))
+}
+
+// the script's state, None if it has never stored any
+pub fn load(config_dir: &Path, script_name: &str) -> Option<String> {
+    fs::read_to_string(path(config_dir, script_name)).ok()
+}
+
+// None removes the state
+pub fn save(config_dir: &Path, script_name: &str, state: Option<&str>) -> Result<(), SimpleError> {
+    let path = path(config_dir, script_name);
+
+    match state {
+        Some(state) => {
+            let dir = config_dir.join(STATE_DIR);
+            fs::create_dir_all(&dir).map_err(|e| {
+                SimpleError::with(&format!("could not create {}", dir.display()), e)
+            })?;
+            fs::write(&path, state)
+                .map_err(|e| SimpleError::with(&format!("could not write {}", path.display()), e))
+        }
+        None => match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(SimpleError::with(
+                &format!("could not remove {}", path.display()),
+                e,
+            )),
+            _ => Ok(()),
+        },
+    }
+}
+
+// forgets the state of every script
+pub fn clear(config_dir: &Path) -> Result<(), SimpleError> {
+    let dir = config_dir.join(STATE_DIR);
+    match fs::remove_dir_all(&dir) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(SimpleError::with(
+            &format!("could not remove {}", dir.display()),
+            e,
+        )),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_script_state() {
+        let config_dir =
+            std::env::temp_dir().join(format!("boop-gtk-script-state-{}", std::process::id()));
+
+        assert_eq!(None, load(&config_dir, "Counter"));
+        save(&config_dir, "Counter", Some("{\"counter\":1}")).unwrap();
+        assert_eq!(
+            Some("{\"counter\":1}".to_string()),
+            load(&config_dir, "Counter")
+        );
+
+        // names can't escape the state directory
+        save(&config_dir, "../Counter", Some("{}")).unwrap();
+        assert!(config_dir.join(STATE_DIR).join("___Counter.json").exists());
+
+        save(&config_dir, "Counter", None).unwrap();
+        assert_eq!(None, load(&config_dir, "Counter"));
+        save(&config_dir, "Counter", None).unwrap();
+
+        clear(&config_dir).unwrap();
+        assert_eq!(None, load(&config_dir, "../Counter"));
+        clear(&config_dir).unwrap();
+
+        fs::remove_dir_all(&config_dir).unwrap();
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::{HashMap, HashSet};
+
+// score given to a query word that matches with a single typo, halved for each further typo
+const TYPO_MATCH_SCORE: isize = 16;
+
+// typo-tolerant index over the words in script names and tags, so queries like "jsno" still find
+// "Format JSON", used when fuzzy matching finds nothing
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    // script name to the words it's indexed by
+    entries: HashMap<String, Vec<String>>,
+    // trigram to the names of scripts with a word containing it
+    trigrams: HashMap<String, HashSet<String>>,
+}
+
+impl SearchIndex {
+    // brings the index up to date with the given (name, tags) of each script, only scripts that
+    // were added, removed or changed are re-indexed
+    pub fn update<'a>(&mut self, scripts: impl Iterator<Item = (&'a str, Option<&'a str>)>) {
+        let scripts: HashMap<&str, Vec<String>> = scripts
+            .map(|(name, tags)| (name, words(name, tags)))
+            .collect();
+
+        let stale: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(name, words)| scripts.get(name.as_str()) != Some(words))
+            .map(|(name, _)| name.clone())
+            .collect();
+        for name in stale {
+            self.remove(&name);
+        }
+
+        for (name, words) in scripts {
+            if !self.entries.contains_key(name) {
+                self.insert(name, words);
+            }
+        }
+    }
+
+    fn insert(&mut self, name: &str, words: Vec<String>) {
+        debug!("indexing {}", name);
+
+        for word in &words {
+            for trigram in trigrams(word) {
+                self.trigrams
+                    .entry(trigram)
+                    .or_default()
+                    .insert(name.to_string());
+            }
+        }
+
+        self.entries.insert(name.to_string(), words);
+    }
+
+    fn remove(&mut self, name: &str) {
+        debug!("removing {} from index", name);
+
+        if let Some(words) = self.entries.remove(name) {
+            for word in &words {
+                for trigram in trigrams(word) {
+                    if let Some(names) = self.trigrams.get_mut(&trigram) {
+                        names.remove(name);
+                        if names.is_empty() {
+                            self.trigrams.remove(&trigram);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    // scores scripts where every word of the query is within a few typos of one of its words,
+    // keyed by script name
+    pub fn search(&self, query: &str) -> HashMap<String, isize> {
+        let query_words = words(query, None);
+        if query_words.is_empty() || query_words.iter().any(|word| word.chars().count() < 3) {
+            return HashMap::new(); // too short to tell typos apart from other words
+        }
+
+        // candidates share at least one trigram with each word of the query
+        let candidates = query_words
+            .iter()
+            .map(|word| {
+                trigrams(word)
+                    .iter()
+                    .filter_map(|trigram| self.trigrams.get(trigram))
+                    .flatten()
+                    .cloned()
+                    .collect::<HashSet<String>>()
+            })
+            .fold(None, |acc: Option<HashSet<String>>, names| match acc {
+                Some(acc) => Some(acc.intersection(&names).cloned().collect()),
+                None => Some(names),
+            })
+            .unwrap_or_default();
+
+        candidates
+            .into_iter()
+            .filter_map(|name| {
+                let words = &self.entries[&name];
+                let typos = query_words
+                    .iter()
+                    .map(|query_word| {
+                        words
+                            .iter()
+                            .map(|word| distance(query_word, word))
+                            .filter(|typos| *typos <= max_typos(query_word))
+                            .min()
+                    })
+                    .sum::<Option<usize>>()?;
+
+                Some((name, (TYPO_MATCH_SCORE >> typos.saturating_sub(1)).max(1)))
+            })
+            .collect()
+    }
+}
+
+// lowercase words in a script's name and comma separated tags
+fn words(name: &str, tags: Option<&str>) -> Vec<String> {
+    name.split(|c: char| !c.is_alphanumeric())
+        .chain(tags.unwrap_or("").split(|c: char| !c.is_alphanumeric()))
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_lowercase())
+        .collect()
+}
+
+// trigrams of a word padded with spaces, e.g. "json" has " js", "jso", "son" and "on "
+fn trigrams(word: &str) -> Vec<String> {
+    let padded: Vec<char> = format!(" {} ", word).chars().collect();
+    padded
+        .windows(3)
+        .map(|trigram| trigram.iter().collect())
+        .collect()
+}
+
+// number of typos tolerated in a query word, longer words are allowed more
+fn max_typos(word: &str) -> usize {
+    match word.chars().count() {
+        0..=4 => 1,
+        _ => 2,
+    }
+}
+
+// optimal string alignment distance: insertions, deletions, substitutions and swapping two
+// adjacent characters each count as one typo
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    d[a.len()][b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(scripts: &[(&str, Option<&str>)]) -> SearchIndex {
+        let mut index = SearchIndex::default();
+        index.update(scripts.iter().cloned());
+        index
+    }
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(0, distance("json", "json"));
+        assert_eq!(1, distance("jsno", "json"));
+        assert_eq!(1, distance("bsae64", "base64"));
+        assert_eq!(1, distance("jsn", "json"));
+        assert_eq!(3, distance("kitten", "sitting"));
+    }
+
+    #[test]
+    fn test_typos() {
+        let index = index(&[
+            ("Format JSON", Some("json,prettify")),
+            ("Base64 Encode", Some("base64,btoa")),
+            ("Sort lines", None),
+        ]);
+
+        assert!(index.search("jsno").contains_key("Format JSON"));
+        assert!(index.search("bsae64").contains_key("Base64 Encode"));
+        assert!(index.search("frmat jsno").contains_key("Format JSON"));
+        assert!(index.search("prettfy").contains_key("Format JSON"));
+        assert!(index.search("xyzzy").is_empty());
+        assert!(index.search("js").is_empty());
+
+        // fewer typos score higher
+        let one = index.search("sotr")["Sort lines"];
+        let two = index.search("sotr linse")["Sort lines"];
+        assert!(one > two);
+    }
+
+    #[test]
+    fn test_update() {
+        let mut index = index(&[("Format JSON", None), ("Sort lines", None)]);
+
+        index.update(vec![("Format JSON", Some("prettify")), ("Reverse", None)].into_iter());
+        assert!(index.search("prettfy").contains_key("Format JSON"));
+        assert!(index.search("sotr").is_empty());
+        assert!(index.search("revrese").contains_key("Reverse"));
+        assert!(!index
+            .trigrams
+            .values()
+            .flatten()
+            .any(|name| name == "Sort lines"));
+    }
+}
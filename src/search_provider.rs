@@ -0,0 +1,159 @@
+// GNOME Shell search provider, typing the name of a script in the Activities overview offers to run
+// it in Boop, served by the D-Bus service
+
+use crate::script::Metadata;
+use std::{collections::HashMap, process};
+use zbus::{dbus_interface, fdo};
+use zvariant::Value;
+
+pub const OBJECT_PATH: &str = "/org/boop/GTK/SearchProvider";
+const APP_ICON: &str = "uk.co.mrbenshef.Boop-GTK";
+
+pub struct SearchProvider {
+    scripts: Vec<Metadata>,
+}
+
+impl SearchProvider {
+    pub fn new(scripts: Vec<Metadata>) -> Self {
+        SearchProvider { scripts }
+    }
+}
+
+// a script matches when every term is part of its name or one of its tags, ignoring case
+fn matches(metadata: &Metadata, terms: &[String]) -> bool {
+    let name = metadata.name.to_lowercase();
+    let tags = metadata.tags.as_deref().unwrap_or_default().to_lowercase();
+
+    terms.iter().all(|term| {
+        let term = term.to_lowercase();
+        name.contains(&term) || tags.contains(&term)
+    })
+}
+
+// opens the window with the command pallete searching for `query`, an already open window is
+// reused
+fn open_pallete(query: &str) -> fdo::Result<()> {
+    let exe = std::env::current_exe().map_err(|e| fdo::Error::Failed(e.to_string()))?;
+
+    process::Command::new(exe)
+        .arg("--select-script")
+        .arg(query)
+        .spawn()
+        .map(|_| ())
+        .map_err(|e| fdo::Error::SpawnFailed(e.to_string()))
+}
+
+// results are identified by the name of the script
+#[dbus_interface(name = "org.gnome.Shell.SearchProvider2")]
+impl SearchProvider {
+    fn get_initial_result_set(&self, terms: Vec<String>) -> Vec<String> {
+        self.scripts
+            .iter()
+            .filter(|metadata| matches(metadata, &terms))
+            .map(|metadata| metadata.name.clone())
+            .collect()
+    }
+
+    fn get_subsearch_result_set(
+        &self,
+        previous_results: Vec<String>,
+        terms: Vec<String>,
+    ) -> Vec<String> {
+        previous_results
+            .into_iter()
+            .filter(|name| {
+                self.scripts
+                    .iter()
+                    .any(|metadata| metadata.name == *name && matches(metadata, &terms))
+            })
+            .collect()
+    }
+
+    fn get_result_metas(&self, identifiers: Vec<String>) -> Vec<HashMap<String, Value>> {
+        identifiers
+            .into_iter()
+            .filter_map(|name| {
+                let metadata = self.scripts.iter().find(|metadata| metadata.name == name)?;
+
+                let mut meta = HashMap::new();
+                meta.insert("id".to_string(), Value::from(name.clone()));
+                meta.insert(
+                    "name".to_string(),
+                    Value::from(format!("Run {} in Boop", name)),
+                );
+                meta.insert(
+                    "description".to_string(),
+                    Value::from(metadata.description.clone()),
+                );
+                meta.insert("gicon".to_string(), Value::from(APP_ICON.to_string()));
+                Some(meta)
+            })
+            .collect()
+    }
+
+    fn activate_result(
+        &self,
+        identifier: &str,
+        _terms: Vec<String>,
+        _timestamp: u32,
+    ) -> fdo::Result<()> {
+        info!("{} activated from the search provider", identifier);
+        open_pallete(identifier)
+    }
+
+    fn launch_search(&self, terms: Vec<String>, _timestamp: u32) -> fdo::Result<()> {
+        open_pallete(&terms.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::script::Script;
+    use std::path::PathBuf;
+
+    fn metadata(name: &str, tags: &str) -> Metadata {
+        Script::from_source(
+            format!(
+                "/** {{ \"api\": 1, \"name\": \"{}\", \"description\": \"\", \"icon\": \"html\",
+                \"tags\": \"{}\" }} **/
+                function main(state) {{}}",
+                name, tags
+            ),
+            PathBuf::new(),
+        )
+        .unwrap()
+        .metadata
+    }
+
+    #[test]
+    fn test_search() {
+        let provider = SearchProvider::new(vec![
+            metadata("Format JSON", "json,prettify"),
+            metadata("Sort lines", "alphabetical"),
+        ]);
+        let terms = |terms: &[&str]| terms.iter().map(|term| term.to_string()).collect();
+
+        assert_eq!(
+            vec!["Format JSON".to_string()],
+            provider.get_initial_result_set(terms(&["format", "json"]))
+        );
+        assert_eq!(
+            vec!["Sort lines".to_string()],
+            provider.get_initial_result_set(terms(&["ALPHA"]))
+        );
+        assert_eq!(
+            Vec::<String>::new(),
+            provider.get_subsearch_result_set(
+                vec!["Format JSON".to_string()],
+                terms(&["format", "xml"])
+            )
+        );
+        assert_eq!(
+            1,
+            provider
+                .get_result_metas(terms(&["Sort lines", "Reverse"]))
+                .len()
+        );
+    }
+}
@@ -0,0 +1,193 @@
+// grows the selection by syntactic unit: word, string or bracket contents, the string or brackets
+// themselves, line, block of lines, then the whole document, a word directly before brackets is
+// included with them so calls like `f(x)` are selected together
+
+// the smallest range larger than `start..end` (offsets in chars) that contains it, or None if the
+// whole text is already selected
+pub fn expand(text: &str, start: usize, end: usize) -> Option<(usize, usize)> {
+    let chars: Vec<char> = text.chars().collect();
+    let (start, end) = (start.min(chars.len()), end.min(chars.len()));
+
+    let mut candidates = vec![word(&chars, start, end), (0, chars.len())];
+    candidates.extend(pairs(&chars, start, end));
+    candidates.extend(lines(&chars, start, end));
+
+    candidates
+        .into_iter()
+        .filter(|&(s, e)| s <= start && e >= end && e - s > end - start)
+        .min_by_key(|&(s, e)| e - s)
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn word(chars: &[char], start: usize, end: usize) -> (usize, usize) {
+    let mut word_start = start;
+    while word_start > 0 && is_word(chars[word_start - 1]) {
+        word_start -= 1;
+    }
+    let mut word_end = end;
+    while word_end < chars.len() && is_word(chars[word_end]) {
+        word_end += 1;
+    }
+
+    (word_start, word_end)
+}
+
+// the contents of quotes and brackets around the selection, and the quotes and brackets with their
+// contents
+fn pairs(chars: &[char], start: usize, end: usize) -> Vec<(usize, usize)> {
+    let mut found = Vec::new();
+    let mut brackets: Vec<(char, usize)> = Vec::new();
+    let mut quote: Option<(char, usize)> = None;
+
+    let mut index = 0;
+    while index < chars.len() {
+        let c = chars[index];
+        match quote {
+            Some((q, open)) => {
+                if c == '\\' {
+                    index += 1; // skip the escaped character
+                } else if c == q {
+                    found.push((open, index + 1));
+                    quote = None;
+                } else if c == '\n' {
+                    quote = None; // strings don't span lines
+                }
+            }
+            None => match c {
+                '"' | '\'' | '`' => quote = Some((c, index)),
+                '(' | '[' | '{' => brackets.push((c, index)),
+                ')' | ']' | '}' => {
+                    let open = match c {
+                        ')' => '(',
+                        ']' => '[',
+                        _ => '{',
+                    };
+                    // unbalanced brackets are ignored
+                    if let Some(position) = brackets.iter().rposition(|&(b, _)| b == open) {
+                        found.push((brackets[position].1, index + 1));
+                        brackets.truncate(position);
+                    }
+                }
+                _ => {}
+            },
+        }
+        index += 1;
+    }
+
+    found
+        .into_iter()
+        .filter(|&(open, close)| open <= start && close >= end)
+        .flat_map(|(open, close)| vec![(open + 1, close - 1), (open, close)])
+        .collect()
+}
+
+// the line around the selection without and with its indentation, and the block of lines around
+// it, bounded by blank lines
+fn lines(chars: &[char], start: usize, end: usize) -> Vec<(usize, usize)> {
+    let line_start = |index: usize| {
+        chars[..index]
+            .iter()
+            .rposition(|&c| c == '\n')
+            .map(|newline| newline + 1)
+            .unwrap_or(0)
+    };
+    let line_end = |index: usize| {
+        chars[index..]
+            .iter()
+            .position(|&c| c == '\n')
+            .map(|newline| index + newline)
+            .unwrap_or_else(|| chars.len())
+    };
+    let is_blank = |from: usize, to: usize| chars[from..to].iter().all(|c| c.is_whitespace());
+
+    let (first, last) = (line_start(start), line_end(end));
+    let mut indented = first;
+    while indented < last && (chars[indented] == ' ' || chars[indented] == '\t') {
+        indented += 1;
+    }
+
+    let mut block_start = first;
+    while block_start > 0 {
+        let previous = line_start(block_start - 1);
+        if is_blank(previous, block_start - 1) {
+            break;
+        }
+        block_start = previous;
+    }
+    let mut block_end = last;
+    while block_end < chars.len() {
+        let next = line_end(block_end + 1);
+        if is_blank(block_end + 1, next) {
+            break;
+        }
+        block_end = next;
+    }
+
+    vec![(indented, last), (first, last), (block_start, block_end)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // expands `text` from the selection marked with | or |..|, returning what's selected after
+    // each expansion
+    fn expansions(text: &str) -> Vec<String> {
+        let start = text.find('|').unwrap();
+        let end = text.rfind('|').unwrap();
+        let text = text.replace('|', "");
+        let chars: Vec<char> = text.chars().collect();
+
+        let (mut start, mut end) = (
+            text[..start].chars().count(),
+            text[..if end > start { end - 1 } else { end }]
+                .chars()
+                .count(),
+        );
+        let mut selections = Vec::new();
+        while let Some((s, e)) = expand(&text, start, end) {
+            selections.push(chars[s..e].iter().collect());
+            start = s;
+            end = e;
+        }
+        selections
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(
+            vec![
+                "name",
+                "user.name",
+                "(user.name)",
+                "log(user.name)",
+                "log(user.name);",
+                "  log(user.name);",
+                "\n  log(user.name);\n",
+                "{\n  log(user.name);\n}",
+                "function f() {\n  log(user.name);\n}",
+                "function f() {\n  log(user.name);\n}\n\nf();",
+            ],
+            expansions("function f() {\n  log(user.na|me);\n}\n\nf();")
+        );
+
+        assert_eq!(
+            vec![
+                "world",
+                "hello world",
+                "\"hello world\"",
+                " \"a\": \"hello world\" ",
+                "{ \"a\": \"hello world\" }"
+            ],
+            expansions("{ \"a\": \"hello w|orld\" }")
+        );
+
+        // partial words grow to the whole word
+        assert_eq!(Some((0, 5)), expand("hello world", 1, 3));
+        assert_eq!(None, expand("abc", 0, 3));
+        assert_eq!(Some((0, 2)), expand("ée", 2, 2));
+    }
+}
@@ -0,0 +1,540 @@
+use crate::{config::Config, script::Script};
+use gdk::ModifierType;
+use simple_error::SimpleError;
+use std::{
+    collections::HashMap,
+    fmt,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+// a chord is abandoned if its next key isn't pressed within this long
+const CHORD_TIMEOUT: Duration = Duration::from_millis(1500);
+
+// shortcuts that belong to the application and can't be taken by scripts
+const RESERVED_SHORTCUTS: [&str; 11] = [
+    "<Primary><Shift>P",
+    "<Primary>F",
+    "<Primary>H",
+    "<Alt><Shift>Right",
+    "<Primary>T",
+    "<Primary>W",
+    "<Primary>O",
+    "<Primary>S",
+    "<Primary><Shift>S",
+    "<Primary>comma",
+    "<Primary><Shift>B",
+];
+
+// a single key press, e.g. "<Primary>K"
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Stroke {
+    key: u32,
+    modifiers: ModifierType,
+}
+
+impl Stroke {
+    fn parse(accelerator: &str) -> Option<Self> {
+        let (key, modifiers) = gtk::accelerator_parse(accelerator);
+        if key == 0 {
+            return None;
+        }
+
+        Some(Stroke::new(key, modifiers))
+    }
+
+    fn new(key: u32, modifiers: ModifierType) -> Self {
+        Stroke {
+            key: gdk::keyval_to_lower(key),
+            modifiers: modifiers & gtk::accelerator_get_default_mod_mask(),
+        }
+    }
+
+    fn label(&self) -> String {
+        gtk::accelerator_get_label(self.key, self.modifiers)
+            .map(|label| label.to_string())
+            .unwrap_or_default()
+    }
+
+    // e.g. "<Primary>k", as `set_accels_for_action` takes it
+    fn accelerator(&self) -> String {
+        gtk::accelerator_name(self.key, self.modifiers)
+            .map(|name| name.to_string())
+            .unwrap_or_default()
+    }
+}
+
+// one or more strokes that must be pressed in order, written as accelerators separated by spaces,
+// e.g. "<Primary>K F" is Ctrl+K followed by F
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Chord(Vec<Stroke>);
+
+impl Chord {
+    fn parse(text: &str) -> Option<Self> {
+        Chord::parse_with(text, Stroke::parse)
+    }
+
+    // strokes are parsed by `parse_stroke`, so chords can be parsed without GTK in tests
+    fn parse_with(text: &str, parse_stroke: impl Fn(&str) -> Option<Stroke>) -> Option<Self> {
+        let strokes = text
+            .split_whitespace()
+            .map(parse_stroke)
+            .collect::<Option<Vec<Stroke>>>()?;
+
+        if strokes.is_empty() {
+            None
+        } else {
+            Some(Chord(strokes))
+        }
+    }
+
+    // true if one chord can't be typed without triggering the other
+    fn overlaps(&self, other: &Chord) -> bool {
+        self.0.iter().zip(other.0.iter()).all(|(a, b)| a == b)
+    }
+}
+
+impl fmt::Display for Chord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let labels: Vec<String> = self.0.iter().map(Stroke::label).collect();
+        write!(f, "{}", labels.join(" "))
+    }
+}
+
+#[derive(Debug)]
+struct Binding {
+    chord: Chord,
+    // None for reserved application shortcuts
+    script_name: Option<String>,
+}
+
+// why a shortcut couldn't be bound
+#[derive(Debug, Clone, PartialEq)]
+enum Reason {
+    Invalid,
+    // overlaps the chord bound to a script, or to the application if None
+    Taken(Option<String>, Chord),
+}
+
+// a shortcut that couldn't be bound
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub script_name: String,
+    pub shortcut: String,
+    reason: Reason,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({}) ", self.script_name, self.shortcut)?;
+        match &self.reason {
+            Reason::Invalid => write!(f, "is not a valid shortcut"),
+            Reason::Taken(script_name, chord) => write!(
+                f,
+                "conflicts with {} ({})",
+                script_name.as_deref().unwrap_or("an application shortcut"),
+                chord
+            ),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum KeyResult {
+    // key is not part of any shortcut
+    Unhandled,
+    // key started (or continued) a chord, see `pending_label`
+    Pending,
+    // chord of more than one key was completed, contains the name of the script to run, single
+    // keys are accelerators of the script's action instead, see `accels`
+    Matched(String),
+    // key did not continue the pending chord
+    Cancelled,
+}
+
+#[derive(Debug, Default)]
+pub struct Shortcuts {
+    bindings: Vec<Binding>,
+    pending: Vec<Stroke>,
+    // when the last key of the pending chord was pressed
+    pending_since: Option<Instant>,
+}
+
+impl Shortcuts {
+    // loads the user's overrides from `shortcuts` in config.json, a JSON object mapping script
+    // names to a shortcut, or null to remove the shortcut a script suggests
+    pub fn load_overrides(config_dir: &Path) -> HashMap<String, Option<String>> {
+        Config::load(config_dir).shortcuts
+    }
+
+    pub fn save_overrides(
+        config_dir: &Path,
+        overrides: &HashMap<String, Option<String>>,
+    ) -> Result<(), SimpleError> {
+        Config::update(config_dir, |config| config.shortcuts = overrides.clone())
+    }
+
+    // bind the shortcuts suggested by scripts, user overrides take precedence, returns the
+    // shortcuts along with the conflicts that prevented some from being bound
+    pub fn new(
+        scripts: &[Script],
+        overrides: &HashMap<String, Option<String>>,
+    ) -> (Self, Vec<Conflict>) {
+        Shortcuts::with_parser(scripts, overrides, Stroke::parse)
+    }
+
+    fn with_parser(
+        scripts: &[Script],
+        overrides: &HashMap<String, Option<String>>,
+        parse_stroke: impl Fn(&str) -> Option<Stroke> + Copy,
+    ) -> (Self, Vec<Conflict>) {
+        let mut shortcuts = Shortcuts::default();
+        let mut conflicts = Vec::new();
+
+        for accelerator in RESERVED_SHORTCUTS.iter() {
+            if let Some(chord) = Chord::parse_with(accelerator, parse_stroke) {
+                shortcuts.bindings.push(Binding {
+                    chord,
+                    script_name: None,
+                });
+            }
+        }
+
+        // user overrides are bound first so they win any conflicts
+        let mut requests: Vec<(&str, &str)> = overrides
+            .iter()
+            .filter_map(|(name, shortcut)| Some((name.as_str(), shortcut.as_deref()?)))
+            .collect();
+        requests.sort();
+        requests.extend(scripts.iter().filter_map(|script| {
+            if overrides.contains_key(&script.metadata.name) {
+                return None;
+            }
+            Some((
+                script.metadata.name.as_str(),
+                script.metadata.shortcut.as_deref()?,
+            ))
+        }));
+
+        for (script_name, text) in requests {
+            let chord = match Chord::parse_with(text, parse_stroke) {
+                Some(chord) => chord,
+                None => {
+                    conflicts.push(Conflict {
+                        script_name: script_name.to_string(),
+                        shortcut: text.to_string(),
+                        reason: Reason::Invalid,
+                    });
+                    continue;
+                }
+            };
+
+            if let Some(existing) = shortcuts.bindings.iter().find(|b| b.chord.overlaps(&chord)) {
+                conflicts.push(Conflict {
+                    script_name: script_name.to_string(),
+                    shortcut: text.to_string(),
+                    reason: Reason::Taken(existing.script_name.clone(), existing.chord.clone()),
+                });
+                continue;
+            }
+
+            info!("binding {} to {}", text, script_name);
+            shortcuts.bindings.push(Binding {
+                chord,
+                script_name: Some(script_name.to_string()),
+            });
+        }
+
+        for conflict in &conflicts {
+            warn!("shortcut not bound: {}", conflict);
+        }
+
+        (shortcuts, conflicts)
+    }
+
+    // the overrides with `shortcut` bound to the script, or its shortcut removed if none, unless
+    // that would leave this or another script without the shortcut it had
+    pub fn rebind(
+        scripts: &[Script],
+        overrides: &HashMap<String, Option<String>>,
+        script_name: &str,
+        shortcut: Option<&str>,
+    ) -> Result<HashMap<String, Option<String>>, Conflict> {
+        Shortcuts::rebind_with_parser(scripts, overrides, script_name, shortcut, Stroke::parse)
+    }
+
+    fn rebind_with_parser(
+        scripts: &[Script],
+        overrides: &HashMap<String, Option<String>>,
+        script_name: &str,
+        shortcut: Option<&str>,
+        parse_stroke: impl Fn(&str) -> Option<Stroke> + Copy,
+    ) -> Result<HashMap<String, Option<String>>, Conflict> {
+        let (_, before) = Shortcuts::with_parser(scripts, overrides, parse_stroke);
+
+        let mut overrides = overrides.clone();
+        overrides.insert(script_name.to_string(), shortcut.map(str::to_string));
+        let (_, after) = Shortcuts::with_parser(scripts, &overrides, parse_stroke);
+
+        match after.into_iter().find(|conflict| {
+            !before.iter().any(|existing| {
+                existing.script_name == conflict.script_name
+                    && existing.shortcut == conflict.shortcut
+            })
+        }) {
+            Some(conflict) => Err(conflict),
+            None => Ok(overrides),
+        }
+    }
+
+    // label of the shortcut bound to a script, e.g. "Ctrl+K F"
+    pub fn label_for(&self, script_name: &str) -> Option<String> {
+        self.bindings
+            .iter()
+            .find(|binding| binding.script_name.as_deref() == Some(script_name))
+            .map(|binding| binding.chord.to_string())
+    }
+
+    // the accelerator of each script bound to a single key, by script name, they're activated by
+    // GTK like any other action while chords are typed through `handle_key`
+    pub fn accels(&self) -> Vec<(String, String)> {
+        self.bindings
+            .iter()
+            .filter_map(
+                |binding| match (&binding.script_name, binding.chord.0.as_slice()) {
+                    (Some(script_name), [stroke]) => {
+                        Some((script_name.clone(), stroke.accelerator()))
+                    }
+                    _ => None,
+                },
+            )
+            .collect()
+    }
+
+    // label of the strokes of the chord typed so far, e.g. "Ctrl+K"
+    pub fn pending_label(&self) -> String {
+        Chord(self.pending.clone()).to_string()
+    }
+
+    pub fn handle_key(&mut self, key: u32, modifiers: ModifierType) -> KeyResult {
+        self.handle_stroke(Stroke::new(key, modifiers), Instant::now())
+    }
+
+    fn handle_stroke(&mut self, stroke: Stroke, now: Instant) -> KeyResult {
+        // a chord that wasn't continued in time is forgotten, the key may start another
+        if let Some(pending_since) = self.pending_since {
+            if now.duration_since(pending_since) > CHORD_TIMEOUT {
+                self.pending.clear();
+            }
+        }
+
+        let was_pending = !self.pending.is_empty();
+        self.pending.push(stroke);
+
+        let pending = &self.pending;
+        let candidates: Vec<&Binding> = self
+            .bindings
+            .iter()
+            .filter(|b| b.chord.0.starts_with(pending))
+            .collect();
+
+        let result = match candidates.as_slice() {
+            [] if was_pending => KeyResult::Cancelled,
+            [] => KeyResult::Unhandled,
+            // single keys are left to their accelerator, as are the application's own shortcuts
+            [binding] if binding.chord.0.len() == pending.len() => match &binding.script_name {
+                Some(script_name) if pending.len() > 1 => KeyResult::Matched(script_name.clone()),
+                _ => KeyResult::Unhandled,
+            },
+            _ => KeyResult::Pending,
+        };
+
+        if result == KeyResult::Pending {
+            self.pending_since = Some(now);
+        } else {
+            self.pending.clear();
+            self.pending_since = None;
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    // parses the accelerators used here like gtk::accelerator_parse, which needs GTK to be
+    // initialized
+    fn parse_stroke(accelerator: &str) -> Option<Stroke> {
+        let mut parts: Vec<&str> = accelerator.split('>').collect();
+        let key = match parts.pop()? {
+            "comma" => ',' as u32,
+            "Right" => *gdk::keys::constants::Right,
+            key if key.chars().count() == 1 => key.chars().next()?.to_ascii_lowercase() as u32,
+            _ => return None,
+        };
+
+        let mut modifiers = ModifierType::empty();
+        for part in parts {
+            modifiers |= match part {
+                "<Primary" => ModifierType::CONTROL_MASK,
+                "<Shift" => ModifierType::SHIFT_MASK,
+                "<Alt" => ModifierType::MOD1_MASK,
+                _ => return None,
+            };
+        }
+
+        Some(Stroke { key, modifiers })
+    }
+
+    fn script(name: &str, shortcut: &str) -> Script {
+        Script::from_source(
+            format!(
+                "/**
+                    {{
+                        \"api\":1,
+                        \"name\":\"{}\",
+                        \"description\":\"\",
+                        \"icon\":\"html\",
+                        \"shortcut\":\"{}\"
+                    }}
+                **/",
+                name, shortcut
+            ),
+            PathBuf::new(),
+        )
+        .unwrap()
+    }
+
+    fn press(shortcuts: &mut Shortcuts, accelerator: &str, now: Instant) -> KeyResult {
+        shortcuts.handle_stroke(parse_stroke(accelerator).unwrap(), now)
+    }
+
+    #[test]
+    fn test_parse() {
+        let chord = Chord::parse_with("<Primary>K  F", parse_stroke).unwrap();
+        assert_eq!(
+            vec![
+                Stroke {
+                    key: 'k' as u32,
+                    modifiers: ModifierType::CONTROL_MASK
+                },
+                Stroke {
+                    key: 'f' as u32,
+                    modifiers: ModifierType::empty()
+                }
+            ],
+            chord.0
+        );
+
+        assert_eq!(None, Chord::parse_with("", parse_stroke));
+        assert_eq!(None, Chord::parse_with("<Primary>K <Hyper>F", parse_stroke));
+
+        let prefix = Chord::parse_with("<Primary>K", parse_stroke).unwrap();
+        assert!(prefix.overlaps(&chord));
+        assert!(chord.overlaps(&prefix));
+        assert!(!chord.overlaps(&Chord::parse_with("<Primary>K G", parse_stroke).unwrap()));
+    }
+
+    #[test]
+    fn test_conflicts() {
+        let scripts = vec![
+            script("Format JSON", "<Primary>K F"),
+            script("Sort Lines", "<Primary>K"),
+            script("Find", "<Primary>F"),
+            script("Broken", "<Hyper>J"),
+            script("Base64", "<Primary>K B"),
+        ];
+        let mut overrides = HashMap::new();
+        overrides.insert("Base64".to_string(), Some("<Alt>B".to_string()));
+
+        let (shortcuts, conflicts) = Shortcuts::with_parser(&scripts, &overrides, parse_stroke);
+        let reasons: Vec<(&str, &Reason)> = conflicts
+            .iter()
+            .map(|conflict| (conflict.script_name.as_str(), &conflict.reason))
+            .collect();
+        assert_eq!(
+            vec![
+                (
+                    "Sort Lines",
+                    &Reason::Taken(
+                        Some("Format JSON".to_string()),
+                        Chord::parse_with("<Primary>K F", parse_stroke).unwrap()
+                    )
+                ),
+                (
+                    "Find",
+                    &Reason::Taken(None, Chord::parse_with("<Primary>F", parse_stroke).unwrap())
+                ),
+                ("Broken", &Reason::Invalid),
+            ],
+            reasons
+        );
+        assert!(shortcuts
+            .bindings
+            .iter()
+            .any(|binding| binding.script_name.as_deref() == Some("Base64")
+                && binding.chord == Chord::parse_with("<Alt>B", parse_stroke).unwrap()));
+
+        // taking the shortcut of another script is refused as it would lose it, removing a
+        // shortcut isn't
+        let conflict = Shortcuts::rebind_with_parser(
+            &scripts,
+            &overrides,
+            "Base64",
+            Some("<Primary>K F"),
+            parse_stroke,
+        )
+        .unwrap_err();
+        assert_eq!("Format JSON", conflict.script_name);
+        let overrides =
+            Shortcuts::rebind_with_parser(&scripts, &overrides, "Format JSON", None, parse_stroke)
+                .unwrap();
+        assert_eq!(Some(&None), overrides.get("Format JSON"));
+    }
+
+    #[test]
+    fn test_handle_key() {
+        let scripts = vec![
+            script("Format JSON", "<Primary>K F"),
+            script("Base64", "<Alt>B"),
+        ];
+        let (mut shortcuts, _) = Shortcuts::with_parser(&scripts, &HashMap::new(), parse_stroke);
+        let now = Instant::now();
+
+        assert_eq!(KeyResult::Unhandled, press(&mut shortcuts, "J", now));
+        // single keys are activated by GTK through their accelerator
+        assert_eq!(KeyResult::Unhandled, press(&mut shortcuts, "<Alt>B", now));
+        // reserved shortcuts are left to the application
+        assert_eq!(
+            KeyResult::Unhandled,
+            press(&mut shortcuts, "<Primary>F", now)
+        );
+
+        assert_eq!(KeyResult::Pending, press(&mut shortcuts, "<Primary>K", now));
+        assert_eq!(
+            KeyResult::Matched("Format JSON".to_string()),
+            press(&mut shortcuts, "F", now + Duration::from_millis(500))
+        );
+
+        // a key that doesn't continue the chord cancels it
+        assert_eq!(KeyResult::Pending, press(&mut shortcuts, "<Primary>K", now));
+        assert_eq!(KeyResult::Cancelled, press(&mut shortcuts, "G", now));
+        assert_eq!(KeyResult::Unhandled, press(&mut shortcuts, "F", now));
+
+        // the next key starts over once the chord has timed out
+        assert_eq!(KeyResult::Pending, press(&mut shortcuts, "<Primary>K", now));
+        let later = now + CHORD_TIMEOUT + Duration::from_millis(1);
+        assert_eq!(KeyResult::Unhandled, press(&mut shortcuts, "F", later));
+        assert_eq!(
+            KeyResult::Pending,
+            press(&mut shortcuts, "<Primary>K", later)
+        );
+        assert_eq!(
+            KeyResult::Unhandled,
+            press(&mut shortcuts, "<Alt>B", later + CHORD_TIMEOUT * 2)
+        );
+    }
+}
@@ -0,0 +1,123 @@
+// counts shown in the status bar for the document, its selection and its line endings
+
+use crate::line_ending::{self, LineEnding};
+use std::time::Duration;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Stats {
+    pub characters: usize,
+    pub words: usize,
+    pub lines: usize,
+    // characters selected, 0 if nothing is
+    pub selected: usize,
+}
+
+impl Stats {
+    pub fn new(text: &str, selection: Option<&str>) -> Self {
+        Stats {
+            characters: text.chars().count(),
+            words: text.split_whitespace().count(),
+            lines: line_ending::line_breaks(text).count() + 1,
+            selected: selection.map_or(0, |selection| selection.chars().count()),
+        }
+    }
+}
+
+// e.g. "0.4s" or "2m 5s"
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    if seconds < 60 {
+        format!("{:.1}s", duration.as_secs_f64())
+    } else {
+        format!("{}m {}s", seconds / 60, seconds % 60)
+    }
+}
+
+// e.g. "12 lines, 40 words, 230 characters (5 selected) · UTF-8 · LF · Sort lines took 0.4s"
+pub fn summary(
+    stats: &Stats,
+    encoding: &str,
+    line_ending: Option<LineEnding>,
+    last_run: Option<(&str, Duration)>,
+) -> String {
+    let plural = |count: usize, noun: &str| {
+        if count == 1 {
+            format!("1 {}", noun)
+        } else {
+            format!("{} {}s", count, noun)
+        }
+    };
+
+    let mut parts = vec![format!(
+        "{}, {}, {}",
+        plural(stats.lines, "line"),
+        plural(stats.words, "word"),
+        plural(stats.characters, "character")
+    )];
+    if stats.selected > 0 {
+        parts[0].push_str(&format!(" ({} selected)", stats.selected));
+    }
+    parts.push(encoding.to_string());
+    if let Some(line_ending) = line_ending {
+        parts.push(line_ending.to_string());
+    }
+    if let Some((script_name, duration)) = last_run {
+        parts.push(format!(
+            "{} took {}",
+            script_name,
+            format_duration(duration)
+        ));
+    }
+
+    parts.join(" \u{b7} ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        assert_eq!(
+            Stats {
+                characters: 0,
+                words: 0,
+                lines: 1,
+                selected: 0
+            },
+            Stats::new("", None)
+        );
+        assert_eq!(
+            Stats {
+                characters: 14,
+                words: 3,
+                lines: 3,
+                selected: 2
+            },
+            Stats::new("héllo\r\nworld\n!", Some("wo"))
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        assert_eq!(
+            "1 line, 2 words, 11 characters (5 selected) \u{b7} UTF-8",
+            summary(
+                &Stats::new("hello world", Some("hello")),
+                "UTF-8",
+                None,
+                None
+            )
+        );
+        assert_eq!(
+            "2 lines, 2 words, 4 characters \u{b7} Windows-1252 \u{b7} CRLF \u{b7} Sort lines took 1m 5s",
+            summary(
+                &Stats::new("a\r\nb", None),
+                "Windows-1252",
+                Some(LineEnding::CrLf),
+                Some(("Sort lines", Duration::from_secs(65)))
+            )
+        );
+        assert_eq!("0.4s", format_duration(Duration::from_millis(400)));
+    }
+}
@@ -0,0 +1,182 @@
+use crate::metrics::Metrics;
+use serde::{Deserialize, Serialize};
+use simple_error::SimpleError;
+use std::{
+    collections::{HashMap, VecDeque},
+    fs,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const STORE_FILE: &str = "store.json";
+// transformations kept in the history, oldest are dropped first
+const MAX_HISTORY: usize = 50;
+// text larger than this isn't kept in the history
+const MAX_HISTORY_TEXT: usize = 16 * 1024;
+
+// data kept between sessions, stored as JSON in the data directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Store {
+    // how many times each script has been run, by script name
+    pub usage: HashMap<String, u64>,
+    // most recent transformations of the documents, oldest first, the current tab's are listed in
+    // the history sidebar
+    pub history: VecDeque<HistoryEntry>,
+    // only recorded if the user opted in to usage metrics
+    pub metrics: Metrics,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    // id of the tab the change was made to
+    #[serde(default)]
+    pub tab: usize,
+    pub script: String,
+    // seconds since the unix epoch
+    pub time: u64,
+    // text before the script ran, unless it was too large to keep
+    pub before: Option<String>,
+}
+
+impl Store {
+    // loads the store, a corrupt store is moved aside so it can be inspected and a new one is
+    // started
+    pub fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join(STORE_FILE);
+
+        match fs::read_to_string(&path) {
+            Ok(source) => serde_jsonrc::from_str(&source).unwrap_or_else(|e| {
+                error!("corrupt store {}: {}", path.display(), e);
+                let corrupt_path = path.with_extension("json.corrupt");
+                if let Err(e) = fs::rename(&path, &corrupt_path) {
+                    error!("could not move corrupt store aside: {}", e);
+                }
+                Store::default()
+            }),
+            Err(_) => Store::default(),
+        }
+    }
+
+    // written to a temporary file first, so the store isn't left half written if we're
+    // interrupted
+    pub fn save(&self, data_dir: &Path) -> Result<(), SimpleError> {
+        let path = data_dir.join(STORE_FILE);
+        let temp_path = path.with_extension("json.tmp");
+        let source = serde_jsonrc::to_string(self)
+            .map_err(|e| SimpleError::with("could not serialize store", e))?;
+
+        fs::create_dir_all(data_dir).map_err(|e| {
+            SimpleError::with(&format!("could not create {}", data_dir.display()), e)
+        })?;
+        fs::write(&temp_path, source).map_err(|e| {
+            SimpleError::with(&format!("could not write {}", temp_path.display()), e)
+        })?;
+        fs::rename(&temp_path, &path)
+            .map_err(|e| SimpleError::with(&format!("could not write {}", path.display()), e))
+    }
+
+    // forgets everything, removing the store from disk
+    pub fn clear(&mut self, data_dir: &Path) -> Result<(), SimpleError> {
+        *self = Store::default();
+
+        let path = data_dir.join(STORE_FILE);
+        match fs::remove_file(&path) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(SimpleError::with(
+                &format!("could not remove {}", path.display()),
+                e,
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    // counts a run of a script
+    pub fn record_run(&mut self, script: &str) {
+        *self.usage.entry(script.to_string()).or_default() += 1;
+    }
+
+    // adds a change a script or pipeline made to a tab's document to the history
+    pub fn record_change(&mut self, tab: usize, script: &str, before: &str) {
+        self.history.push_back(HistoryEntry {
+            tab,
+            script: script.to_string(),
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_secs())
+                .unwrap_or(0),
+            before: Some(before.to_string()).filter(|before| before.len() <= MAX_HISTORY_TEXT),
+        });
+        while self.history.len() > MAX_HISTORY {
+            self.history.pop_front();
+        }
+    }
+
+    // the text from before the change at `index`, which is forgotten along with every change
+    // made to the same tab after it, None if the text wasn't kept
+    pub fn revert(&mut self, index: usize) -> Option<String> {
+        let entry = self.history.get(index)?;
+        let (tab, before) = (entry.tab, entry.before.clone()?);
+
+        let mut position = 0;
+        self.history.retain(|entry| {
+            let kept = position < index || entry.tab != tab;
+            position += 1;
+            kept
+        });
+        Some(before)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_store() {
+        let data_dir = std::env::temp_dir().join(format!("boop-gtk-store-{}", std::process::id()));
+
+        let mut store = Store::load(&data_dir);
+        for _ in 0..MAX_HISTORY + 1 {
+            store.record_run("Sort lines");
+            store.record_change(0, "Sort lines", "b\na");
+        }
+        store.record_run("Format JSON");
+        store.record_change(0, "Format JSON", &"x".repeat(MAX_HISTORY_TEXT + 1));
+        store.save(&data_dir).unwrap();
+
+        let mut store = Store::load(&data_dir);
+        assert_eq!(
+            Some(&(MAX_HISTORY as u64 + 1)),
+            store.usage.get("Sort lines")
+        );
+        assert_eq!(MAX_HISTORY, store.history.len());
+        assert_eq!(None, store.history.back().unwrap().before);
+
+        // text that wasn't kept can't be reverted to
+        assert_eq!(None, store.revert(MAX_HISTORY - 1));
+        assert_eq!(MAX_HISTORY, store.history.len());
+        assert_eq!(Some("b\na".to_string()), store.revert(MAX_HISTORY - 2));
+        assert_eq!(MAX_HISTORY - 2, store.history.len());
+        assert_eq!(None, store.revert(MAX_HISTORY));
+
+        // changes to other tabs are kept
+        store.record_change(1, "Format JSON", "{}");
+        store.record_change(0, "Sort lines", "a\nb");
+        store.record_change(1, "Sort lines", "[]");
+        let index = store.history.len() - 3;
+        assert_eq!(Some("{}".to_string()), store.revert(index));
+        assert_eq!(index + 1, store.history.len());
+        assert_eq!(0, store.history.back().unwrap().tab);
+
+        // corrupt stores are moved aside
+        fs::write(data_dir.join(STORE_FILE), "{ \"usage\": ").unwrap();
+        assert!(Store::load(&data_dir).usage.is_empty());
+        assert!(data_dir.join("store.json.corrupt").exists());
+
+        store.clear(&data_dir).unwrap();
+        assert!(store.usage.is_empty());
+        assert!(!data_dir.join(STORE_FILE).exists());
+
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+}
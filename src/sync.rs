@@ -0,0 +1,194 @@
+// git repositories of scripts, e.g. a team's shared transforms, cloned into `scripts/remote/<name>`
+// and pulled on demand or at startup, the watcher loads the scripts that change
+
+use crate::catalog::REMOTE_DIR;
+use serde::{Deserialize, Serialize};
+use simple_error::{bail, SimpleError};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+};
+
+// written by the preferences window
+const REPOSITORIES_FILE: &str = "repositories.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Repository {
+    // directory the repository is cloned into, and the source its scripts are tagged with
+    pub name: String,
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Repositories {
+    pub repositories: Vec<Repository>,
+    pub sync_on_startup: bool,
+}
+
+impl Repositories {
+    pub fn load(config_dir: &Path) -> Self {
+        let path = config_dir.join(REPOSITORIES_FILE);
+
+        match fs::read_to_string(&path) {
+            Ok(source) => serde_jsonrc::from_str(&source).unwrap_or_else(|e| {
+                error!("invalid repositories file {}: {}", path.display(), e);
+                Repositories::default()
+            }),
+            Err(_) => Repositories::default(),
+        }
+    }
+
+    pub fn save(&self, config_dir: &Path) -> Result<(), SimpleError> {
+        let path = config_dir.join(REPOSITORIES_FILE);
+        let source = serde_jsonrc::to_string_pretty(self)
+            .map_err(|e| SimpleError::with("could not serialize repositories", e))?;
+
+        fs::write(&path, source)
+            .map_err(|e| SimpleError::with(&format!("could not write {}", path.display()), e))
+    }
+
+    // adds the repository at `url`, named after it, e.g. "transforms" for
+    // https://github.com/team/transforms.git
+    pub fn add(&mut self, url: &str) -> Result<&Repository, SimpleError> {
+        let url = url.trim();
+        let name = name_from_url(url);
+        if name.is_empty() {
+            bail!("{} is not a git repository", url);
+        }
+        if self
+            .repositories
+            .iter()
+            .any(|repository| repository.name == name)
+        {
+            bail!("there's already a repository called {}", name);
+        }
+
+        self.repositories.push(Repository {
+            name,
+            url: url.to_string(),
+        });
+        Ok(&self.repositories[self.repositories.len() - 1])
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.repositories
+            .retain(|repository| repository.name != name);
+    }
+}
+
+// the last part of the url without ".git", only letters, numbers, '-', '_' and '.' are kept so the
+// name is always a single directory
+fn name_from_url(url: &str) -> String {
+    let last = url
+        .trim_end_matches('/')
+        .rsplit(|c| c == '/' || c == ':')
+        .next()
+        .unwrap_or_default();
+    let last = last.strip_suffix(".git").unwrap_or(last);
+
+    last.chars()
+        .filter(|c| c.is_alphanumeric() || *c == '-' || *c == '_' || *c == '.')
+        .collect::<String>()
+        .trim_start_matches('.')
+        .to_string()
+}
+
+pub fn repository_dir(scripts_dir: &Path, name: &str) -> PathBuf {
+    scripts_dir.join(REMOTE_DIR).join(name)
+}
+
+// clones the repository, or pulls it if it's been cloned before
+pub fn sync(scripts_dir: &Path, repository: &Repository) -> Result<(), SimpleError> {
+    let dir = repository_dir(scripts_dir, &repository.name);
+
+    let mut command = Command::new("git");
+    if dir.join(".git").is_dir() {
+        info!("pulling {} into {}", repository.url, dir.display());
+        command.arg("-C").arg(&dir).args(&["pull", "--ff-only"]);
+    } else {
+        info!("cloning {} into {}", repository.url, dir.display());
+        fs::create_dir_all(scripts_dir.join(REMOTE_DIR))
+            .map_err(|e| SimpleError::with(&format!("could not create {}", dir.display()), e))?;
+        command
+            .args(&["clone", "--depth", "1", "--"])
+            .arg(&repository.url)
+            .arg(&dir);
+    }
+
+    // runs in the background, so git mustn't wait for a password
+    let output = command
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .map_err(|e| SimpleError::with("could not run git, is it installed?", e))?;
+    if !output.status.success() {
+        bail!(
+            "git failed, {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(())
+}
+
+// syncs each repository on another thread, sending the name and result of each
+pub fn sync_in_background(
+    scripts_dir: PathBuf,
+    repositories: Vec<Repository>,
+    sender: glib::Sender<(String, Result<(), SimpleError>)>,
+) {
+    thread::spawn(move || {
+        for repository in repositories {
+            let result = sync(&scripts_dir, &repository);
+            if sender.send((repository.name, result)).is_err() {
+                warn!("app stopped listening for repository syncs");
+                return;
+            }
+        }
+    });
+}
+
+// removes the clone of a repository that's no longer synced, its scripts are unloaded by the
+// watcher
+pub fn remove_clone(scripts_dir: &Path, name: &str) -> Result<(), SimpleError> {
+    let dir = repository_dir(scripts_dir, name);
+    if !dir.exists() {
+        return Ok(());
+    }
+
+    fs::remove_dir_all(&dir)
+        .map_err(|e| SimpleError::with(&format!("could not remove {}", dir.display()), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add() {
+        assert_eq!(
+            "transforms",
+            name_from_url("https://github.com/team/transforms.git")
+        );
+        assert_eq!("boop-scripts", name_from_url("git@host:me/boop-scripts/"));
+        assert_eq!("etc", name_from_url("https://host/../..etc"));
+
+        let mut repositories = Repositories::default();
+        assert_eq!(
+            "transforms",
+            repositories
+                .add(" https://github.com/team/transforms.git ")
+                .unwrap()
+                .name
+        );
+        assert!(repositories
+            .add("https://example.com/other/transforms")
+            .is_err());
+        assert!(repositories.add(" ").is_err());
+
+        repositories.remove("transforms");
+        assert!(repositories.repositories.is_empty());
+    }
+}
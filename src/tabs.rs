@@ -0,0 +1,619 @@
+// scratchpads open in tabs, the editor is shared by every tab and shows the buffer of the current
+// one, so each tab keeps its own undo history and language
+
+use crate::files;
+use gtk::prelude::*;
+use serde::{Deserialize, Serialize};
+use simple_error::{bail, SimpleError};
+use sourceview::prelude::*;
+use std::{
+    cell::{Cell, RefCell},
+    fs,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+const SESSION_FILE: &str = "session.json";
+// buffers are always UTF-8, as are scratchpads, files are saved in the encoding they were read as
+const UTF8: &str = "UTF-8";
+
+// a tab as it's kept between sessions
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SavedTab {
+    // kept so the changes in the history still belong to the tab, None in older sessions
+    pub id: Option<usize>,
+    pub title: String,
+    pub text: String,
+    // id of the language the text is highlighted as, None for plain text
+    pub language: Option<String>,
+    // offset of the cursor in characters
+    pub cursor: usize,
+    // the file the tab was opened from or saved to
+    pub path: Option<PathBuf>,
+    // the text has changes that weren't saved to the file
+    pub modified: bool,
+    // what the file is saved as, None for UTF-8
+    pub encoding: Option<String>,
+}
+
+// tabs open when the window was closed and the size of the window, stored in the data directory
+#[derive(Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    pub tabs: Vec<SavedTab>,
+    // index of the tab that was shown
+    pub current: usize,
+    // width and height of the window when it wasn't maximized
+    pub window_size: Option<(i32, i32)>,
+    pub maximized: bool,
+}
+
+impl Session {
+    pub fn load(data_dir: &Path) -> Self {
+        let path = data_dir.join(SESSION_FILE);
+
+        match fs::read_to_string(&path) {
+            Ok(source) => serde_jsonrc::from_str(&source).unwrap_or_else(|e| {
+                error!("invalid session file {}: {}", path.display(), e);
+                Session::default()
+            }),
+            Err(_) => Session::default(),
+        }
+    }
+
+    pub fn save(&self, data_dir: &Path) -> Result<(), SimpleError> {
+        fs::create_dir_all(data_dir).map_err(|e| {
+            SimpleError::with(&format!("could not create {}", data_dir.display()), e)
+        })?;
+
+        let path = data_dir.join(SESSION_FILE);
+        let source = serde_jsonrc::to_string(self)
+            .map_err(|e| SimpleError::with("could not serialize session", e))?;
+
+        fs::write(&path, source)
+            .map_err(|e| SimpleError::with(&format!("could not write {}", path.display()), e))
+    }
+}
+
+fn language(id: Option<&str>) -> Option<sourceview::Language> {
+    sourceview::LanguageManager::get_default()?.get_language(id?)
+}
+
+// a document open in a tab
+struct Tab {
+    // stays the same while the tab is open, unlike its index
+    id: usize,
+    buffer: sourceview::Buffer,
+    label: gtk::Label,
+    close_button: gtk::Button,
+    // a script is running on the document, so the tab can't be closed
+    busy: bool,
+    title: String,
+    // the file the document was opened from or saved to, None for scratchpads
+    path: Option<PathBuf>,
+    // what the file was decoded from and is saved as
+    encoding: &'static str,
+}
+
+impl Tab {
+    // files with unsaved changes are marked, scratchpads are kept by the session instead
+    fn update_label(&self) {
+        if self.path.is_some() && self.buffer.get_modified() {
+            self.label.set_text(&format!("{} \u{2022}", self.title));
+        } else {
+            self.label.set_text(&self.title);
+        }
+        self.label.set_tooltip_text(
+            self.path
+                .as_ref()
+                .map(|path| path.display().to_string())
+                .as_deref(),
+        );
+    }
+
+    fn text(&self) -> String {
+        self.buffer
+            .get_text(
+                &self.buffer.get_start_iter(),
+                &self.buffer.get_end_iter(),
+                false,
+            )
+            .map(|text| text.to_string())
+            .unwrap_or_default()
+    }
+
+    fn is_unsaved(&self) -> bool {
+        self.path.is_some() && self.buffer.get_modified()
+    }
+
+    fn save(&mut self, path: PathBuf) -> Result<(), SimpleError> {
+        files::write_text(&path, &self.text(), self.encoding)?;
+
+        info!("saved {} ({})", path.display(), self.encoding);
+        self.title = file_name(&path);
+        self.path = Some(path);
+        self.buffer.set_modified(false);
+        self.update_label();
+        Ok(())
+    }
+}
+
+fn saved_encoding(saved: &SavedTab) -> &'static str {
+    files::ENCODINGS
+        .iter()
+        .find(|encoding| Some(**encoding) == saved.encoding.as_deref())
+        .copied()
+        .unwrap_or(UTF8)
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| path.display().to_string())
+}
+
+#[derive(Clone)]
+pub struct Tabs {
+    notebook: gtk::Notebook,
+    view: sourceview::View,
+    // in the order of the notebook's pages
+    tabs: Rc<RefCell<Vec<Tab>>>,
+    // new tabs are numbered, e.g. "Scratchpad 2"
+    next_number: Rc<Cell<usize>>,
+    next_id: Rc<Cell<usize>>,
+}
+
+impl Tabs {
+    // the buffer the view already has becomes the first tab
+    pub fn new(notebook: gtk::Notebook, view: sourceview::View) -> Self {
+        let buffer = view
+            .get_buffer()
+            .expect("failed to get buffer")
+            .downcast::<sourceview::Buffer>()
+            .expect("faild to downcast TextBuffer to sourceview Buffer");
+
+        let tabs = Tabs {
+            notebook,
+            view,
+            tabs: Rc::new(RefCell::new(Vec::new())),
+            next_number: Rc::new(Cell::new(1)),
+            next_id: Rc::new(Cell::new(0)),
+        };
+        tabs.add_page(buffer, None, None);
+
+        {
+            let tabs_ = tabs.clone();
+            tabs.notebook.connect_switch_page(move |_, _, index| {
+                if let Some(tab) = tabs_.tabs.borrow().get(index as usize) {
+                    tabs_.view.set_buffer(Some(&tab.buffer));
+                    tabs_.view.grab_focus();
+                }
+            });
+        }
+
+        tabs
+    }
+
+    // opens a tab after the others and shows it, its buffer is returned so it can be watched
+    pub fn open(
+        &self,
+        title: Option<&str>,
+        path: Option<PathBuf>,
+        text: &str,
+        language: Option<&sourceview::Language>,
+    ) -> sourceview::Buffer {
+        let buffer = sourceview::Buffer::new(None::<&gtk::TextTagTable>);
+        buffer.set_highlight_syntax(true);
+        buffer.set_language(language);
+        // the text a tab is opened with can't be undone
+        buffer.begin_not_undoable_action();
+        buffer.set_text(text);
+        buffer.end_not_undoable_action();
+        buffer.set_modified(false);
+
+        let index = self.add_page(buffer.clone(), title, path);
+        self.notebook.set_current_page(Some(index as u32));
+        buffer
+    }
+
+    fn add_page(
+        &self,
+        buffer: sourceview::Buffer,
+        title: Option<&str>,
+        path: Option<PathBuf>,
+    ) -> usize {
+        let title = match (title, &path) {
+            (Some(title), _) => title.to_string(),
+            (None, Some(path)) => file_name(path),
+            (None, None) => {
+                let number = self.next_number.get();
+                self.next_number.set(number + 1);
+                format!("Scratchpad {}", number)
+            }
+        };
+
+        let label = gtk::Label::new(None);
+        let close_button =
+            gtk::Button::from_icon_name(Some("window-close-symbolic"), gtk::IconSize::Menu);
+        close_button.set_relief(gtk::ReliefStyle::None);
+        close_button.set_tooltip_text(Some("Close Tab (Ctrl+W)"));
+        let tab_label = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+        tab_label.pack_start(&label, true, true, 0);
+        tab_label.pack_start(&close_button, false, false, 0);
+        tab_label.show_all();
+
+        // pages only hold the place of their tab, the editor below shows the current one
+        let page = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        page.show();
+
+        let id = self.next_id.get();
+        self.next_id.set(id + 1);
+        let tab = Tab {
+            id,
+            buffer: buffer.clone(),
+            label: label.clone(),
+            close_button: close_button.clone(),
+            busy: false,
+            title,
+            path,
+            encoding: UTF8,
+        };
+        tab.update_label();
+        self.tabs.borrow_mut().push(tab);
+        let index = self.notebook.append_page(&page, Some(&tab_label));
+        self.notebook.set_show_tabs(self.tabs.borrow().len() > 1);
+
+        {
+            let tabs = self.clone();
+            // tabs being changed are updated by whatever changes them
+            buffer.connect_modified_changed(move |buffer| {
+                if let Ok(tabs) = tabs.tabs.try_borrow() {
+                    if let Some(tab) = tabs.iter().find(|tab| tab.buffer == *buffer) {
+                        tab.update_label();
+                    }
+                }
+            });
+        }
+        {
+            let tabs = self.clone();
+            close_button.connect_clicked(move |_| {
+                if let Some(index) = tabs.notebook.page_num(&page) {
+                    tabs.close(index as usize);
+                }
+            });
+        }
+
+        index as usize
+    }
+
+    // closing the last tab empties it instead, so there's always a document to run scripts on,
+    // unsaved changes to a file are confirmed first, busy tabs aren't closed
+    pub fn close(&self, index: usize) {
+        let unsaved = match self.tabs.borrow().get(index) {
+            Some(tab) if tab.busy => return,
+            Some(tab) if tab.is_unsaved() => vec![tab.title.clone()],
+            Some(_) => Vec::new(),
+            None => return,
+        };
+        if !unsaved.is_empty() {
+            match self.confirm_unsaved(&unsaved) {
+                gtk::ResponseType::Accept => {
+                    if let Err(e) = self.save(index, None) {
+                        error!("{}", e);
+                        return;
+                    }
+                }
+                gtk::ResponseType::Reject => {}
+                _ => return,
+            }
+        }
+
+        if self.tabs.borrow().len() == 1 {
+            let mut tabs = self.tabs.borrow_mut();
+            let tab = &mut tabs[0];
+            tab.buffer.set_text("");
+            tab.buffer.set_modified(false);
+            tab.path = None;
+            tab.encoding = UTF8;
+            tab.title = "Scratchpad 1".to_string();
+            tab.update_label();
+            self.next_number.set(2);
+            return;
+        }
+
+        self.tabs.borrow_mut().remove(index);
+        self.notebook.remove_page(Some(index as u32));
+        self.notebook.set_show_tabs(self.tabs.borrow().len() > 1);
+    }
+
+    pub fn close_current(&self) {
+        if let Some(index) = self.notebook.get_current_page() {
+            self.close(index as usize);
+        }
+    }
+
+    // a tab can't be closed while a script runs on it, so its result has a document to go to
+    pub fn set_busy(&self, id: usize, busy: bool) {
+        if let Some(tab) = self.tabs.borrow_mut().iter_mut().find(|tab| tab.id == id) {
+            tab.busy = busy;
+            tab.close_button.set_sensitive(!busy);
+        }
+    }
+
+    fn current(&self) -> usize {
+        self.notebook.get_current_page().unwrap_or(0) as usize
+    }
+
+    // id of the tab that's shown
+    pub fn current_id(&self) -> usize {
+        let tabs = self.tabs.borrow();
+        tabs[self.current().min(tabs.len() - 1)].id
+    }
+
+    pub fn title(&self, id: usize) -> Option<String> {
+        self.tabs
+            .borrow()
+            .iter()
+            .find(|tab| tab.id == id)
+            .map(|tab| tab.title.clone())
+    }
+
+    // the title and file of the tab that's shown
+    pub fn current_file(&self) -> (String, Option<PathBuf>) {
+        let tabs = self.tabs.borrow();
+        let tab = &tabs[self.current().min(tabs.len() - 1)];
+        (tab.title.clone(), tab.path.clone())
+    }
+
+    pub fn current_encoding(&self) -> &'static str {
+        self.tabs
+            .borrow()
+            .get(self.current())
+            .map_or(UTF8, |tab| tab.encoding)
+    }
+
+    // the encoding the current tab's file was read as, or should be saved as
+    pub fn set_current_encoding(&self, encoding: &'static str) {
+        let current = self.current();
+        if let Some(tab) = self.tabs.borrow_mut().get_mut(current) {
+            tab.encoding = encoding;
+        }
+    }
+
+    pub fn is_current_unsaved(&self) -> bool {
+        self.tabs
+            .borrow()
+            .get(self.current())
+            .map(Tab::is_unsaved)
+            .unwrap_or(false)
+    }
+
+    // writes the tab to `path`, or the file it belongs to, the tab then belongs to the file it was
+    // written to
+    pub fn save(&self, index: usize, path: Option<PathBuf>) -> Result<PathBuf, SimpleError> {
+        let mut tabs = self.tabs.borrow_mut();
+        let tab = match tabs.get_mut(index) {
+            Some(tab) => tab,
+            None => bail!("no tab {}", index),
+        };
+        let path = match path.or_else(|| tab.path.clone()) {
+            Some(path) => path,
+            None => bail!("{} hasn't been saved to a file", tab.title),
+        };
+
+        tab.save(path.clone())?;
+        Ok(path)
+    }
+
+    pub fn save_current(&self, path: Option<PathBuf>) -> Result<PathBuf, SimpleError> {
+        self.save(self.current(), path)
+    }
+
+    // titles of the files with unsaved changes
+    pub fn unsaved(&self) -> Vec<String> {
+        self.tabs
+            .borrow()
+            .iter()
+            .filter(|tab| tab.is_unsaved())
+            .map(|tab| tab.title.clone())
+            .collect()
+    }
+
+    pub fn save_all(&self) -> Result<(), SimpleError> {
+        let unsaved: Vec<usize> = self
+            .tabs
+            .borrow()
+            .iter()
+            .enumerate()
+            .filter(|(_, tab)| tab.is_unsaved())
+            .map(|(index, _)| index)
+            .collect();
+
+        for index in unsaved {
+            self.save(index, None)?;
+        }
+        Ok(())
+    }
+
+    // asks whether to save the files before they're closed, Accept to save them, Reject to close
+    // them without saving
+    pub fn confirm_unsaved(&self, titles: &[String]) -> gtk::ResponseType {
+        let window = self
+            .notebook
+            .get_toplevel()
+            .and_then(|toplevel| toplevel.downcast::<gtk::Window>().ok());
+        let question = match titles {
+            [title] => format!("Save changes to {} before closing?", title),
+            _ => format!("Save changes to {} files before closing?", titles.len()),
+        };
+
+        let dialog = gtk::MessageDialog::new(
+            window.as_ref(),
+            gtk::DialogFlags::MODAL | gtk::DialogFlags::DESTROY_WITH_PARENT,
+            gtk::MessageType::Question,
+            gtk::ButtonsType::None,
+            &question,
+        );
+        dialog.add_buttons(&[
+            ("Close _Without Saving", gtk::ResponseType::Reject),
+            ("_Cancel", gtk::ResponseType::Cancel),
+            ("_Save", gtk::ResponseType::Accept),
+        ]);
+        dialog.set_default_response(gtk::ResponseType::Accept);
+
+        let response = dialog.run();
+        dialog.close();
+        response
+    }
+
+    pub fn buffers(&self) -> Vec<sourceview::Buffer> {
+        self.tabs
+            .borrow()
+            .iter()
+            .map(|tab| tab.buffer.clone())
+            .collect()
+    }
+
+    pub fn session(&self) -> Session {
+        let tabs = self
+            .tabs
+            .borrow()
+            .iter()
+            .map(|tab| SavedTab {
+                id: Some(tab.id),
+                title: tab.title.clone(),
+                text: tab.text(),
+                language: tab
+                    .buffer
+                    .get_language()
+                    .and_then(|language| language.get_id())
+                    .map(|id| id.to_string()),
+                cursor: tab.buffer.get_property_cursor_position() as usize,
+                path: tab.path.clone(),
+                modified: tab.buffer.get_modified(),
+                encoding: Some(tab.encoding)
+                    .filter(|encoding| *encoding != UTF8)
+                    .map(|encoding| encoding.to_string()),
+            })
+            .collect();
+
+        Session {
+            tabs,
+            current: self.current(),
+            ..Default::default()
+        }
+    }
+
+    // reopens the tabs of a session with their cursors where they were, the first replaces the tab
+    // that's open, buffers of the tabs opened are returned so they can be watched
+    pub fn restore(&self, session: &Session) -> Vec<sourceview::Buffer> {
+        let mut saved_tabs = session.tabs.iter();
+        let first = match saved_tabs.next() {
+            Some(first) => first,
+            None => return Vec::new(),
+        };
+
+        {
+            let mut tabs = self.tabs.borrow_mut();
+            let tab = &mut tabs[0];
+            tab.id = first.id.unwrap_or(tab.id);
+            tab.title = first.title.clone();
+            tab.path = first.path.clone();
+            tab.encoding = saved_encoding(first);
+            tab.buffer
+                .set_language(language(first.language.as_deref()).as_ref());
+            tab.buffer.begin_not_undoable_action();
+            tab.buffer.set_text(&first.text);
+            tab.buffer.end_not_undoable_action();
+            tab.buffer
+                .place_cursor(&tab.buffer.get_iter_at_offset(first.cursor as i32));
+        }
+        // the label is updated once the tabs aren't borrowed
+        self.tabs.borrow()[0].buffer.set_modified(first.modified);
+        self.tabs.borrow()[0].update_label();
+
+        let buffers = saved_tabs
+            .map(|saved| {
+                let buffer = self.open(
+                    Some(&saved.title),
+                    saved.path.clone(),
+                    &saved.text,
+                    language(saved.language.as_deref()).as_ref(),
+                );
+                buffer.place_cursor(&buffer.get_iter_at_offset(saved.cursor as i32));
+                buffer.set_modified(saved.modified);
+                if let Some(tab) = self.tabs.borrow_mut().last_mut() {
+                    tab.id = saved.id.unwrap_or(tab.id);
+                    tab.encoding = saved_encoding(saved);
+                }
+                buffer
+            })
+            .collect();
+
+        let next_id = self
+            .tabs
+            .borrow()
+            .iter()
+            .map(|tab| tab.id)
+            .max()
+            .unwrap_or(0)
+            + 1;
+        self.next_id.set(next_id);
+        self.next_number.set(session.tabs.len() + 1);
+        self.notebook
+            .set_current_page(Some(session.current.min(session.tabs.len() - 1) as u32));
+        buffers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_session() {
+        let data_dir =
+            std::env::temp_dir().join(format!("boop-gtk-session-{}", std::process::id()));
+
+        assert!(Session::load(&data_dir).tabs.is_empty());
+
+        let tabs = vec![
+            SavedTab {
+                id: Some(3),
+                title: "Scratchpad 1".to_string(),
+                text: "{\"a\": 1}".to_string(),
+                language: Some("json".to_string()),
+                cursor: 3,
+                path: Some(PathBuf::from("/tmp/a.json")),
+                modified: true,
+                encoding: Some("Windows-1252".to_string()),
+            },
+            SavedTab {
+                id: None,
+                title: "Scratchpad 2".to_string(),
+                text: String::new(),
+                language: None,
+                cursor: 0,
+                path: None,
+                modified: false,
+                encoding: None,
+            },
+        ];
+        Session {
+            tabs: tabs.clone(),
+            current: 1,
+            window_size: Some((800, 600)),
+            maximized: false,
+        }
+        .save(&data_dir)
+        .unwrap();
+
+        let session = Session::load(&data_dir);
+        assert_eq!(tabs, session.tabs);
+        assert_eq!(1, session.current);
+        assert_eq!(Some((800, 600)), session.window_size);
+
+        fs::remove_dir_all(&data_dir).unwrap();
+    }
+}
@@ -0,0 +1,421 @@
+// the keys of a small Vim layer over the editor, typed keys are parsed into commands and motions
+// are worked out on the text's characters, `keybindings` carries them out in the buffer
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Normal,
+    Insert,
+    Visual,
+    VisualLine,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    // w, b and e
+    WordForward,
+    WordBackward,
+    WordEnd,
+    // 0, ^ and $
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    // gg and G, the first non-blank character of a line counting from 1, the last line if None
+    Line(Option<usize>),
+}
+
+impl Motion {
+    // true if the character the motion stops at is part of the text an operator works on
+    pub fn is_inclusive(self) -> bool {
+        self == Motion::WordEnd
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Command {
+    Move(Motion, usize),
+    // an operator over the text a motion moves across, e.g. d3w
+    Operate(Operator, Motion, usize),
+    // dd, cc and yy over a number of lines
+    OperateLines(Operator, usize),
+    // x
+    DeleteChars(usize),
+    // i, a, I and A
+    Insert,
+    Append,
+    InsertAtLineStart,
+    AppendAtLineEnd,
+    // o and O
+    OpenBelow,
+    OpenAbove,
+    // p and P
+    Paste { before: bool, count: usize },
+    Undo(usize),
+    Redo(usize),
+    // v and V
+    Visual,
+    VisualLine,
+    // d, x, c and y in visual mode
+    OperateSelection(Operator),
+}
+
+enum Parsed<T> {
+    Complete(T),
+    // more keys are needed
+    Incomplete,
+    Invalid,
+}
+
+// keys typed in normal and visual mode, until they make a command
+#[derive(Debug, Default)]
+pub struct Parser {
+    pending: String,
+}
+
+impl Parser {
+    // keys typed so far, e.g. "2d"
+    pub fn pending(&self) -> &str {
+        &self.pending
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    // the command the keys typed make with `c`, None until they make one, keys that can't make
+    // one are forgotten
+    pub fn feed(&mut self, c: char, mode: Mode) -> Option<Command> {
+        self.pending.push(c);
+        match parse(&self.pending, mode) {
+            Parsed::Complete(command) => {
+                self.pending.clear();
+                Some(command)
+            }
+            Parsed::Incomplete => None,
+            Parsed::Invalid => {
+                self.pending.clear();
+                None
+            }
+        }
+    }
+}
+
+// a count in front of keys, "0" on its own is a motion
+fn split_count(keys: &str) -> (Option<usize>, &str) {
+    let digits = keys
+        .char_indices()
+        .take_while(|&(i, c)| c.is_ascii_digit() && !(i == 0 && c == '0'))
+        .count();
+    if digits == 0 {
+        (None, keys)
+    } else {
+        (keys[..digits].parse().ok(), &keys[digits..])
+    }
+}
+
+fn parse_motion(keys: &str, count: Option<usize>) -> Parsed<Motion> {
+    let motion = match keys {
+        "h" => Motion::Left,
+        "l" => Motion::Right,
+        "k" => Motion::Up,
+        "j" => Motion::Down,
+        "w" => Motion::WordForward,
+        "b" => Motion::WordBackward,
+        "e" => Motion::WordEnd,
+        "0" => Motion::LineStart,
+        "^" => Motion::FirstNonBlank,
+        "$" => Motion::LineEnd,
+        "G" => Motion::Line(count),
+        "gg" => Motion::Line(Some(count.unwrap_or(1))),
+        "g" => return Parsed::Incomplete,
+        _ => return Parsed::Invalid,
+    };
+    Parsed::Complete(motion)
+}
+
+fn parse(keys: &str, mode: Mode) -> Parsed<Command> {
+    let (count, keys) = split_count(keys);
+    let n = count.unwrap_or(1);
+
+    if mode == Mode::Visual || mode == Mode::VisualLine {
+        let operator = match keys {
+            "d" | "x" => Some(Operator::Delete),
+            "c" => Some(Operator::Change),
+            "y" => Some(Operator::Yank),
+            _ => None,
+        };
+        if let Some(operator) = operator {
+            return Parsed::Complete(Command::OperateSelection(operator));
+        }
+    }
+
+    let command = match keys {
+        "" => return Parsed::Incomplete,
+        "x" => Command::DeleteChars(n),
+        "i" => Command::Insert,
+        "a" => Command::Append,
+        "I" => Command::InsertAtLineStart,
+        "A" => Command::AppendAtLineEnd,
+        "o" => Command::OpenBelow,
+        "O" => Command::OpenAbove,
+        "p" => Command::Paste {
+            before: false,
+            count: n,
+        },
+        "P" => Command::Paste {
+            before: true,
+            count: n,
+        },
+        "u" => Command::Undo(n),
+        "v" => Command::Visual,
+        "V" => Command::VisualLine,
+        "D" => Command::Operate(Operator::Delete, Motion::LineEnd, 1),
+        "C" => Command::Operate(Operator::Change, Motion::LineEnd, 1),
+        _ => {
+            let mut chars = keys.chars();
+            let operator = match chars.next() {
+                Some('d') => Operator::Delete,
+                Some('c') => Operator::Change,
+                Some('y') => Operator::Yank,
+                _ => {
+                    return match parse_motion(keys, count) {
+                        // the count of gg and G is the line
+                        Parsed::Complete(motion @ Motion::Line(_)) => {
+                            Parsed::Complete(Command::Move(motion, 1))
+                        }
+                        Parsed::Complete(motion) => Parsed::Complete(Command::Move(motion, n)),
+                        Parsed::Incomplete => Parsed::Incomplete,
+                        Parsed::Invalid => Parsed::Invalid,
+                    };
+                }
+            };
+
+            // dd, cc and yy
+            if keys[1..] == keys[..1] {
+                return Parsed::Complete(Command::OperateLines(operator, n));
+            }
+
+            // counts before the operator and the motion multiply, 2d3w deletes 6 words
+            let (motion_count, motion_keys) = split_count(chars.as_str());
+            if motion_keys.is_empty() {
+                return Parsed::Incomplete;
+            }
+            let count = match (count, motion_count) {
+                (None, None) => None,
+                (count, motion_count) => Some(count.unwrap_or(1) * motion_count.unwrap_or(1)),
+            };
+            match parse_motion(motion_keys, count) {
+                Parsed::Complete(motion) => Command::Operate(operator, motion, count.unwrap_or(1)),
+                Parsed::Incomplete => return Parsed::Incomplete,
+                Parsed::Invalid => return Parsed::Invalid,
+            }
+        }
+    };
+    Parsed::Complete(command)
+}
+
+// the offset of the first character on the line `offset` is on
+pub fn line_start(text: &[char], offset: usize) -> usize {
+    text[..offset.min(text.len())]
+        .iter()
+        .rposition(|&c| c == '\n')
+        .map_or(0, |i| i + 1)
+}
+
+// the offset of the line break ending the line `offset` is on, or the end of the text
+pub fn line_end(text: &[char], offset: usize) -> usize {
+    let offset = offset.min(text.len());
+    text[offset..]
+        .iter()
+        .position(|&c| c == '\n')
+        .map_or(text.len(), |i| offset + i)
+}
+
+fn first_non_blank(text: &[char], line_start: usize) -> usize {
+    let end = line_end(text, line_start);
+    text[line_start..end]
+        .iter()
+        .position(|&c| c != ' ' && c != '\t')
+        .map_or(end, |i| line_start + i)
+}
+
+// whitespace, word characters and punctuation, a word is a run of one kind
+fn class(c: char) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if c.is_alphanumeric() || c == '_' {
+        1
+    } else {
+        2
+    }
+}
+
+fn word_forward(text: &[char], mut offset: usize) -> usize {
+    if offset >= text.len() {
+        return text.len();
+    }
+    let start_class = class(text[offset]);
+    while offset < text.len() && start_class != 0 && class(text[offset]) == start_class {
+        offset += 1;
+    }
+    while offset < text.len() && class(text[offset]) == 0 {
+        offset += 1;
+    }
+    offset
+}
+
+fn word_backward(text: &[char], mut offset: usize) -> usize {
+    while offset > 0 && class(text[offset - 1]) == 0 {
+        offset -= 1;
+    }
+    if offset == 0 {
+        return 0;
+    }
+    let word_class = class(text[offset - 1]);
+    while offset > 0 && class(text[offset - 1]) == word_class {
+        offset -= 1;
+    }
+    offset
+}
+
+fn word_end(text: &[char], offset: usize) -> usize {
+    let mut offset = offset + 1;
+    while offset < text.len() && class(text[offset]) == 0 {
+        offset += 1;
+    }
+    if offset >= text.len() {
+        return text.len().saturating_sub(1);
+    }
+    let word_class = class(text[offset]);
+    while offset + 1 < text.len() && class(text[offset + 1]) == word_class {
+        offset += 1;
+    }
+    offset
+}
+
+// the offset of the line `count` lines below (or above if negative) the one `offset` is on, at
+// the same column or the end of the line if it's shorter
+fn vertical(text: &[char], offset: usize, count: isize) -> usize {
+    let column = offset - line_start(text, offset);
+    let mut start = line_start(text, offset);
+    for _ in 0..count.abs() {
+        if count > 0 {
+            let end = line_end(text, start);
+            if end == text.len() {
+                break;
+            }
+            start = end + 1;
+        } else {
+            if start == 0 {
+                break;
+            }
+            start = line_start(text, start - 1);
+        }
+    }
+    (start + column).min(line_end(text, start))
+}
+
+// the offset the cursor at `offset` moves to with `motion`, `count` times
+pub fn target(text: &[char], offset: usize, motion: Motion, count: usize) -> usize {
+    let offset = offset.min(text.len());
+    match motion {
+        Motion::Left => offset.saturating_sub(count).max(line_start(text, offset)),
+        Motion::Right => (offset + count).min(line_end(text, offset)),
+        Motion::Up => vertical(text, offset, -(count as isize)),
+        Motion::Down => vertical(text, offset, count as isize),
+        Motion::WordForward => (0..count).fold(offset, |offset, _| word_forward(text, offset)),
+        Motion::WordBackward => (0..count).fold(offset, |offset, _| word_backward(text, offset)),
+        Motion::WordEnd => (0..count).fold(offset, |offset, _| word_end(text, offset)),
+        Motion::LineStart => line_start(text, offset),
+        Motion::FirstNonBlank => first_non_blank(text, line_start(text, offset)),
+        Motion::LineEnd => line_end(text, vertical(text, offset, count as isize - 1)),
+        Motion::Line(line) => {
+            let start = match line {
+                Some(line) => vertical(text, 0, line.saturating_sub(1) as isize),
+                None => line_start(text, text.len()),
+            };
+            first_non_blank(text, line_start(text, start))
+        }
+    }
+}
+
+// the start of the line `offset` is on and the end of the line `count` - 1 lines below, after its
+// line break if it has one
+pub fn line_range(text: &[char], offset: usize, count: usize) -> (usize, usize) {
+    let last = vertical(text, offset, count as isize - 1);
+    let end = line_end(text, last);
+    (line_start(text, offset), (end + 1).min(text.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(keys: &str, mode: Mode) -> Option<Command> {
+        let mut parser = Parser::default();
+        keys.chars().filter_map(|c| parser.feed(c, mode)).last()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(
+            Some(Command::Move(Motion::Down, 3)),
+            feed("3j", Mode::Normal)
+        );
+        assert_eq!(
+            Some(Command::Operate(Operator::Delete, Motion::WordForward, 6)),
+            feed("2d3w", Mode::Normal)
+        );
+        assert_eq!(
+            Some(Command::OperateLines(Operator::Yank, 2)),
+            feed("2yy", Mode::Normal)
+        );
+        assert_eq!(
+            Some(Command::Move(Motion::Line(Some(10)), 1)),
+            feed("10gg", Mode::Normal)
+        );
+        assert_eq!(
+            Some(Command::Move(Motion::LineStart, 1)),
+            feed("0", Mode::Normal)
+        );
+        assert_eq!(
+            Some(Command::OperateSelection(Operator::Delete)),
+            feed("x", Mode::Visual)
+        );
+        assert_eq!(None, feed("dq", Mode::Normal));
+
+        let mut parser = Parser::default();
+        assert_eq!(None, parser.feed('2', Mode::Normal));
+        assert_eq!(None, parser.feed('d', Mode::Normal));
+        assert_eq!("2d", parser.pending());
+    }
+
+    #[test]
+    fn test_target() {
+        let text: Vec<char> = "let x = 1;\n  foo(bar)\nend".chars().collect();
+        assert_eq!(4, target(&text, 0, Motion::WordForward, 1));
+        assert_eq!(6, target(&text, 0, Motion::WordForward, 2));
+        assert_eq!(2, target(&text, 0, Motion::WordEnd, 1));
+        assert_eq!(13, target(&text, 16, Motion::WordBackward, 1));
+        assert_eq!(13, target(&text, 16, Motion::FirstNonBlank, 1));
+        assert_eq!(21, target(&text, 16, Motion::LineEnd, 1));
+        assert_eq!(14, target(&text, 3, Motion::Down, 1));
+        assert_eq!(25, target(&text, 3, Motion::Down, 5));
+        assert_eq!(22, target(&text, 3, Motion::Line(None), 1));
+        assert_eq!(13, target(&text, 3, Motion::Line(Some(2)), 1));
+        assert_eq!(11, target(&text, 13, Motion::Left, 5));
+
+        assert_eq!((11, 22), line_range(&text, 16, 1));
+        assert_eq!((11, 25), line_range(&text, 16, 9));
+    }
+}
@@ -0,0 +1,175 @@
+// reloads user scripts when they're created, changed or removed, so they can be edited without
+// restarting the app
+
+use crate::script::Script;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+    thread,
+};
+
+// what happened to a script after its file changed, shown to the user
+#[derive(Debug, PartialEq)]
+pub enum ScriptEvent {
+    Reloaded(String),
+    Removed(String),
+    Failed(PathBuf, String),
+}
+
+// watches the scripts directory until the app exits, sending an event for each script reloaded
+pub fn watch(
+    scripts_dir: PathBuf,
+    scripts: Arc<RwLock<Vec<Script>>>,
+    sender: glib::Sender<ScriptEvent>,
+) {
+    let watcher = RecommendedWatcher::new_immediate(move |res: notify::Result<notify::Event>| {
+        let event = match res {
+            Ok(event) => event,
+            Err(e) => {
+                error!("watch error: {:?}", e);
+                return;
+            }
+        };
+
+        // reading a script doesn't change it
+        if let EventKind::Access(_) = event.kind {
+            return;
+        }
+
+        for path in event.paths {
+            if path
+                .extension()
+                .map(|extension| extension != "js")
+                .unwrap_or(true)
+            {
+                continue;
+            }
+
+            debug!("{} changed", path.display());
+            let mut scripts = scripts.write().expect("scripts lock is poisoned");
+            if let Some(event) = reload(&mut scripts, &path) {
+                if sender.send(event).is_err() {
+                    warn!("app stopped listening for script changes");
+                }
+            }
+        }
+    });
+
+    let mut watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(e) => {
+            error!("couldn't create watcher: {}", e);
+            return;
+        }
+    };
+
+    info!("watching {}", scripts_dir.display());
+    if let Err(e) = watcher.watch(&scripts_dir, RecursiveMode::Recursive) {
+        error!("watch start error: {}", e);
+        return;
+    }
+
+    // events are delivered on the watcher's own thread for as long as it's kept alive
+    loop {
+        thread::park();
+    }
+}
+
+// replaces the script loaded from `path` with the file's current contents, or removes it if the
+// file is gone, None if nothing changed
+pub fn reload(scripts: &mut Vec<Script>, path: &Path) -> Option<ScriptEvent> {
+    let index = scripts.iter().position(|script| script.path == path);
+
+    // editors often write the same file more than once when saving
+    let source = fs::read_to_string(path).ok();
+    if let (Some(index), Some(source)) = (index, &source) {
+        if scripts[index].is_loaded_from(source) {
+            return None;
+        }
+    }
+
+    let removed = index.map(|index| {
+        let mut script = scripts.remove(index);
+        script.kill_thread();
+        script
+    });
+
+    let source = match source {
+        Some(source) => source,
+        None => {
+            return removed.map(|script| {
+                info!("{} removed", path.display());
+                ScriptEvent::Removed(script.metadata.name)
+            })
+        }
+    };
+
+    match Script::from_source(source, path.to_path_buf()) {
+        Ok(script) => {
+            info!("{} changed, reloaded", path.display());
+            let name = script.metadata.name.clone();
+            scripts.push(script);
+            scripts.sort_by_key(|script| script.metadata.name.clone());
+            Some(ScriptEvent::Reloaded(name))
+        }
+        Err(e) => {
+            error!("error parsing {}: {}", path.display(), e);
+            Some(ScriptEvent::Failed(path.to_path_buf(), e.to_string()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn source(name: &str) -> String {
+        format!(
+            "/** {{ \"api\": 1, \"name\": \"{}\", \"description\": \"\", \"icon\": \"html\" }} **/
+            function main(state) {{}}",
+            name
+        )
+    }
+
+    #[test]
+    fn test_reload() {
+        let dir = std::env::temp_dir().join(format!("boop-gtk-watcher-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("a.js");
+        let mut scripts = Vec::new();
+
+        fs::write(&path, source("B")).unwrap();
+        assert_eq!(
+            Some(ScriptEvent::Reloaded("B".to_string())),
+            reload(&mut scripts, &path)
+        );
+        assert_eq!(None, reload(&mut scripts, &path));
+
+        fs::write(&path, source("A")).unwrap();
+        assert_eq!(
+            Some(ScriptEvent::Reloaded("A".to_string())),
+            reload(&mut scripts, &path)
+        );
+        assert_eq!(1, scripts.len());
+
+        fs::write(&path, "not a script").unwrap();
+        assert!(matches!(
+            reload(&mut scripts, &path),
+            Some(ScriptEvent::Failed(_, _))
+        ));
+        assert!(scripts.is_empty());
+
+        fs::write(&path, source("A")).unwrap();
+        reload(&mut scripts, &path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            Some(ScriptEvent::Removed("A".to_string())),
+            reload(&mut scripts, &path)
+        );
+        assert!(scripts.is_empty());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}